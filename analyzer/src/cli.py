@@ -1,5 +1,6 @@
 """CLI wrapper for pattern detection with JSON output."""
 
+import argparse
 import json
 import math
 import os
@@ -7,9 +8,8 @@ import sys
 from contextlib import redirect_stdout
 from pathlib import Path
 
-from music21 import chord
-
-from patterns import find_repeats_all_parts, Repeat
+from patterns import (
+    find_repeats_all_parts, find_repeats_in_parts, note_layout, NoteEvent, Repeat)
 
 
 def emit_progress(stage: str, current: int = 0, total: int = 0, message: str = ""):
@@ -25,31 +25,49 @@ def emit_progress(stage: str, current: int = 0, total: int = 0, message: str = "
     sys.stderr.flush()
 
 
-def extract_note_locator(note, index: int) -> dict:
-    """Extract location info from a note for UI highlighting."""
-    if isinstance(note, chord.Chord):
-        pitch = note.pitches[-1].nameWithOctave
-    else:
-        pitch = note.pitch.nameWithOctave
+# Patterns per "patterns" chunk of --stream output
+PATTERN_BATCH = 100
 
-    beat = float(note.beat)
-    return {
+
+def stream_chunks(result: dict, batch: int = PATTERN_BATCH):
+    """The result as chunks for newline-delimited JSON output: each part
+    without its patterns, then its patterns in batches, then every other
+    field. The Rust side rebuilds the result from them as they arrive."""
+    for i, part in enumerate(result.get("parts", [])):
+        patterns = part.get("patterns", [])
+        yield {"type": "part", "part": {**part, "patterns": []}}
+        for start in range(0, len(patterns), batch):
+            yield {"type": "patterns", "part": i, "patterns": patterns[start:start + batch]}
+    yield {"type": "result", "result": {**result, "parts": []}}
+
+
+def extract_note_locator(event: NoteEvent, index: int, layout: bool = False) -> dict:
+    """Extract location info from a note event for UI highlighting."""
+    beat = float(event.note.beat)
+    locator = {
         "index": index,
-        "measure": note.measureNumber,
+        "measure": event.note.measureNumber,
         "beat": None if math.isnan(beat) else beat,
-        "pitch": pitch,
+        "pitch": event.pitch.nameWithOctave,
+        "duration_beats": float(event.duration),
+        "chord_group": event.chord_group,
+        "is_grace": event.is_grace,
+        "tied": event.tied,
     }
+    if layout:
+        locator.update(note_layout(event.note))
+    return locator
 
 
 def _repeats_to_patterns(
-    repeats: list[Repeat], part_index: int, id_offset: int = 0
+    repeats: list[Repeat], part_index: int, id_offset: int = 0, layout: bool = False
 ) -> list[dict]:
     """Convert Repeat objects to JSON-serializable pattern dicts."""
     patterns = []
     for i, r in enumerate(repeats):
         note_locators = [
-            extract_note_locator(n, r.positions[0] + j)
-            for j, n in enumerate(r.notes)
+            extract_note_locator(e, r.positions[0] + j, layout)
+            for j, e in enumerate(r.events)
         ]
         patterns.append({
             "id": id_offset + i,
@@ -62,49 +80,146 @@ def _repeats_to_patterns(
     return patterns
 
 
-def analyze(musicxml_path: str, min_length: int = 4) -> dict:
-    """Analyze MusicXML file and return patterns as JSON-serializable dict."""
+def analyze(
+    musicxml_path: str,
+    min_length: int = 4,
+    chords_as_single_event: bool = True,
+    include_grace_notes: bool = False,
+    split_grand_staff: bool = True,
+    merge_ties: bool = False,
+    layout: bool = False,
+    parts: list[int] | None = None,
+    match_rhythm: bool = True,
+    transposed: bool = False,
+    staff: int | None = None,
+) -> dict:
+    """Analyze MusicXML file and return patterns as JSON-serializable dict.
+
+    Analyzes the given parts, or without them the treble and bass staves
+    picked by find_repeats_all_parts. With `staff`, only that one of them
+    (counting from 0) is analyzed, so the app can run one analyzer per staff.
+    """
     emit_progress("analyzing", 0, 1, "Finding patterns")
-    result = find_repeats_all_parts(musicxml_path, min_length)
+    if parts is None:
+        result = find_repeats_all_parts(
+            musicxml_path, min_length, chords_as_single_event, include_grace_notes,
+            split_grand_staff, merge_ties, match_rhythm, transposed, only=staff)
+        # A single-staff score has no bass rather than an empty one
+        found = [p for p in (result.treble, result.bass) if p]
+    else:
+        if staff is not None:
+            parts = parts[staff:staff + 1]
+        found = find_repeats_in_parts(
+            musicxml_path, parts, min_length, chords_as_single_event,
+            include_grace_notes, merge_ties, match_rhythm, transposed)
     emit_progress("analyzing", 1, 1, "Patterns found")
 
-    treble_patterns = []
-    bass_patterns = []
-
-    if result.treble:
-        treble_patterns = _repeats_to_patterns(
-            result.treble.repeats, part_index=0, id_offset=0)
-
-    if result.bass:
-        # Offset bass pattern IDs to avoid collision with treble
-        bass_id_offset = len(treble_patterns)
-        bass_patterns = _repeats_to_patterns(
-            result.bass.repeats, part_index=1, id_offset=bass_id_offset)
+    staves = []
+    for part in found:
+        # Pattern IDs are offset to stay unique across parts; part_index is
+        # the stream index, so note indices can be located in the score
+        patterns = _repeats_to_patterns(
+            part.repeats, part_index=part.part_index,
+            id_offset=sum(len(s["patterns"]) for s in staves), layout=layout)
+        staves.append({
+            "part_index": part.part_index,
+            "part_name": part.part_name,
+            "patterns": patterns,
+        })
 
     return {
         "file": str(musicxml_path),
         "musicxml_content": Path(musicxml_path).read_text(),
-        "treble": {
-            "part_index": 0,
-            "part_name": result.treble.part_name if result.treble else "Treble",
-            "patterns": treble_patterns,
-        },
-        "bass": {
-            "part_index": 1,
-            "part_name": result.bass.part_name if result.bass else "Bass",
-            "patterns": bass_patterns,
-        },
+        "parts": staves,
     }
 
 
-def main():
-    if len(sys.argv) < 2:
-        print(json.dumps(
-            {"error": "Usage: cli.py <musicxml_path> [min_length]"}))
+def _part_list(value: str) -> list[int]:
+    try:
+        return [int(i) for i in value.split(",")]
+    except ValueError:
+        raise argparse.ArgumentTypeError(f"expected comma-separated part indices, got {value!r}")
+
+
+# Reported by --version; kept in step with pyproject.toml
+ANALYZER_VERSION = "0.1.0"
+
+# Options and inputs this build supports, for the app's health check
+FEATURES = [
+    "expand_chords", "include_grace_notes", "no_split_grand_staff",
+    "merge_ties", "layout", "parts", "ignore_rhythm", "transposed", "stream",
+    "staff", "pdf", "images",
+]
+
+
+class _VersionAction(argparse.Action):
+    """Print the version and features as JSON and exit, without needing a
+    score path."""
+
+    def __init__(self, option_strings, dest, **kwargs):
+        super().__init__(option_strings, dest, nargs=0, **kwargs)
+
+    def __call__(self, parser, namespace, values, option_string=None):
+        print(json.dumps({"version": ANALYZER_VERSION, "features": FEATURES}))
+        sys.exit(0)
+
+
+class _JsonArgumentParser(argparse.ArgumentParser):
+    """Report usage errors as JSON on stdout, like every other failure."""
+
+    def error(self, message):
+        print(json.dumps({"error": f"{message}\n{self.format_usage()}"}))
         sys.exit(1)
 
-    path = sys.argv[1]
-    min_len = int(sys.argv[2]) if len(sys.argv) > 2 else 4
+
+def parse_args(argv: list[str]) -> argparse.Namespace:
+    parser = _JsonArgumentParser(prog="cli.py")
+    parser.add_argument(
+        "--version", action=_VersionAction,
+        help="Print the analyzer version and supported features as JSON")
+    parser.add_argument("musicxml_path")
+    parser.add_argument("min_length", nargs="?", type=int, default=4)
+    parser.add_argument(
+        "--expand-chords", action="store_true",
+        help="Match each chord tone as its own event instead of one per chord")
+    parser.add_argument(
+        "--include-grace-notes", action="store_true",
+        help="Match grace notes as ordinary notes instead of skipping them")
+    parser.add_argument(
+        "--no-split-grand-staff", action="store_true",
+        help="Use the first two parts as treble and bass even when a later "
+             "part is a two-staff piano part")
+    parser.add_argument(
+        "--merge-ties", action="store_true",
+        help="Match tied notes as one sustained note instead of separate notes")
+    parser.add_argument(
+        "--layout", action="store_true",
+        help="Include each note's engraved position (default-x/y and page)")
+    parser.add_argument(
+        "--parts", type=_part_list,
+        help="Comma-separated indices of the parts to analyze (each staff of a "
+             "multi-staff part counts as one) instead of treble and bass")
+    parser.add_argument(
+        "--staff", type=int,
+        help="Analyze only this one (counting from 0) of the staves the other "
+             "options select; the app runs one analyzer per staff")
+    parser.add_argument(
+        "--ignore-rhythm", action="store_true",
+        help="Match notes on pitch alone, whatever their durations")
+    parser.add_argument(
+        "--transposed", action="store_true",
+        help="Match runs repeated at another pitch level by their intervals")
+    parser.add_argument(
+        "--stream", action="store_true",
+        help="Print the result as newline-delimited JSON chunks instead of "
+             "one document")
+    return parser.parse_args(argv)
+
+
+def main():
+    args = parse_args(sys.argv[1:])
+    path = args.musicxml_path
+    min_len = args.min_length
 
     if not Path(path).exists():
         print(json.dumps({"error": f"File not found: {path}"}))
@@ -132,13 +247,27 @@ def main():
             musicxml_path = path
 
         try:
-            result = analyze(musicxml_path, min_len)
+            result = analyze(
+                musicxml_path, min_len,
+                chords_as_single_event=not args.expand_chords,
+                include_grace_notes=args.include_grace_notes,
+                split_grand_staff=not args.no_split_grand_staff,
+                merge_ties=args.merge_ties,
+                layout=args.layout,
+                parts=args.parts,
+                match_rhythm=not args.ignore_rhythm,
+                transposed=args.transposed,
+                staff=args.staff)
         except Exception as e:
             print(json.dumps({"error": str(e)}), file=sys.__stdout__)
             sys.exit(1)
 
     # Output JSON to actual stdout
-    print(json.dumps(result, indent=2))
+    if args.stream:
+        for chunk in stream_chunks(result):
+            print(json.dumps(chunk))
+    else:
+        print(json.dumps(result, indent=2))
 
 
 if __name__ == "__main__":
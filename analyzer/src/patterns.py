@@ -1,7 +1,19 @@
 """Find exact repeated note sequences in MusicXML files."""
 
-from dataclasses import dataclass
-from music21 import converter, chord, stream
+from dataclasses import dataclass, field
+from functools import cache
+from music21 import converter, chord, layout, pitch, stream
+
+
+@dataclass
+class NoteEvent:
+    """A single matchable event: a note, a whole chord, or one chord tone."""
+    note: object                # music21 Note or Chord the event comes from
+    pitch: pitch.Pitch          # Pitch used for matching and display
+    chord_group: int | None     # Index of the chord's first event, if part of a chord
+    is_grace: bool = False
+    duration: float = 0.0       # Quarter notes, including any merged tied notes
+    tied: bool = False          # Continues a tie from the previous note of this pitch
 
 
 @dataclass
@@ -10,6 +22,7 @@ class Repeat:
     count: int
     positions: list[int]
     notes: list
+    events: list[NoteEvent] = field(default_factory=list)
 
 
 @dataclass
@@ -34,6 +47,96 @@ def extract_note_signature(n) -> tuple:
     return (n.pitch.midi, n.quarterLength)
 
 
+@cache
+def _page_breaks(part: stream.Part) -> list[float]:
+    """Offsets in the part where a <print new-page="yes"> starts a page."""
+    return [
+        pl.getOffsetInHierarchy(part)
+        for pl in part.recurse().getElementsByClass(layout.PageLayout)
+        if pl.isNew and pl.getOffsetInHierarchy(part) > 0
+    ]
+
+
+def note_layout(n) -> dict:
+    """Engraved position of a note or chord: default-x/default-y in tenths
+    and the 1-based page. All None when the score carries no layout."""
+    style = n.style if n.hasStyleInformation else None
+    x = getattr(style, "absoluteX", None)
+    y = getattr(style, "absoluteY", None)
+
+    page = None
+    part = n.getContextByClass(stream.Part)
+    if part is not None:
+        breaks = _page_breaks(part)
+        if breaks or x is not None or y is not None:
+            offset = n.getOffsetInHierarchy(part)
+            page = 1 + sum(1 for b in breaks if b <= offset)
+
+    return {
+        "x": None if x is None else float(x),
+        "y": None if y is None else float(y),
+        "page": page,
+    }
+
+
+def _part_events(
+    part: stream.Part,
+    chords_as_single_event: bool = True,
+    include_grace_notes: bool = False,
+    merge_ties: bool = False,
+) -> list[NoteEvent]:
+    """Flatten a part into matchable events.
+
+    By default a chord is one event matched on its top pitch. With
+    chords_as_single_event=False each chord tone becomes its own event
+    (lowest first), all sharing the chord's group index.
+
+    Grace notes are dropped unless include_grace_notes is set, so an
+    ornament neither breaks nor joins a pattern.
+
+    A note that continues a tie (e.g. a whole note written as two tied
+    halves across a barline) is flagged as tied. With merge_ties it is
+    folded into the note it continues instead, whose duration grows by its
+    length, so a sustained note is one event rather than a repeated note.
+    """
+    events = []
+    for n in part.recurse().notes:
+        is_grace = n.duration.isGrace
+        if is_grace and not include_grace_notes:
+            continue
+        tied = n.tie is not None and n.tie.type in ("stop", "continue")
+        if not isinstance(n, chord.Chord):
+            pitches, group = [n.pitch], None
+        elif chords_as_single_event:
+            pitches, group = [n.pitches[-1]], len(events)
+        else:
+            pitches, group = sorted(n.pitches, key=lambda p: p.midi), len(events)
+
+        if tied and merge_ties and _extend_tied(events, pitches, n.quarterLength):
+            continue
+        for p in pitches:
+            events.append(NoteEvent(
+                n, p, group, is_grace, duration=n.quarterLength, tied=tied))
+    return events
+
+
+def _extend_tied(events: list[NoteEvent], pitches: list, length: float) -> bool:
+    """Add a tie continuation's length to the latest event of each pitch.
+
+    Returns False (leaving events untouched) if a pitch has nothing to
+    continue, e.g. a tie stop on the first note of the part.
+    """
+    targets = []
+    for p in pitches:
+        target = next((e for e in reversed(events) if e.pitch.midi == p.midi), None)
+        if target is None:
+            return False
+        targets.append(target)
+    for target in targets:
+        target.duration += length
+    return True
+
+
 def _find_lcp_length(sig1: tuple, sig2: tuple) -> int:
     """Find longest common prefix length between two signatures."""
     lcp_len = 0
@@ -107,21 +210,52 @@ def _extract_common_prefixes(
     return result
 
 
-def _find_repeats_in_part(part: stream.Part, min_length: int = 4) -> list[Repeat]:
+def _signatures(
+    events: list[NoteEvent], match_rhythm: bool = True, transposed: bool = False
+) -> list[tuple]:
+    """What each event must share with another for the two to match.
+
+    By default that is pitch and duration. Without match_rhythm durations
+    are ignored; with transposed the pitch is replaced by the interval from
+    the previous event, so a run restated at another pitch level matches
+    (the step into its first note included).
+    """
+    sigs = []
+    for i, event in enumerate(events):
+        if transposed:
+            pitch = event.pitch.midi - events[i - 1].pitch.midi if i else None
+        else:
+            pitch = event.pitch.midi
+        sigs.append((pitch, event.duration) if match_rhythm else (pitch,))
+    return sigs
+
+
+def _find_repeats_in_part(
+    part: stream.Part,
+    min_length: int = 4,
+    chords_as_single_event: bool = True,
+    include_grace_notes: bool = False,
+    merge_ties: bool = False,
+    match_rhythm: bool = True,
+    transposed: bool = False,
+) -> list[Repeat]:
     """Find maximal exact repeated note sequences in a single part.
 
     Args:
         part: music21 Part object to analyze
         min_length: Minimum pattern length in notes
+        chords_as_single_event: Match chords as one event (see _part_events)
+        include_grace_notes: Match grace notes as ordinary events
+        merge_ties: Match tied notes as one sustained event
+        match_rhythm, transposed: What counts as the same note (see _signatures)
 
     Returns:
         List of Repeat objects sorted by significance (length * count)
     """
-    # Extract notes with signatures
-    notes = []
-    for n in part.recurse().notes:
-        sig = extract_note_signature(n)
-        notes.append((sig, n))
+    # Extract events with signatures
+    events = _part_events(
+        part, chords_as_single_event, include_grace_notes, merge_ties)
+    notes = list(zip(_signatures(events, match_rhythm, transposed), events))
 
     sigs = [n[0] for n in notes]
     n_notes = len(notes)
@@ -203,7 +337,8 @@ def _find_repeats_in_part(part: stream.Part, min_length: int = 4) -> list[Repeat
             length=len(pattern),
             count=len(positions),
             positions=sorted(positions),
-            notes=[notes[positions[0] + j][1] for j in range(len(pattern))],
+            notes=[notes[positions[0] + j][1].note for j in range(len(pattern))],
+            events=[notes[positions[0] + j][1] for j in range(len(pattern))],
         ))
 
     # Sort by significance
@@ -232,15 +367,47 @@ def find_repeats(
     return _find_repeats_in_part(score.parts[part_index], min_length)
 
 
+def _grand_staff(score: stream.Score) -> tuple[int, int] | None:
+    """Indices in score.parts of the first two-staff part's upper and lower staves.
+
+    music21 splits a part with <staves>2</staves> into one PartStaff per staff
+    (routing each note by its <staff> number) and groups them with a
+    StaffGroup, so a grand staff is found by looking for that group.
+    """
+    parts = list(score.parts)
+    for group in score[layout.StaffGroup]:
+        staves = [p for p in group.getSpannedElements() if isinstance(p, stream.PartStaff)]
+        if len(staves) >= 2 and all(s in parts for s in staves[:2]):
+            return parts.index(staves[0]), parts.index(staves[1])
+    return None
+
+
 def find_repeats_all_parts(
     musicxml_path: str,
     min_length: int = 4,
+    chords_as_single_event: bool = True,
+    include_grace_notes: bool = False,
+    split_grand_staff: bool = True,
+    merge_ties: bool = False,
+    match_rhythm: bool = True,
+    transposed: bool = False,
+    only: int | None = None,
 ) -> AllPartsRepeats:
     """Find patterns in both treble and bass clef separately.
 
     Args:
         musicxml_path: Path to MusicXML file
         min_length: Minimum pattern length in notes
+        chords_as_single_event: Match chords as one event (see _part_events)
+        include_grace_notes: Match grace notes as ordinary events
+        split_grand_staff: Take treble and bass from the two staves of a
+            piano-style part when the score has one, instead of simply the
+            first two parts (which for voice + piano would be the voice and
+            the right hand)
+        merge_ties: Match tied notes as one sustained event
+        match_rhythm: Require matching notes to have the same duration
+        transposed: Match runs restated at another pitch level
+        only: Analyze only treble (0) or bass (1), leaving the other None
 
     Returns:
         AllPartsRepeats with separate pattern arrays for treble and bass
@@ -248,22 +415,75 @@ def find_repeats_all_parts(
     score = converter.parse(musicxml_path)
     num_parts = len(score.parts)
 
-    treble = None
-    bass = None
+    indices = [0, 1]
+    if split_grand_staff:
+        indices = list(_grand_staff(score) or indices)
+
+    found = []
+    for slot, (part_index, default_name) in enumerate(zip(indices, ["Treble", "Bass"])):
+        if part_index >= num_parts or only not in (None, slot):
+            found.append(None)
+            continue
+        found.append(_part_repeats(
+            score, part_index, default_name, min_length, chords_as_single_event,
+            include_grace_notes, merge_ties, match_rhythm, transposed))
 
-    if num_parts >= 1:
-        part = score.parts[0]
-        part_name = part.partName or "Treble"
-        repeats = _find_repeats_in_part(part, min_length)
-        treble = PartRepeats(part_index=0, part_name=part_name, repeats=repeats)
+    return AllPartsRepeats(treble=found[0], bass=found[1])
 
-    if num_parts >= 2:
-        part = score.parts[1]
-        part_name = part.partName or "Bass"
-        repeats = _find_repeats_in_part(part, min_length)
-        bass = PartRepeats(part_index=1, part_name=part_name, repeats=repeats)
 
-    return AllPartsRepeats(treble=treble, bass=bass)
+def find_repeats_in_parts(
+    musicxml_path: str,
+    part_indices: list[int],
+    min_length: int = 4,
+    chords_as_single_event: bool = True,
+    include_grace_notes: bool = False,
+    merge_ties: bool = False,
+    match_rhythm: bool = True,
+    transposed: bool = False,
+) -> list[PartRepeats]:
+    """Find patterns in each of the given parts separately.
+
+    Args:
+        musicxml_path: Path to MusicXML file
+        part_indices: Indices into the score's parts, with each staff of a
+            multi-staff part counted as its own part (as music21 splits them)
+        min_length, chords_as_single_event, include_grace_notes, merge_ties,
+        match_rhythm, transposed: As for find_repeats_all_parts
+
+    Returns:
+        PartRepeats for each index, in the order given
+    """
+    score = converter.parse(musicxml_path)
+    num_parts = len(score.parts)
+    for part_index in part_indices:
+        if not 0 <= part_index < num_parts:
+            raise ValueError(f"No part {part_index}; the score has {num_parts}")
+    return [
+        _part_repeats(
+            score, part_index, f"Part {part_index + 1}", min_length,
+            chords_as_single_event, include_grace_notes, merge_ties, match_rhythm,
+            transposed)
+        for part_index in part_indices
+    ]
+
+
+def _part_repeats(
+    score: stream.Score,
+    part_index: int,
+    default_name: str,
+    min_length: int,
+    chords_as_single_event: bool,
+    include_grace_notes: bool,
+    merge_ties: bool,
+    match_rhythm: bool,
+    transposed: bool,
+) -> PartRepeats:
+    part = score.parts[part_index]
+    repeats = _find_repeats_in_part(
+        part, min_length, chords_as_single_event, include_grace_notes, merge_ties,
+        match_rhythm, transposed)
+    return PartRepeats(
+        part_index=part_index, part_name=part.partName or default_name, repeats=repeats)
 
 
 def _print_repeats(repeats: list[Repeat], limit: int = 10) -> None:
@@ -6,10 +6,15 @@ from pathlib import Path
 from src.patterns import (
     _find_lcp_length,
     _extract_common_prefixes,
+    _find_repeats_in_part,
+    _part_events,
+    _signatures,
     find_repeats_all_parts,
+    find_repeats_in_parts,
     extract_note_signature,
+    note_layout,
 )
-from music21 import chord
+from music21 import chord, converter, note, stream, tie
 
 
 # Path to test file
@@ -113,6 +118,269 @@ class TestExtractCommonPrefixes:
         assert prefix not in result
 
 
+def _block_chord_part() -> stream.Part:
+    """I-IV-V-I in block chords, played twice."""
+    part = stream.Part()
+    progression = [["C4", "E4", "G4"], ["F4", "A4", "C5"], ["G4", "B4", "D5"], ["C4", "E4", "G4"]]
+    for _ in range(2):
+        for pitches in progression:
+            part.append(chord.Chord(pitches, quarterLength=1.0))
+    return part
+
+
+class TestChordEvents:
+    """Tests for chord handling in _part_events and pattern detection."""
+
+    def test_chord_is_single_event_by_default(self):
+        events = _part_events(_block_chord_part())
+        assert len(events) == 8
+        assert [e.chord_group for e in events] == list(range(8))
+        assert events[0].pitch.nameWithOctave == "G4"
+
+    def test_expanded_chord_tones_share_group(self):
+        events = _part_events(_block_chord_part(), chords_as_single_event=False)
+        assert len(events) == 24
+        assert [e.pitch.nameWithOctave for e in events[:3]] == ["C4", "E4", "G4"]
+        assert {e.chord_group for e in events[:3]} == {0}
+        assert {e.chord_group for e in events[3:6]} == {3}
+
+    def test_block_chord_progression_repeats(self):
+        repeats = _find_repeats_in_part(_block_chord_part(), min_length=4)
+        assert len(repeats) == 1
+        assert repeats[0].length == 4
+        assert repeats[0].positions == [0, 4]
+
+    def test_expanded_progression_repeats_per_tone(self):
+        repeats = _find_repeats_in_part(
+            _block_chord_part(), min_length=4, chords_as_single_event=False)
+        assert len(repeats) == 1
+        assert repeats[0].length == 12
+        assert repeats[0].positions == [0, 12]
+
+
+def _acciaccatura_part() -> stream.Part:
+    """C-D-E-F played twice, with an acciaccatura before the second E."""
+    part = stream.Part()
+    for name in ["C4", "D4", "E4", "F4", "G4", "C4", "D4"]:
+        part.append(note.Note(name, quarterLength=1.0))
+    grace = note.Note("D#4").getGrace()
+    grace.duration.slash = True
+    part.append(grace)
+    for name in ["E4", "F4"]:
+        part.append(note.Note(name, quarterLength=1.0))
+    return part
+
+
+class TestGraceNotes:
+    """Tests for grace note handling."""
+
+    def test_grace_notes_excluded_by_default(self):
+        events = _part_events(_acciaccatura_part())
+        assert len(events) == 9
+        assert not any(e.is_grace for e in events)
+
+    def test_excluded_grace_note_does_not_break_pattern(self):
+        repeats = _find_repeats_in_part(_acciaccatura_part(), min_length=4)
+        assert len(repeats) == 1
+        assert repeats[0].length == 4
+        assert repeats[0].positions == [0, 5]
+
+    def test_included_grace_note_is_marked(self):
+        events = _part_events(_acciaccatura_part(), include_grace_notes=True)
+        assert len(events) == 10
+        assert [e.is_grace for e in events].count(True) == 1
+        assert events[7].pitch.nameWithOctave == "D#4"
+
+    def test_included_grace_note_breaks_pattern(self):
+        repeats = _find_repeats_in_part(
+            _acciaccatura_part(), min_length=4, include_grace_notes=True)
+        assert repeats == []
+
+
+def _tied_part() -> stream.Part:
+    """C-D-E with the E a whole note written as two tied halves across the
+    barline, then C-D-E again with the E written as one whole note."""
+    part = stream.Part()
+    first, second, third = stream.Measure(1), stream.Measure(2), stream.Measure(3)
+    tie_start = note.Note("E4", quarterLength=2.0)
+    tie_start.tie = tie.Tie("start")
+    tie_stop = note.Note("E4", quarterLength=2.0)
+    tie_stop.tie = tie.Tie("stop")
+    first.append([note.Note("C4"), note.Note("D4"), tie_start])
+    second.append([tie_stop, note.Note("C4"), note.Note("D4")])
+    third.append(note.Note("E4", quarterLength=4.0))
+    part.append([first, second, third])
+    return part
+
+
+class TestTiedNotes:
+    """Tests for notes tied across a barline."""
+
+    def test_tie_continuation_is_flagged(self):
+        events = _part_events(_tied_part())
+        assert len(events) == 6
+        assert [e.tied for e in events] == [False, False, False, True, False, False]
+
+    def test_merged_tie_is_one_sustained_event(self):
+        events = _part_events(_tied_part(), merge_ties=True)
+        assert [e.pitch.nameWithOctave for e in events] == ["C4", "D4", "E4", "C4", "D4", "E4"]
+        assert events[2].duration == 4.0
+        assert not any(e.tied for e in events)
+
+    def test_merged_tie_matches_untied_whole_note(self):
+        merged = _find_repeats_in_part(_tied_part(), min_length=3, merge_ties=True)
+        assert len(merged) == 1
+        assert merged[0].positions == [0, 3]
+        assert _find_repeats_in_part(_tied_part(), min_length=3) == []
+
+
+def _restated_part() -> stream.Part:
+    """C-D-E-F in quarters, C-D-E-F in halves, then D-E-F#-G in quarters."""
+    part = stream.Part()
+    for pitches, length in [("C4 D4 E4 F4", 1.0), ("C4 D4 E4 F4", 2.0), ("D4 E4 F#4 G4", 1.0)]:
+        part.append([note.Note(p, quarterLength=length) for p in pitches.split()])
+    return part
+
+
+class TestMatchingOptions:
+    """Tests for matching without rhythm and across transpositions."""
+
+    def test_rhythm_must_match_by_default(self):
+        assert _find_repeats_in_part(_restated_part(), min_length=4) == []
+
+    def test_ignoring_rhythm_matches_on_pitch(self):
+        repeats = _find_repeats_in_part(_restated_part(), min_length=4, match_rhythm=False)
+        assert [r.positions for r in repeats] == [[0, 4]]
+
+    def test_signatures_are_intervals_when_transposed(self):
+        events = _part_events(_restated_part())
+        assert [s[0] for s in _signatures(events, transposed=True)[:4]] == [None, 2, 2, 1]
+
+    def test_transposed_restatement_matches(self):
+        repeats = _find_repeats_in_part(_restated_part(), min_length=3, transposed=True)
+        assert [r.positions for r in repeats] == [[1, 9]]
+
+
+def _note_xml(step: str, octave: int, duration: int, staff: int | None = None) -> str:
+    staff_xml = f"<staff>{staff}</staff>" if staff else ""
+    return (
+        f"<note><pitch><step>{step}</step><octave>{octave}</octave></pitch>"
+        f"<duration>{duration}</duration>{staff_xml}</note>"
+    )
+
+
+def _voice_and_piano_xml() -> str:
+    """Three measures of a voice part without repetition, then a piano part
+    on one grand staff: right hand C5-D5-E5-F5, left hand C3-G3-E3-G3."""
+    voice, piano = "", ""
+    for m, step in enumerate("GAB", start=1):
+        attributes = "<attributes><divisions>1</divisions></attributes>" if m == 1 else ""
+        voice += f'<measure number="{m}">{attributes}{_note_xml(step, 4, 4)}</measure>'
+
+        attributes = (
+            "<attributes><divisions>1</divisions><staves>2</staves></attributes>"
+            if m == 1 else ""
+        )
+        right = "".join(_note_xml(s, 5, 1, staff=1) for s in "CDEF")
+        left = "".join(_note_xml(s, 3, 1, staff=2) for s in "CGEG")
+        piano += (
+            f'<measure number="{m}">{attributes}{right}'
+            f"<backup><duration>4</duration></backup>{left}</measure>"
+        )
+    return (
+        '<?xml version="1.0" encoding="UTF-8"?><score-partwise version="3.1"><part-list>'
+        '<score-part id="P1"><part-name>Voice</part-name></score-part>'
+        '<score-part id="P2"><part-name>Piano</part-name></score-part></part-list>'
+        f'<part id="P1">{voice}</part><part id="P2">{piano}</part></score-partwise>'
+    )
+
+
+class TestGrandStaff:
+    """Tests for picking treble/bass from a two-staff piano part."""
+
+    @pytest.fixture
+    def score_path(self, tmp_path):
+        path = tmp_path / "voice_and_piano.musicxml"
+        path.write_text(_voice_and_piano_xml())
+        return str(path)
+
+    def test_staves_route_to_hands(self, score_path):
+        result = find_repeats_all_parts(score_path, min_length=4)
+        assert result.treble.part_index == 1
+        assert result.bass.part_index == 2
+        assert result.treble.repeats and result.bass.repeats
+        for r in result.treble.repeats:
+            assert all(n.pitch.octave == 5 for n in r.notes)
+        for r in result.bass.repeats:
+            assert all(n.pitch.octave == 3 for n in r.notes)
+
+    def test_split_can_be_disabled(self, score_path):
+        result = find_repeats_all_parts(score_path, min_length=4, split_grand_staff=False)
+        assert result.treble.part_index == 0
+        assert result.treble.part_name == "Voice"
+        assert result.bass.part_index == 1
+
+    def test_one_staff_can_be_analyzed_alone(self, score_path):
+        result = find_repeats_all_parts(score_path, min_length=4, only=1)
+        assert result.treble is None
+        assert result.bass.part_index == 2
+
+    def test_any_parts_can_be_picked(self, score_path):
+        found = find_repeats_in_parts(score_path, [2, 0], min_length=4)
+        assert [p.part_index for p in found] == [2, 0]
+        assert found[1].part_name == "Voice"
+        with pytest.raises(ValueError):
+            find_repeats_in_parts(score_path, [3], min_length=4)
+
+
+class TestSingleStaff:
+    """Tests for scores with only one staff."""
+
+    def test_solo_part_has_no_bass(self, tmp_path):
+        notes = "".join(_note_xml(s, 5, 1) for s in "CDEF")
+        path = tmp_path / "flute.musicxml"
+        path.write_text(
+            '<?xml version="1.0" encoding="UTF-8"?><score-partwise version="3.1"><part-list>'
+            '<score-part id="P1"><part-name>Flute</part-name></score-part></part-list>'
+            '<part id="P1"><measure number="1"><attributes><divisions>1</divisions>'
+            f'</attributes>{notes}</measure><measure number="2">{notes}</measure>'
+            '</part></score-partwise>'
+        )
+
+        result = find_repeats_all_parts(str(path), min_length=4)
+        assert result.treble.part_name == "Flute"
+        assert result.treble.repeats
+        assert result.bass is None
+
+
+class TestNoteLayout:
+    """Tests for reading engraved note positions."""
+
+    def test_position_and_page(self):
+        notes = [
+            '<note default-x="80" default-y="-15"><pitch><step>C</step><octave>5</octave>'
+            '</pitch><duration>4</duration></note>',
+            '<note default-x="95.5" default-y="-10"><pitch><step>D</step><octave>5</octave>'
+            '</pitch><duration>4</duration></note>',
+        ]
+        xml = (
+            '<?xml version="1.0" encoding="UTF-8"?><score-partwise version="3.1"><part-list>'
+            '<score-part id="P1"><part-name>Piano</part-name></score-part></part-list>'
+            '<part id="P1"><measure number="1"><attributes><divisions>1</divisions>'
+            f'</attributes>{notes[0]}</measure><measure number="2">'
+            f'<print new-page="yes"/>{notes[1]}</measure></part></score-partwise>'
+        )
+        score = converter.parse(xml, format="musicxml")
+        first, second = score.parts[0].recurse().notes
+
+        assert note_layout(first) == {"x": 80.0, "y": -15.0, "page": 1}
+        assert note_layout(second) == {"x": 95.5, "y": -10.0, "page": 2}
+
+    def test_no_layout(self):
+        part = stream.Part([note.Note("C4"), note.Note("D4")])
+        assert note_layout(part.notes[0]) == {"x": None, "y": None, "page": None}
+
+
 class TestFurElisePatterns:
     """Integration tests using Für Elise merged.musicxml."""
 
@@ -1,3 +1,29 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
 fn main() {
+    embed_analyzer_hash();
     tauri_build::build()
 }
+
+/// Compile the SHA-256 of the bundled analyzer into the crate as
+/// `ANALYZER_SHA256`, so `verify_sidecar_integrity` can check the installed
+/// copy. Left unset when the binary hasn't been built yet.
+fn embed_analyzer_hash() {
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let extension = if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    let binary = format!("binaries/analyzer-{}{}", target, extension);
+    println!("cargo:rerun-if-changed={}", binary);
+
+    if let Ok(bytes) = std::fs::read(Path::new(&binary)) {
+        println!(
+            "cargo:rustc-env=ANALYZER_SHA256={:x}",
+            Sha256::digest(&bytes)
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Analyzer results stored on disk by the content hash of the score, so
+//! re-opening an unchanged file skips the sidecar. Results are stored as
+//! the analyzer returned them, before any Rust-side post-processing.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::models::AnalysisResult;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub dir: String,
+    pub entries: usize,
+    /// Total size of the entries on disk.
+    pub bytes: u64,
+}
+
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: PathBuf) -> Self {
+        AnalysisCache { dir }
+    }
+
+    /// Key for analyzing a file with the given sidecar flags: its content
+    /// hash, plus a short hash of the flags when there are any, since they
+    /// change what the analyzer reports.
+    pub fn key(content_hash: &str, sidecar_args: &[String]) -> String {
+        if sidecar_args.is_empty() {
+            return content_hash.to_string();
+        }
+        let args = format!("{:x}", Sha256::digest(sidecar_args.join(" ").as_bytes()));
+        format!("{}-{}", content_hash, &args[..8])
+    }
+
+    pub fn get(&self, key: &str) -> Option<AnalysisResult> {
+        let json = std::fs::read_to_string(self.entry(key)).ok()?;
+        match serde_json::from_str(&json) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, result: &AnalysisResult) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create cache dir: {}", e))?;
+        let json = serde_json::to_string(result)
+            .map_err(|e| format!("Failed to serialize result: {}", e))?;
+        // Written under a temp name first so a reader never sees half a file
+        let tmp = self.dir.join(format!("{}.json.tmp", key));
+        std::fs::write(&tmp, json).map_err(|e| format!("Failed to write cache entry: {}", e))?;
+        std::fs::rename(&tmp, self.entry(key))
+            .map_err(|e| format!("Failed to write cache entry: {}", e))
+    }
+
+    /// Every readable entry with its key, in no particular order, and how
+    /// many entries were skipped because they don't parse (with a warning,
+    /// as in `get`).
+    pub fn entries(&self) -> Result<(Vec<(String, AnalysisResult)>, usize), String> {
+        let (mut entries, mut skipped) = (Vec::new(), 0);
+        for file in self.files(".json")? {
+            let name = file.file_name().to_string_lossy().into_owned();
+            if let Some(key) = name.strip_suffix(".json") {
+                match self.get(key) {
+                    Some(result) => entries.push((key.to_string(), result)),
+                    None => skipped += 1,
+                }
+            }
+        }
+        Ok((entries, skipped))
+    }
+
+    /// Number and size of the entries, without reading them.
+    pub fn stats(&self) -> Result<CacheStats, String> {
+        let mut stats = CacheStats {
+            dir: self.dir.to_string_lossy().into_owned(),
+            entries: 0,
+            bytes: 0,
+        };
+        for file in self.files(".json")? {
+            stats.entries += 1;
+            stats.bytes += file.metadata().map_or(0, |m| m.len());
+        }
+        Ok(stats)
+    }
+
+    /// Delete every entry, including ones left half-written, returning how
+    /// many entries there were.
+    pub fn clear(&self) -> Result<usize, String> {
+        let mut removed = 0;
+        for file in self.files(".json")? {
+            std::fs::remove_file(file.path())
+                .map_err(|e| format!("Failed to remove cache entry: {}", e))?;
+            removed += 1;
+        }
+        for file in self.files(".json.tmp")? {
+            let _ = std::fs::remove_file(file.path());
+        }
+        Ok(removed)
+    }
+
+    /// Files in the cache dir whose name ends in `suffix`; none if the dir
+    /// doesn't exist yet.
+    fn files(&self, suffix: &str) -> Result<Vec<std::fs::DirEntry>, String> {
+        let dir = match std::fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read cache dir: {}", e)),
+        };
+        Ok(dir
+            .flatten()
+            .filter(|file| file.file_name().to_string_lossy().ends_with(suffix))
+            .collect())
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Files whose hash `FileHashes` remembers.
+const HASH_CAPACITY: usize = 64;
+
+struct HashEntry {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+    hash: String,
+}
+
+/// Content hashes of recently seen files, so asking again for an unchanged
+/// file doesn't re-read it. An entry is dropped once the file's modification
+/// time or size changes, and the least recently used goes when full.
+#[derive(Default)]
+pub struct FileHashes {
+    /// Most recently used first.
+    recent: Mutex<VecDeque<HashEntry>>,
+}
+
+impl FileHashes {
+    /// `content_hash` of `path`, from memory when the file is unchanged.
+    pub fn hash(&self, path: &Path) -> Result<String, String> {
+        let metadata =
+            std::fs::metadata(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        let modified = metadata
+            .modified()
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+
+        let mut recent = self.recent.lock().unwrap();
+        let hash = match recent.iter().position(|e| e.path == path) {
+            Some(i) => recent
+                .remove(i)
+                .filter(|e| e.modified == modified && e.len == metadata.len())
+                .map(|e| e.hash),
+            None => None,
+        };
+        let hash = match hash {
+            Some(hash) => hash,
+            None => content_hash(path)?,
+        };
+        recent.push_front(HashEntry {
+            path: path.to_path_buf(),
+            modified,
+            len: metadata.len(),
+            hash: hash.clone(),
+        });
+        recent.truncate(HASH_CAPACITY);
+        Ok(hash)
+    }
+}
+
+/// Hex SHA-256 of a file's bytes.
+pub fn content_hash(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    Ok(format!("{:x}", Sha256::digest(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_by_content_and_flags() {
+        let dir = std::env::temp_dir().join(format!("smrh-cache-test-{}", std::process::id()));
+        let score = dir.join("score.musicxml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&score, "<score-partwise/>").unwrap();
+
+        let hash = content_hash(&score).unwrap();
+        let plain = AnalysisCache::key(&hash, &[]);
+        let expanded = AnalysisCache::key(&hash, &["--expand-chords".to_string()]);
+        assert_eq!(plain.len(), 64);
+        assert_ne!(plain, expanded);
+
+        let cache = AnalysisCache::new(dir.join("cache"));
+        assert!(cache.get(&plain).is_none());
+        let result = AnalysisResult {
+            file: "score.musicxml".to_string(),
+            ..Default::default()
+        };
+        cache.put(&plain, &result).unwrap();
+        assert_eq!(cache.get(&plain).unwrap().file, "score.musicxml");
+        assert!(cache.get(&expanded).is_none());
+
+        cache.put(&expanded, &result).unwrap();
+        std::fs::write(dir.join("cache").join("stale.json.tmp"), "{").unwrap();
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.bytes > 0);
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert_eq!(cache.stats().unwrap().entries, 0);
+        assert!(cache.get(&plain).is_none());
+        assert_eq!(std::fs::read_dir(dir.join("cache")).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_hash_follows_changes_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("smrh-hash-test-{}", std::process::id()));
+        let score = dir.join("score.musicxml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&score, "<score-partwise/>").unwrap();
+
+        let hashes = FileHashes::default();
+        let first = hashes.hash(&score).unwrap();
+        assert_eq!(first, content_hash(&score).unwrap());
+        assert_eq!(hashes.hash(&score).unwrap(), first);
+        assert_eq!(hashes.recent.lock().unwrap().len(), 1);
+
+        // A different size invalidates the entry even within the mtime's
+        // resolution
+        std::fs::write(&score, "<score-partwise></score-partwise>").unwrap();
+        let second = hashes.hash(&score).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(second, content_hash(&score).unwrap());
+        assert_eq!(hashes.recent.lock().unwrap().len(), 1);
+        assert!(hashes.hash(&dir.join("missing")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use crate::models::NoteLocator;
+use crate::pitch;
+
+/// Guess the melodic function of a pattern from its interval content.
+///
+/// Rules, checked in order over the semitone steps between consecutive notes:
+/// - `repeated-note`: every step is 0
+/// - `chromatic`: every step is ±1
+/// - `scale`: every step is ±1 or ±2 (diatonic stepwise motion)
+/// - `arpeggio`: every step is a chord-tone leap (3, 4, 5, 7, 8, 9 or 12)
+///
+/// Anything else, or a pattern with fewer than three notes or an unparseable
+/// pitch, is left unclassified.
+pub fn classify(notes: &[NoteLocator]) -> Option<&'static str> {
+    if notes.len() < 3 {
+        return None;
+    }
+    let pitches: Vec<&str> = notes.iter().map(|n| n.pitch.as_str()).collect();
+    let steps: Vec<i32> = pitch::intervals(&pitches)?
+        .iter()
+        .map(|s| s.abs())
+        .collect();
+
+    if steps.iter().all(|&s| s == 0) {
+        Some("repeated-note")
+    } else if steps.iter().all(|&s| s == 1) {
+        Some("chromatic")
+    } else if steps.iter().all(|&s| s == 1 || s == 2) {
+        Some("scale")
+    } else if steps.iter().all(|s| [3, 4, 5, 7, 8, 9, 12].contains(s)) {
+        Some("arpeggio")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(pitches: &[&str]) -> Vec<NoteLocator> {
+        pitches
+            .iter()
+            .enumerate()
+            .map(|(i, p)| NoteLocator {
+                index: i as i32,
+                pitch: p.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn c_major_scale_fragment() {
+        assert_eq!(
+            classify(&notes(&["C4", "D4", "E4", "F4", "G4"])),
+            Some("scale")
+        );
+    }
+
+    #[test]
+    fn triad_arpeggio() {
+        assert_eq!(
+            classify(&notes(&["C4", "E4", "G4", "C5"])),
+            Some("arpeggio")
+        );
+    }
+
+    #[test]
+    fn chromatic_run() {
+        assert_eq!(
+            classify(&notes(&["E4", "F4", "F#4", "G4", "G#4"])),
+            Some("chromatic")
+        );
+    }
+
+    #[test]
+    fn repeated_note() {
+        assert_eq!(classify(&notes(&["A4", "A4", "A4"])), Some("repeated-note"));
+    }
+
+    #[test]
+    fn mixed_motion_is_unclassified() {
+        assert_eq!(classify(&notes(&["C4", "D4", "A4", "B4"])), None);
+    }
+}
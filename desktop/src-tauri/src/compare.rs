@@ -0,0 +1,251 @@
+//! Passages two scores share, such as an arrangement reusing its original.
+//!
+//! Each score's patterns propose candidate note sequences, which are then
+//! searched for in every analyzed staff of both scores. Matching is on
+//! pitch (enharmonics equal), or on intervals when the scores were analyzed
+//! with `transposition_invariant`, so a passage moved to another key still
+//! counts.
+
+use serde::Serialize;
+
+use crate::models::{AnalysisResult, NoteLocator};
+use crate::musicxml;
+use crate::pitch;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparisonResult {
+    pub file_a: String,
+    pub file_b: String,
+    /// Longest first.
+    pub matches: Vec<CrossMatch>,
+    /// Share of the notes of each score's analyzed staves inside a match.
+    pub coverage_a: f64,
+    pub coverage_b: f64,
+}
+
+/// One note sequence found in both scores.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CrossMatch {
+    pub length: usize,
+    /// Pitches of its first occurrence in the first score.
+    pub pitches: Vec<String>,
+    /// Every occurrence in each score. Notes line up by position, so
+    /// `in_a[i].notes[k]` and `in_b[j].notes[k]` are the same note of the
+    /// passage.
+    pub in_a: Vec<NoteRange>,
+    pub in_b: Vec<NoteRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoteRange {
+    /// `StaffPatternData.part_index` of the staff.
+    pub part_index: i32,
+    pub notes: Vec<NoteLocator>,
+}
+
+/// What is compared of a run of notes: MIDI pitches, or the intervals
+/// between them when `transposed`. None when a pitch can't be read.
+fn key(notes: &[NoteLocator], transposed: bool) -> Option<Vec<i32>> {
+    let midi: Vec<i32> = notes
+        .iter()
+        .map(|n| pitch::to_midi(&n.pitch))
+        .collect::<Option<_>>()?;
+    Some(if transposed {
+        midi.windows(2).map(|w| w[1] - w[0]).collect()
+    } else {
+        midi
+    })
+}
+
+/// The analyzed staves of a result, every note numbered as in its
+/// `NoteLocator.index`.
+fn staves(result: &AnalysisResult) -> Result<Vec<(i32, Vec<NoteLocator>)>, String> {
+    let mut streams = musicxml::stream_notes(&result.musicxml_content)?;
+    Ok(result
+        .parts
+        .iter()
+        .filter_map(|part| {
+            let notes = streams.get_mut(part.part_index as usize)?;
+            Some((part.part_index, std::mem::take(notes)))
+        })
+        .collect())
+}
+
+/// Non-overlapping occurrences of `candidate` (`length` notes) in each staff.
+fn find(
+    staves: &[(i32, Vec<NoteLocator>)],
+    candidate: &[i32],
+    length: usize,
+    transposed: bool,
+) -> Vec<NoteRange> {
+    let mut found = Vec::new();
+    for (part_index, notes) in staves {
+        let mut start = 0;
+        while start + length <= notes.len() {
+            let window = &notes[start..start + length];
+            if key(window, transposed).as_deref() == Some(candidate) {
+                found.push(NoteRange {
+                    part_index: *part_index,
+                    notes: window.to_vec(),
+                });
+                start += length;
+            } else {
+                start += 1;
+            }
+        }
+    }
+    found
+}
+
+fn contains(long: &[i32], short: &[i32]) -> bool {
+    short.is_empty() || long.windows(short.len()).any(|w| w == short)
+}
+
+/// Every pattern of either result that occurs in both, dropping those
+/// inside a longer match.
+pub fn compare(
+    a: &AnalysisResult,
+    b: &AnalysisResult,
+    transposed: bool,
+) -> Result<ComparisonResult, String> {
+    let (staves_a, staves_b) = (staves(a)?, staves(b)?);
+
+    let mut candidates: Vec<(Vec<i32>, usize)> = a
+        .parts
+        .iter()
+        .chain(&b.parts)
+        .flat_map(|part| &part.patterns)
+        .filter(|pattern| pattern.notes.len() >= 2)
+        .filter_map(|pattern| Some((key(&pattern.notes, transposed)?, pattern.notes.len())))
+        .collect();
+    candidates.sort_by(|x, y| y.1.cmp(&x.1).then_with(|| x.0.cmp(&y.0)));
+    candidates.dedup();
+
+    let mut matches: Vec<(Vec<i32>, CrossMatch)> = Vec::new();
+    for (candidate, length) in candidates {
+        if matches
+            .iter()
+            .any(|(longer, _)| contains(longer, &candidate))
+        {
+            continue;
+        }
+        let in_a = find(&staves_a, &candidate, length, transposed);
+        let in_b = find(&staves_b, &candidate, length, transposed);
+        if in_a.is_empty() || in_b.is_empty() {
+            continue;
+        }
+        let pitches = in_a[0].notes.iter().map(|n| n.pitch.clone()).collect();
+        matches.push((
+            candidate,
+            CrossMatch {
+                length,
+                pitches,
+                in_a,
+                in_b,
+            },
+        ));
+    }
+    let matches: Vec<CrossMatch> = matches.into_iter().map(|(_, m)| m).collect();
+
+    Ok(ComparisonResult {
+        file_a: a.file.clone(),
+        file_b: b.file.clone(),
+        coverage_a: coverage(&staves_a, matches.iter().flat_map(|m| &m.in_a)),
+        coverage_b: coverage(&staves_b, matches.iter().flat_map(|m| &m.in_b)),
+        matches,
+    })
+}
+
+fn coverage<'a>(
+    staves: &[(i32, Vec<NoteLocator>)],
+    ranges: impl Iterator<Item = &'a NoteRange>,
+) -> f64 {
+    let total: usize = staves.iter().map(|(_, notes)| notes.len()).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut covered = std::collections::HashSet::new();
+    for range in ranges {
+        covered.extend(range.notes.iter().map(|n| (range.part_index, n.index)));
+    }
+    covered.len() as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    fn score(file: &str, pitches: &[&str]) -> AnalysisResult {
+        let notes: String = pitches
+            .iter()
+            .map(|p| {
+                let (step, octave) = p.split_at(1);
+                format!(
+                    "<note><pitch><step>{}</step><octave>{}</octave></pitch><duration>1</duration></note>",
+                    step, octave
+                )
+            })
+            .collect();
+        AnalysisResult {
+            file: file.to_string(),
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">{}</measure></part></score-partwise>"#,
+                notes
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn with_pattern(mut result: AnalysisResult, pitches: &[&str]) -> AnalysisResult {
+        let notes = pitches
+            .iter()
+            .map(|p| NoteLocator {
+                pitch: p.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        result.parts.push(StaffPatternData {
+            part_index: 0,
+            patterns: vec![Pattern {
+                id: 1,
+                notes,
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        result
+    }
+
+    #[test]
+    fn finds_a_motif_of_one_score_in_the_other() {
+        let original = with_pattern(
+            score("original", &["C4", "D4", "E4", "G4", "C4", "D4", "E4"]),
+            &["C4", "D4", "E4"],
+        );
+        let arrangement = with_pattern(score("arrangement", &["A3", "C4", "D4", "E4", "F4"]), &[]);
+        let comparison = compare(&original, &arrangement, false).unwrap();
+        assert_eq!(comparison.matches.len(), 1);
+        let shared = &comparison.matches[0];
+        assert_eq!(shared.pitches, vec!["C4", "D4", "E4"]);
+        let starts = |ranges: &[NoteRange]| -> Vec<i32> {
+            ranges.iter().map(|r| r.notes[0].index).collect()
+        };
+        assert_eq!(starts(&shared.in_a), vec![0, 4]);
+        assert_eq!(starts(&shared.in_b), vec![1]);
+        assert!((comparison.coverage_a - 6.0 / 7.0).abs() < 1e-9);
+        assert!((comparison.coverage_b - 3.0 / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn transposed_passages_match_by_interval() {
+        let original = with_pattern(score("a", &["C4", "D4", "E4"]), &["C4", "D4", "E4"]);
+        let moved = with_pattern(score("b", &["G4", "A4", "B4"]), &[]);
+        assert!(compare(&original, &moved, false)
+            .unwrap()
+            .matches
+            .is_empty());
+        let comparison = compare(&original, &moved, true).unwrap();
+        assert_eq!(comparison.matches[0].in_b[0].notes[2].pitch, "B4");
+    }
+}
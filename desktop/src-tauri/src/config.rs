@@ -0,0 +1,265 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::OutputLimits;
+
+/// Shortest pattern reported when `min_pattern_length` isn't set. Two-note
+/// repeats are nearly always incidental.
+pub const DEFAULT_MIN_PATTERN_LENGTH: i32 = 3;
+
+/// Shortest pattern the analyzer looks for when it isn't given one. Left to
+/// it when `min_pattern_length` isn't set, so default runs find what they
+/// always have.
+pub const ANALYZER_MIN_LENGTH: i32 = 4;
+
+/// Options accepted by the analysis commands. Every field is optional on the
+/// wire so the frontend only sends what it changes.
+///
+/// Sidecar options change what the analyzer matches and need a new run:
+/// `chords_as_single_event`, `include_grace_notes`, `merge_tied_notes`,
+/// `split_grand_staff`, `parts`, `include_layout`, `match_rhythm`,
+/// `transposition_invariant` and `use_native_engine`. `include_progress_log`,
+/// `index_to_db`, `max_concurrent_staves`, `large_file_threshold_mb`, the
+/// output caps and the timeouts only affect a run as it happens. The rest are
+/// applied in Rust by `postprocess::apply` and can be changed on an existing
+/// result with `reprocess_result`.
+/// `min_pattern_length` is both: the analyzer stops looking below it and
+/// Rust drops anything shorter, so it can be raised on an existing result but
+/// lowering it needs a new run. Lowering `min_occurrences` does too, since
+/// a result no longer has the patterns it dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyzerConfig {
+    /// Drop patterns of fewer notes; `DEFAULT_MIN_PATTERN_LENGTH` when unset.
+    pub min_pattern_length: Option<i32>,
+    /// Drop patterns heard fewer times than this.
+    pub min_occurrences: Option<i32>,
+    /// Label each pattern with a melodic category (see `classify::classify`).
+    pub classify_patterns: bool,
+    /// Label each pattern with the chord its notes spell (see `harmony::infer`).
+    pub infer_harmony: bool,
+    /// Match a chord as one event on its top note; when false every chord
+    /// tone is matched separately (sidecar `--expand-chords`).
+    pub chords_as_single_event: bool,
+    /// Match grace notes like ordinary notes. Off by default so ornaments
+    /// neither break nor join patterns (sidecar `--include-grace-notes`).
+    pub include_grace_notes: bool,
+    /// Match a tied note as one event with the summed duration, rather than
+    /// one event per written note with the continuations flagged `tied`
+    /// (sidecar `--merge-ties`).
+    pub merge_tied_notes: bool,
+    /// Take treble and bass from the two staves of a piano part when the score
+    /// has one, rather than the first two parts (sidecar `--no-split-grand-staff`
+    /// when off).
+    pub split_grand_staff: bool,
+    /// Analyze these parts (`PartInfo.index` from `list_parts`) instead of a
+    /// treble and a bass staff (sidecar `--parts`).
+    pub parts: Option<Vec<usize>>,
+    /// Report each note's engraved position (`NoteLocator.x`/`y`/`page`) for
+    /// overlay rendering (sidecar `--layout`).
+    pub include_layout: bool,
+    /// Require repeated notes to have the same duration as well as pitch
+    /// (sidecar `--ignore-rhythm` when off).
+    pub match_rhythm: bool,
+    /// Match runs restated at another pitch level by their intervals
+    /// (sidecar `--transposed`).
+    pub transposition_invariant: bool,
+    /// Find patterns in Rust (see `native`) instead of running the sidecar,
+    /// for the files and options it supports. Folder and batch jobs always
+    /// use the sidecar.
+    pub use_native_engine: bool,
+    /// Look for motifs restated at shifting pitch levels (see `sequences::detect`).
+    pub detect_sequences: bool,
+    /// Fill in `NoteLocator.absolute_beat` from the score's time signatures.
+    pub compute_absolute_beats: bool,
+    /// Flag patterns recurring at a period that isn't a whole number of bars
+    /// (see `displacement::detect`).
+    pub detect_metric_displacement: bool,
+    /// Link patterns replaying another's pitches with every note value
+    /// scaled, like an augmentation (see `scaling::link`).
+    pub detect_rhythmic_scaling: bool,
+    /// Link patterns whose intervals mirror another's (see `inversion::link`).
+    pub detect_inversions: bool,
+    /// Whether an inversion must mirror semitones or only letter-name steps.
+    pub inversion_match: InversionMatch,
+    /// Fill in `NoteLocator.articulations` from the score. Matching stays
+    /// pitch-based; see `occurrences::compare_articulations`. Has no effect
+    /// unless `numbers_notes_as_written`.
+    pub capture_articulations: bool,
+    /// Fill in `NoteLocator.scale_degree` relative to the score's key
+    /// signature, or a key estimated from its notes when it has none.
+    pub annotate_scale_degrees: bool,
+    /// Fold runs of identical notes into one `NoteLocator` with a `repeat`
+    /// count (see `runs::encode`).
+    pub run_length_encode: bool,
+    /// Also list the patterns in each measure in `AnalysisResult.measure_index`.
+    pub by_measure: bool,
+    /// Numbering used for `NoteLocator.measure`.
+    pub measure_frame: MeasureFrame,
+    /// Keep every progress event of the run in `AnalysisResult.progress_log`.
+    /// Off by default since it grows the payload.
+    pub include_progress_log: bool,
+    /// Also store each analysis in the pattern database `query_patterns`
+    /// searches (see `db`).
+    pub index_to_db: bool,
+    /// Analyzers run at once on one score, each on its own staff (see
+    /// `split`). 1 analyzes every staff in one run.
+    pub max_concurrent_staves: usize,
+    /// Files bigger than this many megabytes need `confirm_large` before
+    /// `analyze_music` runs them. 0 turns the check off.
+    pub large_file_threshold_mb: u64,
+    /// Stop the analyzer once it has printed more than this on stdout.
+    pub max_stdout_mb: usize,
+    /// Stop the analyzer once it has printed more stderr lines than this.
+    pub max_stderr_lines: usize,
+    /// Stop the analyzer once it has run this many seconds. 0 turns the
+    /// limit off.
+    pub timeout_secs: u64,
+    /// Stop the analyzer once it has gone this many seconds without a
+    /// progress event. 0 turns the limit off.
+    pub inactivity_timeout_secs: u64,
+}
+
+/// How measures are numbered in results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeasureFrame {
+    /// The `number` printed in the score (music21's `measureNumber`).
+    #[default]
+    Written,
+    /// 1-based position in playback order, with repeats and voltas unrolled.
+    /// A measure that is played more than once reports its first pass.
+    Played,
+}
+
+/// How closely an inversion has to mirror the original.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InversionMatch {
+    /// Every interval negated to the semitone: a major third up becomes a
+    /// major third down.
+    #[default]
+    Exact,
+    /// Intervals negated in letter-name steps, as inverting within a key
+    /// does: a major third up may come back as a minor third down.
+    Tonal,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        AnalyzerConfig {
+            min_pattern_length: None,
+            min_occurrences: None,
+            classify_patterns: false,
+            infer_harmony: false,
+            chords_as_single_event: true,
+            include_grace_notes: false,
+            merge_tied_notes: false,
+            split_grand_staff: true,
+            parts: None,
+            include_layout: false,
+            match_rhythm: true,
+            transposition_invariant: false,
+            use_native_engine: false,
+            detect_sequences: false,
+            compute_absolute_beats: false,
+            detect_metric_displacement: false,
+            detect_rhythmic_scaling: false,
+            detect_inversions: false,
+            inversion_match: InversionMatch::Exact,
+            capture_articulations: false,
+            annotate_scale_degrees: false,
+            run_length_encode: false,
+            by_measure: false,
+            measure_frame: MeasureFrame::Written,
+            include_progress_log: false,
+            index_to_db: false,
+            max_concurrent_staves: 4,
+            large_file_threshold_mb: 20,
+            max_stdout_mb: 64,
+            max_stderr_lines: 10_000,
+            // Converting a long PDF can take minutes between progress events
+            timeout_secs: 30 * 60,
+            inactivity_timeout_secs: 5 * 60,
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    /// `large_file_threshold_mb` in bytes, or None when the check is off.
+    pub fn large_file_threshold(&self) -> Option<u64> {
+        (self.large_file_threshold_mb > 0).then(|| self.large_file_threshold_mb * 1024 * 1024)
+    }
+
+    /// `min_pattern_length`, or the default when it isn't set.
+    pub fn min_length(&self) -> i32 {
+        self.min_pattern_length
+            .unwrap_or(DEFAULT_MIN_PATTERN_LENGTH)
+    }
+
+    /// Shortest pattern to look for: `min_pattern_length`, or the analyzer's
+    /// default when it isn't set.
+    pub fn search_length(&self) -> i32 {
+        self.min_pattern_length.unwrap_or(ANALYZER_MIN_LENGTH)
+    }
+
+    /// Whether the analyzer numbers notes as `musicxml::stream_notes` does:
+    /// chords once, grace notes skipped and tied notes written out.
+    pub fn numbers_notes_as_written(&self) -> bool {
+        self.chords_as_single_event && !self.include_grace_notes && !self.merge_tied_notes
+    }
+
+    /// `timeout_secs` as a duration, or None when the limit is off.
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    /// `inactivity_timeout_secs` as a duration, or None when the limit is off.
+    pub fn inactivity_timeout(&self) -> Option<Duration> {
+        (self.inactivity_timeout_secs > 0)
+            .then(|| Duration::from_secs(self.inactivity_timeout_secs))
+    }
+
+    pub fn output_limits(&self) -> OutputLimits {
+        OutputLimits {
+            max_stdout_bytes: self.max_stdout_mb * 1024 * 1024,
+            max_stderr_lines: self.max_stderr_lines,
+        }
+    }
+
+    /// Command-line arguments for the analyzer sidecar after the file path.
+    pub fn sidecar_args(&self) -> Vec<String> {
+        // The analyzer's positional `min_length`, which must follow the path.
+        // Only passed when set, so default runs keep the analyzer's default
+        // and their cache keys
+        let mut args: Vec<String> = self.min_pattern_length.iter().map(i32::to_string).collect();
+        if !self.chords_as_single_event {
+            args.push("--expand-chords".to_string());
+        }
+        if self.include_grace_notes {
+            args.push("--include-grace-notes".to_string());
+        }
+        if self.merge_tied_notes {
+            args.push("--merge-ties".to_string());
+        }
+        if !self.split_grand_staff {
+            args.push("--no-split-grand-staff".to_string());
+        }
+        if let Some(parts) = &self.parts {
+            let parts: Vec<String> = parts.iter().map(usize::to_string).collect();
+            args.push("--parts".to_string());
+            args.push(parts.join(","));
+        }
+        if self.include_layout {
+            args.push("--layout".to_string());
+        }
+        if !self.match_rhythm {
+            args.push("--ignore-rhythm".to_string());
+        }
+        if self.transposition_invariant {
+            args.push("--transposed".to_string());
+        }
+        args
+    }
+}
@@ -0,0 +1,638 @@
+//! A SQLite index of analyzed patterns, for querying a large library
+//! without reading every cache entry. Each score is stored once by its
+//! content hash, as the most recent analysis of it with `index_to_db` on,
+//! in the tables `files`, `patterns`, `occurrences` and `notes`. The app's
+//! recent files are kept here too, in `recent_files`.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AnalysisResult, NoteLocator, Pattern, StaffPatternData};
+use crate::musicxml::metadata::ScoreMetadata;
+use crate::recent::RecentFile;
+
+/// The schema, one step per version: a database at version `n` has had the
+/// first `n` applied. Steps are only ever appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: files, their patterns, and each pattern's occurrences and notes
+    "CREATE TABLE files (
+        content_hash TEXT PRIMARY KEY,
+        file TEXT NOT NULL
+    );
+    CREATE TABLE patterns (
+        id INTEGER PRIMARY KEY,
+        content_hash TEXT NOT NULL REFERENCES files (content_hash) ON DELETE CASCADE,
+        staff INTEGER NOT NULL,
+        part_index INTEGER NOT NULL,
+        part_name TEXT NOT NULL,
+        pattern_id INTEGER NOT NULL,
+        length INTEGER NOT NULL,
+        count INTEGER NOT NULL
+    );
+    CREATE INDEX patterns_by_file ON patterns (content_hash, staff, pattern_id);
+    CREATE INDEX patterns_by_count ON patterns (count);
+    CREATE TABLE occurrences (
+        pattern INTEGER NOT NULL REFERENCES patterns (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        start INTEGER NOT NULL,
+        PRIMARY KEY (pattern, position)
+    );
+    CREATE TABLE notes (
+        pattern INTEGER NOT NULL REFERENCES patterns (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        note_index INTEGER NOT NULL,
+        measure INTEGER NOT NULL,
+        beat REAL,
+        pitch TEXT NOT NULL,
+        duration_beats REAL,
+        chord_group INTEGER,
+        is_grace INTEGER NOT NULL,
+        tied INTEGER NOT NULL,
+        PRIMARY KEY (pattern, position)
+    );",
+    // 2: recently opened files, with `part_names` as a JSON array
+    "CREATE TABLE recent_files (
+        path TEXT PRIMARY KEY,
+        opened_at INTEGER NOT NULL,
+        analyzed_at INTEGER,
+        title TEXT,
+        composer TEXT,
+        parts INTEGER NOT NULL,
+        part_names TEXT NOT NULL,
+        thumbnail TEXT
+    );",
+];
+
+/// The columns of `recent_files` in the order `recent_file` reads them.
+const RECENT_COLUMNS: &str =
+    "path, opened_at, analyzed_at, title, composer, parts, part_names, thumbnail";
+
+/// Most recently opened first; files opened within the same second go in
+/// the order they were saved.
+const RECENT_ORDER: &str = "ORDER BY opened_at DESC, rowid DESC";
+
+/// Filters for `query_patterns`; every filter given must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PatternQuery {
+    pub min_count: Option<i32>,
+    /// Space-separated pitches ("C4 D4 E4") the pattern must play in a row.
+    pub pitch_contains: Option<String>,
+}
+
+/// An indexed pattern matching a `PatternQuery`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternMatch {
+    pub content_hash: String,
+    /// Path the score had when it was analyzed.
+    pub file: String,
+    /// Index of the staff in the result's `parts`.
+    pub staff: usize,
+    /// The pattern as the analyzer reported it, without any of the fields
+    /// `postprocess::apply` fills in.
+    pub pattern: Pattern,
+}
+
+pub struct PatternDb {
+    conn: Mutex<Connection>,
+}
+
+impl PatternDb {
+    /// Open the database at `path`, creating it or bringing its schema up
+    /// to date as needed.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create database dir: {}", e))?;
+        }
+        PatternDb::with_connection(Connection::open(path).map_err(failed("open database"))?)
+    }
+
+    /// A database that only lasts as long as this value.
+    pub fn in_memory() -> Result<Self, String> {
+        PatternDb::with_connection(Connection::open_in_memory().map_err(failed("open database"))?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self, String> {
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(failed("enable foreign keys"))?;
+        migrate(&mut conn, MIGRATIONS)?;
+        Ok(PatternDb {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Store `result` as the analysis of the score with `content_hash`,
+    /// replacing what was stored for it.
+    pub fn index(&self, content_hash: &str, result: &AnalysisResult) -> Result<(), String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(failed("start transaction"))?;
+        tx.execute("DELETE FROM files WHERE content_hash = ?1", [content_hash])
+            .map_err(failed("remove indexed file"))?;
+        tx.execute(
+            "INSERT INTO files (content_hash, file) VALUES (?1, ?2)",
+            params![content_hash, result.file],
+        )
+        .map_err(failed("index file"))?;
+        for (staff, data) in result.parts.iter().enumerate() {
+            for pattern in &data.patterns {
+                insert_pattern(&tx, content_hash, staff, data, pattern)?;
+            }
+        }
+        tx.commit().map_err(failed("commit index"))
+    }
+
+    /// Whether the score with `content_hash` has been indexed.
+    pub fn contains(&self, content_hash: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT 1 FROM files WHERE content_hash = ?1",
+            [content_hash],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(failed("look up indexed file"))
+    }
+
+    /// Every indexed pattern matching `query`, ordered by content hash,
+    /// then staff and by pattern id.
+    pub fn query_patterns(&self, query: &PatternQuery) -> Result<Vec<PatternMatch>, String> {
+        let needle = query
+            .pitch_contains
+            .as_deref()
+            .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|p| !p.is_empty());
+        let conn = self.conn.lock().unwrap();
+        // Pitches are matched whole, so "C4" doesn't match inside "C4 C#4"
+        let mut patterns = conn
+            .prepare(
+                "SELECT p.id, f.content_hash, f.file, p.staff, p.pattern_id, p.length, p.count
+                FROM patterns p JOIN files f ON f.content_hash = p.content_hash
+                WHERE (?1 IS NULL OR p.count >= ?1)
+                AND (?2 IS NULL OR instr(
+                    ' ' || (SELECT group_concat(n.pitch, ' ' ORDER BY n.position)
+                        FROM notes n WHERE n.pattern = p.id) || ' ',
+                    ' ' || ?2 || ' ') > 0)
+                ORDER BY f.content_hash, p.staff, p.pattern_id",
+            )
+            .map_err(failed("query patterns"))?;
+        let rows = patterns
+            .query_map(params![query.min_count, needle], |row| {
+                let row_id: i64 = row.get(0)?;
+                let found = PatternMatch {
+                    content_hash: row.get(1)?,
+                    file: row.get(2)?,
+                    staff: row.get(3)?,
+                    pattern: Pattern {
+                        id: row.get(4)?,
+                        length: row.get(5)?,
+                        count: row.get(6)?,
+                        ..Default::default()
+                    },
+                };
+                Ok((row_id, found))
+            })
+            .map_err(failed("query patterns"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(failed("query patterns"))?;
+
+        let mut occurrences = conn
+            .prepare("SELECT start FROM occurrences WHERE pattern = ?1 ORDER BY position")
+            .map_err(failed("query occurrences"))?;
+        let mut notes = conn
+            .prepare(
+                "SELECT note_index, measure, beat, pitch, duration_beats, chord_group, is_grace, tied
+                FROM notes WHERE pattern = ?1 ORDER BY position",
+            )
+            .map_err(failed("query notes"))?;
+        let mut matches = Vec::with_capacity(rows.len());
+        for (row_id, mut found) in rows {
+            found.pattern.positions = occurrences
+                .query_map([row_id], |row| row.get(0))
+                .and_then(|rows| rows.collect())
+                .map_err(failed("query occurrences"))?;
+            found.pattern.notes = notes
+                .query_map([row_id], |row| {
+                    Ok(NoteLocator {
+                        index: row.get(0)?,
+                        measure: row.get(1)?,
+                        beat: row.get(2)?,
+                        pitch: row.get(3)?,
+                        duration_beats: row.get(4)?,
+                        chord_group: row.get(5)?,
+                        is_grace: row.get(6)?,
+                        tied: row.get(7)?,
+                        ..Default::default()
+                    })
+                })
+                .and_then(|rows| rows.collect())
+                .map_err(failed("query notes"))?;
+            matches.push(found);
+        }
+        Ok(matches)
+    }
+
+    /// The recent files, most recently opened first.
+    pub fn recent_files(&self) -> Result<Vec<RecentFile>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut files = conn
+            .prepare(&format!(
+                "SELECT {} FROM recent_files {}",
+                RECENT_COLUMNS, RECENT_ORDER
+            ))
+            .map_err(failed("query recent files"))?;
+        let files = files
+            .query_map([], recent_file)
+            .and_then(|rows| rows.collect())
+            .map_err(failed("query recent files"));
+        files
+    }
+
+    /// Save what `f` makes of the recent file at `path` (`None` if it isn't
+    /// listed), then drop all but the `keep` most recently opened.
+    pub fn update_recent(
+        &self,
+        path: &str,
+        keep: usize,
+        f: impl FnOnce(Option<RecentFile>) -> RecentFile,
+    ) -> Result<RecentFile, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(failed("start transaction"))?;
+        let existing = tx
+            .query_row(
+                &format!(
+                    "SELECT {} FROM recent_files WHERE path = ?1",
+                    RECENT_COLUMNS
+                ),
+                [path],
+                recent_file,
+            )
+            .optional()
+            .map_err(failed("look up recent file"))?;
+        let entry = f(existing);
+        let part_names = serde_json::to_string(&entry.metadata.part_names)
+            .map_err(|e| format!("Failed to serialize part names: {}", e))?;
+        // Replacing deletes the old row, so the entry also moves to the end
+        // of the rowid order
+        tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO recent_files ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                RECENT_COLUMNS
+            ),
+            params![
+                entry.path,
+                entry.opened_at,
+                entry.analyzed_at,
+                entry.metadata.title,
+                entry.metadata.composer,
+                entry.metadata.parts,
+                part_names,
+                entry.thumbnail
+            ],
+        )
+        .map_err(failed("save recent file"))?;
+        tx.execute(
+            &format!(
+                "DELETE FROM recent_files WHERE rowid NOT IN
+                (SELECT rowid FROM recent_files {} LIMIT ?1)",
+                RECENT_ORDER
+            ),
+            [keep],
+        )
+        .map_err(failed("drop old recent files"))?;
+        tx.commit().map_err(failed("commit recent file"))?;
+        Ok(entry)
+    }
+
+    /// Forget the recent file at `path`, returning whether it was listed.
+    pub fn remove_recent(&self, path: &str) -> Result<bool, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM recent_files WHERE path = ?1", [path])
+            .map(|removed| removed > 0)
+            .map_err(failed("remove recent file"))
+    }
+}
+
+fn recent_file(row: &rusqlite::Row) -> rusqlite::Result<RecentFile> {
+    let part_names: String = row.get(6)?;
+    let part_names = serde_json::from_str(&part_names).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    Ok(RecentFile {
+        path: row.get(0)?,
+        metadata: ScoreMetadata {
+            title: row.get(3)?,
+            composer: row.get(4)?,
+            parts: row.get(5)?,
+            part_names,
+        },
+        opened_at: row.get(1)?,
+        analyzed_at: row.get(2)?,
+        thumbnail: row.get(7)?,
+    })
+}
+
+fn insert_pattern(
+    tx: &Transaction,
+    content_hash: &str,
+    staff: usize,
+    data: &StaffPatternData,
+    pattern: &Pattern,
+) -> Result<(), String> {
+    tx.execute(
+        "INSERT INTO patterns
+        (content_hash, staff, part_index, part_name, pattern_id, length, count)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            content_hash,
+            staff,
+            data.part_index,
+            data.part_name,
+            pattern.id,
+            pattern.length,
+            pattern.count
+        ],
+    )
+    .map_err(failed("index pattern"))?;
+    let row_id = tx.last_insert_rowid();
+    for (position, start) in pattern.positions.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO occurrences (pattern, position, start) VALUES (?1, ?2, ?3)",
+            params![row_id, position, start],
+        )
+        .map_err(failed("index occurrence"))?;
+    }
+    for (position, note) in pattern.notes.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO notes
+            (pattern, position, note_index, measure, beat, pitch, duration_beats, chord_group, is_grace, tied)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                row_id,
+                position,
+                note.index,
+                note.measure,
+                note.beat,
+                note.pitch,
+                note.duration_beats,
+                note.chord_group,
+                note.is_grace,
+                note.tied
+            ],
+        )
+        .map_err(failed("index note"))?;
+    }
+    Ok(())
+}
+
+/// Version of the schema `conn` has, 0 for a new database.
+fn schema_version(conn: &Connection) -> Result<usize, String> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+        .map_err(failed("create schema_version"))?;
+    let version: Option<i64> = conn
+        .query_row("SELECT max(version) FROM schema_version", [], |row| {
+            row.get(0)
+        })
+        .map_err(failed("read schema version"))?;
+    Ok(version.unwrap_or(0) as usize)
+}
+
+/// Apply the steps of `migrations` that `conn` hasn't had, each in its own
+/// transaction, returning the version it ends up at.
+fn migrate(conn: &mut Connection, migrations: &[&str]) -> Result<usize, String> {
+    let version = schema_version(conn)?;
+    if version > migrations.len() {
+        return Err(format!(
+            "Database schema version {} is newer than this app's ({})",
+            version,
+            migrations.len()
+        ));
+    }
+    for (step, sql) in migrations.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(failed("start migration"))?;
+        tx.execute_batch(sql)
+            .map_err(|e| format!("Failed to migrate database to version {}: {}", step + 1, e))?;
+        tx.execute("DELETE FROM schema_version", [])
+            .map_err(failed("update schema version"))?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?1)",
+            [step as i64 + 1],
+        )
+        .map_err(failed("update schema version"))?;
+        tx.commit().map_err(failed("commit migration"))?;
+    }
+    Ok(migrations.len())
+}
+
+fn failed(action: &'static str) -> impl Fn(rusqlite::Error) -> String {
+    move |e| format!("Failed to {}: {}", action, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(id: i32, count: i32, pitches: &[&str]) -> Pattern {
+        let notes = pitches
+            .iter()
+            .enumerate()
+            .map(|(index, pitch)| NoteLocator {
+                index: index as i32,
+                measure: 1,
+                beat: Some(index as f64 + 1.0),
+                pitch: pitch.to_string(),
+                tied: index == 0,
+                ..Default::default()
+            })
+            .collect();
+        Pattern {
+            id,
+            length: pitches.len() as i32,
+            count,
+            positions: (0..count).map(|i| i * 8).collect(),
+            notes,
+            ..Default::default()
+        }
+    }
+
+    fn result(file: &str, patterns: Vec<Pattern>) -> AnalysisResult {
+        AnalysisResult {
+            file: file.to_string(),
+            parts: vec![StaffPatternData {
+                part_index: 0,
+                part_name: "Piano".to_string(),
+                patterns,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("smrh-db-{}-{}.sqlite3", name, std::process::id()))
+    }
+
+    #[test]
+    fn indexed_patterns_come_back_whole() {
+        let db = PatternDb::in_memory().unwrap();
+        let scale = pattern(0, 2, &["C4", "D4", "E4", "F4"]);
+        db.index(
+            &"a".repeat(64),
+            &result("scale.musicxml", vec![scale.clone()]),
+        )
+        .unwrap();
+        assert!(db.contains(&"a".repeat(64)).unwrap());
+        assert!(!db.contains(&"b".repeat(64)).unwrap());
+
+        let found = db.query_patterns(&PatternQuery::default()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].file, "scale.musicxml");
+        assert_eq!(found[0].staff, 0);
+        assert_eq!(
+            serde_json::to_value(&found[0].pattern).unwrap(),
+            serde_json::to_value(&scale).unwrap()
+        );
+
+        // Indexing the score again replaces what was stored for it
+        db.index(&"a".repeat(64), &result("moved.musicxml", vec![]))
+            .unwrap();
+        assert!(db
+            .query_patterns(&PatternQuery::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn queries_filter_by_count_and_pitches() {
+        let db = PatternDb::in_memory().unwrap();
+        let scales = vec![
+            pattern(0, 2, &["C4", "D4", "E4", "F4"]),
+            pattern(1, 5, &["C4", "D4", "E4", "F4"]),
+            pattern(2, 2, &["C4", "E4", "G4", "C5"]),
+        ];
+        db.index(&"a".repeat(64), &result("a.musicxml", scales))
+            .unwrap();
+        db.index(
+            &"b".repeat(64),
+            &result("b.musicxml", vec![pattern(0, 3, &["C#4", "D4", "E4"])]),
+        )
+        .unwrap();
+
+        let found = |query: PatternQuery| -> Vec<(char, i32)> {
+            db.query_patterns(&query)
+                .unwrap()
+                .iter()
+                .map(|m| (m.content_hash.chars().next().unwrap(), m.pattern.id))
+                .collect()
+        };
+        assert_eq!(
+            found(PatternQuery::default()),
+            vec![('a', 0), ('a', 1), ('a', 2), ('b', 0)]
+        );
+        let frequent = PatternQuery {
+            min_count: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(found(frequent), vec![('a', 1), ('b', 0)]);
+        let d_e = PatternQuery {
+            pitch_contains: Some(" D4  E4".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(found(d_e), vec![('a', 0), ('a', 1), ('b', 0)]);
+        let d_f = PatternQuery {
+            pitch_contains: Some("D4 F4".to_string()),
+            ..Default::default()
+        };
+        assert!(found(d_f).is_empty());
+        // "C4" is a whole pitch, not the start of "C#4"
+        let c = PatternQuery {
+            pitch_contains: Some("C4 D4".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(found(c), vec![('a', 0), ('a', 1)]);
+    }
+
+    #[test]
+    fn recent_files_keep_the_most_recently_opened() {
+        let db = PatternDb::in_memory().unwrap();
+        let entry = |path: &str, opened_at: u64| RecentFile {
+            path: path.to_string(),
+            metadata: ScoreMetadata {
+                parts: 2,
+                part_names: vec!["Violin".to_string(), String::new()],
+                ..Default::default()
+            },
+            opened_at,
+            analyzed_at: None,
+            thumbnail: None,
+        };
+        for (path, opened_at) in [("a", 10), ("b", 10), ("c", 5)] {
+            let saved = db.update_recent(path, 2, |existing| {
+                assert!(existing.is_none());
+                entry(path, opened_at)
+            });
+            assert_eq!(saved.unwrap(), entry(path, opened_at));
+        }
+        // "c" was opened earliest, and "b" after "a" within the same second
+        let paths = |db: &PatternDb| -> Vec<String> {
+            db.recent_files()
+                .unwrap()
+                .into_iter()
+                .map(|f| f.path)
+                .collect()
+        };
+        assert_eq!(paths(&db), vec!["b", "a"]);
+
+        db.update_recent("a", 2, |existing| {
+            assert_eq!(existing, Some(entry("a", 10)));
+            entry("a", 10)
+        })
+        .unwrap();
+        assert_eq!(paths(&db), vec!["a", "b"]);
+        assert_eq!(db.recent_files().unwrap()[0], entry("a", 10));
+        assert!(db.remove_recent("a").unwrap());
+        assert!(!db.remove_recent("a").unwrap());
+        assert_eq!(paths(&db), vec!["b"]);
+    }
+
+    #[test]
+    fn migrations_bring_an_older_database_up_to_date() {
+        let path = scratch("migrate");
+        let _ = std::fs::remove_file(&path);
+        {
+            let db = PatternDb::open(&path).unwrap();
+            db.index(
+                &"a".repeat(64),
+                &result("a.musicxml", vec![pattern(0, 2, &["C4"])]),
+            )
+            .unwrap();
+        }
+
+        // A later version adding a column keeps what was indexed
+        let mut conn = Connection::open(&path).unwrap();
+        let later = [
+            MIGRATIONS[0],
+            MIGRATIONS[1],
+            "ALTER TABLE files ADD COLUMN title TEXT",
+        ];
+        assert_eq!(schema_version(&conn).unwrap(), 2);
+        assert_eq!(migrate(&mut conn, &later).unwrap(), 3);
+        assert_eq!(schema_version(&conn).unwrap(), 3);
+        let title: Option<String> = conn
+            .query_row("SELECT title FROM files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(title, None);
+        // Already up to date
+        assert_eq!(migrate(&mut conn, &later).unwrap(), 3);
+        drop(conn);
+
+        // This version can't open a database from the later one
+        let error = PatternDb::open(&path).err().unwrap();
+        assert!(error.contains("newer"), "{}", error);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
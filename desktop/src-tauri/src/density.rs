@@ -0,0 +1,131 @@
+//! How much repeated material sounds at each moment of the piece.
+
+use serde::Serialize;
+
+use crate::models::AnalysisResult;
+use crate::musicxml;
+
+/// Slot length `density_timeline` uses when none is given: a sixteenth.
+pub const DEFAULT_RESOLUTION: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DensityTimeline {
+    /// Length of each slot in quarter notes. Slot `i` starts `i * resolution`
+    /// quarter notes into the piece, on the `absolute_beat` timeline.
+    pub resolution: f64,
+    pub values: Vec<f64>,
+}
+
+/// Pattern notes sounding in each slot, counting every occurrence of every
+/// pattern, so a note shared by two patterns counts twice. A note covering
+/// part of a slot adds that fraction, which keeps the curve smooth at any
+/// resolution. Onsets and lengths are read from the score in written order.
+pub fn density_timeline(
+    result: &AnalysisResult,
+    resolution: f64,
+) -> Result<DensityTimeline, String> {
+    if resolution.is_nan() || resolution <= 0.0 {
+        return Err(format!("Resolution must be positive, got {}", resolution));
+    }
+    let streams = musicxml::read_streams(&result.musicxml_content)?;
+    let mut values: Vec<f64> = Vec::new();
+
+    for staff in result.staves() {
+        let Some(stream) = usize::try_from(staff.part_index)
+            .ok()
+            .and_then(|i| streams.get(i))
+        else {
+            continue;
+        };
+        for pattern in &staff.patterns {
+            for &position in &pattern.positions {
+                let first = usize::try_from(position).unwrap_or(usize::MAX);
+                let occurrence = stream
+                    .iter()
+                    .skip(first)
+                    .take(pattern.length.max(0) as usize);
+                for (note, onset) in occurrence {
+                    let end = onset + note.duration_beats.unwrap_or(0.0);
+                    add(&mut values, *onset, end, resolution);
+                }
+            }
+        }
+    }
+
+    Ok(DensityTimeline { resolution, values })
+}
+
+/// Add the share of each slot that `start..end` covers.
+fn add(values: &mut Vec<f64>, start: f64, end: f64, resolution: f64) {
+    if end <= start || start < 0.0 {
+        return;
+    }
+    let first = (start / resolution).floor() as usize;
+    let last = (end / resolution).ceil() as usize;
+    if values.len() < last {
+        values.resize(last, 0.0);
+    }
+    for (slot, value) in values.iter_mut().enumerate().take(last).skip(first) {
+        let slot_start = slot as f64 * resolution;
+        let overlap = end.min(slot_start + resolution) - start.max(slot_start);
+        if overlap > 0.0 {
+            *value += overlap / resolution;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    /// C D E F | C D E F in quarter notes, with the bar repeated as one
+    /// pattern and D E of the first bar as another.
+    fn overlapping() -> AnalysisResult {
+        let note = |step: char| {
+            format!(
+                "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                 <duration>1</duration></note>",
+                step
+            )
+        };
+        let bar: String = "CDEF".chars().map(note).collect();
+        let pattern = |id, length, positions| Pattern {
+            id,
+            length,
+            count: 1,
+            positions,
+            ..Default::default()
+        };
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![pattern(0, 4, vec![0, 4]), pattern(1, 2, vec![1])],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">
+<attributes><divisions>1</divisions></attributes>{bar}</measure>
+<measure number="2">{bar}</measure></part></score-partwise>"#,
+                bar = bar
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn density_peaks_where_patterns_overlap() {
+        let timeline = density_timeline(&overlapping(), 1.0).unwrap();
+        assert_eq!(
+            timeline.values,
+            vec![1.0, 2.0, 2.0, 1.0, 1.0, 1.0, 1.0, 1.0]
+        );
+
+        let coarse = density_timeline(&overlapping(), 2.0).unwrap();
+        assert_eq!(coarse.values, vec![1.5, 1.5, 1.0, 1.0]);
+        assert_eq!(
+            density_timeline(&overlapping(), 0.5).unwrap().values.len(),
+            16
+        );
+        assert!(density_timeline(&overlapping(), 0.0).is_err());
+    }
+}
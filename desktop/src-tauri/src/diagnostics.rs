@@ -0,0 +1,271 @@
+//! The analyzer health check behind `check_analyzer`: whether the bundled
+//! sidecar is there, intact and runnable, with a hint at the likely cause
+//! when it isn't, since a spawn error alone doesn't say.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::integrity;
+
+/// Emitted with the report when the check run at startup fails.
+pub const UNHEALTHY_EVENT: &str = "analyzer-unhealthy";
+
+/// How long `--version` may take. A bundled analyzer unpacks itself and
+/// imports music21 first, which is slow on a cold start.
+pub const VERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the analyzer prints for `--version`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AnalyzerVersion {
+    pub version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// How the `--version` run went: its exit code and output, or why it
+/// couldn't be started.
+#[derive(Debug, Clone, Default)]
+pub struct VersionRun {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagnosticReport {
+    /// Every check passed.
+    pub healthy: bool,
+    /// The analyzer found, if any.
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+    pub features: Vec<String>,
+    /// The OS and architecture the app was built for, which the analyzer
+    /// has to match.
+    pub platform: String,
+    pub checks: Vec<Check>,
+}
+
+impl DiagnosticReport {
+    /// The checks that don't need the analyzer run: that it is among
+    /// `candidates`, can be executed and, when `expected` is recorded,
+    /// hashes to it.
+    pub fn inspect(candidates: &[PathBuf], expected: Option<&str>) -> Self {
+        let mut report = DiagnosticReport {
+            healthy: true,
+            path: None,
+            version: None,
+            features: Vec::new(),
+            platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            checks: Vec::new(),
+        };
+        match integrity::locate(candidates) {
+            Ok(path) => {
+                report.add("found", true, path.display().to_string());
+                report.path = Some(path.clone());
+            }
+            Err(message) => {
+                report.add("found", false, message);
+                return report;
+            }
+        }
+        let path = report.path.clone().unwrap_or_default();
+        let (passed, detail) = executable(&path);
+        report.add("executable", passed, detail);
+        if let Some(expected) = expected {
+            match integrity::sha256_file(&path) {
+                Ok(sha256) if sha256.eq_ignore_ascii_case(expected) => {
+                    report.add("checksum", true, sha256)
+                }
+                Ok(sha256) => report.add(
+                    "checksum",
+                    false,
+                    format!(
+                        "sha256 {} doesn't match the {} recorded at build time; reinstall",
+                        sha256, expected
+                    ),
+                ),
+                Err(message) => report.add("checksum", false, message),
+            }
+        }
+        report
+    }
+
+    /// Add the outcome of running the analyzer with `--version`.
+    pub fn record_version_run(&mut self, run: Result<VersionRun, String>) {
+        let run = match run {
+            Ok(run) => run,
+            Err(message) => {
+                let detail = match spawn_hint(&message) {
+                    Some(hint) => format!("{} ({})", message, hint),
+                    None => message,
+                };
+                return self.add("runs", false, detail);
+            }
+        };
+        if run.exit_code != Some(0) {
+            let status = match run.exit_code {
+                Some(code) => format!("exited with code {}", code),
+                // Gatekeeper kills unsigned or quarantined binaries outright
+                None => "was killed before it finished; on macOS this is usually \
+                         an unsigned or quarantined binary"
+                    .to_string(),
+            };
+            let stderr = run.stderr.lines().last().unwrap_or_default();
+            return self.add(
+                "runs",
+                false,
+                format!("{} {}", status, stderr).trim().into(),
+            );
+        }
+        self.add("runs", true, "exited successfully".to_string());
+        match parse_version(&run.stdout) {
+            Ok(version) => {
+                self.add("version", true, version.version.clone());
+                self.version = Some(version.version);
+                self.features = version.features;
+            }
+            Err(message) => self.add("version", false, message),
+        }
+    }
+
+    fn add(&mut self, name: &str, passed: bool, detail: String) {
+        self.healthy &= passed;
+        self.checks.push(Check {
+            name: name.to_string(),
+            passed,
+            detail,
+        });
+    }
+}
+
+/// The `--version` JSON, from the last line of stdout that parses.
+pub fn parse_version(stdout: &str) -> Result<AnalyzerVersion, String> {
+    stdout
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str(line.trim()).ok())
+        .ok_or_else(|| "Analyzer printed no version; it may predate --version".to_string())
+}
+
+/// A likely cause of a spawn error, from the OS error in it.
+fn spawn_hint(message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    if message.contains("exec format error") || message.contains("os error 8") {
+        Some("the analyzer was built for another architecture")
+    } else if message.contains("bad cpu type") || message.contains("os error 86") {
+        Some("the analyzer was built for another architecture; Apple silicon needs Rosetta for it")
+    } else if message.contains("%1 is not a valid win32 application")
+        || message.contains("os error 193")
+    {
+        Some("the analyzer was built for another architecture")
+    } else if message.contains("permission denied") || message.contains("os error 13") {
+        Some("the analyzer isn't executable or was blocked by security software")
+    } else if message.contains("no such file") || message.contains("os error 2") {
+        Some("the analyzer or a library it needs is missing")
+    } else {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> (bool, String) {
+    use std::os::unix::fs::PermissionsExt;
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.permissions().mode() & 0o111 != 0 => (true, "executable".to_string()),
+        Ok(meta) => (
+            false,
+            format!(
+                "mode {:o} isn't executable; reinstall or chmod +x it",
+                meta.permissions().mode() & 0o777
+            ),
+        ),
+        Err(e) => (false, format!("Failed to read analyzer: {}", e)),
+    }
+}
+
+#[cfg(not(unix))]
+fn executable(path: &Path) -> (bool, String) {
+    match std::fs::metadata(path) {
+        Ok(_) => (true, "executable".to_string()),
+        Err(e) => (false, format!("Failed to read analyzer: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyzer(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("analyzer");
+        std::fs::write(&path, "abc").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn a_healthy_analyzer_reports_its_version() {
+        let dir = std::env::temp_dir().join(format!("smrh-diag-ok-{}", std::process::id()));
+        let path = analyzer(&dir);
+        let mut report = DiagnosticReport::inspect(std::slice::from_ref(&path), None);
+        assert!(report.healthy);
+        assert_eq!(report.path, Some(path));
+        report.record_version_run(Ok(VersionRun {
+            exit_code: Some(0),
+            stdout: "loading\n{\"version\": \"0.1.0\", \"features\": [\"stream\"]}\n".into(),
+            stderr: String::new(),
+        }));
+        assert!(report.healthy);
+        assert_eq!(report.version.as_deref(), Some("0.1.0"));
+        assert_eq!(report.features, vec!["stream"]);
+        let names: Vec<&str> = report.checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["found", "executable", "runs", "version"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_say_what_went_wrong() {
+        let dir = std::env::temp_dir().join(format!("smrh-diag-bad-{}", std::process::id()));
+        let missing = DiagnosticReport::inspect(&[dir.join("analyzer")], None);
+        assert!(!missing.healthy);
+        assert_eq!(missing.checks.len(), 1);
+        assert!(missing.checks[0]
+            .detail
+            .starts_with(integrity::ANALYZER_NOT_FOUND));
+
+        let path = analyzer(&dir);
+        let tampered = DiagnosticReport::inspect(std::slice::from_ref(&path), Some("00"));
+        assert!(!tampered.healthy);
+        assert!(tampered.checks[2].detail.contains("reinstall"));
+
+        let mut report = DiagnosticReport::inspect(&[path], None);
+        report.record_version_run(Err("Exec format error (os error 8)".to_string()));
+        assert!(!report.healthy);
+        assert!(report.checks[2].detail.contains("another architecture"));
+
+        let mut report = DiagnosticReport::inspect(&[], None);
+        report.record_version_run(Ok(VersionRun {
+            exit_code: Some(1),
+            stderr: "Traceback\nModuleNotFoundError: music21".to_string(),
+            ..Default::default()
+        }));
+        assert_eq!(
+            report.checks[1].detail,
+            "exited with code 1 ModuleNotFoundError: music21"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+//! Patterns that recur at a steady period out of step with the barline, like
+//! a three-beat ostinato against 4/4.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::AnalysisResult;
+use crate::musicxml::{self, timing};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MetricDisplacement {
+    /// Quarter notes from one occurrence to the next.
+    pub period: f64,
+    /// Length of a bar of the meter the pattern starts in.
+    pub bar_length: f64,
+    /// How much further past the barline each occurrence starts than the
+    /// last, between 0 and `bar_length`.
+    pub displacement: f64,
+}
+
+const EPSILON: f64 = 1e-6;
+
+/// A steady period in the onsets of at least three occurrences that isn't a
+/// whole number of bars. Unevenly spaced occurrences give None.
+pub fn detect(onsets: &[f64], bar_length: f64) -> Option<MetricDisplacement> {
+    if onsets.len() < 3 || bar_length <= 0.0 {
+        return None;
+    }
+    let period = onsets[1] - onsets[0];
+    let steady = onsets
+        .windows(2)
+        .all(|w| (w[1] - w[0] - period).abs() < EPSILON);
+    let displacement = period.rem_euclid(bar_length);
+    let aligned = displacement < EPSILON || bar_length - displacement < EPSILON;
+    (steady && period > EPSILON && !aligned).then_some(MetricDisplacement {
+        period,
+        bar_length,
+        displacement,
+    })
+}
+
+/// Set `metric_displacement` on every pattern, timing its occurrences from
+/// the score. Needs measures in the written frame.
+pub fn annotate(result: &mut AnalysisResult) -> Result<(), String> {
+    let onsets = musicxml::stream_onsets(&result.musicxml_content)?;
+    let timings = timing::measure_timings(&result.musicxml_content)?;
+    for staff in result.staves_mut() {
+        let stream = usize::try_from(staff.part_index)
+            .ok()
+            .and_then(|i| onsets.get(i));
+        for pattern in &mut staff.patterns {
+            let bar_length = pattern
+                .notes
+                .first()
+                .and_then(|n| timings.get(&n.measure))
+                .map(|t| t.bar_length);
+            let starts: Option<Vec<f64>> = pattern
+                .positions
+                .iter()
+                .map(|&p| stream?.get(usize::try_from(p).ok()?).copied())
+                .collect();
+            pattern.metric_displacement = starts
+                .zip(bar_length)
+                .and_then(|(starts, bar)| detect(&starts, bar));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    /// Quarter notes in 4/4, four to a measure.
+    fn score(steps: &str) -> String {
+        let notes: Vec<String> = steps
+            .chars()
+            .map(|step| {
+                format!(
+                    "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                     <duration>1</duration></note>",
+                    step
+                )
+            })
+            .collect();
+        let measures: String = notes
+            .chunks(4)
+            .enumerate()
+            .map(|(i, notes)| {
+                let attributes = if i == 0 {
+                    "<attributes><divisions>1</divisions><time><beats>4</beats>\
+                     <beat-type>4</beat-type></time></attributes>"
+                } else {
+                    ""
+                };
+                format!(
+                    r#"<measure number="{}">{}{}</measure>"#,
+                    i + 1,
+                    attributes,
+                    notes.concat()
+                )
+            })
+            .collect();
+        format!(
+            r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+            measures
+        )
+    }
+
+    fn pattern(length: i32, positions: Vec<i32>) -> Pattern {
+        Pattern {
+            length,
+            count: positions.len() as i32,
+            positions,
+            notes: vec![NoteLocator {
+                measure: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn three_against_four_ostinato() {
+        let mut result = AnalysisResult {
+            // C D E four times over three bars, then a bar-long motif
+            musicxml_content: score("CDECDECDECDEFGABFGABFGAB"),
+            parts: vec![StaffPatternData {
+                patterns: vec![pattern(3, vec![0, 3, 6, 9]), pattern(4, vec![12, 16, 20])],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        annotate(&mut result).unwrap();
+
+        let ostinato = result.parts[0].patterns[0].metric_displacement.unwrap();
+        assert_eq!(
+            (ostinato.period, ostinato.bar_length, ostinato.displacement),
+            (3.0, 4.0, 3.0)
+        );
+        assert_eq!(result.parts[0].patterns[1].metric_displacement, None);
+    }
+
+    #[test]
+    fn bar_aligned_or_uneven_repeats_are_not_displaced() {
+        assert_eq!(detect(&[0.0, 4.0, 8.0], 4.0), None);
+        assert_eq!(detect(&[0.0, 3.0, 7.0], 4.0), None);
+        assert_eq!(detect(&[0.0, 3.0], 4.0), None);
+        assert_eq!(detect(&[0.0, 5.0, 10.0], 4.0).unwrap().displacement, 1.0);
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Largest score we are willing to download.
+const MAX_DOWNLOAD_BYTES: u64 = 20 * 1024 * 1024;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SCORE_EXTENSIONS: &[&str] = &["musicxml", "xml", "mxl"];
+const SCORE_CONTENT_TYPES: &[&str] = &[
+    "application/vnd.recordare.musicxml+xml",
+    "application/vnd.recordare.musicxml",
+    "application/xml",
+    "text/xml",
+];
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A score written to the temp dir, removed when dropped.
+pub struct TempScore {
+    path: PathBuf,
+}
+
+impl TempScore {
+    /// Save `bytes` as `smrh-<kind>-<pid>-<n>.<ext>`.
+    pub fn write(kind: &str, ext: &str, bytes: &[u8]) -> Result<Self, String> {
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "smrh-{}-{}-{}.{}",
+            kind,
+            std::process::id(),
+            n,
+            ext
+        ));
+        std::fs::write(&path, bytes).map_err(|e| format!("Failed to save {}: {}", kind, e))?;
+        Ok(TempScore { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempScore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Download a MusicXML/MXL score to a temp file.
+pub async fn fetch_score(url: &str) -> Result<TempScore, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    let url_ext = Path::new(parsed.path())
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    let client = reqwest::Client::builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let mut response = client
+        .get(parsed)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Download failed: {}", e))?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_lowercase());
+
+    let ext = match url_ext.as_deref() {
+        Some(ext) if SCORE_EXTENSIONS.contains(&ext) => ext.to_string(),
+        _ => match content_type.as_deref() {
+            Some("application/vnd.recordare.musicxml") => "mxl".to_string(),
+            Some(ct) if SCORE_CONTENT_TYPES.contains(&ct) => "musicxml".to_string(),
+            _ => {
+                return Err(format!(
+                    "Unsupported download: expected a .musicxml/.xml/.mxl file (content-type: {})",
+                    content_type.as_deref().unwrap_or("unknown")
+                ))
+            }
+        },
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_DOWNLOAD_BYTES)
+    {
+        return Err(format!(
+            "File exceeds download limit of {} MB",
+            MAX_DOWNLOAD_BYTES / (1024 * 1024)
+        ));
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download interrupted: {}", e))?
+    {
+        if (bytes.len() + chunk.len()) as u64 > MAX_DOWNLOAD_BYTES {
+            return Err(format!(
+                "File exceeds download limit of {} MB",
+                MAX_DOWNLOAD_BYTES / (1024 * 1024)
+            ));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    TempScore::write("download", &ext, &bytes)
+}
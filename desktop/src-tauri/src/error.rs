@@ -0,0 +1,135 @@
+//! The error every command returns, tagged with its `kind` so the frontend
+//! can tell a missing file from a crashed analyzer and offer the right fix.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::folder;
+
+/// Message of the error an analysis stopped by `cancel_analysis` returns.
+pub const ANALYSIS_CANCELLED: &str = "analysis cancelled";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AnalyzeError {
+    /// Reading or writing a file failed.
+    Io { message: String, not_found: bool },
+    /// Not a kind of file the analyzer reads.
+    UnsupportedFormat { message: String },
+    /// Over `large_file_threshold_mb`; retry with `confirm_large`. Also a
+    /// file over `files::MAX_READ_BYTES` to read whole.
+    FileTooLarge { message: String },
+    /// A file the user hasn't opened in the app, which the webview may not
+    /// read (see `files::FileScope`).
+    Forbidden { message: String },
+    /// The analyzer couldn't be found or started.
+    Spawn { message: String },
+    /// The analyzer's output couldn't be read as a result.
+    Parse { message: String },
+    /// The analyzer reported an error, exited unsuccessfully or printed too
+    /// much. `stderr` holds its non-progress stderr lines.
+    Sidecar {
+        message: String,
+        stderr: Vec<String>,
+        exit_code: Option<i32>,
+    },
+    /// The analyzer ran past one of `AnalyzerConfig`'s timeouts.
+    Timeout {
+        message: String,
+        stderr: Vec<String>,
+    },
+    /// Stopped through `cancel_analysis`.
+    Cancelled { message: String },
+    /// Any other failure.
+    Other { message: String },
+}
+
+impl AnalyzeError {
+    pub fn cancelled() -> Self {
+        AnalyzeError::Cancelled {
+            message: ANALYSIS_CANCELLED.to_string(),
+        }
+    }
+
+    pub fn io(context: &str, e: std::io::Error) -> Self {
+        AnalyzeError::Io {
+            message: format!("Failed to {}: {}", context, e),
+            not_found: e.kind() == std::io::ErrorKind::NotFound,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AnalyzeError::Io { message, .. }
+            | AnalyzeError::UnsupportedFormat { message }
+            | AnalyzeError::FileTooLarge { message }
+            | AnalyzeError::Forbidden { message }
+            | AnalyzeError::Spawn { message }
+            | AnalyzeError::Parse { message }
+            | AnalyzeError::Sidecar { message, .. }
+            | AnalyzeError::Timeout { message, .. }
+            | AnalyzeError::Cancelled { message }
+            | AnalyzeError::Other { message } => message,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, AnalyzeError::Cancelled { .. })
+    }
+}
+
+impl fmt::Display for AnalyzeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// The modules' `String` errors, which carry no kind.
+impl From<String> for AnalyzeError {
+    fn from(message: String) -> Self {
+        AnalyzeError::Other { message }
+    }
+}
+
+/// Check that `path` exists and is a score the analyzer reads, before
+/// starting it.
+pub fn check_score(path: &Path) -> Result<(), AnalyzeError> {
+    std::fs::metadata(path).map_err(|e| AnalyzeError::io("read file", e))?;
+    if !folder::is_supported(path) {
+        return Err(AnalyzeError::UnsupportedFormat {
+            message: format!(
+                "Unsupported file type: {}. Supported: {}",
+                path.display(),
+                folder::SUPPORTED_EXTENSIONS.join(", ")
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_tagged_by_kind() {
+        let missing = check_score(Path::new("/nonexistent/score.musicxml")).unwrap_err();
+        assert!(matches!(
+            missing,
+            AnalyzeError::Io {
+                not_found: true,
+                ..
+            }
+        ));
+
+        let json = serde_json::to_value(AnalyzeError::cancelled()).unwrap();
+        assert_eq!(json["kind"], "cancelled");
+        assert_eq!(json["message"], ANALYSIS_CANCELLED);
+
+        let other = AnalyzeError::from("Invalid fingerprint".to_string());
+        assert_eq!(other.to_string(), "Invalid fingerprint");
+        assert_eq!(serde_json::to_value(other).unwrap()["kind"], "other");
+    }
+}
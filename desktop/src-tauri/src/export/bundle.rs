@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::models::AnalysisResult;
+use crate::musicxml::highlight;
+
+/// Which artifacts go into the bundle. Everything is included by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BundleOptions {
+    pub json: bool,
+    pub csv: bool,
+    pub musicxml: bool,
+    pub report: bool,
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        BundleOptions {
+            json: true,
+            csv: true,
+            musicxml: true,
+            report: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleManifest {
+    pub path: String,
+    pub files: Vec<String>,
+}
+
+/// Write the selected artifacts into a ZIP archive at `path`.
+pub fn write_bundle(
+    result: &AnalysisResult,
+    path: &str,
+    options: &BundleOptions,
+) -> Result<BundleManifest, String> {
+    let mut artifacts: Vec<(&str, String)> = Vec::new();
+    if options.json {
+        artifacts.push(("analysis.json", super::to_json(result)?));
+    }
+    if options.csv {
+        artifacts.push(("patterns.csv", super::to_csv(result)));
+    }
+    if options.musicxml {
+        let colors = highlight::pattern_note_colors(result);
+        artifacts.push((
+            "highlighted.musicxml",
+            highlight::highlight(&result.musicxml_content, &colors)?,
+        ));
+    }
+    if options.report {
+        artifacts.push(("report.txt", super::to_report(result)));
+    }
+
+    let file = File::create(path).map_err(|e| format!("Failed to create bundle: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+    for (name, content) in &artifacts {
+        zip.start_file(*name, SimpleFileOptions::default())
+            .map_err(|e| format!("Failed to add {} to bundle: {}", name, e))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write {} to bundle: {}", name, e))?;
+    }
+    zip.finish()
+        .map_err(|e| format!("Failed to finish bundle: {}", e))?;
+
+    Ok(BundleManifest {
+        path: path.to_string(),
+        files: artifacts.iter().map(|(name, _)| name.to_string()).collect(),
+    })
+}
@@ -0,0 +1,238 @@
+//! A WAV click track sounding on each note of a pattern's occurrences, for
+//! practicing along with the repetition.
+
+use std::io::Cursor;
+
+use crate::models::AnalysisResult;
+use crate::musicxml::{self, timing};
+use crate::occurrences;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Tempo used when neither the caller nor the score gives one.
+pub const DEFAULT_TEMPO: f64 = 120.0;
+/// Length of one click.
+const CLICK_SECONDS: f64 = 0.03;
+/// Silence after the last click.
+const TAIL_SECONDS: f64 = 0.5;
+
+/// Onsets of every note of every occurrence of a pattern, on the
+/// `absolute_beat` timeline, with the first note of each occurrence marked
+/// as accented.
+pub fn pattern_onsets(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+) -> Result<Vec<(f64, bool)>, String> {
+    let (staff_data, pattern) = occurrences::find_pattern(result, staff, pattern_id)?;
+    let streams = musicxml::read_streams(&result.musicxml_content)?;
+    let stream = usize::try_from(staff_data.part_index)
+        .ok()
+        .and_then(|i| streams.get(i))
+        .ok_or_else(|| format!("The score has no part {}", staff_data.part_index))?;
+
+    let mut onsets: Vec<(f64, bool)> = Vec::new();
+    for &position in &pattern.positions {
+        let first = usize::try_from(position).unwrap_or(usize::MAX);
+        let occurrence = stream
+            .iter()
+            .skip(first)
+            .take(pattern.length.max(0) as usize);
+        for (i, (_, onset)) in occurrence.enumerate() {
+            onsets.push((*onset, i == 0));
+        }
+    }
+    onsets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    onsets.dedup_by(|later, earlier| {
+        let same = (later.0 - earlier.0).abs() < 1e-9;
+        if same {
+            earlier.1 |= later.1;
+        }
+        same
+    });
+    Ok(onsets)
+}
+
+/// Tempo changes as (onset in quarters, quarter notes per minute): `tempo`
+/// throughout if given, otherwise the score's `<sound tempo>` marks, falling
+/// back to `DEFAULT_TEMPO` before the first.
+pub fn tempo_map(xml: &str, tempo: Option<f64>) -> Result<Vec<(f64, f64)>, String> {
+    if let Some(tempo) = tempo {
+        if tempo.is_nan() || tempo <= 0.0 {
+            return Err(format!("Tempo must be positive, got {}", tempo));
+        }
+        return Ok(vec![(0.0, tempo)]);
+    }
+    let mut map = vec![(0.0, DEFAULT_TEMPO)];
+    for (at, bpm) in timing::tempo_changes(xml)? {
+        if bpm <= 0.0 {
+            continue;
+        }
+        if at <= 0.0 {
+            map[0].1 = bpm;
+        } else {
+            map.push((at, bpm));
+        }
+    }
+    Ok(map)
+}
+
+/// Seconds from the start of the piece to `beat`, following `tempo_map`.
+pub fn seconds_at(tempo_map: &[(f64, f64)], beat: f64) -> f64 {
+    let mut seconds = 0.0;
+    for (i, &(start, bpm)) in tempo_map.iter().enumerate() {
+        if beat <= start {
+            break;
+        }
+        let end = tempo_map.get(i + 1).map_or(beat, |next| next.0.min(beat));
+        seconds += (end - start) * 60.0 / bpm;
+    }
+    seconds
+}
+
+/// Mono 16-bit PCM with a short decaying click at each onset, accented
+/// clicks an octave higher and louder.
+pub fn render(onsets: &[(f64, bool)], tempo_map: &[(f64, f64)]) -> Result<Vec<u8>, String> {
+    let rate = f64::from(SAMPLE_RATE);
+    let starts: Vec<(usize, bool)> = onsets
+        .iter()
+        .map(|&(beat, accent)| {
+            (
+                (seconds_at(tempo_map, beat) * rate).round() as usize,
+                accent,
+            )
+        })
+        .collect();
+    let click_len = (CLICK_SECONDS * rate) as usize;
+    let total =
+        starts.last().map_or(0, |(start, _)| start + click_len) + (TAIL_SECONDS * rate) as usize;
+
+    let mut samples = vec![0i16; total];
+    for &(start, accent) in &starts {
+        let (frequency, amplitude) = if accent { (2000.0, 0.9) } else { (1000.0, 0.6) };
+        for (i, sample) in samples.iter_mut().skip(start).take(click_len).enumerate() {
+            let t = i as f64 / rate;
+            let decay = 1.0 - i as f64 / click_len as f64;
+            let value = (std::f64::consts::TAU * frequency * t).sin() * amplitude * decay;
+            *sample = (value * f64::from(i16::MAX)) as i16;
+        }
+    }
+    wav(&samples)
+}
+
+/// A WAV file of mono 16-bit samples at `SAMPLE_RATE`.
+pub fn wav(samples: &[i16]) -> Result<Vec<u8>, String> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let wav_error = |e: hound::Error| format!("Failed to write WAV: {}", e);
+    let mut bytes = Cursor::new(Vec::with_capacity(44 + samples.len() * 2));
+    let mut writer = hound::WavWriter::new(&mut bytes, spec).map_err(wav_error)?;
+    let mut pcm = writer.get_i16_writer(samples.len() as u32);
+    for &sample in samples {
+        pcm.write_sample(sample);
+    }
+    pcm.flush().map_err(wav_error)?;
+    writer.finalize().map_err(wav_error)?;
+    Ok(bytes.into_inner())
+}
+
+pub fn to_click_track(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    tempo: Option<f64>,
+) -> Result<Vec<u8>, String> {
+    let onsets = pattern_onsets(result, staff, pattern_id)?;
+    let tempo_map = tempo_map(&result.musicxml_content, tempo)?;
+    render(&onsets, &tempo_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    /// C D | C D in quarter notes, optionally marked with a tempo up front.
+    fn result(sound: &str) -> AnalysisResult {
+        let note = |step| {
+            format!(
+                "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                 <duration>1</duration></note>",
+                step
+            )
+        };
+        let bar = format!("{}{}", note("C"), note("D"));
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 0,
+                    length: 2,
+                    count: 2,
+                    positions: vec![0, 2],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">
+<attributes><divisions>1</divisions><time><beats>2</beats><beat-type>4</beat-type></time></attributes>
+{sound}{bar}</measure><measure number="2">{bar}</measure></part></score-partwise>"#,
+                sound = sound,
+                bar = bar
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn samples(wav: &[u8]) -> Vec<i16> {
+        wav[44..]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn clicks_land_on_the_onsets() {
+        let wav = to_click_track(&result(""), 0, 0, Some(120.0)).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(
+            u32::from_le_bytes(wav[24..28].try_into().unwrap()),
+            SAMPLE_RATE
+        );
+
+        // Quarters at 120 bpm are half a second apart
+        let samples = samples(&wav);
+        let sounding = |at: usize| samples[at..at + 100].iter().any(|s| *s != 0);
+        for click in [0, 22_050, 44_100, 66_150] {
+            assert!(sounding(click), "no click at sample {}", click);
+        }
+        assert!(!sounding(11_000));
+        assert!(to_click_track(&result(""), 0, 0, Some(0.0)).is_err());
+    }
+
+    #[test]
+    fn tempo_is_read_from_the_score() {
+        let marked = result(r#"<sound tempo="60"/>"#);
+        assert_eq!(
+            tempo_map(&marked.musicxml_content, None).unwrap(),
+            vec![(0.0, 60.0)]
+        );
+        assert_eq!(
+            tempo_map(&marked.musicxml_content, Some(90.0)).unwrap(),
+            vec![(0.0, 90.0)]
+        );
+        assert_eq!(
+            tempo_map(&result("").musicxml_content, None).unwrap(),
+            vec![(0.0, DEFAULT_TEMPO)]
+        );
+
+        // Two quarters at 60, then two at 120
+        let map = [(0.0, 60.0), (2.0, 120.0)];
+        assert_eq!(seconds_at(&map, 2.0), 2.0);
+        assert_eq!(seconds_at(&map, 4.0), 3.0);
+    }
+}
@@ -0,0 +1,133 @@
+//! Patterns as a GraphViz graph, linking those whose occurrences meet.
+
+use crate::models::AnalysisResult;
+use crate::musicxml::highlight;
+use crate::occurrences;
+
+/// Measures apart two occurrences may be and still be linked when no
+/// distance is given: 1 links back-to-back occurrences as well as
+/// overlapping ones.
+pub const DEFAULT_WITHIN_MEASURES: i32 = 1;
+
+struct Node {
+    name: String,
+    label: String,
+    color: &'static str,
+    spans: Vec<(i32, i32)>,
+}
+
+/// An undirected `.dot` graph with a node per pattern, labeled with its
+/// length and count, and an edge between two patterns for each pair of
+/// their occurrences starting within `within_measures` of the other's end.
+/// Edges are labeled with that number of pairs.
+pub fn to_dot(result: &AnalysisResult, within_measures: i32) -> String {
+    let mut nodes: Vec<Node> = Vec::new();
+    for (staff, data) in result.parts.iter().enumerate() {
+        let notes = occurrences::staff_notes(result, data);
+        for pattern in &data.patterns {
+            nodes.push(Node {
+                name: format!("staff{}_{}", staff, pattern.id),
+                label: format!(
+                    "{} {}\\n{} notes × {}",
+                    data.part_name.replace('"', "\\\""),
+                    pattern.id,
+                    pattern.length,
+                    pattern.count
+                ),
+                color: highlight::pattern_color(pattern.id),
+                spans: occurrences::spans(pattern, &notes, result.measure_map.as_ref()),
+            });
+        }
+    }
+
+    let mut dot =
+        String::from("graph patterns {\n  node [shape=box, style=filled, fontcolor=white];\n");
+    for node in &nodes {
+        dot.push_str(&format!(
+            "  {} [label=\"{}\", fillcolor=\"{}\"];\n",
+            node.name, node.label, node.color
+        ));
+    }
+    for (i, a) in nodes.iter().enumerate() {
+        for b in &nodes[i + 1..] {
+            let meetings = a
+                .spans
+                .iter()
+                .flat_map(|x| b.spans.iter().map(move |y| gap(*x, *y)))
+                .filter(|&gap| gap <= within_measures)
+                .count();
+            if meetings > 0 {
+                dot.push_str(&format!(
+                    "  {} -- {} [label=\"{}\", penwidth={}];\n",
+                    a.name,
+                    b.name,
+                    meetings,
+                    meetings.min(8)
+                ));
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Measures from the end of the earlier span to the start of the later:
+/// 1 for back-to-back spans, 0 or less when they overlap.
+fn gap(a: (i32, i32), b: (i32, i32)) -> i32 {
+    a.0.max(b.0) - a.1.min(b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    /// One occurrence over `start..=end`, described by its notes, as there
+    /// is no score to read it from.
+    fn pattern(id: i32, start: i32, end: i32) -> Pattern {
+        Pattern {
+            id,
+            length: 2,
+            count: 1,
+            positions: vec![0],
+            notes: [start, end]
+                .into_iter()
+                .map(|measure| NoteLocator {
+                    measure,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn links_patterns_that_meet() {
+        let result = AnalysisResult {
+            parts: vec![
+                StaffPatternData {
+                    part_name: "Violin".to_string(),
+                    patterns: vec![pattern(0, 1, 2), pattern(1, 3, 3), pattern(2, 8, 9)],
+                    ..Default::default()
+                },
+                StaffPatternData {
+                    part_index: 1,
+                    patterns: vec![pattern(0, 2, 2)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let dot = to_dot(&result, DEFAULT_WITHIN_MEASURES);
+        assert!(dot.starts_with("graph patterns {"));
+        assert!(dot.contains(r##"staff0_1 [label="Violin 1\n2 notes × 1", fillcolor="#A77F35"];"##));
+        assert!(dot.contains("staff0_0 -- staff0_1 [label=\"1\""));
+        assert!(dot.contains("staff0_0 -- staff1_0"));
+        assert!(dot.contains("staff0_1 -- staff1_0"));
+        assert!(!dot.contains("staff0_2 --"));
+
+        let tight = to_dot(&result, 0);
+        assert!(!tight.contains("staff0_0 -- staff0_1"));
+        assert!(tight.contains("staff0_0 -- staff1_0"));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::models::AnalysisResult;
+use crate::occurrences;
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+h1 { font-size: 1.4rem; }
+table { border-collapse: collapse; margin-bottom: 2rem; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.3rem 0.6rem; text-align: left; }
+tr.pattern { cursor: pointer; }
+tr.pattern:hover, tr.selected { background: #fff3c4; }
+.strip { display: flex; gap: 1px; margin: 0.5rem 0 2rem; }
+.strip div { flex: 1; height: 2rem; background: #e8590c; }
+.strip div.selected { outline: 2px solid #1864ab; outline-offset: -2px; }
+.legend { font-size: 0.85rem; color: #666; }
+code { font-size: 0.9rem; }
+"#;
+
+/// Clicking a pattern row outlines the measures it occupies in the strip.
+const SCRIPT: &str = r#"
+document.querySelectorAll("tr.pattern").forEach((row) => {
+  row.addEventListener("click", () => {
+    const selected = !row.classList.contains("selected");
+    document.querySelectorAll(".selected").forEach((e) => e.classList.remove("selected"));
+    if (!selected) return;
+    row.classList.add("selected");
+    for (const m of row.dataset.measures.split(" ")) {
+      document.querySelector(`.strip div[data-measure="${m}"]`)?.classList.add("selected");
+    }
+  });
+});
+"#;
+
+/// Self-contained HTML page with a table of patterns per staff and a heat
+/// strip of how many pattern occurrences touch each measure.
+pub fn to_html_report(result: &AnalysisResult) -> String {
+    let mut heat: Vec<usize> = Vec::new();
+    let mut tables = String::new();
+
+    for staff in result.staves() {
+        let notes = occurrences::staff_notes(result, staff);
+        if let Some(last) = notes.iter().map(|n| n.measure).max() {
+            grow(&mut heat, last);
+        }
+
+        tables.push_str(&format!(
+            "<h2>{} ({} patterns)</h2>\n<table>\n<tr><th>Pattern</th><th>Category</th>\
+             <th>Notes</th><th>Count</th><th>Measures</th><th>Pitches</th></tr>\n",
+            escape(&staff.part_name),
+            staff.patterns.len()
+        ));
+        for pattern in &staff.patterns {
+            let spans = occurrences::spans(pattern, &notes, result.measure_map.as_ref());
+            let mut measures: Vec<i32> = Vec::new();
+            for &(start, end) in &spans {
+                grow(&mut heat, end);
+                for m in start.max(1)..=end {
+                    heat[m as usize - 1] += 1;
+                    measures.push(m);
+                }
+            }
+            measures.sort();
+            measures.dedup();
+
+            let span_text: Vec<String> = spans
+                .iter()
+                .map(|&(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{}–{}", start, end)
+                    }
+                })
+                .collect();
+            let pitches: Vec<&str> = pattern.notes.iter().map(|n| n.pitch.as_str()).collect();
+            let measure_attr: Vec<String> = measures.iter().map(i32::to_string).collect();
+            tables.push_str(&format!(
+                "<tr class=\"pattern\" data-measures=\"{}\"><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+                measure_attr.join(" "),
+                pattern.id,
+                escape(pattern.category.as_deref().unwrap_or("")),
+                pattern.length,
+                pattern.count,
+                span_text.join(", "),
+                escape(&pitches.join(" "))
+            ));
+        }
+        tables.push_str("</table>\n");
+    }
+
+    let hottest = heat.iter().copied().max().unwrap_or(0).max(1);
+    let strip: String = heat
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            format!(
+                "<div data-measure=\"{m}\" title=\"m. {m}: {count} occurrences\" \
+                 style=\"opacity: {:.2}\"></div>",
+                0.08 + 0.92 * count as f64 / hottest as f64,
+                m = i + 1,
+                count = count
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Repetition analysis: {file}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>Repetition analysis: {file}</h1>\n\
+         <p class=\"legend\">Each bar is a measure; darker measures hold more pattern \
+         occurrences. Click a pattern to outline its measures.</p>\n\
+         <div class=\"strip\">{strip}</div>\n{tables}<script>{SCRIPT}</script>\n</body>\n</html>\n",
+        file = escape(&result.file),
+    )
+}
+
+/// Extend `heat` to cover measures `1..=measure`.
+fn grow(heat: &mut Vec<usize>, measure: i32) {
+    let len = usize::try_from(measure).unwrap_or(0);
+    if heat.len() < len {
+        heat.resize(len, 0);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    #[test]
+    fn tables_and_heat_strip() {
+        let result = AnalysisResult {
+            file: "<Etude>.musicxml".to_string(),
+            parts: vec![StaffPatternData {
+                part_name: "Piano".to_string(),
+                patterns: vec![Pattern {
+                    id: 4,
+                    length: 2,
+                    count: 1,
+                    positions: vec![0],
+                    notes: vec![
+                        NoteLocator {
+                            measure: 2,
+                            pitch: "C4".to_string(),
+                            ..Default::default()
+                        },
+                        NoteLocator {
+                            measure: 3,
+                            pitch: "E-4".to_string(),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let html = to_html_report(&result);
+        assert!(html.contains("<title>Repetition analysis: &lt;Etude&gt;.musicxml</title>"));
+        assert!(html.contains("<tr class=\"pattern\" data-measures=\"2 3\"><td>4</td>"));
+        assert!(html.contains("<td>2–3</td><td><code>C4 E-4</code></td>"));
+        assert_eq!(html.matches("<div data-measure=").count(), 3);
+        assert!(html.contains("title=\"m. 1: 0 occurrences\""));
+        assert!(!html.contains("http"));
+    }
+}
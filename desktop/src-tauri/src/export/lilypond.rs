@@ -0,0 +1,166 @@
+//! Pattern notes as a LilyPond `\relative` snippet.
+
+use crate::models::{NoteLocator, Pattern};
+use crate::pitch::{self, Spelling};
+
+/// `\relative` starting pitch (middle C, "c'").
+const RELATIVE_START: i32 = 4 * 7;
+
+/// LilyPond durations with their length in quarter notes, dotted values
+/// included, longest first.
+pub(super) const DURATIONS: &[(f64, &str)] = &[
+    (6.0, "1."),
+    (4.0, "1"),
+    (3.0, "2."),
+    (2.0, "2"),
+    (1.5, "4."),
+    (1.0, "4"),
+    (0.75, "8."),
+    (0.5, "8"),
+    (0.375, "16."),
+    (0.25, "16"),
+    (0.125, "32"),
+];
+
+/// Render a pattern's first occurrence, e.g. `\relative c' { c4 d e8 fis }`.
+/// Durations are only written when they change, as LilyPond carries the last
+/// one forward; notes without a duration are written as quarters.
+pub fn to_lilypond(pattern: &Pattern) -> Result<String, String> {
+    let mut previous_step = RELATIVE_START;
+    let mut previous_duration: Option<String> = None;
+    let mut notes = Vec::new();
+
+    for note in &pattern.notes {
+        let length = note.duration_beats.unwrap_or(1.0);
+        let grace = note.is_grace || length <= 0.0;
+        // A grace note's written value isn't in the result; use an eighth
+        let duration = if grace {
+            "8".to_string()
+        } else {
+            duration(length)
+        };
+
+        let mut text = if grace {
+            "\\grace ".to_string()
+        } else {
+            String::new()
+        };
+        if is_rest(note) {
+            text.push('r');
+        } else {
+            let spelling = pitch::parse(&note.pitch)
+                .ok_or_else(|| format!("Unsupported pitch for LilyPond: {}", note.pitch))?;
+            let step = pitch::diatonic(&note.pitch).unwrap_or(previous_step);
+            text.push_str(&note_name(spelling));
+            text.push_str(&octave_marks(step - previous_step));
+            previous_step = step;
+        }
+
+        if grace || previous_duration.as_deref() != Some(duration.as_str()) {
+            text.push_str(&duration);
+        }
+        if !grace {
+            previous_duration = Some(duration);
+        }
+        notes.push(text);
+    }
+
+    Ok(format!("\\relative c' {{ {} }}", notes.join(" ")))
+}
+
+fn is_rest(note: &NoteLocator) -> bool {
+    note.pitch.is_empty() || note.pitch.eq_ignore_ascii_case("rest")
+}
+
+/// Dutch note names, LilyPond's default ("fis", "bes", "es", "as").
+fn note_name(spelling: Spelling) -> String {
+    let step = spelling.step.to_ascii_lowercase();
+    let accidental = if spelling.alter >= 0 { "is" } else { "es" };
+    let mut name = format!(
+        "{}{}",
+        step,
+        accidental.repeat(spelling.alter.unsigned_abs() as usize)
+    );
+    // "ees" and "aes" are spelled "es" and "as"
+    if spelling.alter < 0 && matches!(step, 'e' | 'a') {
+        name.remove(1);
+    }
+    name
+}
+
+/// In relative mode a note lands within a fourth of the previous one; marks
+/// move it by octaves from there.
+fn octave_marks(steps: i32) -> String {
+    let octaves = (steps + 3).div_euclid(7);
+    if octaves >= 0 {
+        "'".repeat(octaves as usize)
+    } else {
+        ",".repeat(octaves.unsigned_abs() as usize)
+    }
+}
+
+fn duration(quarters: f64) -> String {
+    if let Some((_, name)) = DURATIONS.iter().find(|(q, _)| (q - quarters).abs() < 1e-6) {
+        return name.to_string();
+    }
+    // Tuplets and other odd lengths: a scaled quarter, e.g. "4*2/3"
+    for denominator in 1..=16 {
+        let numerator = quarters * denominator as f64;
+        if (numerator - numerator.round()).abs() < 1e-6 {
+            return format!("4*{}/{}", numerator.round() as i64, denominator);
+        }
+    }
+    "4".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(notes: &[(&str, f64)]) -> Pattern {
+        Pattern {
+            length: notes.len() as i32,
+            notes: notes
+                .iter()
+                .map(|&(pitch, beats)| NoteLocator {
+                    pitch: pitch.to_string(),
+                    duration_beats: Some(beats),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_relative_pitches_and_changing_durations() {
+        let motif = pattern(&[
+            ("E5", 0.5),
+            ("D#5", 0.5),
+            ("E5", 0.5),
+            ("B4", 0.5),
+            ("D5", 0.5),
+            ("C5", 0.5),
+            ("A4", 1.0),
+        ]);
+        assert_eq!(
+            to_lilypond(&motif).unwrap(),
+            "\\relative c' { e'8 dis e b d c a4 }"
+        );
+    }
+
+    #[test]
+    fn handles_flats_leaps_rests_and_tuplets() {
+        let motif = pattern(&[
+            ("E-4", 1.5),
+            ("rest", 0.5),
+            ("A-3", 1.0 / 3.0),
+            ("B-4", 1.0 / 3.0),
+            ("C2", 2.0),
+        ]);
+        assert_eq!(
+            to_lilypond(&motif).unwrap(),
+            "\\relative c' { es4. r8 as,4*1/3 bes' c,,,2 }"
+        );
+    }
+}
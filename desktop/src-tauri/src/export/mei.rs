@@ -0,0 +1,211 @@
+//! A minimal MEI document of the analyzed notes, with pattern membership on
+//! each note, for musicology tools that read MEI rather than MusicXML.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use quick_xml::escape::escape;
+
+use super::lilypond::DURATIONS;
+use crate::models::AnalysisResult;
+use crate::musicxml::{self, highlight};
+use crate::pitch;
+
+pub const MEI_VERSION: &str = "5.0";
+
+/// Patterns a note belongs to and the notes it occupies the same place as in
+/// the other occurrences of each.
+#[derive(Default)]
+struct Membership {
+    patterns: Vec<i32>,
+    corresp: Vec<String>,
+}
+
+fn note_id(stream: usize, index: usize) -> String {
+    format!("s{}n{}", stream + 1, index)
+}
+
+/// One staff per stream of the score and one layer per staff, holding the
+/// notes the analyzer matched (a chord as its top tone, rests left out).
+/// A pattern note has `type="pattern-<id>"` for each pattern it belongs to,
+/// the color of the first, and `corresp` listing the notes at the same place
+/// in the pattern's other occurrences.
+pub fn to_mei(result: &AnalysisResult) -> Result<String, String> {
+    let streams = musicxml::read_streams(&result.musicxml_content)?;
+
+    let mut members: BTreeMap<(usize, usize), Membership> = BTreeMap::new();
+    for staff in result.staves() {
+        let Ok(stream) = usize::try_from(staff.part_index) else {
+            continue;
+        };
+        for pattern in &staff.patterns {
+            let starts: Vec<usize> = pattern
+                .positions
+                .iter()
+                .filter_map(|&p| usize::try_from(p).ok())
+                .collect();
+            for offset in 0..pattern.length.max(0) as usize {
+                for &start in &starts {
+                    let member = members.entry((stream, start + offset)).or_default();
+                    member.patterns.push(pattern.id);
+                    member.corresp.extend(
+                        starts
+                            .iter()
+                            .filter(|&&other| other != start)
+                            .map(|&other| format!("#{}", note_id(stream, other + offset))),
+                    );
+                }
+            }
+        }
+    }
+
+    // Measures in the order they first appear in any stream
+    let mut measures: Vec<i32> = Vec::new();
+    for (note, _) in streams.iter().flatten() {
+        if !measures.contains(&note.measure) {
+            measures.push(note.measure);
+        }
+    }
+
+    let title = Path::new(&result.file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut mei = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <mei xmlns=\"http://www.music-encoding.org/ns/mei\" meiversion=\"{}\">\n\
+         <meiHead><fileDesc><titleStmt><title>{}</title></titleStmt><pubStmt/></fileDesc></meiHead>\n\
+         <music><body><mdiv><score>\n<scoreDef><staffGrp>\n",
+        MEI_VERSION,
+        escape(title.as_str())
+    );
+    for n in 1..=streams.len() {
+        mei.push_str(&format!("<staffDef n=\"{}\" lines=\"5\"/>\n", n));
+    }
+    mei.push_str("</staffGrp></scoreDef>\n<section>\n");
+
+    for &measure in &measures {
+        mei.push_str(&format!("<measure n=\"{}\">\n", measure));
+        for (stream, notes) in streams.iter().enumerate() {
+            mei.push_str(&format!("<staff n=\"{}\"><layer n=\"1\">", stream + 1));
+            for (index, (note, _)) in notes.iter().enumerate() {
+                if note.measure != measure {
+                    continue;
+                }
+                mei.push_str(&note_element(
+                    &note_id(stream, index),
+                    &note.pitch,
+                    note.duration_beats.unwrap_or(1.0),
+                    members.get(&(stream, index)),
+                ));
+            }
+            mei.push_str("</layer></staff>\n");
+        }
+        mei.push_str("</measure>\n");
+    }
+
+    mei.push_str("</section>\n</score></mdiv></body></music>\n</mei>\n");
+    Ok(mei)
+}
+
+fn note_element(id: &str, name: &str, quarters: f64, member: Option<&Membership>) -> String {
+    let mut attributes = format!("xml:id=\"{}\"", id);
+    if let Some(spelling) = pitch::parse(name) {
+        attributes.push_str(&format!(
+            " pname=\"{}\" oct=\"{}\"",
+            spelling.step.to_ascii_lowercase(),
+            spelling.octave
+        ));
+        if let Some(accid) = accidental(spelling.alter) {
+            attributes.push_str(&format!(" accid=\"{}\"", accid));
+        }
+    }
+    let (dur, dots) = duration(quarters);
+    attributes.push_str(&format!(" dur=\"{}\"", dur));
+    if dots > 0 {
+        attributes.push_str(&format!(" dots=\"{}\"", dots));
+    }
+
+    if let Some(member) = member {
+        let types: Vec<String> = member
+            .patterns
+            .iter()
+            .map(|id| format!("pattern-{}", id))
+            .collect();
+        attributes.push_str(&format!(" type=\"{}\"", types.join(" ")));
+        if let Some(&first) = member.patterns.first() {
+            attributes.push_str(&format!(" color=\"{}\"", highlight::pattern_color(first)));
+        }
+        if !member.corresp.is_empty() {
+            attributes.push_str(&format!(" corresp=\"{}\"", member.corresp.join(" ")));
+        }
+    }
+    format!("<note {}/>", attributes)
+}
+
+fn accidental(alter: i32) -> Option<&'static str> {
+    match alter {
+        -2 => Some("ff"),
+        -1 => Some("f"),
+        1 => Some("s"),
+        2 => Some("x"),
+        _ => None,
+    }
+}
+
+/// MEI `dur` and `dots` of the written value closest to `quarters`.
+fn duration(quarters: f64) -> (&'static str, usize) {
+    let (_, name) = DURATIONS
+        .iter()
+        .min_by(|a, b| (a.0 - quarters).abs().total_cmp(&(b.0 - quarters).abs()))
+        .copied()
+        .unwrap_or((1.0, "4"));
+    let dur = name.trim_end_matches('.');
+    (dur, name.len() - dur.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    /// C D | C D, quarter then dotted half, with the bar repeated.
+    fn result() -> AnalysisResult {
+        let bar = "<note><pitch><step>C</step><alter>1</alter><octave>4</octave></pitch>\
+                   <duration>1</duration></note>\
+                   <note><pitch><step>D</step><octave>4</octave></pitch>\
+                   <duration>3</duration></note>";
+        AnalysisResult {
+            file: "/scores/Étude & Co.musicxml".to_string(),
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 1,
+                    length: 2,
+                    count: 2,
+                    positions: vec![0, 2],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">
+<attributes><divisions>1</divisions></attributes>{bar}</measure>
+<measure number="2">{bar}</measure></part></score-partwise>"#,
+                bar = bar
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pattern_members_link_to_each_other() {
+        let mei = to_mei(&result()).unwrap();
+        assert!(mei.contains("<title>Étude &amp; Co</title>"));
+        assert_eq!(mei.matches("<measure ").count(), 2);
+        assert!(mei.contains(
+            r##"<note xml:id="s1n0" pname="c" oct="4" accid="s" dur="4" type="pattern-1" color="#A77F35" corresp="#s1n2"/>"##
+        ));
+        assert!(mei.contains(r##"xml:id="s1n3" pname="d" oct="4" dur="2" dots="1""##));
+        assert!(mei.contains(r##"corresp="#s1n1""##));
+    }
+}
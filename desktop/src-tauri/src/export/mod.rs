@@ -0,0 +1,78 @@
+//! File exports built from an `AnalysisResult`.
+
+pub mod bundle;
+pub mod click;
+pub mod graph;
+pub mod html;
+pub mod lilypond;
+pub mod mei;
+pub mod practice;
+pub mod score;
+
+use crate::models::AnalysisResult;
+use crate::occurrences;
+
+pub fn to_json(result: &AnalysisResult) -> Result<String, String> {
+    serde_json::to_string_pretty(result).map_err(|e| format!("Failed to serialize result: {}", e))
+}
+
+/// One row per pattern occurrence.
+pub fn to_csv(result: &AnalysisResult) -> String {
+    let mut csv = String::from(
+        "staff,pattern_id,length,count,occurrence,position,start_measure,end_measure,pitches\n",
+    );
+    for staff in result.staves() {
+        for pattern in &staff.patterns {
+            let (start, end) = occurrences::first_span(pattern);
+            let pitches = pattern
+                .notes
+                .iter()
+                .map(|n| n.pitch.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            for (i, pos) in pattern.positions.iter().enumerate() {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{}\n",
+                    csv_field(&staff.part_name),
+                    pattern.id,
+                    pattern.length,
+                    pattern.count,
+                    i,
+                    pos,
+                    start,
+                    end,
+                    csv_field(&pitches)
+                ));
+            }
+        }
+    }
+    csv
+}
+
+/// Plain-text summary for people who don't have the app.
+pub fn to_report(result: &AnalysisResult) -> String {
+    let mut report = format!("Repetition analysis: {}\n", result.file);
+    for staff in result.staves() {
+        report.push_str(&format!(
+            "\n{} ({} patterns)\n",
+            staff.part_name,
+            staff.patterns.len()
+        ));
+        for pattern in &staff.patterns {
+            let (start, end) = occurrences::first_span(pattern);
+            report.push_str(&format!(
+                "  Pattern {}: {} notes, {}x, first at m. {}-{}, positions {:?}\n",
+                pattern.id, pattern.length, pattern.count, start, end, pattern.positions
+            ));
+        }
+    }
+    report
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AnalysisResult, Pattern};
+use crate::occurrences;
+use crate::pitch;
+
+/// Interval range (in semitones) at which the spread component saturates.
+const SATURATING_SPREAD: f64 = 24.0;
+/// Pattern length (in notes) at which the length component saturates.
+const SATURATING_LENGTH: f64 = 16.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DifficultyOrder {
+    Ascending,
+    /// Hardest first.
+    #[default]
+    Descending,
+}
+
+/// How hard a pattern is to play, each component normalized to 0–1.
+///
+/// ```text
+/// spread       = min((highest - lowest semitone) / 24, 1)
+/// length       = min(pattern.length / 16, 1)
+/// chromaticism = share of steps that move by a semitone
+/// score        = mean of the three
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct Difficulty {
+    pub score: f64,
+    pub spread: f64,
+    pub length: f64,
+    pub chromaticism: f64,
+}
+
+pub fn difficulty(pattern: &Pattern) -> Difficulty {
+    let midi: Vec<i32> = pattern
+        .notes
+        .iter()
+        .filter_map(|n| pitch::to_midi(&n.pitch))
+        .collect();
+
+    let spread = match (midi.iter().min(), midi.iter().max()) {
+        (Some(lo), Some(hi)) => ((hi - lo) as f64 / SATURATING_SPREAD).min(1.0),
+        _ => 0.0,
+    };
+    let length = (pattern.length as f64 / SATURATING_LENGTH).clamp(0.0, 1.0);
+    let steps: Vec<i32> = midi.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    let chromaticism = if steps.is_empty() {
+        0.0
+    } else {
+        steps.iter().filter(|&&s| s == 1).count() as f64 / steps.len() as f64
+    };
+
+    Difficulty {
+        score: (spread + length + chromaticism) / 3.0,
+        spread,
+        length,
+        chromaticism,
+    }
+}
+
+/// Markdown checklist of every pattern, ranked by difficulty, with the
+/// measures each occurrence spans.
+pub fn to_practice_plan(result: &AnalysisResult, order: DifficultyOrder) -> String {
+    // Without the score only the first occurrence's measures are known
+    let staff_notes: Vec<_> = result
+        .staves()
+        .into_iter()
+        .map(|staff| occurrences::staff_notes(result, staff))
+        .collect();
+
+    let mut items: Vec<_> = result
+        .staves()
+        .into_iter()
+        .zip(&staff_notes)
+        .flat_map(|(staff, notes)| {
+            staff
+                .patterns
+                .iter()
+                .map(move |p| (staff, notes, p, difficulty(p)))
+        })
+        .collect();
+    items.sort_by(|a, b| {
+        let ordering = a.3.score.total_cmp(&b.3.score);
+        match order {
+            DifficultyOrder::Ascending => ordering,
+            DifficultyOrder::Descending => ordering.reverse(),
+        }
+    });
+
+    let mut plan = format!("# Practice plan: {}\n\n", result.file);
+    plan.push_str(match order {
+        DifficultyOrder::Ascending => "Easiest patterns first.\n\n",
+        DifficultyOrder::Descending => "Hardest patterns first.\n\n",
+    });
+    for (staff, notes, pattern, difficulty) in items {
+        let category = pattern
+            .category
+            .as_deref()
+            .map(|c| format!(" ({})", c))
+            .unwrap_or_default();
+        let spans: Vec<String> = occurrences::spans(pattern, notes, result.measure_map.as_ref())
+            .into_iter()
+            .map(|(start, end)| {
+                if start == end {
+                    format!("m. {}", start)
+                } else {
+                    format!("m. {}–{}", start, end)
+                }
+            })
+            .collect();
+        let pitches: Vec<&str> = pattern.notes.iter().map(|n| n.pitch.as_str()).collect();
+
+        plan.push_str(&format!(
+            "- [ ] **{}, pattern {}**{}: difficulty {:.2}, {} notes, {}x\n",
+            staff.part_name, pattern.id, category, difficulty.score, pattern.length, pattern.count
+        ));
+        plan.push_str(&format!("  - Measures: {}\n", spans.join(", ")));
+        plan.push_str(&format!("  - Notes: `{}`\n", pitches.join(" ")));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, StaffPatternData};
+
+    fn pattern(id: i32, pitches: &[&str], positions: Vec<i32>) -> Pattern {
+        Pattern {
+            id,
+            length: pitches.len() as i32,
+            count: positions.len() as i32,
+            positions,
+            notes: pitches
+                .iter()
+                .enumerate()
+                .map(|(i, p)| NoteLocator {
+                    index: i as i32,
+                    measure: 1,
+                    pitch: p.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn result(patterns: Vec<Pattern>) -> AnalysisResult {
+        AnalysisResult {
+            file: "etude.musicxml".to_string(),
+            parts: vec![StaffPatternData {
+                part_name: "Piano".to_string(),
+                patterns,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chromatic_leaps_rank_above_repeated_notes() {
+        let easy = pattern(0, &["C4", "C4", "C4"], vec![0, 3]);
+        let hard = pattern(1, &["C4", "C#4", "D4", "C5", "B4"], vec![6, 11]);
+        assert!(difficulty(&hard).score > difficulty(&easy).score);
+
+        let plan = to_practice_plan(&result(vec![easy, hard]), DifficultyOrder::Descending);
+        let first = plan.find("pattern 1").unwrap();
+        let second = plan.find("pattern 0").unwrap();
+        assert!(first < second);
+
+        let plan = to_practice_plan(
+            &result(vec![pattern(0, &["C4", "C4"], vec![0, 2])]),
+            DifficultyOrder::Ascending,
+        );
+        assert!(plan.contains("- [ ] **Piano, pattern 0**"));
+    }
+
+    #[test]
+    fn lists_measures_of_every_occurrence_from_score() {
+        let mut result = result(vec![pattern(0, &["C4", "D4"], vec![0, 2])]);
+        result.musicxml_content = r#"<score-partwise><part id="P1">
+<measure number="1"><note><pitch><step>C</step><octave>4</octave></pitch></note>
+<note><pitch><step>D</step><octave>4</octave></pitch></note></measure>
+<measure number="2"><note><rest/></note><note><pitch><step>C</step><octave>4</octave></pitch></note></measure>
+<measure number="3"><note><pitch><step>D</step><octave>4</octave></pitch></note></measure>
+</part></score-partwise>"#
+            .to_string();
+
+        let plan = to_practice_plan(&result, DifficultyOrder::Descending);
+        assert!(plan.contains("  - Measures: m. 1, m. 2–3\n"));
+    }
+}
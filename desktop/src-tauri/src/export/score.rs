@@ -0,0 +1,163 @@
+//! The score with chosen pattern occurrences colored, as MusicXML or
+//! rendered to SVG/PDF by MuseScore.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::musicxml::highlight::{self, NoteColors};
+
+/// Where MuseScore is looked for when `SMRH_MUSESCORE` isn't set, before
+/// falling back to `mscore` on the `PATH`.
+const MUSESCORE_PATHS: &[&str] = &[
+    "/Applications/MuseScore 4.app/Contents/MacOS/mscore",
+    "C:\\Program Files\\MuseScore 4\\bin\\MuseScore4.exe",
+    "/usr/bin/mscore",
+    "/usr/bin/musescore",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreFormat {
+    #[default]
+    Musicxml,
+    Svg,
+    Pdf,
+}
+
+impl ScoreFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ScoreFormat::Musicxml => "musicxml",
+            ScoreFormat::Svg => "svg",
+            ScoreFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Name of the save dialog's file filter.
+    pub fn description(self) -> &'static str {
+        match self {
+            ScoreFormat::Musicxml => "MusicXML",
+            ScoreFormat::Svg => "SVG image",
+            ScoreFormat::Pdf => "PDF document",
+        }
+    }
+
+    /// Whether MuseScore has to render the colored MusicXML.
+    pub fn needs_render(self) -> bool {
+        self != ScoreFormat::Musicxml
+    }
+}
+
+/// Occurrences of one pattern to color: `length` notes from each position
+/// in the stream `part_index`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Highlight {
+    pub part_index: i32,
+    pub pattern_id: i32,
+    pub positions: Vec<i32>,
+    pub length: i32,
+    /// Defaults to the pattern's color in the app.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+pub fn note_colors(highlights: &[Highlight]) -> NoteColors {
+    let mut colors = NoteColors::new();
+    for h in highlights {
+        let color = h
+            .color
+            .clone()
+            .unwrap_or_else(|| highlight::pattern_color(h.pattern_id).to_string());
+        for &pos in &h.positions {
+            for index in pos..pos + h.length {
+                colors.insert((h.part_index, index), color.clone());
+            }
+        }
+    }
+    colors
+}
+
+/// `xml` with the notes of `highlights` colored.
+pub fn colored_score(xml: &str, highlights: &[Highlight]) -> Result<String, String> {
+    highlight::highlight(xml, &note_colors(highlights))
+}
+
+/// `<stem>-highlighted.<ext>`, offered as the save dialog's file name.
+pub fn default_file_name(path: &Path, format: ScoreFormat) -> String {
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "score".into(), |s| s.to_string_lossy());
+    format!("{}-highlighted.{}", stem, format.extension())
+}
+
+/// The MuseScore executable: `SMRH_MUSESCORE`, the first install found in
+/// `MUSESCORE_PATHS`, otherwise `mscore`.
+pub fn musescore() -> PathBuf {
+    if let Some(path) = std::env::var_os("SMRH_MUSESCORE") {
+        return PathBuf::from(path);
+    }
+    MUSESCORE_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+        .unwrap_or_else(|| PathBuf::from("mscore"))
+}
+
+/// Arguments converting `input` to `output`, the format following from its
+/// extension. MuseScore writes one SVG per page, numbering them from
+/// `<stem>-1.svg`.
+pub fn render_args(input: &Path, output: &Path) -> Vec<String> {
+    vec![
+        "-o".to_string(),
+        output.to_string_lossy().into_owned(),
+        input.to_string_lossy().into_owned(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCORE: &str = r#"<score-partwise><part id="P1"><measure number="1">
+<note><pitch><step>C</step><octave>4</octave></pitch></note>
+<note><pitch><step>D</step><octave>4</octave></pitch></note>
+<note><pitch><step>E</step><octave>4</octave></pitch></note>
+<note><pitch><step>F</step><octave>4</octave></pitch></note>
+</measure></part></score-partwise>"#;
+
+    #[test]
+    fn colors_each_occurrence() {
+        let highlights = [
+            Highlight {
+                part_index: 0,
+                pattern_id: 1,
+                positions: vec![0, 2],
+                length: 1,
+                color: None,
+            },
+            Highlight {
+                part_index: 0,
+                pattern_id: 2,
+                positions: vec![1],
+                length: 1,
+                color: Some("#FF0000".to_string()),
+            },
+        ];
+        let out = colored_score(SCORE, &highlights).unwrap();
+        let default = format!(r#"color="{}""#, highlight::pattern_color(1));
+        assert_eq!(out.matches(&default).count(), 2);
+        assert!(out.contains(r##"<note color="#FF0000"><pitch><step>D"##));
+        assert!(out.contains("<note><pitch><step>F"));
+    }
+
+    #[test]
+    fn file_name_and_render_args_follow_format() {
+        let name = default_file_name(Path::new("/scores/minuet.mxl"), ScoreFormat::Pdf);
+        assert_eq!(name, "minuet-highlighted.pdf");
+        assert!(!ScoreFormat::Musicxml.needs_render());
+
+        let args = render_args(Path::new("/tmp/in.musicxml"), Path::new("/out/a.svg"));
+        assert_eq!(args, vec!["-o", "/out/a.svg", "/tmp/in.musicxml"]);
+    }
+}
@@ -0,0 +1,322 @@
+//! Reading files for the webview. A command the webview can call must not
+//! read whatever path a script passes it, so only scores the user chose are
+//! readable: picked in `open_score`'s dialog, dropped on the window, opened
+//! with the app from the OS, in a folder picked in `pick_folder`'s dialog,
+//! or restored from a project they opened. The grants persist so the last
+//! file reopens on the next start.
+//!
+//! Every command that reads a score at a path it is given checks it with
+//! `FileScope::check` first, and `analyze_folder` checks its folder with
+//! `FileScope::check_folder`. The exceptions read no score: the exports and
+//! `export_logs` write to where the user chose in a save dialog (as does
+//! `save_project`, which checks the score it embeds), `remove_recent` and
+//! `unwatch_file` only compare the path, and `load_project` reads a
+//! project, which isn't a score.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Serialize;
+
+use crate::error::AnalyzeError;
+use crate::folder;
+use crate::musicxml::mxl;
+
+/// Largest file read in one piece; bigger ones have to be read with
+/// `read_file_chunked`.
+pub const MAX_READ_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Largest piece `read_file_chunked` returns at once.
+pub const MAX_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Granted paths remembered, the least recently granted dropped first.
+const MAX_GRANTS: usize = 200;
+
+/// Formats that are never text, so they are always sent as base64.
+const BINARY_EXTENSIONS: &[&str] = &["mxl", "mid", "midi", "pdf", "png", "jpg", "jpeg"];
+
+pub struct FileScope {
+    path: PathBuf,
+    granted: Mutex<Vec<PathBuf>>,
+}
+
+/// A whole file, as text when it is text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "encoding", rename_all = "snake_case")]
+pub enum FileContents {
+    Text { text: String },
+    Base64 { data: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileChunk {
+    pub offset: u64,
+    /// Bytes in `data`, before encoding.
+    pub length: usize,
+    /// Size of the whole file.
+    pub total: u64,
+    pub data: String,
+    /// Whether this chunk reaches the end of the file.
+    pub done: bool,
+}
+
+impl FileScope {
+    /// The grants stored at `path`, none when the file is missing or can't
+    /// be read (with a warning).
+    pub fn load(path: PathBuf) -> Self {
+        let granted = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable file grants {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        FileScope {
+            path,
+            granted: Mutex::new(granted),
+        }
+    }
+
+    /// Let the webview read `path`, which the user chose, or every score
+    /// under it when it's a folder.
+    pub fn grant(&self, path: &Path) -> Result<(), String> {
+        let path = std::fs::canonicalize(path)
+            .map_err(|e| format!("Failed to resolve {}: {}", path.display(), e))?;
+        let mut granted = self.granted.lock().unwrap();
+        granted.retain(|p| *p != path);
+        granted.insert(0, path);
+        granted.truncate(MAX_GRANTS);
+        let json = serde_json::to_string(&*granted)
+            .map_err(|e| format!("Failed to serialize file grants: {}", e))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to save file grants: {}", e))?;
+        }
+        std::fs::write(&self.path, json).map_err(|e| format!("Failed to save file grants: {}", e))
+    }
+
+    /// `path` resolved, if it is a score the user granted.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, AnalyzeError> {
+        let resolved = std::fs::canonicalize(path).map_err(|e| AnalyzeError::io("read file", e))?;
+        if !folder::is_supported(&resolved) {
+            return Err(AnalyzeError::UnsupportedFormat {
+                message: format!("Not a score: {}", path.display()),
+            });
+        }
+        if !self.is_granted(&resolved) {
+            return Err(AnalyzeError::Forbidden {
+                message: format!(
+                    "{} wasn't opened by the user; open it from the app first",
+                    path.display()
+                ),
+            });
+        }
+        Ok(resolved)
+    }
+
+    /// `path` resolved, if it is a folder the user granted or one in it.
+    pub fn check_folder(&self, path: &Path) -> Result<PathBuf, AnalyzeError> {
+        let resolved =
+            std::fs::canonicalize(path).map_err(|e| AnalyzeError::io("read folder", e))?;
+        if !resolved.is_dir() || !self.is_granted(&resolved) {
+            return Err(AnalyzeError::Forbidden {
+                message: format!(
+                    "{} wasn't picked by the user; pick it from the app first",
+                    path.display()
+                ),
+            });
+        }
+        Ok(resolved)
+    }
+
+    /// Whether `resolved` was granted or is in a granted folder. A file
+    /// starts with no path but its own, so one test covers both.
+    fn is_granted(&self, resolved: &Path) -> bool {
+        self.granted
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|granted| resolved.starts_with(granted))
+    }
+
+    /// `mxl::read_score` of `path`, if it is a score the user granted.
+    pub fn read_score(&self, path: &Path) -> Result<String, AnalyzeError> {
+        mxl::read_score(&self.check(path)?).map_err(AnalyzeError::from)
+    }
+}
+
+/// Refuse a file over `MAX_READ_BYTES`.
+pub fn check_size(path: &Path) -> Result<u64, AnalyzeError> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| AnalyzeError::io("read file", e))?
+        .len();
+    if size > MAX_READ_BYTES {
+        return Err(AnalyzeError::FileTooLarge {
+            message: format!(
+                "{} is {} MB, over the {} MB read limit; use read_file_chunked",
+                path.display(),
+                size / (1024 * 1024),
+                MAX_READ_BYTES / (1024 * 1024)
+            ),
+        });
+    }
+    Ok(size)
+}
+
+/// The whole of `path` (already checked), as text unless it's a binary
+/// format or isn't UTF-8.
+pub fn read_contents(path: &Path) -> Result<FileContents, AnalyzeError> {
+    check_size(path)?;
+    let bytes = std::fs::read(path).map_err(|e| AnalyzeError::io("read file", e))?;
+    let binary = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| BINARY_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    let bytes = if binary {
+        bytes
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => return Ok(FileContents::Text { text }),
+            Err(e) => e.into_bytes(),
+        }
+    };
+    Ok(FileContents::Base64 {
+        data: STANDARD.encode(bytes),
+    })
+}
+
+/// Up to `length` bytes of `path` (already checked) from `offset`, at most
+/// `MAX_CHUNK_BYTES`.
+pub fn read_chunk(path: &Path, offset: u64, length: usize) -> Result<FileChunk, AnalyzeError> {
+    let mut file = File::open(path).map_err(|e| AnalyzeError::io("read file", e))?;
+    let total = file
+        .metadata()
+        .map_err(|e| AnalyzeError::io("read file", e))?
+        .len();
+    let length = length
+        .min(MAX_CHUNK_BYTES)
+        .min(total.saturating_sub(offset) as usize);
+    let mut buffer = vec![0; length];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut buffer))
+        .map_err(|e| AnalyzeError::io("read file", e))?;
+    Ok(FileChunk {
+        offset,
+        length,
+        total,
+        data: STANDARD.encode(&buffer),
+        done: offset + length as u64 >= total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_granted_scores_are_readable() {
+        let dir = std::env::temp_dir().join(format!("smrh-files-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let score = dir.join("sonata.musicxml");
+        std::fs::write(&score, "<score-partwise/>").unwrap();
+        let secret = dir.join("notes.txt");
+        std::fs::write(&secret, "secret").unwrap();
+        let grants = dir.join("scope").join("grants.json");
+
+        let scope = FileScope::load(grants.clone());
+        assert!(matches!(
+            scope.check(&score),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+        // As the commands reading a score do, such as `list_parts`
+        assert!(matches!(
+            scope.read_score(&score),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+        scope.grant(&score).unwrap();
+        scope.grant(&secret).unwrap();
+        assert!(matches!(
+            scope.check(&secret),
+            Err(AnalyzeError::UnsupportedFormat { .. })
+        ));
+
+        // Grants survive a restart, and a path reaching the score another
+        // way resolves to it
+        let scope = FileScope::load(grants);
+        let resolved = scope.check(&dir.join(".").join("sonata.musicxml")).unwrap();
+        assert_eq!(scope.read_score(&score).unwrap(), "<score-partwise/>");
+        assert_eq!(
+            read_contents(&resolved).unwrap(),
+            FileContents::Text {
+                text: "<score-partwise/>".to_string()
+            }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_granted_folder_grants_the_scores_in_it() {
+        let dir = std::env::temp_dir().join(format!("smrh-files-dir-{}", std::process::id()));
+        let picked = dir.join("picked");
+        std::fs::create_dir_all(picked.join("nested")).unwrap();
+        std::fs::create_dir_all(dir.join("other")).unwrap();
+        let nested = picked.join("nested").join("etude.mxl");
+        std::fs::write(&nested, "").unwrap();
+        let outside = dir.join("other").join("etude.mxl");
+        std::fs::write(&outside, "").unwrap();
+
+        let scope = FileScope::load(dir.join("grants.json"));
+        assert!(matches!(
+            scope.check_folder(&picked),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+        scope.grant(&picked).unwrap();
+        assert!(scope.check_folder(&picked).is_ok());
+        assert!(scope.check_folder(&picked.join("nested")).is_ok());
+        assert!(scope.check(&nested).is_ok());
+        assert!(matches!(
+            scope.check(&outside),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+        assert!(matches!(
+            scope.check_folder(&dir),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+        // A granted score isn't a folder to analyze
+        scope.grant(&outside).unwrap();
+        assert!(matches!(
+            scope.check_folder(&outside),
+            Err(AnalyzeError::Forbidden { .. })
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn binary_files_are_base64_and_chunked() {
+        let dir = std::env::temp_dir().join(format!("smrh-files-bin-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let midi = dir.join("song.mid");
+        std::fs::write(&midi, b"MThd\x00\x00\x00\x06").unwrap();
+
+        assert_eq!(
+            read_contents(&midi).unwrap(),
+            FileContents::Base64 {
+                data: STANDARD.encode(b"MThd\x00\x00\x00\x06")
+            }
+        );
+        let first = read_chunk(&midi, 0, 5).unwrap();
+        assert_eq!((first.length, first.total, first.done), (5, 8, false));
+        let rest = read_chunk(&midi, 5, 100).unwrap();
+        assert_eq!(rest.data, STANDARD.encode(b"\x00\x00\x06"));
+        assert!(rest.done);
+        assert_eq!(read_chunk(&midi, 20, 4).unwrap().length, 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
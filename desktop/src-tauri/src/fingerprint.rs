@@ -0,0 +1,132 @@
+//! Fingerprints for spotting near-duplicate scores: re-exports, editions
+//! with a few notes changed, or the same piece in another key.
+//!
+//! A fingerprint is a 64-bit SimHash of the score's melodic intervals taken
+//! three at a time within each staff, so absolute pitch, layout and
+//! everything but the notes are ignored. Similar scores differ in few bits.
+
+use std::collections::HashMap;
+
+use crate::musicxml;
+use crate::pitch;
+
+/// Intervals per feature.
+const GRAM: usize = 3;
+const BITS: u32 = 64;
+
+/// Fingerprint of a score as 16 hex digits.
+pub fn fingerprint(xml: &str) -> Result<String, String> {
+    let mut features: HashMap<Vec<i32>, i64> = HashMap::new();
+    for stream in musicxml::stream_notes(xml)? {
+        let midi: Vec<i32> = stream
+            .iter()
+            .filter_map(|n| pitch::to_midi(&n.pitch))
+            .collect();
+        let intervals: Vec<i32> = midi.windows(2).map(|w| w[1] - w[0]).collect();
+        for gram in intervals.windows(GRAM) {
+            *features.entry(gram.to_vec()).or_default() += 1;
+        }
+    }
+
+    let mut weights = [0i64; BITS as usize];
+    for (gram, count) in &features {
+        let hash = fnv1a(gram);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += count;
+            } else {
+                *weight -= count;
+            }
+        }
+    }
+    let simhash = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, &w)| w > 0)
+        .fold(0u64, |hash, (bit, _)| hash | 1 << bit);
+    Ok(format!("{:016x}", simhash))
+}
+
+/// Share of bits two fingerprints agree on, from 0 to 1. Unrelated scores
+/// land around 0.5.
+pub fn similarity(a: &str, b: &str) -> Result<f64, String> {
+    let parse = |f: &str| {
+        u64::from_str_radix(f.trim(), 16).map_err(|e| format!("Invalid fingerprint {}: {}", f, e))
+    };
+    let differing = (parse(a)? ^ parse(b)?).count_ones();
+    Ok(1.0 - f64::from(differing) / f64::from(BITS))
+}
+
+/// FNV-1a, which unlike std's hasher is the same on every build.
+fn fnv1a(gram: &[i32]) -> u64 {
+    gram.iter()
+        .flat_map(|i| i.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quarter notes at the given MIDI numbers, written with sharps.
+    fn score(midi: &[i32]) -> String {
+        const STEPS: [(&str, i32); 12] = [
+            ("C", 0),
+            ("C", 1),
+            ("D", 0),
+            ("D", 1),
+            ("E", 0),
+            ("F", 0),
+            ("F", 1),
+            ("G", 0),
+            ("G", 1),
+            ("A", 0),
+            ("A", 1),
+            ("B", 0),
+        ];
+        let notes: String = midi
+            .iter()
+            .map(|&m| {
+                let (step, alter) = STEPS[m.rem_euclid(12) as usize];
+                format!(
+                    "<note><pitch><step>{}</step><alter>{}</alter><octave>{}</octave></pitch>\
+                     <duration>1</duration></note>",
+                    step,
+                    alter,
+                    m / 12 - 1
+                )
+            })
+            .collect();
+        format!(
+            r#"<score-partwise><part id="P1"><measure number="1">{}</measure></part></score-partwise>"#,
+            notes
+        )
+    }
+
+    const TUNE: [i32; 32] = [
+        60, 62, 64, 65, 67, 65, 64, 62, 60, 64, 67, 72, 71, 69, 67, 65, 64, 65, 67, 69, 71, 72, 67,
+        64, 62, 59, 60, 64, 62, 65, 64, 60,
+    ];
+
+    #[test]
+    fn transposition_matches_and_other_tunes_do_not() {
+        let original = fingerprint(&score(&TUNE)).unwrap();
+        let transposed: Vec<i32> = TUNE.iter().map(|m| m + 5).collect();
+        assert_eq!(fingerprint(&score(&transposed)).unwrap(), original);
+
+        let mut edited = TUNE;
+        edited[20] = 74;
+        let edited = fingerprint(&score(&edited)).unwrap();
+        let other: Vec<i32> = (0..32).map(|i| 48 + (i * 7) % 24).collect();
+        let other = fingerprint(&score(&other)).unwrap();
+
+        let near = similarity(&original, &edited).unwrap();
+        let far = similarity(&original, &other).unwrap();
+        assert!(near > 0.75, "edited copy only {} similar", near);
+        assert!(near > far);
+        assert_eq!(similarity(&original, &original).unwrap(), 1.0);
+        assert!(similarity(&original, "xyz").is_err());
+    }
+}
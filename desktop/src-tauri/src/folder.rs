@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::AnalyzeError;
+use crate::jobs::Stopped;
+use crate::models::AnalysisResult;
+
+/// File types the analyzer accepts.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "pdf", "jpg", "jpeg", "png", "musicxml", "mxl", "mid", "midi",
+];
+
+/// Emitted as `analysis-folder-started` once the folder has been scanned, or
+/// as `analysis-batch-started` when a batch is queued.
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderStarted {
+    pub job_id: u64,
+    pub total: usize,
+}
+
+/// Identifies one file of a folder or batch job in its progress events.
+#[derive(Debug, Clone, Copy)]
+pub struct FileTag {
+    pub job_id: u64,
+    /// Index of the file in the job's list.
+    pub file_id: usize,
+}
+
+/// Emitted as `analysis-item-complete` when one file of a job finishes.
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemComplete {
+    pub job_id: u64,
+    pub file_id: usize,
+    pub path: String,
+    pub result: Option<AnalysisResult>,
+    pub error: Option<AnalyzeError>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FolderSummary {
+    pub job_id: u64,
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Cancelled analyzers that ignored the terminate request and were killed.
+    pub forced_kills: usize,
+}
+
+/// How one file of a folder or batch job ended.
+#[derive(Debug, Clone, Copy)]
+pub enum FileOutcome {
+    Succeeded,
+    Failed,
+    /// Cancelled before its analysis started.
+    Skipped,
+    /// Cancelled while the analyzer was running.
+    Stopped(Stopped),
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| SUPPORTED_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Supported score files in `dir`, sorted, optionally descending into
+/// subdirectories.
+pub fn collect_scores(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let entries = std::fs::read_dir(&current)
+            .map_err(|e| format!("Failed to read folder {}: {}", current.display(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+            } else if is_supported(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
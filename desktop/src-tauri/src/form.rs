@@ -0,0 +1,161 @@
+//! The piece cut into equal sections, with the patterns heard in each, for
+//! reading its form (where material is introduced and where it returns).
+
+use serde::Serialize;
+
+use crate::models::AnalysisResult;
+use crate::musicxml::timing;
+use crate::occurrences::PatternRef;
+use crate::recurrence;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Section {
+    /// First and last measure of the section, in the result's measure frame.
+    pub start_measure: i32,
+    pub end_measure: i32,
+    /// Pattern occurrences starting in the section.
+    pub occurrences: usize,
+    /// Distinct patterns with an occurrence starting in the section.
+    pub patterns: usize,
+    /// Patterns whose first occurrence starts in the section, in staff order.
+    pub introduced: Vec<PatternRef>,
+}
+
+/// Split the measures into `sections` runs of equal length (the earlier ones
+/// one shorter when they don't divide evenly) and place each occurrence in
+/// the section its first measure falls in.
+pub fn section_analysis(result: &AnalysisResult, sections: usize) -> Result<Vec<Section>, String> {
+    let measures = measures(result)?;
+    if sections == 0 || sections > measures.len() {
+        return Err(format!(
+            "Can't split {} measures into {} sections",
+            measures.len(),
+            sections
+        ));
+    }
+    let total = measures.len();
+    let mut split: Vec<Section> = (0..sections)
+        .map(|i| Section {
+            start_measure: measures[i * total / sections],
+            end_measure: measures[(i + 1) * total / sections - 1],
+            occurrences: 0,
+            patterns: 0,
+            introduced: Vec::new(),
+        })
+        .collect();
+
+    for pattern in recurrence::pattern_recurrence_map(result) {
+        let pattern_ref = PatternRef {
+            staff: pattern.staff,
+            pattern_id: pattern.pattern_id,
+        };
+        let mut last_section = None;
+        for (i, start) in pattern.measures.iter().enumerate() {
+            let Some(section) = split.iter().rposition(|s| s.start_measure <= *start) else {
+                continue;
+            };
+            split[section].occurrences += 1;
+            if last_section != Some(section) {
+                split[section].patterns += 1;
+                last_section = Some(section);
+            }
+            if i == 0 {
+                split[section].introduced.push(pattern_ref);
+            }
+        }
+    }
+    Ok(split)
+}
+
+/// Measure numbers in the result's frame, in order: played positions when
+/// measures are reported in the played frame, otherwise the written numbers.
+fn measures(result: &AnalysisResult) -> Result<Vec<i32>, String> {
+    if let Some(map) = &result.measure_map {
+        return Ok((1..=map.played.len() as i32).collect());
+    }
+    let mut measures: Vec<i32> = timing::measure_timings(&result.musicxml_content)?
+        .into_keys()
+        .collect();
+    measures.sort();
+    Ok(measures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    /// Sixteen measures of C D E F.
+    fn score() -> String {
+        let bar: String = "CDEF"
+            .chars()
+            .map(|step| {
+                format!(
+                    "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                     <duration>1</duration></note>",
+                    step
+                )
+            })
+            .collect();
+        let measures: String = (1..=16)
+            .map(|m| format!(r#"<measure number="{}">{}</measure>"#, m, bar))
+            .collect();
+        format!(
+            r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+            measures
+        )
+    }
+
+    /// A one-bar pattern at the given measures.
+    fn pattern(id: i32, measures: &[i32]) -> Pattern {
+        Pattern {
+            id,
+            length: 4,
+            count: measures.len() as i32,
+            positions: measures.iter().map(|m| (m - 1) * 4).collect(),
+            notes: vec![NoteLocator {
+                measure: measures[0],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sixteen_measures_in_four_sections() {
+        let result = AnalysisResult {
+            parts: vec![StaffPatternData {
+                // A theme stated, developed and recapitulated, and a second
+                // idea introduced in the development
+                patterns: vec![pattern(0, &[1, 3, 9, 13]), pattern(1, &[6, 7, 10])],
+                ..Default::default()
+            }],
+            musicxml_content: score(),
+            ..Default::default()
+        };
+
+        let sections = section_analysis(&result, 4).unwrap();
+        let bounds: Vec<(i32, i32)> = sections
+            .iter()
+            .map(|s| (s.start_measure, s.end_measure))
+            .collect();
+        assert_eq!(bounds, vec![(1, 4), (5, 8), (9, 12), (13, 16)]);
+
+        let counts: Vec<(usize, usize)> = sections
+            .iter()
+            .map(|s| (s.occurrences, s.patterns))
+            .collect();
+        assert_eq!(counts, vec![(2, 1), (2, 1), (2, 2), (1, 1)]);
+
+        let theme = PatternRef {
+            staff: 0,
+            pattern_id: 0,
+        };
+        assert_eq!(sections[0].introduced, vec![theme]);
+        assert_eq!(sections[1].introduced[0].pattern_id, 1);
+        assert!(sections[2].introduced.is_empty());
+
+        assert!(section_analysis(&result, 0).is_err());
+        assert!(section_analysis(&result, 17).is_err());
+    }
+}
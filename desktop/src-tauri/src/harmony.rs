@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use crate::models::NoteLocator;
+use crate::pitch;
+
+/// Chord qualities as semitones above the root, with the suffix used in the
+/// chord symbol.
+const CHORDS: &[(&str, &[i32])] = &[
+    ("", &[0, 4, 7]),
+    ("m", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus4", &[0, 5, 7]),
+    ("6", &[0, 4, 7, 9]),
+    ("7", &[0, 4, 7, 10]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+];
+
+/// Chord symbol ("C", "G7", "F#m7") for the pitch classes of a pattern.
+///
+/// The distinct pitch classes must be exactly the tones of a chord in
+/// `CHORDS`, with no extra notes and no missing ones. When they fit several
+/// roots (C6 and Am7 share their notes, as do Cm7 and Eb6) the lowest note
+/// decides, and if it isn't one of those roots the set is ambiguous. No
+/// match, fewer than three pitch classes or an unparseable pitch give None.
+///
+/// The root is spelled as in the score, with `b` for flats ("B-" -> "Bb").
+pub fn infer(notes: &[NoteLocator]) -> Option<String> {
+    // Pitch class -> first spelling seen
+    let mut classes: BTreeMap<i32, pitch::Spelling> = BTreeMap::new();
+    let mut lowest: Option<i32> = None;
+    for note in notes {
+        let spelling = pitch::parse(&note.pitch)?;
+        let midi = pitch::to_midi(&note.pitch)?;
+        classes.entry(midi.rem_euclid(12)).or_insert(spelling);
+        lowest = Some(lowest.map_or(midi, |l| l.min(midi)));
+    }
+    if classes.len() < 3 {
+        return None;
+    }
+
+    let matches: Vec<(i32, String)> = classes
+        .iter()
+        .flat_map(|(&root, spelling)| {
+            let mut intervals: Vec<i32> =
+                classes.keys().map(|c| (c - root).rem_euclid(12)).collect();
+            intervals.sort();
+            CHORDS
+                .iter()
+                .filter(move |(_, tones)| *tones == intervals.as_slice())
+                .map(move |(suffix, _)| (root, format!("{}{}", root_name(spelling), suffix)))
+        })
+        .collect();
+    match matches.as_slice() {
+        [] => None,
+        [(_, symbol)] => Some(symbol.clone()),
+        _ => {
+            let bass = lowest?.rem_euclid(12);
+            matches
+                .into_iter()
+                .find(|(root, _)| *root == bass)
+                .map(|(_, symbol)| symbol)
+        }
+    }
+}
+
+fn root_name(spelling: &pitch::Spelling) -> String {
+    let accidental = if spelling.alter >= 0 { "#" } else { "b" };
+    format!(
+        "{}{}",
+        spelling.step,
+        accidental.repeat(spelling.alter.unsigned_abs() as usize)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(pitches: &[&str]) -> Vec<NoteLocator> {
+        pitches
+            .iter()
+            .map(|p| NoteLocator {
+                pitch: p.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn major_triad_in_any_voicing() {
+        assert_eq!(infer(&notes(&["C4", "E4", "G4"])), Some("C".to_string()));
+        assert_eq!(
+            infer(&notes(&["G3", "C4", "E4", "G4", "C5"])),
+            Some("C".to_string())
+        );
+        assert_eq!(infer(&notes(&["B-3", "D4", "F4"])), Some("Bb".to_string()));
+    }
+
+    #[test]
+    fn dominant_seventh() {
+        assert_eq!(
+            infer(&notes(&["G3", "B3", "D4", "F4"])),
+            Some("G7".to_string())
+        );
+        assert_eq!(
+            infer(&notes(&["C4", "E-4", "G4", "B-4"])),
+            Some("Cm7".to_string())
+        );
+    }
+
+    #[test]
+    fn ambiguous_or_unrecognised_sets() {
+        // C6 and Am7 share their notes; a G in the bass names neither
+        assert_eq!(infer(&notes(&["G3", "C4", "E4", "A4"])), None);
+        assert_eq!(
+            infer(&notes(&["C4", "E4", "G4", "A4"])),
+            Some("C6".to_string())
+        );
+        assert_eq!(
+            infer(&notes(&["A3", "C4", "E4", "G4"])),
+            Some("Am7".to_string())
+        );
+        // A scale fragment, and too few pitch classes
+        assert_eq!(infer(&notes(&["C4", "D4", "E4", "F4"])), None);
+        assert_eq!(infer(&notes(&["C4", "G4", "C5"])), None);
+        assert_eq!(infer(&notes(&["C4", "E4", "X"])), None);
+    }
+}
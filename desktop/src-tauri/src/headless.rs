@@ -0,0 +1,402 @@
+//! Command-line analysis without the GUI, for scripts and servers:
+//!
+//! ```text
+//! music-repetition-highlighter analyze <file> [--json] [--out <path>]
+//!     [--config <config.json>] [--quiet]
+//! ```
+//!
+//! The file goes through the analysis pipeline of `analyze_music`, cache,
+//! native engine, staff splitting, timeouts and `postprocess::apply`
+//! included, with the options saved in the app's settings unless
+//! `--config` names a JSON `AnalyzerConfig`. Progress and the analyzer's
+//! warnings go to stderr. The result goes to stdout, as JSON with `--json`
+//! and as a summary otherwise, and also to `--out` as JSON. The Tauri shell
+//! plugin needs a running app, so the analyzer is started with
+//! `std::process` here (see `process::spawn`).
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::cache::{self, AnalysisCache};
+use crate::config::AnalyzerConfig;
+use crate::error::{self, AnalyzeError};
+use crate::jobs::AnalysisSlots;
+use crate::models::{AnalysisResult, Progress};
+use crate::process::{self, Process};
+use crate::settings::Settings;
+use crate::sidecar::Host;
+use crate::{integrity, postprocess};
+
+/// `identifier` in tauri.conf.json, which names the app's config dir.
+const APP_IDENTIFIER: &str = "com.davidrios.music-repetition-highlighter";
+
+pub const EXIT_OK: i32 = 0;
+/// Any failure without a code of its own.
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+/// The file is missing, unreadable or not a score.
+pub const EXIT_INPUT: i32 = 3;
+/// The analyzer couldn't be started, failed or printed no usable result.
+pub const EXIT_ANALYZER: i32 = 4;
+
+const USAGE: &str = "Usage: music-repetition-highlighter analyze <file> [--json] [--out <path>] \
+                     [--config <config.json>] [--quiet]";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub path: PathBuf,
+    pub json: bool,
+    pub out: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub quiet: bool,
+}
+
+/// The options of `analyze` in `args` (without the program name). None
+/// when the app wasn't started for the command line.
+pub fn parse_args(args: &[String]) -> Option<Result<Options, String>> {
+    let (command, rest) = args.split_first()?;
+    if command != "analyze" {
+        return None;
+    }
+    Some(parse_analyze(rest))
+}
+
+fn parse_analyze(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--quiet" | "-q" => options.quiet = true,
+            "--out" | "-o" => options.out = Some(value(arg)?),
+            "--config" => options.config = Some(value(arg)?),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            _ if path.is_some() => return Err(format!("Unexpected argument {}", arg)),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    options.path = path.ok_or("Missing the file to analyze")?;
+    Ok(options)
+}
+
+/// Run the command line in `args`, returning the process's exit status, or
+/// None when it isn't one and the GUI should start.
+pub fn main(args: &[String]) -> Option<i32> {
+    let parsed = parse_args(args)?;
+    #[cfg(windows)]
+    attach_console();
+    let options = match parsed {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return Some(EXIT_USAGE);
+        }
+    };
+    Some(match analyze(&options) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit_code(&e)
+        }
+    })
+}
+
+pub fn exit_code(error: &AnalyzeError) -> i32 {
+    match error {
+        AnalyzeError::Io { .. }
+        | AnalyzeError::UnsupportedFormat { .. }
+        | AnalyzeError::FileTooLarge { .. }
+        | AnalyzeError::Forbidden { .. } => EXIT_INPUT,
+        AnalyzeError::Spawn { .. }
+        | AnalyzeError::Parse { .. }
+        | AnalyzeError::Sidecar { .. }
+        | AnalyzeError::Timeout { .. } => EXIT_ANALYZER,
+        AnalyzeError::Cancelled { .. } | AnalyzeError::Other { .. } => EXIT_FAILED,
+    }
+}
+
+fn analyze(options: &Options) -> Result<(), AnalyzeError> {
+    let config = match &options.config {
+        Some(path) => {
+            let json =
+                std::fs::read_to_string(path).map_err(|e| AnalyzeError::io("read config", e))?;
+            serde_json::from_str(&json).map_err(|e| format!("Invalid config: {}", e))?
+        }
+        None => match app_dir(AppDir::Config) {
+            Some(dir) => Settings::load(dir.join("settings.json")).get(),
+            None => AnalyzerConfig::default(),
+        },
+    };
+
+    error::check_score(&options.path)?;
+    log_to_stderr();
+    let console = Console {
+        quiet: options.quiet,
+        slots: AnalysisSlots::for_this_machine(),
+    };
+    let path = options.path.to_string_lossy();
+    let mut result = tauri::async_runtime::block_on(async {
+        match app_dir(AppDir::Data) {
+            Some(dir) => {
+                let cache = AnalysisCache::new(dir.join("analysis-cache"));
+                let key = crate::cache_key(&cache::content_hash(&options.path)?, &path, &config);
+                crate::analyze_through_cache(&console, &cache, &key, &path, &config, None, None)
+                    .await
+                    .map(|(result, _)| result)
+            }
+            None => crate::run_analyzer(&console, &path, &config, None, None).await,
+        }
+    })?;
+    postprocess::apply(&mut result, &config)?;
+
+    let json =
+        serde_json::to_string(&result).map_err(|e| format!("Failed to serialize result: {}", e))?;
+    if let Some(out) = &options.out {
+        std::fs::write(out, &json).map_err(|e| AnalyzeError::io("write results", e))?;
+    }
+    let printed = if options.json { json } else { summary(&result) };
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", printed).map_err(|e| AnalyzeError::io("write results", e))
+}
+
+/// Runs the analyzer next to this executable, printing its progress.
+#[derive(Clone)]
+struct Console {
+    quiet: bool,
+    slots: AnalysisSlots,
+}
+
+impl Host for Console {
+    fn candidates(&self) -> Vec<PathBuf> {
+        integrity::candidates(None, integrity::exe_dir())
+    }
+
+    fn spawn(&self, analyzer: &Path, args: &[String]) -> Result<Process, AnalyzeError> {
+        process::spawn(analyzer, args).map_err(|e| AnalyzeError::Spawn {
+            message: format!("Failed to spawn analyzer {}: {}", analyzer.display(), e),
+        })
+    }
+
+    fn emit_progress(&self, progress: &Progress) {
+        if !self.quiet {
+            eprintln!("{}", progress_line(progress));
+        }
+    }
+
+    fn slots(&self) -> AnalysisSlots {
+        self.slots.clone()
+    }
+}
+
+/// The analyzer's stderr lines other than progress, which the app logs
+/// (see `sidecar::run`), and warnings, printed to stderr as they are.
+fn log_to_stderr() {
+    let printed = Targets::new()
+        .with_target("analyzer", Level::INFO)
+        .with_default(Level::WARN);
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .without_time()
+        .with_level(false)
+        .with_target(false);
+    let _ = tracing_subscriber::registry()
+        .with(layer)
+        .with(printed)
+        .try_init();
+}
+
+/// Give `println!` the console of the shell that started the app, which a
+/// release build on Windows, a GUI program, doesn't get by itself.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // SAFETY: AttachConsole has no memory-safety preconditions; it fails
+    // harmlessly when there is no parent console or one is attached
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// `[analyzing] 3/8 Finding repeats in Flute`
+fn progress_line(progress: &Progress) -> String {
+    let count = if progress.determinate {
+        format!(" {}/{}", progress.current, progress.total)
+    } else {
+        String::new()
+    };
+    format!(
+        "[{}]{} {}",
+        progress.stage.as_str(),
+        count,
+        progress.message
+    )
+    .trim_end()
+    .to_string()
+}
+
+/// A line per analyzed staff with its pattern count.
+fn summary(result: &AnalysisResult) -> String {
+    let mut lines = vec![format!(
+        "{}: {} patterns",
+        result.file,
+        postprocess::pattern_count(result)
+    )];
+    for part in &result.parts {
+        let longest = part.patterns.iter().map(|p| p.length).max().unwrap_or(0);
+        lines.push(format!(
+            "  {}: {} patterns, longest {} notes",
+            part.part_name,
+            part.patterns.len(),
+            longest
+        ));
+    }
+    lines.join("\n")
+}
+
+/// The app's directories `app_dir` finds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AppDir {
+    /// Settings, as Tauri's `app_config_dir`.
+    Config,
+    /// The analysis cache among others, as Tauri's `app_data_dir`.
+    Data,
+}
+
+/// Where Tauri puts the app's `kind` of directory.
+fn app_dir(kind: AppDir) -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|h| h.join("Library").join("Application Support"))
+    } else {
+        let (variable, default) = match kind {
+            AppDir::Config => ("XDG_CONFIG_HOME", ".config"),
+            AppDir::Data => ("XDG_DATA_HOME", ".local/share"),
+        };
+        std::env::var_os(variable)
+            .map(PathBuf::from)
+            .or_else(|| home().map(|h| h.join(default)))
+    };
+    base.map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData, Stage};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_analyze_and_leaves_other_launches_to_the_gui() {
+        assert_eq!(parse_args(&args(&["score.musicxml"])), None);
+        assert_eq!(parse_args(&[]), None);
+
+        let options = parse_args(&args(&[
+            "analyze", "bach.mxl", "--json", "--out", "out.json", "-q",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            options,
+            Options {
+                path: PathBuf::from("bach.mxl"),
+                json: true,
+                out: Some(PathBuf::from("out.json")),
+                config: None,
+                quiet: true,
+            }
+        );
+
+        let error = |a: &[&str]| parse_args(&args(a)).unwrap().unwrap_err();
+        assert_eq!(error(&["analyze"]), "Missing the file to analyze");
+        assert_eq!(error(&["analyze", "a.mxl", "--out"]), "--out needs a value");
+        assert_eq!(
+            error(&["analyze", "a.mxl", "--tempo"]),
+            "Unknown option --tempo"
+        );
+        assert_eq!(main(&args(&["analyze"])), Some(EXIT_USAGE));
+    }
+
+    #[test]
+    fn failures_exit_by_kind() {
+        let missing = error::check_score(Path::new("/nonexistent/a.musicxml")).unwrap_err();
+        assert_eq!(exit_code(&missing), EXIT_INPUT);
+        let crashed = AnalyzeError::Sidecar {
+            message: "boom".to_string(),
+            stderr: Vec::new(),
+            exit_code: Some(1),
+        };
+        assert_eq!(exit_code(&crashed), EXIT_ANALYZER);
+        assert_eq!(
+            main(&args(&[
+                "analyze",
+                "/nonexistent/a.musicxml",
+                "--config",
+                "/nonexistent.json"
+            ])),
+            Some(EXIT_INPUT)
+        );
+    }
+
+    #[test]
+    fn progress_and_summary_lines() {
+        let progress = Progress {
+            progress_type: "progress".to_string(),
+            stage: Stage::Analyze,
+            current: 3,
+            total: 8,
+            message: "Finding repeats in Flute".to_string(),
+            fraction: None,
+            determinate: false,
+            job_id: None,
+            file_id: None,
+        };
+        assert_eq!(
+            progress_line(&progress),
+            "[analyzing] Finding repeats in Flute"
+        );
+        assert_eq!(
+            progress_line(&progress.with_derived()),
+            "[analyzing] 3/8 Finding repeats in Flute"
+        );
+
+        let result = AnalysisResult {
+            file: "flute.musicxml".to_string(),
+            parts: vec![StaffPatternData {
+                part_name: "Flute".to_string(),
+                patterns: vec![
+                    Pattern {
+                        length: 4,
+                        ..Default::default()
+                    },
+                    Pattern {
+                        length: 6,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            summary(&result),
+            "flute.musicxml: 2 patterns\n  Flute: 2 patterns, longest 6 notes"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Locating the bundled analyzer, and checking it against the checksum
+//! recorded at build time.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Set by build.rs from `binaries/analyzer-<target>`.
+pub const EXPECTED_SHA256: Option<&str> = option_env!("ANALYZER_SHA256");
+
+/// Start of the error for an install missing its analyzer.
+pub const ANALYZER_NOT_FOUND: &str = "analyzer not found in bundle; reinstall";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SidecarIntegrity {
+    pub path: PathBuf,
+    pub sha256: String,
+    pub expected: String,
+    pub matches: bool,
+}
+
+/// Where the analyzer may be installed: the resource dir, then next to the
+/// app's executable (where the shell plugin runs sidecars from).
+pub fn candidates(resource_dir: Option<PathBuf>, exe_dir: Option<PathBuf>) -> Vec<PathBuf> {
+    let name = format!("analyzer{}", std::env::consts::EXE_SUFFIX);
+    resource_dir
+        .into_iter()
+        .chain(exe_dir)
+        .map(|dir| dir.join(&name))
+        .collect()
+}
+
+/// Directory of the running executable.
+pub fn exe_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    exe.parent().map(Path::to_path_buf)
+}
+
+/// The first of `candidates` that exists, or an error listing every path
+/// probed.
+pub fn locate(candidates: &[PathBuf]) -> Result<&PathBuf, String> {
+    candidates.iter().find(|p| p.is_file()).ok_or_else(|| {
+        let probed: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+        format!("{} (looked in: {})", ANALYZER_NOT_FOUND, probed.join(", "))
+    })
+}
+
+/// Hash the first of `candidates` that exists and compare it to `expected`.
+pub fn verify(candidates: &[PathBuf], expected: Option<&str>) -> Result<SidecarIntegrity, String> {
+    let expected = expected.ok_or("No analyzer checksum was recorded in this build")?;
+    let path = locate(candidates)?;
+    let sha256 = sha256_file(path)?;
+    Ok(SidecarIntegrity {
+        path: path.clone(),
+        matches: sha256.eq_ignore_ascii_case(expected),
+        sha256,
+        expected: expected.to_string(),
+    })
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open analyzer: {}", e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read analyzer: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn compares_the_first_existing_candidate() {
+        let dir = std::env::temp_dir().join(format!("smrh-integrity-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let analyzer = dir.join("analyzer");
+        std::fs::write(&analyzer, "abc").unwrap();
+        let candidates = [dir.join("missing"), analyzer.clone()];
+
+        let ok = verify(&candidates, Some(ABC_SHA256)).unwrap();
+        assert_eq!((ok.path, ok.matches), (analyzer.clone(), true));
+
+        std::fs::write(&analyzer, "abd").unwrap();
+        let tampered = verify(&candidates, Some(ABC_SHA256)).unwrap();
+        assert!(!tampered.matches);
+        assert_ne!(tampered.sha256, ABC_SHA256);
+
+        assert!(verify(&candidates, None).is_err());
+        let missing = verify(&candidates[..1], Some(ABC_SHA256)).unwrap_err();
+        assert!(missing.starts_with(ANALYZER_NOT_FOUND));
+        assert!(missing.contains(&candidates[0].display().to_string()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
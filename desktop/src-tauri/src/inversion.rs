@@ -0,0 +1,95 @@
+//! Melodic inversion: a motif restated with every interval turned upside
+//! down, so a rising third becomes a falling one.
+
+use crate::config::InversionMatch;
+use crate::models::{NoteLocator, Pattern};
+use crate::pitch;
+
+/// Steps between consecutive notes, in semitones for an exact match or in
+/// letter-name steps for a tonal one. None when a pitch doesn't parse.
+fn steps(notes: &[NoteLocator], matching: InversionMatch) -> Option<Vec<i32>> {
+    let positions: Option<Vec<i32>> = notes
+        .iter()
+        .map(|n| match matching {
+            InversionMatch::Exact => pitch::to_midi(&n.pitch),
+            InversionMatch::Tonal => pitch::diatonic(&n.pitch),
+        })
+        .collect();
+    Some(positions?.windows(2).map(|w| w[1] - w[0]).collect())
+}
+
+/// Whether `b` moves exactly opposite to `a` at every step. Motifs without
+/// melodic motion (a repeated note) mirror themselves and aren't counted.
+pub fn is_inversion(a: &[NoteLocator], b: &[NoteLocator], matching: InversionMatch) -> bool {
+    let (Some(a), Some(b)) = (steps(a, matching), steps(b, matching)) else {
+        return false;
+    };
+    !a.is_empty()
+        && a.len() == b.len()
+        && a.iter().any(|&s| s != 0)
+        && a.iter().zip(&b).all(|(x, y)| *x == -y)
+}
+
+/// Set `inversion_of` on each pattern that inverts one starting earlier in
+/// the staff, naming the earliest such pattern.
+pub fn link(patterns: &mut [Pattern], matching: InversionMatch) {
+    let start = |p: &Pattern| p.positions.iter().copied().min().unwrap_or(i32::MAX);
+    let mut order: Vec<usize> = (0..patterns.len()).collect();
+    order.sort_by_key(|&i| start(&patterns[i]));
+
+    for (k, &i) in order.iter().enumerate() {
+        let found = order[..k]
+            .iter()
+            .find(|&&j| is_inversion(&patterns[j].notes, &patterns[i].notes, matching))
+            .map(|&j| patterns[j].id);
+        patterns[i].inversion_of = found;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motif(id: i32, position: i32, pitches: &[&str]) -> Pattern {
+        Pattern {
+            id,
+            length: pitches.len() as i32,
+            count: 2,
+            positions: vec![position],
+            notes: pitches
+                .iter()
+                .map(|p| NoteLocator {
+                    pitch: p.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exact_and_tonal_inversions_are_linked() {
+        let mut patterns = vec![
+            motif(0, 0, &["C4", "E4", "G4", "F4"]),
+            // Exact mirror: +4 +3 -2 becomes -4 -3 +2
+            motif(1, 8, &["G4", "E-4", "C4", "D4"]),
+            // Diatonic mirror only: a minor third down where a major one rose
+            motif(2, 16, &["G4", "E4", "C4", "D4"]),
+            motif(3, 24, &["C4", "D4", "E4", "F4"]),
+        ];
+        link(&mut patterns, InversionMatch::Exact);
+        let links: Vec<Option<i32>> = patterns.iter().map(|p| p.inversion_of).collect();
+        assert_eq!(links, vec![None, Some(0), None, None]);
+
+        link(&mut patterns, InversionMatch::Tonal);
+        let links: Vec<Option<i32>> = patterns.iter().map(|p| p.inversion_of).collect();
+        assert_eq!(links, vec![None, Some(0), Some(0), None]);
+
+        let repeated = motif(4, 0, &["C4", "C4", "C4"]);
+        assert!(!is_inversion(
+            &repeated.notes,
+            &repeated.notes,
+            InversionMatch::Exact
+        ));
+    }
+}
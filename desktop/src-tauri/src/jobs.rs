@@ -0,0 +1,104 @@
+//! Shared state for running analyses: a cap on concurrent sidecars and
+//! cancellation flags for single analyses and multi-file jobs.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Limits how many analyzer sidecars run at once. Every sidecar holds a
+/// slot while it runs, each staff's of a split score included (see
+/// `split::schedule`).
+#[derive(Clone)]
+pub struct AnalysisSlots(Arc<Semaphore>);
+
+impl AnalysisSlots {
+    pub fn new(permits: usize) -> Self {
+        AnalysisSlots(Arc::new(Semaphore::new(permits.max(1))))
+    }
+
+    /// One slot per core, capped so a big batch can't starve the machine.
+    pub fn for_this_machine() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(cores.min(4))
+    }
+
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        // The semaphore is never closed, so acquiring can't fail
+        self.0
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("analysis semaphore closed")
+    }
+}
+
+/// Set once when a job is cancelled. Running sidecars wait on it so they
+/// can be stopped mid-analysis rather than only between files.
+#[derive(Default)]
+pub struct CancelFlag {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once `cancel` has been called.
+    pub async fn cancelled(&self) {
+        // Registered before the check so a cancel in between isn't missed
+        let notified = self.notify.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// How a cancelled analysis was stopped.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Stopped {
+    /// The analyzer ignored the terminate request (e.g. stuck in native
+    /// code) and had to be killed.
+    pub forced_kill: bool,
+}
+
+/// Cancellation flags for running analyses and for jobs that span several.
+#[derive(Default)]
+pub struct Jobs {
+    next_id: AtomicU64,
+    cancelled: Mutex<HashMap<u64, Arc<CancelFlag>>>,
+}
+
+impl Jobs {
+    pub fn start(&self) -> (u64, Arc<CancelFlag>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let flag = Arc::new(CancelFlag::default());
+        self.cancelled.lock().unwrap().insert(id, flag.clone());
+        (id, flag)
+    }
+
+    /// Returns false if no such job is running.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.cancelled.lock().unwrap().get(&id) {
+            Some(flag) => {
+                flag.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, id: u64) {
+        self.cancelled.lock().unwrap().remove(&id);
+    }
+}
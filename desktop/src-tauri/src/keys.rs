@@ -0,0 +1,180 @@
+//! Key of a score and the scale degree of each pitch within it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::pitch;
+
+const LETTERS: &str = "CDEFGAB";
+const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [i32; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Major,
+    Minor,
+}
+
+impl Mode {
+    /// Mode named by a `<key><mode>`. Church modes are rare enough to read
+    /// as major.
+    pub fn from_musicxml(mode: Option<&str>) -> Self {
+        match mode {
+            Some("minor") => Mode::Minor,
+            _ => Mode::Major,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    /// Tonic spelled like the analyzer's pitches ("F#", "B-").
+    pub tonic: String,
+    pub mode: Mode,
+    /// Sharps (positive) or flats (negative) in the signature.
+    pub fifths: i32,
+    /// True when guessed from the notes because the score has no `<key>`.
+    pub estimated: bool,
+}
+
+/// Where a pitch sits in a key: degree 1–7 by letter name from the tonic,
+/// and how many semitones its accidental moves it off the key's scale
+/// (F# in C major is degree 4, alteration +1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaleDegree {
+    pub degree: i32,
+    pub alteration: i32,
+}
+
+impl Key {
+    /// The key a `<key><fifths>` signature names.
+    pub fn from_fifths(fifths: i32, mode: Mode) -> Self {
+        // Walk the circle of fifths from C (or A): a fifth is four letters
+        // and seven semitones up
+        let (start_letter, start_class) = match mode {
+            Mode::Major => (0, 0),
+            Mode::Minor => (5, 9),
+        };
+        let letter = (start_letter + fifths * 4).rem_euclid(7) as usize;
+        let step = LETTERS.as_bytes()[letter] as char;
+        let natural = MAJOR_SCALE[letter];
+        let pitch_class = (start_class + fifths * 7).rem_euclid(12);
+        let alter = ((pitch_class - natural + 6).rem_euclid(12)) - 6;
+        let accidental = if alter >= 0 { "#" } else { "-" };
+        Key {
+            tonic: format!(
+                "{}{}",
+                step,
+                accidental.repeat(alter.unsigned_abs() as usize)
+            ),
+            mode,
+            fifths,
+            estimated: false,
+        }
+    }
+
+    /// Major key signature (-7 to 7 fifths) whose scale holds the most of
+    /// `pitches`, fewer accidentals winning ties. None without a readable
+    /// pitch. A relative minor can't be told apart this way.
+    pub fn estimate<'a>(pitches: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut histogram = [0usize; 12];
+        let mut any = false;
+        for midi in pitches.into_iter().filter_map(pitch::to_midi) {
+            histogram[midi.rem_euclid(12) as usize] += 1;
+            any = true;
+        }
+        if !any {
+            return None;
+        }
+
+        let in_scale = |fifths: i32| -> usize {
+            let tonic = (fifths * 7).rem_euclid(12);
+            MAJOR_SCALE
+                .iter()
+                .map(|step| histogram[((tonic + step) % 12) as usize])
+                .sum()
+        };
+        let fifths = (-7..=7)
+            .max_by_key(|&f: &i32| (in_scale(f), std::cmp::Reverse(f.abs())))
+            .unwrap_or(0);
+        Some(Key {
+            estimated: true,
+            ..Key::from_fifths(fifths, Mode::Major)
+        })
+    }
+
+    /// Scale degree of a pitch name, None when it doesn't parse.
+    pub fn scale_degree(&self, name: &str) -> Option<ScaleDegree> {
+        let spelling = pitch::parse(name)?;
+        let tonic = pitch::parse(&format!("{}0", self.tonic))?;
+        let letter = |c: char| LETTERS.find(c).map(|i| i as i32);
+        let steps = (letter(spelling.step)? - letter(tonic.step)?).rem_euclid(7);
+
+        let scale = match self.mode {
+            Mode::Major => MAJOR_SCALE,
+            Mode::Minor => MINOR_SCALE,
+        };
+        let tonic_class = pitch::to_midi(&format!("{}0", self.tonic))?.rem_euclid(12);
+        let expected = (tonic_class + scale[steps as usize]).rem_euclid(12);
+        let actual = pitch::to_midi(name)?.rem_euclid(12);
+        Some(ScaleDegree {
+            degree: steps + 1,
+            alteration: (actual - expected + 6).rem_euclid(12) - 6,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn degree(key: &Key, name: &str) -> (i32, i32) {
+        let d = key.scale_degree(name).unwrap();
+        (d.degree, d.alteration)
+    }
+
+    #[test]
+    fn degrees_in_c_major() {
+        let key = Key::from_fifths(0, Mode::Major);
+        assert_eq!(key.tonic, "C");
+        assert_eq!(degree(&key, "C4"), (1, 0));
+        assert_eq!(degree(&key, "G5"), (5, 0));
+        assert_eq!(degree(&key, "B3"), (7, 0));
+        assert_eq!(degree(&key, "F#4"), (4, 1));
+        assert_eq!(degree(&key, "B-4"), (7, -1));
+    }
+
+    #[test]
+    fn degrees_in_sharp_keys() {
+        let key = Key::from_fifths(2, Mode::Major);
+        assert_eq!(key.tonic, "D");
+        assert_eq!(degree(&key, "F#4"), (3, 0));
+        assert_eq!(degree(&key, "C#5"), (7, 0));
+        assert_eq!(degree(&key, "C5"), (7, -1));
+
+        let key = Key::from_fifths(3, Mode::Minor);
+        assert_eq!(key.tonic, "F#");
+        assert_eq!(degree(&key, "A4"), (3, 0));
+        assert_eq!(degree(&key, "E#4"), (7, 1));
+        assert_eq!(Key::from_fifths(-3, Mode::Major).tonic, "E-");
+    }
+
+    #[test]
+    fn estimates_key_from_notes() {
+        let key = Key::estimate(["G4", "A4", "B4", "C5", "D5", "F#5", "G5"]).unwrap();
+        assert_eq!(
+            (key.tonic.as_str(), key.fifths, key.estimated),
+            ("G", 1, true)
+        );
+        assert_eq!(Key::estimate(["C4", "E4", "G4"]).unwrap().fifths, 0);
+        assert_eq!(Key::estimate([]), None);
+    }
+}
@@ -1,143 +1,1548 @@
-use serde::{Deserialize, Serialize};
-use tauri_plugin_shell::ShellExt;
-use tauri_plugin_shell::process::CommandEvent;
+mod cache;
+mod classify;
+mod compare;
+mod config;
+mod db;
+mod density;
+mod diagnostics;
+mod displacement;
+mod download;
+mod error;
+mod export;
+mod files;
+mod fingerprint;
+mod folder;
+mod form;
+mod harmony;
+mod headless;
+mod integrity;
+mod inversion;
+mod jobs;
+mod keys;
+mod library;
+mod line_buffer;
+mod logging;
+mod loops;
+mod metrics;
+mod models;
+mod motif;
+mod musicxml;
+mod native;
+mod occurrences;
+mod open;
+mod output;
+mod packed;
+mod pitch;
+mod playback;
+mod postprocess;
+mod prefetch;
+mod process;
+mod project;
+mod recent;
+mod recurrence;
+mod runs;
+mod scaling;
+mod sequences;
+mod settings;
+mod sidecar;
+mod split;
+mod stream;
+mod token;
+mod warnings;
+mod watch;
+
+pub use config::AnalyzerConfig;
+pub use models::*;
+
+use std::path::Path;
+use std::sync::Arc;
+
 use tauri::{Emitter, Manager};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NoteLocator {
-    pub index: i32,
-    pub measure: i32,
-    pub beat: Option<f64>,
-    pub pitch: String,
+use error::AnalyzeError;
+
+/// Message of the `file_too_large` error `analyze_music` returns for a file
+/// over the size threshold, so the frontend can ask before retrying with
+/// `confirm_large`.
+pub const FILE_TOO_LARGE: &str = "file too large, pass confirm_large";
+
+/// Analyze a file. Its analysis id, for `cancel_analysis`, is sent in an
+/// `analyze-started` event before the analyzer runs.
+#[tauri::command]
+async fn analyze_music(
+    app: tauri::AppHandle,
+    path: String,
+    config: Option<AnalyzerConfig>,
+    confirm_large: Option<bool>,
+) -> Result<AnalysisResult, AnalyzeError> {
+    app.state::<files::FileScope>().check(Path::new(&path))?;
+    let config = app.state::<settings::Settings>().or_saved(config);
+    check_file_size(&app, &path, &config, confirm_large.unwrap_or(false))?;
+
+    let jobs = app.state::<jobs::Jobs>();
+    let (analysis_id, cancel) = jobs.start();
+    let _ = app.emit(
+        "analyze-started",
+        AnalysisStarted {
+            analysis_id,
+            path: path.clone(),
+        },
+    );
+    let outcome = analyze_cached(&app, &path, &config, &cancel, None).await;
+    jobs.finish(analysis_id);
+    if cancel.is_cancelled() {
+        emit_cancelled(&app);
+        return Err(AnalyzeError::cancelled());
+    }
+
+    let mut result = outcome?;
+    postprocess::apply(&mut result, &config)?;
+    if let Err(e) = recent::record_analysis(&app.state::<db::PatternDb>(), &path, &result) {
+        tracing::warn!("Failed to record recent file: {}", e);
+    }
+    emit_complete(&app, &result);
+    Ok(result)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Pattern {
-    pub id: i32,
-    pub length: i32,
-    pub count: i32,
-    pub positions: Vec<i32>,
-    pub notes: Vec<NoteLocator>,
+/// `analyze_music` for very large scores: the result is sent in
+/// `analysis-chunk` events (see `stream::Chunk`) as the analyzer prints it,
+/// each staff followed by its patterns in batches and the remaining fields
+/// last, and only a summary is returned. A result that doesn't come from a
+/// single analyzer run (a cached one, the native engine's or a score split
+/// by staff) is sent in the same chunks once it is ready. The chunks are the
+/// result before the Rust-side options; `reprocess_result` applies those.
+#[tauri::command]
+async fn analyze_music_streamed(
+    app: tauri::AppHandle,
+    path: String,
+    config: Option<AnalyzerConfig>,
+    confirm_large: Option<bool>,
+) -> Result<stream::StreamSummary, AnalyzeError> {
+    app.state::<files::FileScope>().check(Path::new(&path))?;
+    let config = app.state::<settings::Settings>().or_saved(config);
+    check_file_size(&app, &path, &config, confirm_large.unwrap_or(false))?;
+
+    let jobs = app.state::<jobs::Jobs>();
+    let (analysis_id, cancel) = jobs.start();
+    let _ = app.emit(
+        "analyze-started",
+        AnalysisStarted {
+            analysis_id,
+            path: path.clone(),
+        },
+    );
+    let sent = std::sync::atomic::AtomicUsize::new(0);
+    let forward = |chunk: &stream::Chunk| {
+        sent.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let _ = app.emit(stream::CHUNK_EVENT, chunk);
+    };
+    let outcome = analyze_cached(&app, &path, &config, &cancel, Some(&forward)).await;
+    jobs.finish(analysis_id);
+    if cancel.is_cancelled() {
+        emit_cancelled(&app);
+        return Err(AnalyzeError::cancelled());
+    }
+
+    let mut result = outcome?;
+    let mut summary = stream::summary(&result, stream::PATTERN_BATCH);
+    if sent.load(std::sync::atomic::Ordering::Relaxed) == 0 {
+        for chunk in stream::chunks(result.clone(), stream::PATTERN_BATCH) {
+            forward(&chunk);
+        }
+    }
+    summary.chunks = sent.into_inner();
+
+    postprocess::apply(&mut result, &config)?;
+    if let Err(e) = recent::record_analysis(&app.state::<db::PatternDb>(), &path, &result) {
+        tracing::warn!("Failed to record recent file: {}", e);
+    }
+    emit_complete(&app, &result);
+    Ok(summary)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct StaffPatternData {
-    pub part_index: i32,
-    pub part_name: String,
-    pub patterns: Vec<Pattern>,
+/// Stop an `analyze_music` call, killing its analyzer if it is running. The
+/// call then fails with a `cancelled` error after a final `cancelled`
+/// progress event. Returns false if the analysis has already finished.
+#[tauri::command]
+fn cancel_analysis(jobs: tauri::State<'_, jobs::Jobs>, analysis_id: u64) -> bool {
+    jobs.cancel(analysis_id)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AnalysisResult {
-    pub file: String,
-    pub treble: StaffPatternData,
-    pub bass: StaffPatternData,
-    pub musicxml_content: String
+/// `analyze_music` returning MessagePack bytes (an `ArrayBuffer` on the
+/// frontend, see `utils/msgpack.ts`) instead of JSON, for large scores.
+#[tauri::command]
+async fn analyze_music_packed(
+    app: tauri::AppHandle,
+    path: String,
+    config: Option<AnalyzerConfig>,
+    confirm_large: Option<bool>,
+) -> Result<tauri::ipc::Response, AnalyzeError> {
+    let result = analyze_music(app, path, config, confirm_large).await?;
+    Ok(tauri::ipc::Response::new(packed::to_msgpack(&result)?))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AnalysisError {
-    pub error: String,
+/// Start analyzing a file in the background so a later `analyze_music` of
+/// it is served from the cache. Returns a job id for `await_prefetch` and
+/// `cancel_prefetch`.
+#[tauri::command]
+async fn prefetch_analysis(
+    app: tauri::AppHandle,
+    path: String,
+    config: Option<AnalyzerConfig>,
+) -> Result<u64, AnalyzeError> {
+    app.state::<files::FileScope>().check(Path::new(&path))?;
+    let config = app.state::<settings::Settings>().or_saved(config);
+    let hash = app.state::<cache::FileHashes>().hash(Path::new(&path))?;
+    let key = cache_key(&hash, &path, &config);
+    let (job_id, cancel, status) = app.state::<prefetch::Prefetches>().start(key.clone());
+
+    tauri::async_runtime::spawn(async move {
+        let outcome = prefetch_into_cache(&app, &path, &config, &key, &cancel).await;
+        let _ = status.send(Some(outcome));
+        app.state::<prefetch::Prefetches>().finish(job_id);
+    });
+    Ok(job_id)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Progress {
-    #[serde(rename = "type")]
-    pub progress_type: String,
-    pub stage: String,
-    pub current: i32,
-    pub total: i32,
-    pub message: String,
+#[tauri::command]
+async fn await_prefetch(
+    prefetches: tauri::State<'_, prefetch::Prefetches>,
+    job_id: u64,
+) -> Result<prefetch::PrefetchStatus, AnalyzeError> {
+    prefetches
+        .wait(job_id)
+        .await
+        .ok_or_else(|| AnalyzeError::from(format!("No prefetch job {}", job_id)))
 }
 
 #[tauri::command]
-async fn analyze_music(app: tauri::AppHandle, path: String) -> Result<AnalysisResult, String> {
-    // Debug: print resource path
-    if let Ok(resource_dir) = app.path().resource_dir() {
-        eprintln!("Resource dir: {:?}", resource_dir);
+fn cancel_prefetch(prefetches: tauri::State<'_, prefetch::Prefetches>, job_id: u64) -> bool {
+    prefetches.cancel(job_id)
+}
+
+async fn prefetch_into_cache(
+    app: &tauri::AppHandle,
+    path: &str,
+    config: &AnalyzerConfig,
+    key: &str,
+    cancel: &jobs::CancelFlag,
+) -> prefetch::PrefetchStatus {
+    let cache = app.state::<cache::AnalysisCache>();
+    if cache.get(key).is_some() {
+        return prefetch::PrefetchStatus::Ready;
     }
 
-    let sidecar = app
-        .shell()
-        .sidecar("analyzer")
-        .map_err(|e| format!("Failed to create sidecar: {}", e))?
-        .args([&path]);
-
-    eprintln!("Sidecar created, attempting to spawn...");
-
-    let (mut rx, _child) = sidecar
-        .spawn()
-        .map_err(|e| format!("Failed to spawn sidecar: {} (path: {})", e, path))?;
-
-    let mut stdout_buffer = String::new();
-    let mut stderr_lines: Vec<String> = Vec::new();
-    let mut exit_code: Option<i32> = None;
-
-    while let Some(event) = rx.recv().await {
-        match event {
-            CommandEvent::Stderr(line_bytes) => {
-                let line = String::from_utf8_lossy(&line_bytes);
-                // Try to parse as progress JSON
-                if let Ok(progress) = serde_json::from_str::<Progress>(&line) {
-                    let _ = app.emit("analyze-progress", &progress);
+    if cancel.is_cancelled() {
+        return prefetch::PrefetchStatus::Cancelled;
+    }
+    let outcome = match run_analyzer(app, path, config, Some(cancel), None).await {
+        Err(e) if e.is_cancelled() => return prefetch::PrefetchStatus::Cancelled,
+        Ok(mut result) => {
+            result.progress_log.clear();
+            cache.put(key, &result).map_err(AnalyzeError::from)
+        }
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(()) => prefetch::PrefetchStatus::Ready,
+        Err(error) => prefetch::PrefetchStatus::Failed { error },
+    }
+}
+
+#[tauri::command]
+async fn analyze_music_url(
+    app: tauri::AppHandle,
+    url: String,
+    config: Option<AnalyzerConfig>,
+) -> Result<AnalysisResult, AnalyzeError> {
+    let config = app.state::<settings::Settings>().or_saved(config);
+    let download = download::fetch_score(&url).await?;
+
+    let mut result = run_analyzer(
+        &app,
+        &download.path().to_string_lossy(),
+        &config,
+        None,
+        None,
+    )
+    .await?;
+    result.file = url;
+    postprocess::apply(&mut result, &config)?;
+    emit_complete(&app, &result);
+    Ok(result)
+}
+
+/// Analyze only measures `start_measure..=end_measure` of a MusicXML file,
+/// reporting the result in the full score's measure and note numbering.
+#[tauri::command]
+async fn analyze_selection(
+    app: tauri::AppHandle,
+    path: String,
+    start_measure: i32,
+    end_measure: i32,
+    config: Option<AnalyzerConfig>,
+) -> Result<AnalysisResult, AnalyzeError> {
+    let config = app.state::<settings::Settings>().or_saved(config);
+    let xml = app
+        .state::<files::FileScope>()
+        .read_score(Path::new(&path))?;
+    let excerpt = musicxml::excerpt::excerpt(&xml, start_measure, end_measure)?;
+    let temp = download::TempScore::write("selection", "musicxml", excerpt.xml.as_bytes())?;
+
+    let mut result =
+        run_analyzer(&app, &temp.path().to_string_lossy(), &config, None, None).await?;
+    musicxml::excerpt::restore(&mut result, &excerpt, &xml)?;
+    result.file = path;
+    postprocess::apply(&mut result, &config)?;
+    emit_complete(&app, &result);
+    Ok(result)
+}
+
+/// Analyze every supported score in a folder picked with `pick_folder`,
+/// emitting each result as an `analysis-item-complete` event as soon as it
+/// finishes.
+#[tauri::command]
+async fn analyze_folder(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, jobs::Jobs>,
+    dir: String,
+    recursive: Option<bool>,
+    config: Option<AnalyzerConfig>,
+) -> Result<folder::FolderSummary, AnalyzeError> {
+    let dir = app
+        .state::<files::FileScope>()
+        .check_folder(Path::new(&dir))?;
+    let files = folder::collect_scores(&dir, recursive.unwrap_or(false))?
+        .into_iter()
+        .map(|file| file.to_string_lossy().into_owned())
+        .collect();
+    let config = app.state::<settings::Settings>().or_saved(config);
+    Ok(run_job(&app, &jobs, "analysis-folder-started", files, &config, None).await)
+}
+
+/// Analyze a list of scores as one job, up to `concurrency` at a time (and
+/// never more than the machine's analysis slots). Progress events carry the
+/// job id and the file's index in `paths`; each result is emitted as an
+/// `analysis-item-complete` event as soon as it finishes. Cancel with
+/// `cancel_folder_analysis`.
+#[tauri::command]
+async fn analyze_batch(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, jobs::Jobs>,
+    paths: Vec<String>,
+    concurrency: Option<usize>,
+    config: Option<AnalyzerConfig>,
+) -> Result<folder::FolderSummary, AnalyzeError> {
+    let scope = app.state::<files::FileScope>();
+    for path in &paths {
+        scope.check(Path::new(path))?;
+    }
+    let config = app.state::<settings::Settings>().or_saved(config);
+    let started = "analysis-batch-started";
+    Ok(run_job(&app, &jobs, started, paths, &config, concurrency).await)
+}
+
+/// Run a folder or batch job over `files`, announcing it with
+/// `started_event`.
+async fn run_job(
+    app: &tauri::AppHandle,
+    jobs: &jobs::Jobs,
+    started_event: &str,
+    files: Vec<String>,
+    config: &AnalyzerConfig,
+    concurrency: Option<usize>,
+) -> folder::FolderSummary {
+    let (job_id, cancelled) = jobs.start();
+    let _ = app.emit(
+        started_event,
+        folder::FolderStarted {
+            job_id,
+            total: files.len(),
+        },
+    );
+    let limit = concurrency.map(|n| Arc::new(tokio::sync::Semaphore::new(n.max(1))));
+
+    let tasks: Vec<_> = files
+        .into_iter()
+        .enumerate()
+        .map(|(file_id, path)| {
+            let app = app.clone();
+            let config = config.clone();
+            let cancelled = cancelled.clone();
+            let limit = limit.clone();
+            tauri::async_runtime::spawn(async move {
+                let _job_slot = match limit {
+                    Some(limit) => Some(limit.acquire_owned().await),
+                    None => None,
+                };
+                if cancelled.is_cancelled() {
+                    return folder::FileOutcome::Skipped;
+                }
+
+                let tag = folder::FileTag { job_id, file_id };
+                let output =
+                    sidecar::run(&app, &path, &config, Some(&cancelled), Some(tag), None).await;
+                if let Ok(sidecar::SidecarOutput {
+                    stopped: Some(stopped),
+                    ..
+                }) = output
+                {
+                    return folder::FileOutcome::Stopped(stopped);
+                }
+                let outcome = output.and_then(|mut output| {
+                    let mut result = sidecar::parse_result(&mut output)?;
+                    result.file = path.clone();
+                    if let Ok(hash) = app.state::<cache::FileHashes>().hash(Path::new(&path)) {
+                        index_analysis(&app, &hash, &result, &config, true);
+                    }
+                    if config.include_progress_log {
+                        result.progress_log = output.progress;
+                    }
+                    postprocess::apply(&mut result, &config)?;
+                    Ok(result)
+                });
+                let succeeded = outcome.is_ok();
+                let (result, error) = match outcome {
+                    Ok(result) => (Some(result), None),
+                    Err(e) => (None, Some(e)),
+                };
+                let _ = app.emit(
+                    "analysis-item-complete",
+                    folder::ItemComplete {
+                        job_id,
+                        file_id,
+                        path,
+                        result,
+                        error,
+                    },
+                );
+                if succeeded {
+                    folder::FileOutcome::Succeeded
                 } else {
-                    // Not progress - collect for potential error reporting
-                    stderr_lines.push(line.to_string());
+                    folder::FileOutcome::Failed
+                }
+            })
+        })
+        .collect();
+
+    let mut summary = folder::FolderSummary {
+        job_id,
+        total: tasks.len(),
+        ..Default::default()
+    };
+    for task in tasks {
+        match task.await {
+            Ok(folder::FileOutcome::Succeeded) => summary.succeeded += 1,
+            Ok(folder::FileOutcome::Failed) | Err(_) => summary.failed += 1,
+            Ok(folder::FileOutcome::Skipped) => summary.cancelled += 1,
+            Ok(folder::FileOutcome::Stopped(stopped)) => {
+                summary.cancelled += 1;
+                if stopped.forced_kill {
+                    summary.forced_kills += 1;
                 }
             }
-            CommandEvent::Stdout(line_bytes) => {
-                stdout_buffer.push_str(&String::from_utf8_lossy(&line_bytes));
-                stdout_buffer.push('\n');
-            }
-            CommandEvent::Terminated(payload) => {
-                exit_code = payload.code;
-                break;
-            }
-            CommandEvent::Error(err) => {
-                return Err(format!("Command error: {}", err));
+        }
+    }
+    jobs.finish(job_id);
+    summary
+}
+
+/// Stop a folder or batch job: files not yet started are skipped and running
+/// analyzers are terminated, then killed if they don't exit within a grace
+/// period. The job's command resolves once they have stopped, with forced kills
+/// counted in its summary.
+#[tauri::command]
+fn cancel_folder_analysis(jobs: tauri::State<'_, jobs::Jobs>, job_id: u64) -> bool {
+    jobs.cancel(job_id)
+}
+
+/// Tell the frontend whether the finished analysis found anything, so an
+/// empty result can be shown as "no repetition" rather than a blank view.
+fn emit_complete(app: &tauri::AppHandle, result: &AnalysisResult) {
+    app.state::<playback::Player>().load(result);
+    let event = match postprocess::pattern_count(result) {
+        0 => AnalysisComplete::NoPatterns,
+        count => AnalysisComplete::PatternsFound { count },
+    };
+    let _ = app.emit("analyze-complete", &event);
+}
+
+fn emit_cancelled(app: &tauri::AppHandle) {
+    let progress = Progress {
+        progress_type: "progress".to_string(),
+        stage: Stage::Cancelled,
+        current: 0,
+        total: 0,
+        message: "Analysis cancelled".to_string(),
+        fraction: None,
+        determinate: false,
+        job_id: None,
+        file_id: None,
+    }
+    .with_derived();
+    let _ = app.emit("analyze-progress", &progress);
+}
+
+/// Emit an `open-file-request` for each of `paths`, or queue them for
+/// `take_open_requests` when the frontend isn't listening yet.
+fn request_open(app: &tauri::AppHandle, paths: Vec<std::path::PathBuf>, source: open::OpenSource) {
+    let scope = app.state::<files::FileScope>();
+    let requests = paths
+        .iter()
+        .map(|path| {
+            let request = open::OpenFileRequest::new(path, source);
+            if request.error.is_none() {
+                if let Err(e) = scope.grant(path) {
+                    tracing::warn!("Failed to grant {}: {}", path.display(), e);
+                }
             }
-            _ => {}
+            request
+        })
+        .collect();
+    for request in app.state::<open::OpenRequests>().submit(requests) {
+        let _ = app.emit(open::OPEN_FILE_EVENT, &request);
+    }
+}
+
+/// Warn about a file over the configured size threshold, refusing it with
+/// `FILE_TOO_LARGE` unless the caller confirmed. Smaller files pass silently.
+fn check_file_size(
+    app: &tauri::AppHandle,
+    path: &str,
+    config: &AnalyzerConfig,
+    confirmed: bool,
+) -> Result<(), AnalyzeError> {
+    let Some(threshold) = config.large_file_threshold() else {
+        return Ok(());
+    };
+    let size = std::fs::metadata(path)
+        .map_err(|e| AnalyzeError::io("read file", e))?
+        .len();
+    if size <= threshold {
+        return Ok(());
+    }
+
+    let _ = app.emit(
+        "analyze-large-file",
+        LargeFileWarning {
+            path: path.to_string(),
+            size_bytes: size,
+            threshold_bytes: threshold,
+            confirmed,
+        },
+    );
+    if confirmed {
+        Ok(())
+    } else {
+        Err(AnalyzeError::FileTooLarge {
+            message: FILE_TOO_LARGE.to_string(),
+        })
+    }
+}
+
+/// Analyze a local file, reusing the cached analyzer result when the file
+/// is unchanged (waiting for a prefetch of it if one is running). Chunks
+/// are only forwarded when the analyzer runs.
+async fn analyze_cached(
+    app: &tauri::AppHandle,
+    path: &str,
+    config: &AnalyzerConfig,
+    cancel: &jobs::CancelFlag,
+    forward: Option<&stream::Sink<'_>>,
+) -> Result<AnalysisResult, AnalyzeError> {
+    let hash = app.state::<cache::FileHashes>().hash(Path::new(path))?;
+    let key = cache_key(&hash, path, config);
+    app.state::<prefetch::Prefetches>().wait_for_key(&key).await;
+
+    let cache = app.state::<cache::AnalysisCache>();
+    let (result, cached) =
+        analyze_through_cache(app, &cache, &key, path, config, Some(cancel), forward).await?;
+    index_analysis(app, &hash, &result, config, !cached);
+    Ok(result)
+}
+
+/// The result cached under `key`, or else the analyzer's, which is cached.
+/// Returns whether it was cached.
+async fn analyze_through_cache(
+    host: &impl sidecar::Host,
+    cache: &cache::AnalysisCache,
+    key: &str,
+    path: &str,
+    config: &AnalyzerConfig,
+    cancel: Option<&jobs::CancelFlag>,
+    forward: Option<&stream::Sink<'_>>,
+) -> Result<(AnalysisResult, bool), AnalyzeError> {
+    if let Some(mut result) = cache.get(key) {
+        result.file = path.to_string();
+        let progress = Progress {
+            progress_type: "progress".to_string(),
+            stage: Stage::Cache,
+            current: 1,
+            total: 1,
+            message: "Loaded cached analysis".to_string(),
+            fraction: None,
+            determinate: false,
+            job_id: None,
+            file_id: None,
         }
+        .with_derived();
+        host.emit_progress(&progress);
+        if config.include_progress_log {
+            result.progress_log.push(progress);
+        }
+        return Ok((result, true));
+    }
+
+    if cancel.is_some_and(jobs::CancelFlag::is_cancelled) {
+        return Err(AnalyzeError::cancelled());
+    }
+    let mut result = run_analyzer(host, path, config, cancel, forward).await?;
+    // The log describes this run, not later cache hits
+    let progress_log = std::mem::take(&mut result.progress_log);
+    if let Err(e) = cache.put(key, &result) {
+        tracing::warn!("Failed to cache analysis: {}", e);
+    }
+    result.progress_log = progress_log;
+    Ok((result, false))
+}
+
+/// Store an analyzer result in the pattern database when `index_to_db` is
+/// on. Unless `replace`, a score that is already there is left as it is.
+fn index_analysis(
+    app: &tauri::AppHandle,
+    hash: &str,
+    result: &AnalysisResult,
+    config: &AnalyzerConfig,
+    replace: bool,
+) {
+    if !config.index_to_db {
+        return;
+    }
+    let db = app.state::<db::PatternDb>();
+    let indexed = if replace {
+        Ok(false)
+    } else {
+        db.contains(hash)
+    };
+    if let Err(e) = indexed.and_then(|indexed| match indexed {
+        true => Ok(()),
+        false => db.index(hash, result),
+    }) {
+        tracing::warn!("Failed to index analysis: {}", e);
+    }
+}
+
+/// Cache key for analyzing `path`, kept apart for the native engine as its
+/// results can differ in detail from the sidecar's.
+fn cache_key(hash: &str, path: &str, config: &AnalyzerConfig) -> String {
+    let mut args = config.sidecar_args();
+    if uses_native_engine(path, config) {
+        args.push("native".to_string());
+    }
+    cache::AnalysisCache::key(hash, &args)
+}
+
+fn uses_native_engine(path: &str, config: &AnalyzerConfig) -> bool {
+    config.use_native_engine && native::supports(Path::new(path), config)
+}
+
+/// Run the analyzer on a local file, forwarding progress events and, from
+/// the sidecar, result chunks: the native engine when it's selected and
+/// supports the file, otherwise the sidecar.
+async fn run_analyzer(
+    host: &impl sidecar::Host,
+    path: &str,
+    config: &AnalyzerConfig,
+    cancel: Option<&jobs::CancelFlag>,
+    forward: Option<&stream::Sink<'_>>,
+) -> Result<AnalysisResult, AnalyzeError> {
+    if uses_native_engine(path, config) {
+        error::check_score(Path::new(path))?;
+        let xml = musicxml::mxl::read_score(Path::new(path))?;
+        // Takes a slot as a sidecar would, since it's as heavy
+        let _slot = host.slots().acquire().await;
+        let mut result = native::analyze(&xml, config)?;
+        result.file = path.to_string();
+        return Ok(result);
+    }
+    let mut output = sidecar::run(host, path, config, cancel, None, forward).await?;
+    if output.stopped.is_some() {
+        return Err(AnalyzeError::cancelled());
+    }
+    let mut result = sidecar::parse_result(&mut output)?;
+    // Not the analyzer's, which is a temp file for compressed scores
+    result.file = path.to_string();
+    if config.include_progress_log {
+        result.progress_log = output.progress;
+    }
+    Ok(result)
+}
+
+/// Run the analyzer and return exactly what it printed alongside the parse
+/// outcome. Only available in debug builds or with `SMRH_DEBUG=1`, since the
+/// raw output of a large score is huge.
+#[tauri::command]
+async fn analyze_music_raw(
+    app: tauri::AppHandle,
+    path: String,
+    config: Option<AnalyzerConfig>,
+) -> Result<sidecar::RawAnalysis, AnalyzeError> {
+    if !cfg!(debug_assertions) && std::env::var("SMRH_DEBUG").as_deref() != Ok("1") {
+        return Err(AnalyzeError::from(
+            "Raw analyzer output is disabled; set SMRH_DEBUG=1 to enable it".to_string(),
+        ));
+    }
+
+    app.state::<files::FileScope>().check(Path::new(&path))?;
+    let mut config = app.state::<settings::Settings>().or_saved(config);
+    // One run, so the output is what a single analyzer printed
+    config.max_concurrent_staves = 1;
+    let mut output = sidecar::run(&app, &path, &config, None, None, None).await?;
+    let (result, parse_error) = match sidecar::parse_result(&mut output) {
+        Ok(result) => (Some(result), None),
+        Err(e) => (None, Some(e)),
+    };
+    Ok(sidecar::RawAnalysis {
+        stdout: output.stdout,
+        stderr: output.stderr_lines,
+        exit_code: output.exit_code,
+        result,
+        parse_error,
+    })
+}
+
+/// Re-apply the Rust-side options of `config` to a result returned by an
+/// earlier analysis, without running the analyzer again. Sidecar options in
+/// `config` are ignored; changing those needs a new `analyze_music`.
+#[tauri::command]
+fn reprocess_result(
+    mut result: AnalysisResult,
+    config: AnalyzerConfig,
+) -> Result<AnalysisResult, AnalyzeError> {
+    postprocess::reset(&mut result);
+    postprocess::apply(&mut result, &config)?;
+    Ok(result)
+}
+
+/// Put a file at the top of the recent files, reading its title, composer
+/// and part count if it's MusicXML. `analyze_music` does this too, with the
+/// time of the analysis.
+#[tauri::command]
+fn add_recent(
+    db: tauri::State<'_, db::PatternDb>,
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<recent::RecentFile, AnalyzeError> {
+    scope.check(Path::new(&path))?;
+    recent::add(&db, &path).map_err(AnalyzeError::from)
+}
+
+/// Recently opened files, most recent first.
+#[tauri::command]
+fn get_recent(
+    db: tauri::State<'_, db::PatternDb>,
+) -> Result<Vec<recent::RecentFile>, AnalyzeError> {
+    db.recent_files().map_err(AnalyzeError::from)
+}
+
+/// Drop a file from the recent files, returning whether it was listed.
+#[tauri::command]
+fn remove_recent(db: tauri::State<'_, db::PatternDb>, path: String) -> Result<bool, AnalyzeError> {
+    db.remove_recent(&path).map_err(AnalyzeError::from)
+}
+
+/// Options the analysis commands use when they're called without a config.
+#[tauri::command]
+fn get_settings(settings: tauri::State<'_, settings::Settings>) -> AnalyzerConfig {
+    settings.get()
+}
+
+#[tauri::command]
+fn set_settings(
+    settings: tauri::State<'_, settings::Settings>,
+    config: AnalyzerConfig,
+) -> Result<(), AnalyzeError> {
+    settings.set(config).map_err(AnalyzeError::from)
+}
+
+/// Totals over every cached analysis: pieces, mean repetition score,
+/// pattern categories and patterns per piece.
+#[tauri::command]
+async fn library_stats(
+    cache: tauri::State<'_, cache::AnalysisCache>,
+) -> Result<library::LibraryStats, AnalyzeError> {
+    library::library_stats(&cache).map_err(AnalyzeError::from)
+}
+
+#[tauri::command]
+async fn get_cache_stats(
+    cache: tauri::State<'_, cache::AnalysisCache>,
+) -> Result<cache::CacheStats, AnalyzeError> {
+    cache.stats().map_err(AnalyzeError::from)
+}
+
+/// Delete every cached analysis, returning how many there were.
+#[tauri::command]
+async fn clear_analysis_cache(
+    cache: tauri::State<'_, cache::AnalysisCache>,
+) -> Result<usize, AnalyzeError> {
+    cache.clear().map_err(AnalyzeError::from)
+}
+
+/// Indexed patterns matching every filter in `query`, across all pieces
+/// analyzed with `index_to_db` on.
+#[tauri::command]
+async fn query_patterns(
+    db: tauri::State<'_, db::PatternDb>,
+    query: Option<db::PatternQuery>,
+) -> Result<Vec<db::PatternMatch>, AnalyzeError> {
+    db.query_patterns(&query.unwrap_or_default())
+        .map_err(AnalyzeError::from)
+}
+
+#[tauri::command]
+fn repetition_score(result: AnalysisResult) -> metrics::RepetitionScore {
+    metrics::repetition_score(&result)
+}
+
+#[tauri::command]
+async fn export_bundle(
+    result: AnalysisResult,
+    path: String,
+    options: Option<export::bundle::BundleOptions>,
+) -> Result<export::bundle::BundleManifest, AnalyzeError> {
+    export::bundle::write_bundle(&result, &path, &options.unwrap_or_default())
+        .map_err(AnalyzeError::from)
+}
+
+/// WAV click track sounding each note of a pattern's occurrences, written to
+/// `path`. Plays at `tempo` if given, otherwise at the score's tempo marks.
+#[tauri::command]
+async fn export_click_track(
+    result: AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    tempo: Option<f64>,
+    path: String,
+) -> Result<String, AnalyzeError> {
+    let wav = export::click::to_click_track(&result, staff, pattern_id, tempo)?;
+    std::fs::write(&path, wav).map_err(|e| AnalyzeError::io("write click track", e))?;
+    Ok(path)
+}
+
+/// Copy of the score with each pattern occurrence labeled in a text
+/// direction above its first note, written to `path` for printing.
+#[tauri::command]
+async fn export_annotated_musicxml(
+    result: AnalysisResult,
+    path: String,
+    scheme: Option<musicxml::annotate::LabelScheme>,
+) -> Result<String, AnalyzeError> {
+    let xml = musicxml::annotate::annotate(
+        &result.musicxml_content,
+        &result,
+        scheme.unwrap_or_default(),
+    )?;
+    std::fs::write(&path, xml).map_err(|e| AnalyzeError::io("write score", e))?;
+    Ok(path)
+}
+
+/// Copy of the score at `path` with the `highlights` colored, saved where
+/// the user picks in a save dialog. SVG and PDF are rendered by MuseScore.
+/// Returns the saved path, or `None` when the dialog was cancelled.
+#[tauri::command]
+async fn export_score(
+    app: tauri::AppHandle,
+    path: String,
+    format: export::score::ScoreFormat,
+    highlights: Vec<export::score::Highlight>,
+) -> Result<Option<String>, AnalyzeError> {
+    use tauri_plugin_dialog::DialogExt;
+    use tauri_plugin_shell::ShellExt;
+
+    let source = Path::new(&path);
+    let xml = app.state::<files::FileScope>().read_score(source)?;
+    let colored = export::score::colored_score(&xml, &highlights)?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter(format.description(), &[format.extension()])
+        .set_file_name(export::score::default_file_name(source, format))
+        .save_file(move |picked| {
+            let _ = tx.send(picked);
+        });
+    let Some(picked) = rx.await.ok().flatten() else {
+        return Ok(None);
+    };
+    let output = picked
+        .into_path()
+        .map_err(|e| format!("Failed to resolve save path: {}", e))?;
+
+    if !format.needs_render() {
+        std::fs::write(&output, colored).map_err(|e| AnalyzeError::io("write score", e))?;
+        return Ok(Some(output.to_string_lossy().into_owned()));
     }
 
-    // Check for error JSON in stdout first (Python prints errors to stdout as JSON)
-    if let Ok(err) = serde_json::from_str::<AnalysisError>(&stdout_buffer) {
-        return Err(err.error);
+    let input = download::TempScore::write("export", "musicxml", colored.as_bytes())?;
+    let musescore = export::score::musescore();
+    let rendered = app
+        .shell()
+        .command(&musescore)
+        .args(export::score::render_args(input.path(), &output))
+        .output()
+        .await
+        .map_err(|e| AnalyzeError::Spawn {
+            message: format!("Failed to run MuseScore ({}): {}", musescore.display(), e),
+        })?;
+    if !rendered.status.success() {
+        return Err(AnalyzeError::Sidecar {
+            message: format!("MuseScore failed to render {}", output.display()),
+            stderr: String::from_utf8_lossy(&rendered.stderr)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            exit_code: rendered.status.code(),
+        });
+    }
+    Ok(Some(output.to_string_lossy().into_owned()))
+}
+
+/// GraphViz `.dot` text linking patterns whose occurrences overlap or lie
+/// within `within_measures` (default 1) of each other.
+#[tauri::command]
+fn export_pattern_graph(result: AnalysisResult, within_measures: Option<i32>) -> String {
+    export::graph::to_dot(
+        &result,
+        within_measures.unwrap_or(export::graph::DEFAULT_WITHIN_MEASURES),
+    )
+}
+
+/// Write a standalone HTML report to `path` and return the path.
+#[tauri::command]
+async fn export_html_report(result: AnalysisResult, path: String) -> Result<String, AnalyzeError> {
+    let html = export::html::to_html_report(&result);
+    std::fs::write(&path, html).map_err(|e| AnalyzeError::io("write HTML report", e))?;
+    Ok(path)
+}
+
+/// Minimal MEI document of the score with pattern membership on each note,
+/// written to `path`.
+#[tauri::command]
+async fn export_mei(result: AnalysisResult, path: String) -> Result<String, AnalyzeError> {
+    let mei = export::mei::to_mei(&result)?;
+    std::fs::write(&path, mei).map_err(|e| AnalyzeError::io("write MEI", e))?;
+    Ok(path)
+}
+
+/// A pattern's first occurrence as a LilyPond snippet for copy-pasting.
+#[tauri::command]
+fn export_pattern_lilypond(pattern: Pattern) -> Result<String, AnalyzeError> {
+    export::lilypond::to_lilypond(&pattern).map_err(AnalyzeError::from)
+}
+
+/// Ranked Markdown checklist of the patterns, written to `path`.
+#[tauri::command]
+async fn export_practice_plan(
+    result: AnalysisResult,
+    path: String,
+    order: Option<export::practice::DifficultyOrder>,
+) -> Result<(), AnalyzeError> {
+    let plan = export::practice::to_practice_plan(&result, order.unwrap_or_default());
+    std::fs::write(&path, plan).map_err(|e| AnalyzeError::io("write practice plan", e))
+}
+
+/// Playback order of the written measures in a score, for translating
+/// between the written and played measure frames.
+#[tauri::command]
+async fn get_measure_map(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<musicxml::repeats::MeasureMap, AnalyzeError> {
+    let xml = scope.read_score(Path::new(&path))?;
+    musicxml::repeats::measure_map(&xml).map_err(AnalyzeError::from)
+}
+
+/// Notes of a pattern whose articulations or slurring differ between its
+/// occurrences.
+#[tauri::command]
+fn compare_articulations(
+    result: AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+) -> Result<Vec<occurrences::ArticulationDifference>, AnalyzeError> {
+    occurrences::compare_articulations(&result, staff, pattern_id).map_err(AnalyzeError::from)
+}
+
+/// First note where two occurrences of a pattern differ, None if identical.
+#[tauri::command]
+fn compare_occurrences(
+    result: AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    first: usize,
+    second: usize,
+) -> Result<Option<occurrences::Divergence>, AnalyzeError> {
+    occurrences::compare_occurrences(&result, staff, pattern_id, first, second)
+        .map_err(AnalyzeError::from)
+}
+
+/// Title, parts, length and a time estimate of a score, read natively so
+/// the frontend can preview it while it is analyzed (or before).
+#[tauri::command]
+async fn get_score_metadata(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<musicxml::metadata::ScorePreview, AnalyzeError> {
+    let xml = scope.read_score(Path::new(&path))?;
+    musicxml::metadata::read_preview(&xml).map_err(AnalyzeError::from)
+}
+
+/// The staves of a score, for choosing `AnalyzerConfig.parts`.
+#[tauri::command]
+async fn list_parts(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<Vec<musicxml::parts::PartInfo>, AnalyzeError> {
+    let xml = scope.read_score(Path::new(&path))?;
+    musicxml::parts::list_parts(&xml).map_err(AnalyzeError::from)
+}
+
+/// Measures where the score's meter or key changes, for navigation.
+#[tauri::command]
+async fn structural_markers(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<Vec<musicxml::markers::StructuralMarker>, AnalyzeError> {
+    let xml = scope.read_score(Path::new(&path))?;
+    musicxml::markers::structural_markers(&xml).map_err(AnalyzeError::from)
+}
+
+/// Compares staves `first` and `second` of the result, by default the
+/// first two.
+#[tauri::command]
+fn longest_shared_motif(
+    result: AnalysisResult,
+    first: Option<usize>,
+    second: Option<usize>,
+) -> Option<motif::SharedMotif> {
+    motif::longest_shared_motif(&result, first.unwrap_or(0), second.unwrap_or(1))
+}
+
+/// Passages two scores share, each analyzed (or taken from the cache) first
+/// with the same options.
+#[tauri::command]
+async fn compare_scores(
+    app: tauri::AppHandle,
+    path_a: String,
+    path_b: String,
+    config: Option<AnalyzerConfig>,
+    confirm_large: Option<bool>,
+) -> Result<compare::ComparisonResult, AnalyzeError> {
+    let transposed = app
+        .state::<settings::Settings>()
+        .or_saved(config.clone())
+        .transposition_invariant;
+    let a = analyze_music(app.clone(), path_a, config.clone(), confirm_large).await?;
+    let b = analyze_music(app, path_b, config, confirm_large).await?;
+    compare::compare(&a, &b, transposed).map_err(AnalyzeError::from)
+}
+
+/// Tightest measure range holding `occurrences` (default 2) occurrences of a
+/// pattern, for looped practice.
+#[tauri::command]
+fn suggest_loop_range(
+    result: AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    occurrences: Option<usize>,
+) -> Result<Option<(i32, i32)>, AnalyzeError> {
+    loops::suggest_loop_range(
+        &result,
+        staff,
+        pattern_id,
+        occurrences.unwrap_or(loops::DEFAULT_LOOP_OCCURRENCES),
+    )
+    .map_err(AnalyzeError::from)
+}
+
+/// Measure gaps between consecutive occurrences of every pattern.
+#[tauri::command]
+fn pattern_recurrence_map(result: AnalysisResult) -> Vec<recurrence::PatternRecurrence> {
+    recurrence::pattern_recurrence_map(&result)
+}
+
+/// Pattern notes sounding in each slot of `resolution` quarter notes
+/// (default a sixteenth), for animating alongside playback.
+#[tauri::command]
+fn density_timeline(
+    result: AnalysisResult,
+    resolution: Option<f64>,
+) -> Result<density::DensityTimeline, AnalyzeError> {
+    density::density_timeline(&result, resolution.unwrap_or(density::DEFAULT_RESOLUTION))
+        .map_err(AnalyzeError::from)
+}
+
+/// The piece split into `sections` equal runs of measures, with the
+/// occurrences in each and the patterns it introduces.
+#[tauri::command]
+fn section_analysis(
+    result: AnalysisResult,
+    sections: usize,
+) -> Result<Vec<form::Section>, AnalyzeError> {
+    form::section_analysis(&result, sections).map_err(AnalyzeError::from)
+}
+
+/// Compares staves `first` and `second` of the result, by default the
+/// first two.
+#[tauri::command]
+fn staff_exclusive_patterns(
+    result: AnalysisResult,
+    first: Option<usize>,
+    second: Option<usize>,
+) -> motif::StaffPartition {
+    motif::staff_exclusive_patterns(&result, first.unwrap_or(0), second.unwrap_or(1))
+}
+
+/// Hex SHA-256 of a file's content, remembered until the file changes.
+#[tauri::command]
+async fn file_hash(
+    hashes: tauri::State<'_, cache::FileHashes>,
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<String, AnalyzeError> {
+    hashes
+        .hash(&scope.check(Path::new(&path))?)
+        .map_err(AnalyzeError::from)
+}
+
+/// Fingerprint of a score's melodic intervals, equal for transpositions
+/// and close for near-duplicates (see `fingerprint_similarity`).
+#[tauri::command]
+async fn score_fingerprint(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<String, AnalyzeError> {
+    let xml = scope.read_score(Path::new(&path))?;
+    fingerprint::fingerprint(&xml).map_err(AnalyzeError::from)
+}
+
+/// Share of two fingerprints that agrees, from 0 (opposite) to 1 (same).
+#[tauri::command]
+fn fingerprint_similarity(a: String, b: String) -> Result<f64, AnalyzeError> {
+    fingerprint::similarity(&a, &b).map_err(AnalyzeError::from)
+}
+
+/// Emit `file-changed` with the path whenever the file is saved, once the
+/// save has settled, until the window closes or `unwatch_file` or
+/// `unwatch_all` is called. Returns false when the file is
+/// already watched, so a second call doesn't double the events.
+#[tauri::command]
+fn watch_file(
+    app: tauri::AppHandle,
+    watchers: tauri::State<'_, watch::Watchers>,
+    path: String,
+) -> Result<bool, AnalyzeError> {
+    let path = app.state::<files::FileScope>().check(Path::new(&path))?;
+    let name = path.display().to_string();
+    let watching = watchers.watch(&path, move || {
+        let _ = app.emit(watch::FILE_CHANGED_EVENT, &name);
+    })?;
+    Ok(watching)
+}
+
+/// Stop watching a file, returning false when it wasn't watched.
+#[tauri::command]
+fn unwatch_file(watchers: tauri::State<'_, watch::Watchers>, path: String) -> bool {
+    // A file deleted since it was watched can no longer be canonicalized
+    let path = std::fs::canonicalize(&path).unwrap_or_else(|_| path.into());
+    watchers.unwatch(&path)
+}
+
+/// Stop every file watcher, returning how many were running.
+#[tauri::command]
+fn unwatch_all(watchers: tauri::State<'_, watch::Watchers>) -> usize {
+    watchers.unwatch_all()
+}
+
+/// Play every occurrence of a pattern of the latest analysis, back to back,
+/// on the default output device. `playback-position` events follow it
+/// until it ends or `stop_playback` is called.
+#[tauri::command]
+fn play_pattern(
+    app: tauri::AppHandle,
+    player: tauri::State<'_, playback::Player>,
+    pattern_id: i32,
+    part_index: i32,
+) -> Result<playback::PlaybackStarted, AnalyzeError> {
+    let tones = player.with_result(|r| playback::pattern_tones(r, part_index, pattern_id))?;
+    start_playback(&app, &player, tones)
+}
+
+/// Measures `start_measure..=end_measure` of the latest analysis, as in
+/// `play_pattern`.
+#[tauri::command]
+fn play_range(
+    app: tauri::AppHandle,
+    player: tauri::State<'_, playback::Player>,
+    start_measure: i32,
+    end_measure: i32,
+) -> Result<playback::PlaybackStarted, AnalyzeError> {
+    let tones = player.with_result(|r| playback::range_tones(r, start_measure, end_measure))?;
+    start_playback(&app, &player, tones)
+}
+
+/// Save the score, its analysis, settings and annotations as a project at
+/// `path`, adding the `.smrh` extension if it has none, with a copy of the
+/// score inside when `embed_source` is set. Returns the path written.
+#[tauri::command]
+async fn save_project(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+    state: project::ProjectState,
+    embed_source: Option<bool>,
+) -> Result<String, AnalyzeError> {
+    let embed = embed_source.unwrap_or(false);
+    if embed && state.embedded_source.is_none() {
+        scope.check(Path::new(&state.source_path))?;
+    }
+    let mut path = std::path::PathBuf::from(path);
+    if path.extension().is_none() {
+        path.set_extension(project::PROJECT_EXTENSION);
     }
+    project::save(&path, &state, embed)?;
+    Ok(path.to_string_lossy().into_owned())
+}
 
-    // Check exit code
-    if exit_code != Some(0) {
-        // Filter out Python warnings, keep only actual errors
-        let filtered_stderr: String = stderr_lines
-            .iter()
-            .filter(|line| !line.contains("Warning") && !line.contains("warnings.warn"))
-            .cloned()
-            .collect::<Vec<_>>()
-            .join("\n");
-        let error_msg = if filtered_stderr.trim().is_empty() {
-            format!("Process failed with exit code: {:?}", exit_code)
-        } else {
-            filtered_stderr
-        };
-        return Err(format!("Analyzer failed: {}", error_msg));
+/// Open a project saved by `save_project`. A score that is no longer where
+/// it was is restored from the project's copy, if it has one, into the app
+/// data dir, and the copy becomes readable; a score still in place is only
+/// readable if the user opened it before. Its analysis becomes the one
+/// `play_pattern` and `play_range` play from.
+#[tauri::command]
+async fn load_project(
+    app: tauri::AppHandle,
+    path: String,
+) -> Result<project::ProjectState, AnalyzeError> {
+    let mut state = project::load(Path::new(&path))?;
+    let restore_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to find app data dir: {}", e))?
+        .join("restored-scores");
+    // A project names its score, so opening one mustn't grant the path
+    if project::restore_source(&mut state, &restore_dir)? {
+        if let Err(e) = app
+            .state::<files::FileScope>()
+            .grant(Path::new(&state.source_path))
+        {
+            tracing::warn!("Failed to grant {}: {}", state.source_path, e);
+        }
     }
+    app.state::<playback::Player>().load(&state.result);
+    Ok(state)
+}
 
-    serde_json::from_str::<AnalysisResult>(&stdout_buffer)
-        .map_err(|e| format!("Failed to parse output: {} (got: {:?})", e, stdout_buffer))
+/// Returns false if nothing was playing.
+#[tauri::command]
+fn stop_playback(player: tauri::State<'_, playback::Player>) -> bool {
+    player.stop()
+}
+
+/// Hold the playback where it is until `resume_playback`. Returns false if
+/// nothing was playing.
+#[tauri::command]
+fn pause_playback(player: tauri::State<'_, playback::Player>) -> bool {
+    player.pause()
 }
 
+/// Returns false if nothing was playing.
 #[tauri::command]
-async fn read_file(path: String) -> Result<String, String> {
-    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))
+fn resume_playback(player: tauri::State<'_, playback::Player>) -> bool {
+    player.resume()
+}
+
+fn start_playback(
+    app: &tauri::AppHandle,
+    player: &playback::Player,
+    tones: Vec<playback::Tone>,
+) -> Result<playback::PlaybackStarted, AnalyzeError> {
+    let playing = player.play(player.sink()?, playback::samples(&tones));
+    let started = playback::PlaybackStarted {
+        playback_id: playing.id,
+        seconds: playback::duration(&tones),
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        playback::follow(&playing, &tones, |position| {
+            let _ = app.emit(playback::POSITION_EVENT, position);
+        })
+        .await;
+        app.state::<playback::Player>().finish(playing.id);
+    });
+    Ok(started)
+}
+
+/// Token naming the file's content and the options, for reproducing an
+/// analysis elsewhere.
+#[tauri::command]
+async fn make_analysis_token(
+    hashes: tauri::State<'_, cache::FileHashes>,
+    scope: tauri::State<'_, files::FileScope>,
+    settings: tauri::State<'_, settings::Settings>,
+    path: String,
+    config: Option<AnalyzerConfig>,
+) -> Result<String, AnalyzeError> {
+    token::make(&token::AnalysisToken {
+        content_hash: hashes.hash(&scope.check(Path::new(&path))?)?,
+        config: settings.or_saved(config),
+    })
+    .map_err(AnalyzeError::from)
+}
+
+#[tauri::command]
+fn parse_analysis_token(token: String) -> Result<token::AnalysisToken, AnalyzeError> {
+    token::parse(&token).map_err(AnalyzeError::from)
+}
+
+/// Whether the bundled analyzer is there, intact and runs, with its version
+/// and features, for the troubleshooting panel.
+#[tauri::command]
+async fn check_analyzer(
+    app: tauri::AppHandle,
+) -> Result<diagnostics::DiagnosticReport, AnalyzeError> {
+    Ok(diagnose(&app).await)
+}
+
+async fn diagnose(app: &tauri::AppHandle) -> diagnostics::DiagnosticReport {
+    use tauri_plugin_shell::ShellExt;
+
+    let candidates = integrity::candidates(app.path().resource_dir().ok(), integrity::exe_dir());
+    let mut report =
+        diagnostics::DiagnosticReport::inspect(&candidates, integrity::EXPECTED_SHA256);
+    if report.path.is_none() {
+        return report;
+    }
+    let run = async {
+        let output = app
+            .shell()
+            .sidecar("analyzer")
+            .map_err(|e| format!("Failed to create sidecar: {}", e))?
+            .arg("--version")
+            .output()
+            .await
+            .map_err(|e| format!("Failed to spawn sidecar: {}", e))?;
+        Ok(diagnostics::VersionRun {
+            exit_code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    };
+    let run = tokio::time::timeout(diagnostics::VERSION_TIMEOUT, run)
+        .await
+        .unwrap_or_else(|_| {
+            Err(format!(
+                "Analyzer didn't answer --version within {}s",
+                diagnostics::VERSION_TIMEOUT.as_secs()
+            ))
+        });
+    report.record_version_run(run);
+    report
+}
+
+/// Compare the installed analyzer's SHA-256 with the one recorded when the
+/// app was built, to catch a corrupted or replaced sidecar.
+#[tauri::command]
+async fn verify_sidecar_integrity(
+    app: tauri::AppHandle,
+) -> Result<integrity::SidecarIntegrity, AnalyzeError> {
+    let candidates = integrity::candidates(app.path().resource_dir().ok(), integrity::exe_dir());
+    integrity::verify(&candidates, integrity::EXPECTED_SHA256).map_err(AnalyzeError::from)
+}
+
+/// Files the app was asked to open before the frontend was listening for
+/// `open-file-request`, which it gets directly from now on.
+#[tauri::command]
+fn take_open_requests(
+    requests: tauri::State<'_, open::OpenRequests>,
+) -> Vec<open::OpenFileRequest> {
+    requests.take()
+}
+
+/// The last `lines` lines logged (default `logging::DEFAULT_RECENT_LINES`),
+/// oldest first.
+#[tauri::command]
+async fn get_recent_logs(
+    app: tauri::AppHandle,
+    lines: Option<usize>,
+) -> Result<Vec<String>, AnalyzeError> {
+    let dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| format!("Failed to find log dir: {}", e))?;
+    logging::recent_lines(&dir, lines.unwrap_or(logging::DEFAULT_RECENT_LINES))
+        .map_err(AnalyzeError::from)
+}
+
+/// Every log, oldest first, as one file at `dest_path` to attach to a bug
+/// report. Returns the bytes written.
+#[tauri::command]
+async fn export_logs(app: tauri::AppHandle, dest_path: String) -> Result<u64, AnalyzeError> {
+    let dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| format!("Failed to find log dir: {}", e))?;
+    tracing::info!("Exporting logs to {}", dest_path);
+    logging::export(&dir, Path::new(&dest_path)).map_err(AnalyzeError::from)
+}
+
+/// Pick a score in an open dialog, letting the read commands read it.
+/// Returns its path, or `None` when the dialog was cancelled.
+#[tauri::command]
+async fn open_score(app: tauri::AppHandle) -> Result<Option<String>, AnalyzeError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter("Music Document", folder::SUPPORTED_EXTENSIONS)
+        .pick_file(move |picked| {
+            let _ = tx.send(picked);
+        });
+    let Some(picked) = rx.await.ok().flatten() else {
+        return Ok(None);
+    };
+    let path = picked
+        .into_path()
+        .map_err(|e| format!("Failed to resolve picked file: {}", e))?;
+    app.state::<files::FileScope>().grant(&path)?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// Pick a folder in an open dialog, letting `analyze_folder` analyze it and
+/// the read commands read the scores in it. Returns its path, or `None`
+/// when the dialog was cancelled.
+#[tauri::command]
+async fn pick_folder(app: tauri::AppHandle) -> Result<Option<String>, AnalyzeError> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog().file().pick_folder(move |picked| {
+        let _ = tx.send(picked);
+    });
+    let Some(picked) = rx.await.ok().flatten() else {
+        return Ok(None);
+    };
+    let path = picked
+        .into_path()
+        .map_err(|e| format!("Failed to resolve picked folder: {}", e))?;
+    app.state::<files::FileScope>().grant(&path)?;
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+/// A score the user opened as MusicXML text, decompressed if it's an `.mxl`
+/// and converted if it's MIDI.
+#[tauri::command]
+async fn read_file(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<String, AnalyzeError> {
+    let path = scope.check(Path::new(&path))?;
+    files::check_size(&path)?;
+    musicxml::mxl::read_score(&path).map_err(AnalyzeError::from)
+}
+
+/// A score the user opened as it is on disk: text, or base64 for binary
+/// formats such as PDFs, images and `.mxl`s.
+#[tauri::command]
+async fn read_file_raw(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+) -> Result<files::FileContents, AnalyzeError> {
+    files::read_contents(&scope.check(Path::new(&path))?)
+}
+
+/// Up to `length` bytes (default and at most `files::MAX_CHUNK_BYTES`) of a
+/// score the user opened from `offset`, base64-encoded, for files too big
+/// for `read_file_raw`.
+#[tauri::command]
+async fn read_file_chunked(
+    scope: tauri::State<'_, files::FileScope>,
+    path: String,
+    offset: u64,
+    length: Option<usize>,
+) -> Result<files::FileChunk, AnalyzeError> {
+    let path = scope.check(Path::new(&path))?;
+    files::read_chunk(&path, offset, length.unwrap_or(files::MAX_CHUNK_BYTES))
+}
+
+/// Run `analyze` from the command line without opening a window (see
+/// `headless`). Returns the exit status, or None for a normal launch.
+pub fn run_headless(args: Vec<String>) -> Option<i32> {
+    headless::main(&args)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        // First, so a second launch exits before setting anything up
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            // A score opened from the OS while the app runs
+            let launched = open::launch_paths(argv.into_iter().skip(1), Some(Path::new(&cwd)));
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+            request_open(app, launched, open::OpenSource::Launch);
+        }))
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(jobs::AnalysisSlots::for_this_machine())
+        .manage(jobs::Jobs::default())
+        .manage(prefetch::Prefetches::default())
+        .manage(cache::FileHashes::default())
+        .manage(watch::Watchers::default())
+        .manage(playback::Player::default())
+        .manage(open::OpenRequests::default())
         .setup(|app| {
+            let level = if cfg!(debug_assertions) {
+                tracing::Level::DEBUG
+            } else {
+                tracing::Level::INFO
+            };
+            match logging::init(&app.path().app_log_dir()?, level) {
+                // Logging stops once the guard is dropped, so it lives with the app
+                Ok(guard) => {
+                    app.manage(guard);
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(cache::AnalysisCache::new(data_dir.join("analysis-cache")));
+            let db = db::PatternDb::open(&data_dir.join("patterns.sqlite3")).or_else(|e| {
+                tracing::warn!("{}; indexing to memory instead", e);
+                db::PatternDb::in_memory()
+            })?;
+            app.manage(db);
+            app.manage(files::FileScope::load(data_dir.join("file-scope.json")));
+            let settings_path = app.path().app_config_dir()?.join("settings.json");
+            app.manage(settings::Settings::load(settings_path));
+
+            // A score the OS launched the app to open
+            let cwd = std::env::current_dir().ok();
+            let launched = open::launch_paths(std::env::args().skip(1), cwd.as_deref());
+            request_open(app.handle(), launched, open::OpenSource::Launch);
+
+            // Surface a broken install now rather than at the first analysis
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let report = diagnose(&handle).await;
+                if !report.healthy {
+                    let _ = handle.emit(diagnostics::UNHEALTHY_EVENT, &report);
+                }
+            });
+
             #[cfg(debug_assertions)]
             if let Ok(worktree) = std::env::var("WORKTREE_NAME") {
                 if let Some(window) = app.get_webview_window("main") {
@@ -146,7 +1551,106 @@ pub fn run() {
             }
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![analyze_music, read_file])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .on_window_event(|window, event| match event {
+            // Dropping the watchers ends their notify threads, which would
+            // otherwise outlive the window that asked for them
+            tauri::WindowEvent::Destroyed => {
+                window.state::<watch::Watchers>().unwatch_all();
+            }
+            tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) => {
+                request_open(window.app_handle(), paths.clone(), open::OpenSource::Drop);
+            }
+            _ => {}
+        })
+        .invoke_handler(tauri::generate_handler![
+            add_recent,
+            analyze_batch,
+            analyze_music,
+            analyze_music_url,
+            analyze_music_packed,
+            analyze_music_raw,
+            analyze_music_streamed,
+            analyze_selection,
+            analyze_folder,
+            await_prefetch,
+            cancel_analysis,
+            cancel_folder_analysis,
+            cancel_prefetch,
+            check_analyzer,
+            clear_analysis_cache,
+            compare_articulations,
+            compare_occurrences,
+            compare_scores,
+            density_timeline,
+            export_annotated_musicxml,
+            export_bundle,
+            export_click_track,
+            export_html_report,
+            export_logs,
+            export_mei,
+            export_pattern_graph,
+            export_pattern_lilypond,
+            export_practice_plan,
+            export_score,
+            file_hash,
+            fingerprint_similarity,
+            get_cache_stats,
+            get_measure_map,
+            get_recent,
+            get_recent_logs,
+            get_score_metadata,
+            get_settings,
+            library_stats,
+            list_parts,
+            load_project,
+            longest_shared_motif,
+            make_analysis_token,
+            open_score,
+            parse_analysis_token,
+            pattern_recurrence_map,
+            pause_playback,
+            pick_folder,
+            play_pattern,
+            play_range,
+            prefetch_analysis,
+            query_patterns,
+            read_file,
+            read_file_chunked,
+            read_file_raw,
+            remove_recent,
+            repetition_score,
+            reprocess_result,
+            resume_playback,
+            save_project,
+            score_fingerprint,
+            section_analysis,
+            set_settings,
+            staff_exclusive_patterns,
+            stop_playback,
+            structural_markers,
+            suggest_loop_range,
+            take_open_requests,
+            unwatch_all,
+            unwatch_file,
+            verify_sidecar_integrity,
+            watch_file
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            // Stops the watcher threads of a window that didn't close cleanly
+            tauri::RunEvent::Exit => {
+                app.state::<watch::Watchers>().unwatch_all();
+            }
+            // macOS opens associated files through an event, not arguments
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            tauri::RunEvent::Opened { urls } => {
+                let paths = urls
+                    .iter()
+                    .filter_map(|url| url.to_file_path().ok())
+                    .collect();
+                request_open(app, paths, open::OpenSource::Association);
+            }
+            _ => {}
+        });
 }
@@ -0,0 +1,143 @@
+//! Aggregate figures over every analysis in the cache, treating it as a
+//! small corpus of the pieces the user has opened.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::cache::AnalysisCache;
+use crate::classify;
+use crate::metrics;
+use crate::models::AnalysisResult;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LibraryStats {
+    /// Distinct scores, however many option sets each was analyzed with.
+    pub pieces: usize,
+    /// Cache entries that couldn't be read.
+    pub skipped: usize,
+    /// Mean `repetition_score` over the pieces, 0 with none.
+    pub average_score: f64,
+    /// Melodic categories with how many patterns fall in each, most common
+    /// first. Uncategorized patterns aren't counted.
+    pub categories: Vec<(String, usize)>,
+    /// Patterns per piece, with how many pieces have that many, ascending.
+    pub pattern_counts: Vec<(usize, usize)>,
+}
+
+/// Cached results by content hash, and the number of unreadable entries. A
+/// score cached under several option sets is taken from whichever entry is
+/// read first.
+fn pieces(cache: &AnalysisCache) -> Result<(HashMap<String, AnalysisResult>, usize), String> {
+    let (entries, skipped) = cache.entries()?;
+    let mut pieces: HashMap<String, AnalysisResult> = HashMap::new();
+    for (key, result) in entries {
+        // Keys are the content hash, then "-<flags hash>" if any flags
+        let content = key.split('-').next().unwrap_or(&key).to_string();
+        pieces.entry(content).or_insert(result);
+    }
+    Ok((pieces, skipped))
+}
+
+/// Stats over the cache's entries, each score counted once.
+pub fn library_stats(cache: &AnalysisCache) -> Result<LibraryStats, String> {
+    let (pieces, skipped) = pieces(cache)?;
+    Ok(summarize(pieces.values(), skipped))
+}
+
+fn summarize<'a>(pieces: impl Iterator<Item = &'a AnalysisResult>, skipped: usize) -> LibraryStats {
+    let mut count = 0;
+    let mut total_score = 0.0;
+    let mut categories: HashMap<String, usize> = HashMap::new();
+    let mut pattern_counts: BTreeMap<usize, usize> = BTreeMap::new();
+
+    for result in pieces {
+        count += 1;
+        total_score += metrics::repetition_score(result).score;
+        let patterns: Vec<_> = result
+            .staves()
+            .into_iter()
+            .flat_map(|s| &s.patterns)
+            .collect();
+        *pattern_counts.entry(patterns.len()).or_default() += 1;
+        for pattern in patterns {
+            // Cached results are stored before classification
+            let category = pattern
+                .category
+                .clone()
+                .or_else(|| classify::classify(&pattern.notes).map(str::to_string));
+            if let Some(category) = category {
+                *categories.entry(category).or_default() += 1;
+            }
+        }
+    }
+
+    let mut categories: Vec<(String, usize)> = categories.into_iter().collect();
+    categories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    LibraryStats {
+        pieces: count,
+        skipped,
+        average_score: if count == 0 {
+            0.0
+        } else {
+            total_score / count as f64
+        },
+        categories,
+        pattern_counts: pattern_counts.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    fn scale_pattern(id: i32) -> Pattern {
+        let notes = ["C4", "D4", "E4", "F4"]
+            .iter()
+            .map(|pitch| NoteLocator {
+                pitch: pitch.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        Pattern {
+            id,
+            length: 4,
+            count: 2,
+            positions: vec![0, 4],
+            notes,
+            ..Default::default()
+        }
+    }
+
+    fn result(patterns: Vec<Pattern>) -> AnalysisResult {
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn aggregates_the_cached_pieces() {
+        let dir = std::env::temp_dir().join(format!("smrh-library-test-{}", std::process::id()));
+        let cache = AnalysisCache::new(dir.clone());
+        let a = "a".repeat(64);
+        let scales = result(vec![scale_pattern(0), scale_pattern(1)]);
+        cache.put(&a, &scales).unwrap();
+        // The same score analyzed with other flags
+        cache.put(&format!("{}-0123abcd", a), &scales).unwrap();
+        cache.put(&"b".repeat(64), &result(vec![])).unwrap();
+        std::fs::write(dir.join(format!("{}.json", "c".repeat(64))), "{not json").unwrap();
+
+        let stats = library_stats(&cache).unwrap();
+        assert_eq!((stats.pieces, stats.skipped), (2, 1));
+        assert_eq!(stats.categories, vec![("scale".to_string(), 2)]);
+        assert_eq!(stats.pattern_counts, vec![(0, 1), (2, 1)]);
+        assert!(stats.average_score > 0.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
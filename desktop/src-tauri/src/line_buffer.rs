@@ -0,0 +1,69 @@
+/// Reassembles lines from raw output chunks. Bytes are only decoded once a
+/// whole line has arrived, so a multi-byte character split across two chunks
+/// (an accidental like "F♯", a composer's name) survives intact.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Add a chunk and return every line it completed, without line endings.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(offset) = self.pending[start..].iter().position(|&b| b == b'\n') {
+            let end = start + offset;
+            lines.push(decode(&self.pending[start..end]));
+            start = end + 1;
+        }
+        self.pending.drain(..start);
+        lines
+    }
+
+    /// Bytes of a line still waiting for its newline.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The trailing line if output didn't end with a newline.
+    pub fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let line = decode(&self.pending);
+        self.pending.clear();
+        Some(line)
+    }
+}
+
+fn decode(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_character_split_across_chunks() {
+        let text = "{\"pitch\": \"F♯4\"}\n".as_bytes();
+        // '♯' is three bytes; split after its first one
+        let split = text.iter().position(|&b| b == 0xE2).unwrap() + 1;
+
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push(&text[..split]).is_empty());
+        assert_eq!(buffer.push(&text[split..]), vec!["{\"pitch\": \"F♯4\"}"]);
+        assert_eq!(buffer.finish(), None);
+    }
+
+    #[test]
+    fn splits_multiple_lines_and_keeps_trailing_partial() {
+        let mut buffer = LineBuffer::default();
+        assert_eq!(buffer.push(b"one\r\ntwo\nthr"), vec!["one", "two"]);
+        assert_eq!(buffer.push(b"ee"), Vec::<String>::new());
+        assert_eq!(buffer.finish(), Some("three".to_string()));
+    }
+}
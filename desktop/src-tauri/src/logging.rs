@@ -0,0 +1,155 @@
+//! `tracing` events written to daily log files in the app log dir by
+//! `tracing-appender`, so release builds (which have no console) keep
+//! diagnostics users can attach to a bug report with `export_logs`.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Logs are named `app.<date>.log`, one per day.
+const LOG_PREFIX: &str = "app";
+const LOG_SUFFIX: &str = "log";
+
+/// Days of logs kept, the current one included.
+const KEPT_LOGS: usize = 4;
+
+/// Lines `get_recent_logs` returns when not told how many.
+pub const DEFAULT_RECENT_LINES: usize = 200;
+
+/// The daily log files in `dir`, the oldest removed once there are more
+/// than `KEPT_LOGS`.
+fn appender(dir: &Path) -> Result<RollingFileAppender, String> {
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix(LOG_SUFFIX)
+        .max_log_files(KEPT_LOGS)
+        .build(dir)
+        .map_err(|e| format!("Failed to open log: {}", e))
+}
+
+/// The logs in `dir`, oldest first.
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let is_log = name.starts_with(&format!("{}.", LOG_PREFIX))
+                && name.ends_with(&format!(".{}", LOG_SUFFIX));
+            let metadata = entry.metadata().ok().filter(|m| m.is_file() && is_log)?;
+            Some((metadata.modified().ok()?, entry.path()))
+        })
+        .collect();
+    // A day's log is named by its date, which orders logs of the same mtime
+    files.sort();
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+/// The last `limit` lines logged, oldest first.
+pub fn recent_lines(dir: &Path, limit: usize) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    for file in log_files(dir).into_iter().rev() {
+        let text =
+            std::fs::read_to_string(&file).map_err(|e| format!("Failed to read log: {}", e))?;
+        let mut older: Vec<String> = text.lines().rev().map(str::to_string).collect();
+        older.truncate(limit - lines.len().min(limit));
+        lines.extend(older);
+        if lines.len() >= limit {
+            break;
+        }
+    }
+    lines.reverse();
+    Ok(lines)
+}
+
+/// Every log in `dir`, oldest first, written to `dest` as one file.
+/// Returns the bytes written.
+pub fn export(dir: &Path, dest: &Path) -> Result<u64, String> {
+    let mut out = File::create(dest).map_err(|e| format!("Failed to create export: {}", e))?;
+    let mut written = 0;
+    for file in log_files(dir) {
+        let mut log = File::open(&file).map_err(|e| format!("Failed to read log: {}", e))?;
+        written += std::io::copy(&mut log, &mut out)
+            .map_err(|e| format!("Failed to export log: {}", e))?;
+    }
+    Ok(written)
+}
+
+/// Send `tracing` events at `max_level` and above to the logs in `dir`, and
+/// in debug builds to stderr as well. Lines are written on a thread of
+/// their own until the returned guard is dropped.
+pub fn init(dir: &Path, max_level: Level) -> Result<WorkerGuard, String> {
+    let (writer, guard) = tracing_appender::non_blocking(appender(dir)?);
+    let console = cfg!(debug_assertions).then(|| fmt::layer().with_writer(std::io::stderr));
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(max_level))
+        .with(fmt::layer().with_writer(writer).with_ansi(false))
+        .with(console)
+        .try_init()
+        .map_err(|e| format!("Failed to start logging: {}", e))?;
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_logged_and_exported_oldest_first() {
+        let dir = std::env::temp_dir().join(format!("smrh-logs-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // A log from an earlier day, and a file that isn't a log
+        std::fs::write(dir.join("app.2026-01-30.log"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a log\n").unwrap();
+        let earlier = SystemTime::now() - std::time::Duration::from_secs(86_400);
+        File::options()
+            .write(true)
+            .open(dir.join("app.2026-01-30.log"))
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+
+        let (writer, guard) = tracing_appender::non_blocking(appender(&dir).unwrap());
+        let subscriber = fmt()
+            .with_writer(writer)
+            .with_ansi(false)
+            .with_max_level(Level::INFO)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("not logged");
+            tracing::warn!(code = 1, "analyzer exited");
+        });
+        // Flushes what the writer thread has queued
+        drop(guard);
+
+        assert_eq!(log_files(&dir).len(), 2);
+        let recent = recent_lines(&dir, 2).unwrap();
+        assert_eq!(recent[0], "two");
+        assert!(
+            recent[1].contains("WARN") && recent[1].ends_with("analyzer exited code=1"),
+            "{}",
+            recent[1]
+        );
+        assert_eq!(recent_lines(&dir, 100).unwrap().len(), 3);
+
+        let dest = dir.join("export.txt");
+        let written = export(&dir, &dest).unwrap();
+        let exported = std::fs::read_to_string(&dest).unwrap();
+        assert_eq!(written, exported.len() as u64);
+        assert!(exported.starts_with("one\ntwo\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
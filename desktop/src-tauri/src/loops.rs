@@ -0,0 +1,99 @@
+//! Measure ranges for looped practice of a pattern.
+
+use crate::models::AnalysisResult;
+use crate::occurrences;
+
+/// Occurrences a loop covers unless the caller asks for more.
+pub const DEFAULT_LOOP_OCCURRENCES: usize = 2;
+
+/// Smallest (start, end) measure range holding at least `min_occurrences`
+/// whole occurrences of a pattern, ties going to the earliest. None when the
+/// pattern occurs fewer times than that.
+pub fn suggest_loop_range(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    min_occurrences: usize,
+) -> Result<Option<(i32, i32)>, String> {
+    let (staff_data, pattern) = occurrences::find_pattern(result, staff, pattern_id)?;
+
+    let notes = occurrences::staff_notes(result, staff_data);
+    let spans = occurrences::spans(pattern, &notes, result.measure_map.as_ref());
+    Ok(tightest_range(spans, min_occurrences))
+}
+
+fn tightest_range(mut spans: Vec<(i32, i32)>, min_occurrences: usize) -> Option<(i32, i32)> {
+    spans.sort();
+    spans
+        .windows(min_occurrences.max(1))
+        .map(|window| {
+            let end = window
+                .iter()
+                .map(|&(_, end)| end)
+                .max()
+                .unwrap_or(window[0].1);
+            (window[0].0, end)
+        })
+        .min_by_key(|&(start, end)| end - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    /// A score whose first staff has one note per measure, 1 through 24.
+    fn result_with_pattern(positions: Vec<i32>) -> AnalysisResult {
+        let measures: String = (1..=24)
+            .map(|m| {
+                format!(
+                    r#"<measure number="{}"><note><pitch><step>C</step><octave>4</octave></pitch></note></measure>"#,
+                    m
+                )
+            })
+            .collect();
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 3,
+                    length: 2,
+                    count: positions.len() as i32,
+                    notes: (0..2)
+                        .map(|i| NoteLocator {
+                            index: positions[0] + i,
+                            measure: positions[0] + i + 1,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    positions,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+                measures
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn picks_tight_cluster_over_earlier_spread_occurrences() {
+        // Occurrences start in measures 1, 9, 17 and 19
+        let result = result_with_pattern(vec![0, 8, 16, 18]);
+        assert_eq!(
+            suggest_loop_range(&result, 0, 3, 2).unwrap(),
+            Some((17, 20))
+        );
+        assert_eq!(suggest_loop_range(&result, 0, 3, 3).unwrap(), Some((9, 20)));
+    }
+
+    #[test]
+    fn spread_pattern_needs_the_whole_span() {
+        let result = result_with_pattern(vec![0, 22]);
+        assert_eq!(suggest_loop_range(&result, 0, 3, 2).unwrap(), Some((1, 24)));
+        assert_eq!(suggest_loop_range(&result, 0, 3, 3).unwrap(), None);
+        assert!(suggest_loop_range(&result, 1, 3, 2).is_err());
+    }
+}
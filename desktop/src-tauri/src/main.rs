@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args = std::env::args().skip(1).collect();
+    if let Some(code) = music_repetition_highlighter_lib::run_headless(args) {
+        std::process::exit(code);
+    }
     music_repetition_highlighter_lib::run()
 }
@@ -0,0 +1,139 @@
+use serde::Serialize;
+
+use crate::models::{AnalysisResult, StaffPatternData};
+
+/// Pattern length (in notes) at which the length component saturates at 1.0.
+const SATURATING_LENGTH: f64 = 16.0;
+
+const COVERAGE_WEIGHT: f64 = 0.5;
+const COUNT_WEIGHT: f64 = 0.25;
+const LENGTH_WEIGHT: f64 = 0.25;
+
+/// How repetitive a piece is, normalized to 0–1 for ranking.
+///
+/// ```text
+/// coverage = covered notes / spanned notes        (all staves summed)
+/// count    = 1 - 1 / mean(pattern.count)          (0 when no patterns)
+/// length   = min(mean(pattern.length) / 16, 1)
+/// score    = 0.5 * coverage + 0.25 * count + 0.25 * length
+/// ```
+///
+/// A note is "covered" when it falls inside any occurrence of any pattern.
+/// The analyzer doesn't report how many notes a staff has, so "spanned" is
+/// measured up to the furthest note index any occurrence reaches.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepetitionScore {
+    pub score: f64,
+    pub coverage: f64,
+    pub count: f64,
+    pub length: f64,
+}
+
+pub fn repetition_score(result: &AnalysisResult) -> RepetitionScore {
+    let staves = result.staves();
+
+    let (covered, spanned) = staves
+        .iter()
+        .map(|staff| staff_coverage(staff))
+        .fold((0, 0), |(c, s), (sc, ss)| (c + sc, s + ss));
+    let coverage = if spanned == 0 {
+        0.0
+    } else {
+        covered as f64 / spanned as f64
+    };
+
+    let patterns: Vec<_> = staves.iter().flat_map(|s| &s.patterns).collect();
+    let (count, length) = if patterns.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let n = patterns.len() as f64;
+        let mean_count = patterns.iter().map(|p| p.count as f64).sum::<f64>() / n;
+        let mean_length = patterns.iter().map(|p| p.length as f64).sum::<f64>() / n;
+        (
+            (1.0 - 1.0 / mean_count.max(1.0)).clamp(0.0, 1.0),
+            (mean_length / SATURATING_LENGTH).clamp(0.0, 1.0),
+        )
+    };
+
+    RepetitionScore {
+        score: COVERAGE_WEIGHT * coverage + COUNT_WEIGHT * count + LENGTH_WEIGHT * length,
+        coverage,
+        count,
+        length,
+    }
+}
+
+/// Returns (covered, spanned) note counts for one staff.
+fn staff_coverage(staff: &StaffPatternData) -> (usize, usize) {
+    let spanned = staff
+        .patterns
+        .iter()
+        .flat_map(|p| p.positions.iter().map(move |&pos| pos + p.length))
+        .max()
+        .unwrap_or(0)
+        .max(0) as usize;
+
+    let mut covered = vec![false; spanned];
+    for pattern in &staff.patterns {
+        for &pos in &pattern.positions {
+            let start = pos.max(0) as usize;
+            let end = ((pos + pattern.length).max(0) as usize).min(spanned);
+            for slot in covered.iter_mut().take(end).skip(start) {
+                *slot = true;
+            }
+        }
+    }
+
+    (covered.iter().filter(|&&c| c).count(), spanned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Pattern;
+
+    fn pattern(id: i32, length: i32, positions: Vec<i32>) -> Pattern {
+        Pattern {
+            id,
+            length,
+            count: positions.len() as i32,
+            positions,
+            ..Default::default()
+        }
+    }
+
+    fn result(treble: Vec<Pattern>, bass: Vec<Pattern>) -> AnalysisResult {
+        let staff = |part_index, patterns| StaffPatternData {
+            part_index,
+            part_name: String::new(),
+            patterns,
+            ..Default::default()
+        };
+        AnalysisResult {
+            file: "test.musicxml".to_string(),
+            parts: vec![staff(0, treble), staff(1, bass)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn repetitive_scores_higher_than_sparse() {
+        let repetitive = result(
+            vec![pattern(0, 8, vec![0, 8, 16, 24])],
+            vec![pattern(1, 4, vec![0, 4, 8, 12, 16, 20, 24, 28])],
+        );
+        let sparse = result(vec![pattern(0, 4, vec![0, 60])], vec![]);
+
+        let high = repetition_score(&repetitive);
+        let low = repetition_score(&sparse);
+        assert!(high.score > low.score);
+        assert!(high.coverage > low.coverage);
+        assert!((0.0..=1.0).contains(&high.score));
+    }
+
+    #[test]
+    fn empty_result_scores_zero() {
+        let score = repetition_score(&result(vec![], vec![]));
+        assert_eq!(score.score, 0.0);
+    }
+}
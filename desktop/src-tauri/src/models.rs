@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+
+use crate::displacement::MetricDisplacement;
+use crate::keys::Key;
+use crate::musicxml::repeats::MeasureMap;
+use crate::occurrences::PatternRef;
+use crate::sequences::Sequence;
+use crate::warnings::AnalyzerWarning;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteLocator {
+    pub index: i32,
+    pub measure: i32,
+    pub beat: Option<f64>,
+    /// Onset in quarter notes from the start of the piece, when
+    /// `compute_absolute_beats` is on.
+    #[serde(default)]
+    pub absolute_beat: Option<f64>,
+    pub pitch: String,
+    /// Written length in quarter notes (0 for a grace note).
+    #[serde(default)]
+    pub duration_beats: Option<f64>,
+    /// Index of the first event of the chord this note belongs to, if any.
+    #[serde(default)]
+    pub chord_group: Option<i32>,
+    /// Only ever true when grace notes are included in matching.
+    #[serde(default)]
+    pub is_grace: bool,
+    /// Continues a tie from the previous note of the same pitch. Never set
+    /// when tied notes are merged.
+    #[serde(default)]
+    pub tied: bool,
+    /// Engraved position from the score's `default-x`/`default-y`, in tenths,
+    /// when `include_layout` is on and the score has layout.
+    #[serde(default)]
+    pub x: Option<f64>,
+    #[serde(default)]
+    pub y: Option<f64>,
+    /// 1-based page the note is engraved on.
+    #[serde(default)]
+    pub page: Option<i32>,
+    /// Degree 1–7 from the tonic of `AnalysisResult.key`, by letter name,
+    /// when `annotate_scale_degrees` is on.
+    #[serde(default)]
+    pub scale_degree: Option<i32>,
+    /// Semitones the note is raised (positive) or lowered off the key's
+    /// scale, so F# in C major is degree 4 altered +1.
+    #[serde(default)]
+    pub degree_alteration: Option<i32>,
+    /// Identical notes this entry stands for when `run_length_encode` folded
+    /// them (see `runs::encode`); 0 when notes aren't folded.
+    #[serde(default)]
+    pub repeat: i32,
+    /// Articulations written on the note, "slur" for a slurred note, when
+    /// `capture_articulations` is on.
+    #[serde(default)]
+    pub articulations: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pattern {
+    pub id: i32,
+    pub length: i32,
+    pub count: i32,
+    pub positions: Vec<i32>,
+    pub notes: Vec<NoteLocator>,
+    /// Melodic category ("scale", "arpeggio", ...), set when `classify_patterns` is on.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Chord symbol ("G7") its notes spell, set when `infer_harmony` is on.
+    #[serde(default)]
+    pub harmony: Option<String>,
+    /// Steady period out of step with the barline, set when
+    /// `detect_metric_displacement` is on.
+    #[serde(default)]
+    pub metric_displacement: Option<MetricDisplacement>,
+    /// Id of an earlier pattern this one rescales and the factor applied to
+    /// its note values, set when `detect_rhythmic_scaling` is on.
+    #[serde(default)]
+    pub scaled_from: Option<(i32, f64)>,
+    /// Id of an earlier pattern whose intervals this one mirrors, set when
+    /// `detect_inversions` is on.
+    #[serde(default)]
+    pub inversion_of: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StaffPatternData {
+    pub part_index: i32,
+    pub part_name: String,
+    pub patterns: Vec<Pattern>,
+    /// Set when `detect_sequences` is on.
+    #[serde(default)]
+    pub sequences: Vec<Sequence>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalysisResult {
+    pub file: String,
+    /// Every analyzed staff in score order: one for a solo flute part, two
+    /// for piano, four for a string quartet. Pattern ids are unique across
+    /// them.
+    pub parts: Vec<StaffPatternData>,
+    pub musicxml_content: String,
+    /// False when the score parsed but no repetition was detected in any staff.
+    #[serde(default)]
+    pub patterns_found: bool,
+    /// Playback order of written measures, set when measures are reported in
+    /// the played frame.
+    #[serde(default)]
+    pub measure_map: Option<MeasureMap>,
+    /// Key the scale degrees are measured in, when `annotate_scale_degrees`
+    /// is on.
+    #[serde(default)]
+    pub key: Option<Key>,
+    /// Patterns in each measure, in score order, when `by_measure` is on.
+    #[serde(default)]
+    pub measure_index: Vec<(i32, Vec<PatternRef>)>,
+    /// Warnings the analyzer printed, deduplicated by message.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<AnalyzerWarning>,
+    /// Every progress event of the run, when `include_progress_log` is on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub progress_log: Vec<Progress>,
+}
+
+impl AnalysisResult {
+    /// Staff `staff` of `parts`; commands select staves by this index.
+    pub fn staff(&self, staff: usize) -> Option<&StaffPatternData> {
+        self.parts.get(staff)
+    }
+
+    /// The staves the score has, in score order.
+    pub fn staves(&self) -> Vec<&StaffPatternData> {
+        self.parts.iter().collect()
+    }
+
+    pub fn staves_mut(&mut self) -> Vec<&mut StaffPatternData> {
+        self.parts.iter_mut().collect()
+    }
+}
+
+/// Payload of the `analyze-complete` event emitted after a successful analysis.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AnalysisComplete {
+    PatternsFound { count: usize },
+    NoPatterns,
+}
+
+/// Payload of the `analyze-started` event, carrying the id `cancel_analysis`
+/// takes.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisStarted {
+    pub analysis_id: u64,
+    pub path: String,
+}
+
+/// Payload of the `analyze-large-file` event emitted when a file is over
+/// `AnalyzerConfig::large_file_threshold_mb`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeFileWarning {
+    pub path: String,
+    pub size_bytes: u64,
+    pub threshold_bytes: u64,
+    /// False when the analysis was refused for lack of `confirm_large`.
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnalysisError {
+    pub error: String,
+}
+
+/// Where an analysis is, as reported in `Progress.stage`. Serialized as the
+/// plain strings the analyzer already emits; unknown names round-trip
+/// through `Other` so a newer analyzer doesn't break older builds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Stage {
+    Queued,
+    Spawn,
+    /// PDF or image being turned into MusicXML.
+    Convert,
+    /// A PDF page being rendered to an image.
+    Extract,
+    /// Optical music recognition of one page.
+    Omr,
+    /// Per-page MusicXML being combined.
+    Merge,
+    Analyze,
+    Parse,
+    Cache,
+    Done,
+    /// The analysis was stopped by `cancel_analysis`; no more events follow.
+    Cancelled,
+    Other(String),
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Stage::Queued => "queued",
+            Stage::Spawn => "spawn",
+            Stage::Convert => "converting",
+            Stage::Extract => "extracting",
+            Stage::Omr => "omr",
+            Stage::Merge => "merging",
+            Stage::Analyze => "analyzing",
+            Stage::Parse => "parse",
+            Stage::Cache => "cache",
+            Stage::Done => "done",
+            Stage::Cancelled => "cancelled",
+            Stage::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for Stage {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "queued" => Stage::Queued,
+            "spawn" => Stage::Spawn,
+            "converting" => Stage::Convert,
+            "extracting" => Stage::Extract,
+            "omr" => Stage::Omr,
+            "merging" => Stage::Merge,
+            "analyzing" => Stage::Analyze,
+            "parse" => Stage::Parse,
+            "cache" => Stage::Cache,
+            "done" => Stage::Done,
+            "cancelled" => Stage::Cancelled,
+            _ => Stage::Other(name),
+        }
+    }
+}
+
+impl From<Stage> for String {
+    fn from(stage: Stage) -> Self {
+        match stage {
+            Stage::Other(name) => name,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    #[serde(rename = "type")]
+    pub progress_type: String,
+    pub stage: Stage,
+    pub current: i32,
+    pub total: i32,
+    pub message: String,
+    /// `current / total` in 0–1, or None while the total isn't known yet.
+    /// Filled in on the Rust side; the analyzer doesn't send it.
+    #[serde(default)]
+    pub fraction: Option<f64>,
+    /// Whether the stage has a known total, i.e. can show a filling bar
+    /// rather than a spinner. Filled in on the Rust side too.
+    #[serde(default)]
+    pub determinate: bool,
+    /// Set on the progress of a batch or folder job's files: the job, and
+    /// the file's index in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<usize>,
+}
+
+impl Progress {
+    /// Fill in the fields derived from `current` and `total` before the
+    /// event is forwarded to the frontend.
+    pub fn with_derived(mut self) -> Self {
+        self.determinate = self.total > 0;
+        self.fraction = self
+            .determinate
+            .then(|| (self.current as f64 / self.total as f64).clamp(0.0, 1.0));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(current: i32, total: i32) -> Progress {
+        Progress {
+            progress_type: "progress".to_string(),
+            stage: Stage::Analyze,
+            current,
+            total,
+            message: String::new(),
+            fraction: None,
+            determinate: false,
+            job_id: None,
+            file_id: None,
+        }
+        .with_derived()
+    }
+
+    #[test]
+    fn fraction_is_none_until_total_is_known() {
+        assert_eq!(progress(0, 0).fraction, None);
+        assert_eq!(progress(3, -1).fraction, None);
+        assert_eq!(progress(1, 4).fraction, Some(0.25));
+        assert_eq!(progress(5, 4).fraction, Some(1.0));
+    }
+
+    #[test]
+    fn determinate_only_with_a_total() {
+        assert!(!progress(0, 0).determinate);
+        assert!(!progress(2, -1).determinate);
+        assert!(progress(0, 12).determinate);
+    }
+
+    #[test]
+    fn single_staff_result_has_one_staff() {
+        let json = r#"{"file": "flute.musicxml", "musicxml_content": "",
+            "parts": [{"part_index": 0, "part_name": "Flute", "patterns": []}]}"#;
+        let result: AnalysisResult = serde_json::from_str(json).unwrap();
+        assert_eq!(result.staves().len(), 1);
+        assert_eq!(result.staff(0).unwrap().part_name, "Flute");
+        assert!(result.staff(1).is_none());
+    }
+
+    #[test]
+    fn stages_keep_their_wire_names() {
+        let line =
+            r#"{"type": "progress", "stage": "omr", "current": 1, "total": 3, "message": ""}"#;
+        let progress: Progress = serde_json::from_str(line).unwrap();
+        assert_eq!(progress.stage, Stage::Omr);
+
+        let unknown: Stage = serde_json::from_str(r#""tuning""#).unwrap();
+        assert_eq!(unknown, Stage::Other("tuning".to_string()));
+        assert_eq!(serde_json::to_string(&unknown).unwrap(), r#""tuning""#);
+        assert_eq!(
+            serde_json::to_string(&Stage::Analyze).unwrap(),
+            r#""analyzing""#
+        );
+        let cancelled: Stage = serde_json::from_str(r#""cancelled""#).unwrap();
+        assert_eq!(cancelled, Stage::Cancelled);
+    }
+
+    #[test]
+    fn only_batch_progress_is_tagged() {
+        let single = serde_json::to_value(progress(1, 2)).unwrap();
+        assert!(single.get("file_id").is_none());
+
+        let mut tagged = progress(1, 2);
+        (tagged.job_id, tagged.file_id) = (Some(3), Some(7));
+        let tagged = serde_json::to_value(tagged).unwrap();
+        assert_eq!(tagged["job_id"], 3);
+        assert_eq!(tagged["file_id"], 7);
+    }
+}
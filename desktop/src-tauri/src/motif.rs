@@ -0,0 +1,257 @@
+//! Comparing the patterns of two staves by their octave-independent pitch
+//! content.
+
+use serde::Serialize;
+
+use crate::models::{AnalysisResult, Pattern};
+use crate::pitch;
+
+/// The longest run of pitch classes found in a pattern of each of two
+/// staves.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SharedMotif {
+    pub length: usize,
+    /// Pitch classes of the motif (C = 0 ... B = 11).
+    pub pitch_classes: Vec<i32>,
+    pub first_pitches: Vec<String>,
+    pub second_pitches: Vec<String>,
+    /// Note index of each occurrence of the motif in each staff.
+    pub first_positions: Vec<i32>,
+    pub second_positions: Vec<i32>,
+}
+
+/// Patterns of two staves split by which of them they occur in, compared by
+/// pitch classes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StaffPartition {
+    /// Ids of patterns of the first staff with no counterpart in the second.
+    pub first_only: Vec<i32>,
+    pub second_only: Vec<i32>,
+    pub shared: Vec<SharedPattern>,
+}
+
+/// One pitch-class sequence found as a pattern in both staves.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SharedPattern {
+    pub pitch_classes: Vec<i32>,
+    pub first_ids: Vec<i32>,
+    pub second_ids: Vec<i32>,
+}
+
+/// A pattern's notes as pitch classes, so a motif doubled an octave or two
+/// lower in the left hand still compares equal.
+pub fn pitch_classes(pattern: &Pattern) -> Option<Vec<i32>> {
+    pattern
+        .notes
+        .iter()
+        .map(|n| pitch::to_midi(&n.pitch).map(|m| m.rem_euclid(12)))
+        .collect()
+}
+
+/// Longest contiguous pitch-class sequence that appears inside a pattern of
+/// each of the staves `first` and `second` (indices into `parts`). Ties go
+/// to the first pair found.
+pub fn longest_shared_motif(
+    result: &AnalysisResult,
+    first: usize,
+    second: usize,
+) -> Option<SharedMotif> {
+    let a: Vec<_> = with_pitch_classes(staff_patterns(result, first));
+    let b: Vec<_> = with_pitch_classes(staff_patterns(result, second));
+
+    let mut best: Option<(usize, &Pattern, usize, &Pattern, usize)> = None;
+    for (ap, apc) in &a {
+        for (bp, bpc) in &b {
+            let (len, ai, bi) = longest_common_run(apc, bpc);
+            if len > 0 && best.is_none_or(|(best_len, ..)| len > best_len) {
+                best = Some((len, ap, ai, bp, bi));
+            }
+        }
+    }
+
+    let (length, ap, ai, bp, bi) = best?;
+    let names = |p: &Pattern, start: usize| -> Vec<String> {
+        p.notes[start..start + length]
+            .iter()
+            .map(|n| n.pitch.clone())
+            .collect()
+    };
+    Some(SharedMotif {
+        length,
+        pitch_classes: pitch_classes(ap)?[ai..ai + length].to_vec(),
+        first_pitches: names(ap, ai),
+        second_pitches: names(bp, bi),
+        first_positions: ap.positions.iter().map(|p| p + ai as i32).collect(),
+        second_positions: bp.positions.iter().map(|p| p + bi as i32).collect(),
+    })
+}
+
+/// Partition the patterns of staves `first` and `second` into those only
+/// in one and those in both. Patterns with an unparseable pitch can't be
+/// compared and count as exclusive.
+pub fn staff_exclusive_patterns(
+    result: &AnalysisResult,
+    first: usize,
+    second: usize,
+) -> StaffPartition {
+    let (first, second) = (
+        staff_patterns(result, first),
+        staff_patterns(result, second),
+    );
+    let a = with_pitch_classes(first);
+    let b = with_pitch_classes(second);
+    let ids_matching = |patterns: &[(&Pattern, Vec<i32>)], pcs: &[i32]| -> Vec<i32> {
+        patterns
+            .iter()
+            .filter(|(_, other)| other == pcs)
+            .map(|(p, _)| p.id)
+            .collect()
+    };
+
+    let mut partition = StaffPartition::default();
+    for pattern in first {
+        let shared = pitch_classes(pattern).filter(|pcs| !ids_matching(&b, pcs).is_empty());
+        match shared {
+            Some(pcs) => {
+                if !partition.shared.iter().any(|s| s.pitch_classes == pcs) {
+                    partition.shared.push(SharedPattern {
+                        first_ids: ids_matching(&a, &pcs),
+                        second_ids: ids_matching(&b, &pcs),
+                        pitch_classes: pcs,
+                    });
+                }
+            }
+            None => partition.first_only.push(pattern.id),
+        }
+    }
+    partition.second_only = second
+        .iter()
+        .filter(|p| pitch_classes(p).is_none_or(|pcs| ids_matching(&a, &pcs).is_empty()))
+        .map(|p| p.id)
+        .collect();
+    partition
+}
+
+/// Patterns of a staff, none if the score doesn't have it.
+fn staff_patterns(result: &AnalysisResult, staff: usize) -> &[Pattern] {
+    result.staff(staff).map_or(&[], |data| &data.patterns)
+}
+
+fn with_pitch_classes(patterns: &[Pattern]) -> Vec<(&Pattern, Vec<i32>)> {
+    patterns
+        .iter()
+        .filter_map(|p| pitch_classes(p).map(|pc| (p, pc)))
+        .collect()
+}
+
+/// Longest common substring of `a` and `b` as (length, start in a, start in b).
+fn longest_common_run(a: &[i32], b: &[i32]) -> (usize, usize, usize) {
+    let mut best = (0, 0, 0);
+    let mut prev = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        let mut row = vec![0; b.len() + 1];
+        for j in 1..=b.len() {
+            if a[i - 1] == b[j - 1] {
+                row[j] = prev[j - 1] + 1;
+                if row[j] > best.0 {
+                    best = (row[j], i - row[j], j - row[j]);
+                }
+            }
+        }
+        prev = row;
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, StaffPatternData};
+
+    fn pattern(id: i32, pitches: &[&str], positions: Vec<i32>) -> Pattern {
+        Pattern {
+            id,
+            length: pitches.len() as i32,
+            count: positions.len() as i32,
+            positions,
+            notes: pitches
+                .iter()
+                .map(|p| NoteLocator {
+                    pitch: p.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn result(treble: Vec<Pattern>, bass: Vec<Pattern>) -> AnalysisResult {
+        AnalysisResult {
+            parts: vec![
+                StaffPatternData {
+                    patterns: treble,
+                    ..Default::default()
+                },
+                StaffPatternData {
+                    part_index: 1,
+                    patterns: bass,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_four_note_motif_shared_across_octaves() {
+        let result = result(
+            vec![
+                pattern(0, &["A5", "C5", "D5", "E5", "G5", "B4"], vec![0, 20]),
+                pattern(1, &["F4", "F4", "F4"], vec![8, 12]),
+            ],
+            vec![pattern(2, &["C3", "D3", "E3", "G3"], vec![4, 16, 30])],
+        );
+
+        let motif = longest_shared_motif(&result, 0, 1).unwrap();
+        assert_eq!(motif.length, 4);
+        assert_eq!(motif.pitch_classes, vec![0, 2, 4, 7]);
+        assert_eq!(motif.first_pitches, vec!["C5", "D5", "E5", "G5"]);
+        assert_eq!(motif.first_positions, vec![1, 21]);
+        assert_eq!(motif.second_positions, vec![4, 16, 30]);
+    }
+
+    #[test]
+    fn partitions_shared_and_exclusive_patterns() {
+        let result = result(
+            vec![
+                pattern(0, &["C5", "E5", "G5"], vec![0, 3]),
+                pattern(1, &["A5", "B5", "A5"], vec![6, 9]),
+            ],
+            vec![
+                pattern(2, &["C3", "E3", "G3"], vec![0, 8]),
+                pattern(3, &["D3", "D3", "F3", "F3"], vec![3, 11]),
+            ],
+        );
+
+        let partition = staff_exclusive_patterns(&result, 0, 1);
+        assert_eq!(partition.first_only, vec![1]);
+        assert_eq!(partition.second_only, vec![3]);
+        assert_eq!(
+            partition.shared,
+            vec![SharedPattern {
+                pitch_classes: vec![0, 4, 7],
+                first_ids: vec![0],
+                second_ids: vec![2],
+            }]
+        );
+    }
+
+    #[test]
+    fn nothing_shared() {
+        let result = result(
+            vec![pattern(0, &["C4", "D4"], vec![0, 2])],
+            vec![pattern(1, &["F#2", "G#2"], vec![0, 2])],
+        );
+        assert_eq!(longest_shared_motif(&result, 0, 1), None);
+    }
+}
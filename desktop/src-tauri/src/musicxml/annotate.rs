@@ -0,0 +1,185 @@
+//! Pattern labels written into the score as `<direction>` text, for
+//! printing with the motifs numbered.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::{Reader, Writer};
+use serde::Deserialize;
+
+use super::{is_element, read_note_body, staves_per_part, stream_index, xml_error, NoteKind};
+use crate::models::AnalysisResult;
+
+/// How occurrences are labeled. Patterns are numbered from 1 in result
+/// order, staff by staff.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelScheme {
+    /// "P1", "P2", ...
+    #[default]
+    Numbered,
+    /// "A", "B", ..., "Z", "AA", ...
+    Lettered,
+    /// "P1.1", "P1.2", ...: the pattern, then which occurrence it is.
+    NumberedOccurrences,
+}
+
+impl LabelScheme {
+    /// Label of occurrence `occurrence` (0-based) of pattern `pattern`
+    /// (0-based).
+    pub fn label(self, pattern: usize, occurrence: usize) -> String {
+        match self {
+            LabelScheme::Numbered => format!("P{}", pattern + 1),
+            LabelScheme::Lettered => letters(pattern),
+            LabelScheme::NumberedOccurrences => format!("P{}.{}", pattern + 1, occurrence + 1),
+        }
+    }
+}
+
+/// Spreadsheet-style column letters: 0 is "A", 26 is "AA".
+fn letters(mut n: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// Labels keyed by (stream index, note index) of the note each occurrence
+/// starts on. Occurrences starting together share the note, comma-separated.
+fn occurrence_labels(result: &AnalysisResult, scheme: LabelScheme) -> HashMap<(i32, i32), String> {
+    let mut labels: HashMap<(i32, i32), String> = HashMap::new();
+    let patterns = result
+        .staves()
+        .into_iter()
+        .flat_map(|staff| staff.patterns.iter().map(move |p| (staff.part_index, p)));
+    for (number, (stream, pattern)) in patterns.enumerate() {
+        for (occurrence, &position) in pattern.positions.iter().enumerate() {
+            let label = scheme.label(number, occurrence);
+            labels
+                .entry((stream, position))
+                .and_modify(|l| {
+                    l.push_str(", ");
+                    l.push_str(&label);
+                })
+                .or_insert(label);
+        }
+    }
+    labels
+}
+
+/// Rewrite `xml` with a `<direction>` above the first note of every pattern
+/// occurrence, naming it with `scheme`.
+pub fn annotate(xml: &str, result: &AnalysisResult, scheme: LabelScheme) -> Result<String, String> {
+    let labels = occurrence_labels(result, scheme);
+    let staves = staves_per_part(xml)?;
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    let mut part: Option<usize> = None;
+    let mut counters: HashMap<i32, i32> = HashMap::new();
+
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+        match event {
+            Event::Start(ref e) if is_element(e, "part") => {
+                part = Some(part.map_or(0, |p| p + 1));
+                writer.write_event(event).map_err(xml_error)?;
+            }
+            Event::Start(e) if is_element(&e, "note") => {
+                let start = e.into_owned();
+                let body = read_note_body(&mut reader)?;
+
+                let kind = NoteKind::from_events(&body);
+                if !kind.rest && !kind.grace && !kind.chord {
+                    let stream = stream_index(&staves, part.unwrap_or(0), kind.staff);
+                    let counter = counters.entry(stream).or_insert(0);
+                    if let Some(label) = labels.get(&(stream, *counter)) {
+                        let direction = format!(
+                            "<direction placement=\"above\"><direction-type>\
+                             <words font-weight=\"bold\">{}</words></direction-type>\
+                             <staff>{}</staff></direction>",
+                            escape(label.as_str()),
+                            kind.staff
+                        );
+                        writer
+                            .get_mut()
+                            .write_all(direction.as_bytes())
+                            .map_err(|e| format!("Failed to write score: {}", e))?;
+                    }
+                    *counter += 1;
+                }
+
+                writer.write_event(Event::Start(start)).map_err(xml_error)?;
+                for inner in body {
+                    writer.write_event(inner).map_err(xml_error)?;
+                }
+            }
+            Event::Eof => break,
+            event => writer.write_event(event).map_err(xml_error)?,
+        }
+    }
+
+    String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    const SCORE: &str = r#"<score-partwise><part id="P1"><measure number="1">
+<note><pitch><step>C</step><octave>4</octave></pitch></note>
+<note><pitch><step>D</step><octave>4</octave></pitch></note>
+<note><rest/></note>
+<note><pitch><step>C</step><octave>4</octave></pitch></note>
+<note><chord/><pitch><step>E</step><octave>4</octave></pitch></note>
+<note><pitch><step>D</step><octave>4</octave></pitch></note>
+</measure></part></score-partwise>"#;
+
+    fn result() -> AnalysisResult {
+        let pattern = |id, positions| Pattern {
+            id,
+            length: 2,
+            count: 2,
+            positions,
+            ..Default::default()
+        };
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![pattern(4, vec![0, 2]), pattern(9, vec![2])],
+                ..Default::default()
+            }],
+            musicxml_content: SCORE.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn labels_go_before_each_occurrence() {
+        let out = annotate(SCORE, &result(), LabelScheme::Numbered).unwrap();
+        assert_eq!(out.matches("<direction ").count(), 2);
+        assert!(out.contains(
+            "<measure number=\"1\">\n<direction placement=\"above\"><direction-type>\
+             <words font-weight=\"bold\">P1</words></direction-type><staff>1</staff></direction>\
+             <note><pitch><step>C</step>"
+        ));
+        // After the rest, before the chord rather than inside it
+        assert!(out.contains(
+            "<words font-weight=\"bold\">P1, P2</words></direction-type><staff>1</staff>\
+             </direction><note><pitch><step>C</step>"
+        ));
+
+        let by_occurrence = annotate(SCORE, &result(), LabelScheme::NumberedOccurrences).unwrap();
+        assert!(by_occurrence.contains(">P1.2, P2.1<"));
+        assert_eq!(letters(0), "A");
+        assert_eq!(letters(27), "AB");
+    }
+}
@@ -0,0 +1,239 @@
+use std::io::Cursor;
+
+use quick_xml::events::Event;
+use quick_xml::{Reader, Writer};
+
+use super::highlight::with_attribute;
+use super::{is_element, measure_number, stream_notes, xml_error};
+use crate::models::AnalysisResult;
+
+/// A range of measures cut out of a score, renumbered from 1.
+#[derive(Debug, Clone)]
+pub struct Excerpt {
+    pub xml: String,
+    /// Written number of each measure kept, so excerpt measure `k` is
+    /// `measures[k - 1]` in the full score.
+    pub measures: Vec<i32>,
+}
+
+/// Measures numbered `start..=end` of every part. The `<attributes>` of the
+/// measures skipped before the range are repeated at the top of its first
+/// measure, so divisions, clef, key and meter still apply.
+pub fn excerpt(xml: &str, start: i32, end: i32) -> Result<Excerpt, String> {
+    if start > end {
+        return Err(format!("Invalid measure range {}–{}", start, end));
+    }
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut measures: Vec<i32> = Vec::new();
+
+    let mut part: Option<usize> = None;
+    let mut number = 0;
+    let mut kept = 0;
+    let mut carried: Vec<Event<'static>> = Vec::new();
+
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+        match event {
+            Event::Start(ref e) if is_element(e, "part") => {
+                part = Some(part.map_or(0, |p| p + 1));
+                (number, kept) = (0, 0);
+                carried.clear();
+                writer.write_event(event).map_err(xml_error)?;
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if is_element(e, "measure") => {
+                let is_start = matches!(event, Event::Start(_));
+                number = measure_number(e).unwrap_or(number + 1);
+                if !(start..=end).contains(&number) {
+                    if is_start {
+                        skip_measure(&mut reader, &mut carried)?;
+                    }
+                    continue;
+                }
+
+                kept += 1;
+                if part == Some(0) {
+                    measures.push(number);
+                }
+                let renumbered = with_attribute(e, "number", &kept.to_string());
+                if !is_start {
+                    writer
+                        .write_event(Event::Empty(renumbered))
+                        .map_err(xml_error)?;
+                    continue;
+                }
+                writer
+                    .write_event(Event::Start(renumbered))
+                    .map_err(xml_error)?;
+                if kept == 1 {
+                    for carried in carried.drain(..) {
+                        writer.write_event(carried).map_err(xml_error)?;
+                    }
+                }
+            }
+            Event::Eof => break,
+            event => writer.write_event(event).map_err(xml_error)?,
+        }
+    }
+
+    if measures.is_empty() {
+        return Err(format!("The score has no measures {}–{}", start, end));
+    }
+    let xml = String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)?;
+    Ok(Excerpt { xml, measures })
+}
+
+/// Read a measure left out of the excerpt through its end, adding its
+/// `<attributes>` to `carried`.
+fn skip_measure(
+    reader: &mut Reader<&[u8]>,
+    carried: &mut Vec<Event<'static>>,
+) -> Result<(), String> {
+    let mut in_attributes = false;
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+        let name = match &event {
+            Event::Start(e) => Some(e.local_name().as_ref().to_vec()),
+            Event::End(e) => Some(e.local_name().as_ref().to_vec()),
+            Event::Eof => return Ok(()),
+            _ => None,
+        };
+        match (&event, name.as_deref()) {
+            (Event::End(_), Some(b"measure")) => return Ok(()),
+            (Event::Start(_), Some(b"attributes")) => in_attributes = true,
+            _ => {}
+        }
+        let closes_attributes = matches!(
+            (&event, name.as_deref()),
+            (Event::End(_), Some(b"attributes"))
+        );
+        if in_attributes {
+            carried.push(event.into_owned());
+        }
+        if closes_attributes {
+            in_attributes = false;
+        }
+    }
+}
+
+/// Put a result analyzed from an excerpt back in the full score's frame:
+/// measures take their written numbers, note indices and positions move past
+/// the notes before the range, and `musicxml_content` becomes the full
+/// score, so the result reads as if that score had been analyzed.
+pub fn restore(
+    result: &mut AnalysisResult,
+    excerpt: &Excerpt,
+    original: &str,
+) -> Result<(), String> {
+    let first = excerpt.measures.first().copied().unwrap_or(0);
+    let streams = stream_notes(original)?;
+    for staff in result.staves_mut() {
+        let offset = usize::try_from(staff.part_index)
+            .ok()
+            .and_then(|i| streams.get(i))
+            .map_or(0, |notes| {
+                notes.iter().take_while(|n| n.measure < first).count()
+            }) as i32;
+        for pattern in &mut staff.patterns {
+            for position in &mut pattern.positions {
+                *position += offset;
+            }
+            for note in &mut pattern.notes {
+                note.index += offset;
+                if let Some(&written) = usize::try_from(note.measure - 1)
+                    .ok()
+                    .and_then(|k| excerpt.measures.get(k))
+                {
+                    note.measure = written;
+                }
+            }
+        }
+    }
+    result.musicxml_content = original.to_string();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    /// Four measures of C D E F, divisions and meter set in the first.
+    fn score() -> String {
+        let bar: String = "CDEF"
+            .chars()
+            .map(|step| {
+                format!(
+                    "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                     <duration>1</duration></note>",
+                    step
+                )
+            })
+            .collect();
+        let measures: String = (1..=4)
+            .map(|m| {
+                let attributes = if m == 1 {
+                    "<attributes><divisions>1</divisions><time><beats>4</beats>\
+                     <beat-type>4</beat-type></time></attributes>"
+                } else {
+                    ""
+                };
+                format!(r#"<measure number="{}">{}{}</measure>"#, m, attributes, bar)
+            })
+            .collect();
+        format!(
+            r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+            measures
+        )
+    }
+
+    #[test]
+    fn excerpt_is_renumbered_and_keeps_attributes() {
+        let cut = excerpt(&score(), 3, 4).unwrap();
+        assert_eq!(cut.measures, vec![3, 4]);
+        assert!(cut
+            .xml
+            .contains(r#"<measure number="1"><attributes><divisions>1</divisions>"#));
+        assert_eq!(cut.xml.matches("<measure ").count(), 2);
+
+        let notes = &stream_notes(&cut.xml).unwrap()[0];
+        assert_eq!(notes.len(), 8);
+        assert_eq!(notes[4].measure, 2);
+        assert!(excerpt(&score(), 7, 9).is_err());
+    }
+
+    #[test]
+    fn restored_result_reports_original_measures() {
+        let original = score();
+        let cut = excerpt(&original, 3, 4).unwrap();
+        // As the analyzer reports a pattern C D E F in the excerpt's frame
+        let notes = (0..4)
+            .map(|i| NoteLocator {
+                index: i,
+                measure: 1,
+                ..Default::default()
+            })
+            .collect();
+        let mut result = AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    length: 4,
+                    count: 2,
+                    positions: vec![0, 4],
+                    notes,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: cut.xml.clone(),
+            ..Default::default()
+        };
+
+        restore(&mut result, &cut, &original).unwrap();
+        let pattern = &result.parts[0].patterns[0];
+        assert_eq!(pattern.positions, vec![8, 12]);
+        assert_eq!(pattern.notes[0].measure, 3);
+        assert_eq!(pattern.notes[0].index, 8);
+        assert_eq!(result.musicxml_content, original);
+    }
+}
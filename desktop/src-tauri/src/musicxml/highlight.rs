@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+
+use super::{is_element, read_note_body, staves_per_part, stream_index, xml_error, NoteKind};
+use crate::models::AnalysisResult;
+
+/// Solid pattern colors, kept in sync with `COLORS_SOLID` in `src/utils/color.ts`.
+pub const PATTERN_COLORS: [&str; 8] = [
+    "#0081AF", "#A77F35", "#A14DA0", "#628B48", "#C33C54", "#1D3354", "#72A98F", "#8E7F74",
+];
+
+pub fn pattern_color(pattern_id: i32) -> &'static str {
+    PATTERN_COLORS[pattern_id.rem_euclid(PATTERN_COLORS.len() as i32) as usize]
+}
+
+/// Colors keyed by (stream index, note index), one entry per note covered by
+/// any occurrence of a pattern.
+pub type NoteColors = HashMap<(i32, i32), String>;
+
+pub fn pattern_note_colors(result: &AnalysisResult) -> NoteColors {
+    let mut colors = NoteColors::new();
+    for staff in result.staves() {
+        for pattern in &staff.patterns {
+            for &pos in &pattern.positions {
+                for index in pos..pos + pattern.length {
+                    colors.insert(
+                        (staff.part_index, index),
+                        pattern_color(pattern.id).to_string(),
+                    );
+                }
+            }
+        }
+    }
+    colors
+}
+
+/// Rewrite `xml` with a `color` attribute on every `<note>` listed in `colors`.
+/// Chord tones take the color of the chord they belong to.
+pub fn highlight(xml: &str, colors: &NoteColors) -> Result<String, String> {
+    let staves = staves_per_part(xml)?;
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+
+    let mut part: Option<usize> = None;
+    let mut counters: HashMap<i32, i32> = HashMap::new();
+    let mut chord_colors: HashMap<i32, Option<String>> = HashMap::new();
+
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+        match event {
+            Event::Start(ref e) if is_element(e, "part") => {
+                part = Some(part.map_or(0, |p| p + 1));
+                writer.write_event(event).map_err(xml_error)?;
+            }
+            Event::Start(e) if is_element(&e, "note") => {
+                let start = e.into_owned();
+                let body = read_note_body(&mut reader)?;
+
+                let kind = NoteKind::from_events(&body);
+                let color = if kind.rest || kind.grace {
+                    None
+                } else {
+                    let stream = stream_index(&staves, part.unwrap_or(0), kind.staff);
+                    if kind.chord {
+                        chord_colors.get(&stream).cloned().flatten()
+                    } else {
+                        let counter = counters.entry(stream).or_insert(0);
+                        let color = colors.get(&(stream, *counter)).cloned();
+                        *counter += 1;
+                        chord_colors.insert(stream, color.clone());
+                        color
+                    }
+                };
+
+                let start = match color {
+                    Some(color) => with_attribute(&start, "color", &color),
+                    None => start,
+                };
+                writer.write_event(Event::Start(start)).map_err(xml_error)?;
+                for inner in body {
+                    writer.write_event(inner).map_err(xml_error)?;
+                }
+            }
+            Event::Eof => break,
+            event => writer.write_event(event).map_err(xml_error)?,
+        }
+    }
+
+    String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)
+}
+
+/// Copy of `start` with `name` set to `value`, replacing any existing value.
+pub fn with_attribute(start: &BytesStart, name: &str, value: &str) -> BytesStart<'static> {
+    let mut updated = BytesStart::new(String::from_utf8_lossy(start.name().as_ref()).into_owned());
+    updated.extend_attributes(
+        start
+            .attributes()
+            .flatten()
+            .filter(|a| a.key.as_ref() != name.as_bytes()),
+    );
+    updated.push_attribute((name, value));
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCORE: &str = r#"<score-partwise><part id="P1"><measure number="1">
+<note><pitch><step>C</step><octave>4</octave></pitch></note>
+<note><rest/></note>
+<note><pitch><step>E</step><octave>4</octave></pitch></note>
+<note><chord/><pitch><step>G</step><octave>4</octave></pitch></note>
+<note><pitch><step>D</step><octave>4</octave></pitch></note>
+</measure></part></score-partwise>"#;
+
+    #[test]
+    fn colors_notes_by_music21_index() {
+        let mut colors = NoteColors::new();
+        colors.insert((0, 1), "#FF0000".to_string());
+
+        let out = highlight(SCORE, &colors).unwrap();
+        // E4 is note index 1 (the rest is skipped) and its chord tone follows it
+        assert_eq!(out.matches(r##"color="#FF0000""##).count(), 2);
+        assert!(out.contains(r##"<note color="#FF0000"><pitch><step>E"##));
+        assert!(out.contains("<note><pitch><step>D"));
+    }
+
+    #[test]
+    fn grand_staff_notes_map_to_separate_streams() {
+        let xml = r#"<score-partwise><part id="P1"><measure number="1">
+<attributes><staves>2</staves></attributes>
+<note><pitch><step>C</step><octave>5</octave></pitch><staff>1</staff></note>
+<backup><duration>4</duration></backup>
+<note><pitch><step>C</step><octave>3</octave></pitch><staff>2</staff></note>
+</measure></part></score-partwise>"#;
+        let mut colors = NoteColors::new();
+        colors.insert((1, 0), "#00FF00".to_string());
+
+        let out = highlight(xml, &colors).unwrap();
+        assert!(out.contains(r##"<note color="#00FF00"><pitch><step>C</step><octave>3"##));
+        assert_eq!(out.matches("color=").count(), 1);
+    }
+}
@@ -0,0 +1,159 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+
+use super::{attribute, is_element, measure_number, xml_error};
+use crate::keys::{Key, Mode};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+    Time,
+    Key,
+}
+
+/// A meter or key taking effect at a measure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StructuralMarker {
+    pub measure: i32,
+    pub kind: MarkerKind,
+    /// "6/8", "common" or "G major".
+    pub value: String,
+}
+
+/// Every time and key signature in the first part, including the opening
+/// ones. A signature restating the one in force isn't a change and is left
+/// out.
+pub fn structural_markers(xml: &str) -> Result<Vec<StructuralMarker>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut markers: Vec<StructuralMarker> = Vec::new();
+    let mut measure = 0;
+    let mut element: Option<Vec<u8>> = None;
+    // Pieces of the signature being read
+    let mut time_symbol: Option<String> = None;
+    let (mut beats, mut beat_type) = (String::new(), String::new());
+    let (mut fifths, mut mode): (Option<i32>, Option<String>) = (None, None);
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::End(e) if e.local_name().as_ref() == b"part" => break,
+            Event::Start(e) if is_element(&e, "measure") => {
+                measure = measure_number(&e).unwrap_or(measure + 1);
+            }
+            Event::Start(e) => {
+                if is_element(&e, "time") {
+                    time_symbol = attribute(&e, "symbol");
+                    beats.clear();
+                    beat_type.clear();
+                } else if is_element(&e, "key") {
+                    fifths = None;
+                    mode = None;
+                }
+                element = Some(e.local_name().as_ref().to_vec());
+            }
+            Event::Text(t) => {
+                let text = t.unescape().map_err(xml_error)?;
+                let text = text.trim();
+                match element.as_deref() {
+                    Some(b"beats") => beats.push_str(text),
+                    Some(b"beat-type") => beat_type.push_str(text),
+                    Some(b"fifths") => fifths = text.parse().ok(),
+                    Some(b"mode") => mode = Some(text.to_string()),
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                match e.local_name().as_ref() {
+                    b"time" => {
+                        let value = match time_symbol.take().as_deref() {
+                            Some(symbol @ ("common" | "cut")) => symbol.to_string(),
+                            _ => format!("{}/{}", beats, beat_type),
+                        };
+                        push_change(&mut markers, measure, MarkerKind::Time, value);
+                    }
+                    b"key" => {
+                        if let Some(fifths) = fifths {
+                            let key =
+                                Key::from_fifths(fifths, Mode::from_musicxml(mode.as_deref()));
+                            let value = format!("{} {}", key.tonic, key.mode.as_str());
+                            push_change(&mut markers, measure, MarkerKind::Key, value);
+                        }
+                    }
+                    _ => {}
+                }
+                element = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(markers)
+}
+
+fn push_change(markers: &mut Vec<StructuralMarker>, measure: i32, kind: MarkerKind, value: String) {
+    let current = markers.iter().rev().find(|m| m.kind == kind);
+    if current.is_none_or(|m| m.value != value) {
+        markers.push(StructuralMarker {
+            measure,
+            kind,
+            value,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(fifths: i32, beats: u32, beat_type: u32) -> String {
+        format!(
+            "<attributes><key><fifths>{}</fifths><mode>major</mode></key>\
+             <time><beats>{}</beats><beat-type>{}</beat-type></time></attributes>",
+            fifths, beats, beat_type
+        )
+    }
+
+    #[test]
+    fn reports_meter_and_key_changes() {
+        let xml = format!(
+            r#"<score-partwise><part id="P1">
+<measure number="1">{}</measure>
+<measure number="2"/>
+<measure number="3">{}</measure>
+<measure number="4"/>
+</part><part id="P2"><measure number="1">{}</measure></part></score-partwise>"#,
+            attributes(0, 4, 4),
+            attributes(1, 6, 8),
+            attributes(0, 4, 4)
+        );
+
+        let markers = structural_markers(&xml).unwrap();
+        let marker = |measure, kind, value: &str| StructuralMarker {
+            measure,
+            kind,
+            value: value.to_string(),
+        };
+        assert_eq!(
+            markers,
+            vec![
+                marker(1, MarkerKind::Key, "C major"),
+                marker(1, MarkerKind::Time, "4/4"),
+                marker(3, MarkerKind::Key, "G major"),
+                marker(3, MarkerKind::Time, "6/8"),
+            ]
+        );
+    }
+
+    #[test]
+    fn restated_signature_is_not_a_change() {
+        let xml = format!(
+            r#"<score-partwise><part id="P1"><measure number="1">{a}</measure>
+<measure number="2">{a}</measure></part></score-partwise>"#,
+            a = attributes(-2, 3, 4)
+        );
+        let markers = structural_markers(&xml).unwrap();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].value, "B- major");
+    }
+}
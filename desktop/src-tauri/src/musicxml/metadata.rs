@@ -0,0 +1,187 @@
+//! Title, composer and size of a score, for listing it without opening it
+//! and for previewing it before it is analyzed.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+use super::parts::{list_parts, PartInfo};
+use super::{attribute, is_element, xml_error};
+
+/// Seconds an analyzer run takes before it looks at a note: starting Python,
+/// importing music21 and parsing the file.
+const STARTUP_SECONDS: f64 = 2.0;
+
+/// Seconds per pair of notes compared within a staff; matching compares
+/// every note with every later one.
+const SECONDS_PER_PAIR: f64 = 2e-7;
+
+/// Staves analyzed when `AnalyzerConfig.parts` isn't set: a treble and a
+/// bass.
+pub const DEFAULT_STAVES: usize = 2;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoreMetadata {
+    /// `<work-title>`, or `<movement-title>` when there is none.
+    pub title: Option<String>,
+    /// The `<creator type="composer">`.
+    pub composer: Option<String>,
+    /// `<score-part>`s in the part list.
+    pub parts: usize,
+    /// The `<part-name>` of each of them, empty where the score gives none.
+    #[serde(default)]
+    pub part_names: Vec<String>,
+}
+
+/// What `get_score_metadata` shows of a score before it is analyzed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScorePreview {
+    #[serde(flatten)]
+    pub metadata: ScoreMetadata,
+    /// Measures in the first part.
+    pub measures: usize,
+    /// The staves it can be analyzed by, as `list_parts` gives them.
+    pub staves: Vec<PartInfo>,
+    /// A rough guess at how long analyzing the default staves takes.
+    pub estimated_seconds: f64,
+}
+
+pub fn read_metadata(xml: &str) -> Result<ScoreMetadata, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut metadata = ScoreMetadata::default();
+    let mut movement_title = None;
+    // The element whose text is being read
+    let mut field: Option<&str> = None;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if is_element(&e, "work-title") => field = Some("title"),
+            Event::Start(e) if is_element(&e, "movement-title") => field = Some("movement"),
+            Event::Start(e)
+                if is_element(&e, "creator")
+                    && attribute(&e, "type").as_deref() == Some("composer") =>
+            {
+                field = Some("composer");
+            }
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "score-part") => {
+                metadata.parts += 1;
+                metadata.part_names.push(String::new());
+            }
+            Event::Start(e) if is_element(&e, "part-name") => field = Some("part-name"),
+            Event::Text(t) => {
+                let text = t.unescape().map_err(xml_error)?.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                match field.take() {
+                    Some("title") => metadata.title = Some(text),
+                    Some("movement") => movement_title = Some(text),
+                    Some("composer") => metadata.composer = Some(text),
+                    Some("part-name") => {
+                        if let Some(name) = metadata.part_names.last_mut() {
+                            *name = text;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(_) => field = None,
+            // Everything of interest comes before the first part
+            Event::Start(e) if is_element(&e, "part") => break,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    metadata.title = metadata.title.or(movement_title);
+    Ok(metadata)
+}
+
+pub fn read_preview(xml: &str) -> Result<ScorePreview, String> {
+    let staves = list_parts(xml)?;
+    let notes = staves.iter().take(DEFAULT_STAVES).map(|staff| staff.notes);
+    Ok(ScorePreview {
+        metadata: read_metadata(xml)?,
+        measures: first_part_measures(xml)?,
+        estimated_seconds: estimate_seconds(notes),
+        staves,
+    })
+}
+
+/// Seconds an analysis of staves with these note counts might take.
+pub fn estimate_seconds(notes: impl IntoIterator<Item = usize>) -> f64 {
+    let pairs: f64 = notes
+        .into_iter()
+        .map(|n| (n * n.saturating_sub(1) / 2) as f64)
+        .sum();
+    STARTUP_SECONDS + pairs * SECONDS_PER_PAIR
+}
+
+fn first_part_measures(xml: &str) -> Result<usize, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut measures = 0;
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "measure") => measures += 1,
+            Event::End(e) if e.local_name().as_ref() == b"part" => break,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(measures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_title_composer_and_parts() {
+        let xml = r#"<score-partwise><work><work-title>Für Elise</work-title></work>
+            <movement-title>WoO 59</movement-title>
+            <identification><creator type="lyricist">Nobody</creator>
+            <creator type="composer">Ludwig van Beethoven</creator></identification>
+            <part-list><score-part id="P1"/><score-part id="P2"><part-name>B</part-name></score-part></part-list>
+            <part id="P1"/></score-partwise>"#;
+        let metadata = read_metadata(xml).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Für Elise"));
+        assert_eq!(metadata.composer.as_deref(), Some("Ludwig van Beethoven"));
+        assert_eq!(metadata.parts, 2);
+        assert_eq!(metadata.part_names, vec!["", "B"]);
+
+        let untitled =
+            read_metadata("<score-partwise><movement-title>Trio</movement-title></score-partwise>")
+                .unwrap();
+        assert_eq!(untitled.title.as_deref(), Some("Trio"));
+        assert_eq!(untitled.composer, None);
+    }
+
+    #[test]
+    fn previews_measures_staves_and_a_time_estimate() {
+        let part = |id: &str, notes: usize| {
+            let note = "<note><pitch><step>C</step><octave>4</octave></pitch><duration>1</duration></note>";
+            format!(
+                r#"<part id="{}"><measure number="1">{}</measure><measure number="2"/></part>"#,
+                id,
+                note.repeat(notes)
+            )
+        };
+        let xml = format!(
+            r#"<score-partwise><work><work-title>Bourrée</work-title></work>
+            <part-list><score-part id="P1"><part-name>Lute</part-name></score-part>
+            <score-part id="P2"><part-name>Bass</part-name></score-part></part-list>
+            {}{}</score-partwise>"#,
+            part("P1", 4),
+            part("P2", 3)
+        );
+        let preview = read_preview(&xml).unwrap();
+        assert_eq!(preview.metadata.title.as_deref(), Some("Bourrée"));
+        assert_eq!(preview.metadata.part_names, vec!["Lute", "Bass"]);
+        assert_eq!(preview.measures, 2);
+        assert_eq!(
+            preview.staves.iter().map(|s| s.notes).collect::<Vec<_>>(),
+            vec![4, 3]
+        );
+        assert_eq!(preview.estimated_seconds, estimate_seconds([4, 3]));
+        assert!(estimate_seconds([10_000]) > estimate_seconds([100, 100]));
+    }
+}
@@ -0,0 +1,467 @@
+//! Standard MIDI files (`.mid`) converted to MusicXML, so they go through
+//! the same analysis as scores and their notes get measures and beats.
+//!
+//! Onsets and lengths are quantized to sixteenth notes. Each track with
+//! notes becomes a part holding one voice: notes starting together form a
+//! chord, each held until the next onset at the latest, and notes crossing
+//! a barline are split and tied. Percussion (channel 10) is left out.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use quick_xml::escape::escape;
+
+/// `<divisions>` of the generated score: one per sixteenth note.
+pub const DIVISIONS: i64 = 4;
+
+/// Zero-based channel 10, which General MIDI reserves for drums.
+const PERCUSSION_CHANNEL: u8 = 9;
+
+const SHARP_NAMES: [(&str, i32); 12] = [
+    ("C", 0),
+    ("C", 1),
+    ("D", 0),
+    ("D", 1),
+    ("E", 0),
+    ("F", 0),
+    ("F", 1),
+    ("G", 0),
+    ("G", 1),
+    ("A", 0),
+    ("A", 1),
+    ("B", 0),
+];
+const FLAT_NAMES: [(&str, i32); 12] = [
+    ("C", 0),
+    ("D", -1),
+    ("D", 0),
+    ("E", -1),
+    ("E", 0),
+    ("F", 0),
+    ("G", -1),
+    ("G", 0),
+    ("A", -1),
+    ("A", 0),
+    ("B", -1),
+    ("B", 0),
+];
+
+/// Note values in sixteenths, longest first, with their `<type>` and
+/// whether they're dotted.
+const NOTE_VALUES: [(i64, &str, bool); 8] = [
+    (16, "whole", false),
+    (12, "half", true),
+    (8, "half", false),
+    (6, "quarter", true),
+    (4, "quarter", false),
+    (3, "eighth", true),
+    (2, "eighth", false),
+    (1, "16th", false),
+];
+
+pub fn is_midi(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mid") || e.eq_ignore_ascii_case("midi"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MidiNote {
+    key: u8,
+    start: i64,
+    end: i64,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+    name: Option<String>,
+    notes: Vec<MidiNote>,
+}
+
+/// What the conversion needs of a parsed file.
+#[derive(Debug)]
+struct Smf {
+    ticks_per_quarter: i64,
+    tracks: Vec<Track>,
+    /// The first time signature, as (beats, beat type).
+    time: Option<(i64, i64)>,
+    /// The first key signature, in fifths.
+    fifths: Option<i32>,
+    /// The first tempo, in quarter notes per minute.
+    tempo: Option<f64>,
+}
+
+/// The tempo, meter, key and notes of a MIDI file, as parsed by `midly`.
+fn parse(bytes: &[u8]) -> Result<Smf, String> {
+    let file = midly::Smf::parse(bytes).map_err(midi_error)?;
+    let ticks_per_quarter = match file.header.timing {
+        midly::Timing::Metrical(ticks) if ticks.as_int() > 0 => i64::from(ticks.as_int()),
+        _ => return Err(midi_error("SMPTE timing isn't supported")),
+    };
+
+    let mut smf = Smf {
+        ticks_per_quarter,
+        tracks: Vec::new(),
+        time: None,
+        fifths: None,
+        tempo: None,
+    };
+    for events in &file.tracks {
+        let track = read_track(events, &mut smf);
+        smf.tracks.push(track);
+    }
+    Ok(smf)
+}
+
+fn read_track(events: &[midly::TrackEvent], smf: &mut Smf) -> Track {
+    use midly::{MetaMessage, MidiMessage, TrackEventKind};
+
+    let mut track = Track::default();
+    // Start ticks of sounding notes by (channel, key), oldest first
+    let mut sounding: HashMap<(u8, u8), Vec<i64>> = HashMap::new();
+    let mut tick = 0i64;
+
+    for event in events {
+        tick += i64::from(event.delta.as_int());
+        match event.kind {
+            TrackEventKind::Meta(meta) => match meta {
+                MetaMessage::TrackName(name) if track.name.is_none() => {
+                    let name = String::from_utf8_lossy(name).trim().to_string();
+                    track.name = Some(name).filter(|n| !n.is_empty());
+                }
+                MetaMessage::EndOfTrack => break,
+                MetaMessage::Tempo(micros) if smf.tempo.is_none() && micros.as_int() > 0 => {
+                    smf.tempo = Some(60_000_000.0 / f64::from(micros.as_int()));
+                }
+                MetaMessage::TimeSignature(beats, beat_type, ..) if smf.time.is_none() => {
+                    smf.time = Some((i64::from(beats), 1i64 << beat_type.min(6)));
+                }
+                MetaMessage::KeySignature(fifths, _) if smf.fifths.is_none() => {
+                    smf.fifths = Some(i32::from(fifths));
+                }
+                _ => {}
+            },
+            TrackEventKind::Midi { channel, message } => {
+                if channel.as_int() == PERCUSSION_CHANNEL {
+                    continue;
+                }
+                let channel = channel.as_int();
+                match message {
+                    MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                        sounding
+                            .entry((channel, key.as_int()))
+                            .or_default()
+                            .push(tick);
+                    }
+                    MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                        let key = key.as_int();
+                        let started = sounding.get_mut(&(channel, key));
+                        if let Some(start) = started.filter(|s| !s.is_empty()).map(|s| s.remove(0))
+                        {
+                            track.notes.push(MidiNote {
+                                key,
+                                start,
+                                end: tick,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Notes never released end with the track
+    for ((_, key), starts) in sounding {
+        for start in starts {
+            track.notes.push(MidiNote {
+                key,
+                start,
+                end: tick,
+            });
+        }
+    }
+    track.notes.sort_by_key(|n| (n.start, n.key));
+    track
+}
+
+/// A stretch of one part: a chord (rest when `keys` is empty) held for
+/// `length` sixteenths from `start`.
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    start: i64,
+    length: i64,
+    keys: Vec<u8>,
+}
+
+/// The chords and rests of a track, on the sixteenth-note grid.
+fn segments(notes: &[MidiNote], ticks_per_quarter: i64) -> Vec<Segment> {
+    let quantize = |tick: i64| (tick * DIVISIONS * 2 + ticks_per_quarter) / (ticks_per_quarter * 2);
+    // Keys and latest end of the notes starting at each onset
+    let mut onsets: BTreeMap<i64, (Vec<u8>, i64)> = BTreeMap::new();
+    for note in notes {
+        let start = quantize(note.start);
+        let end = quantize(note.end).max(start + 1);
+        let (keys, latest) = onsets.entry(start).or_default();
+        if !keys.contains(&note.key) {
+            keys.push(note.key);
+        }
+        *latest = (*latest).max(end);
+    }
+
+    let mut out = Vec::new();
+    let mut time = 0;
+    let starts: Vec<i64> = onsets.keys().copied().collect();
+    for (i, (&start, (keys, latest))) in onsets.iter().enumerate() {
+        if start > time {
+            out.push(Segment {
+                start: time,
+                length: start - time,
+                keys: Vec::new(),
+            });
+        }
+        let end = starts
+            .get(i + 1)
+            .map_or(*latest, |&next| (*latest).min(next));
+        let mut keys = keys.clone();
+        keys.sort_unstable();
+        out.push(Segment {
+            start,
+            length: end - start,
+            keys,
+        });
+        time = end;
+    }
+    out
+}
+
+/// Convert a MIDI file to MusicXML, one part per track with notes.
+pub fn to_musicxml(bytes: &[u8]) -> Result<String, String> {
+    let smf = parse(bytes)?;
+    let (beats, beat_type) = smf
+        .time
+        .filter(|&(beats, beat_type)| beats > 0 && (beats * 16) % beat_type == 0)
+        .unwrap_or((4, 4));
+    let measure_length = beats * 16 / beat_type;
+    let fifths = smf.fifths.unwrap_or(0).clamp(-7, 7);
+    let names = if fifths < 0 {
+        &FLAT_NAMES
+    } else {
+        &SHARP_NAMES
+    };
+
+    let tracks: Vec<&Track> = smf.tracks.iter().filter(|t| !t.notes.is_empty()).collect();
+    if tracks.is_empty() {
+        return Err(midi_error("no notes"));
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<score-partwise version=\"3.1\">\n<part-list>\n",
+    );
+    for (i, track) in tracks.iter().enumerate() {
+        let name = track
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Track {}", i + 1));
+        let _ = writeln!(
+            xml,
+            "<score-part id=\"P{}\"><part-name>{}</part-name></score-part>",
+            i + 1,
+            escape(&name)
+        );
+    }
+    xml.push_str("</part-list>\n");
+
+    for (i, track) in tracks.iter().enumerate() {
+        let mean_key =
+            track.notes.iter().map(|n| f64::from(n.key)).sum::<f64>() / track.notes.len() as f64;
+        let clef = if mean_key >= 60.0 {
+            "<sign>G</sign><line>2</line>"
+        } else {
+            "<sign>F</sign><line>4</line>"
+        };
+        let _ = writeln!(xml, "<part id=\"P{}\">", i + 1);
+        let _ = write!(
+            xml,
+            "<measure number=\"1\">\n<attributes><divisions>{}</divisions>\
+             <key><fifths>{}</fifths></key><time><beats>{}</beats><beat-type>{}</beat-type></time>\
+             <clef>{}</clef></attributes>\n",
+            DIVISIONS, fifths, beats, beat_type, clef
+        );
+        if let (0, Some(tempo)) = (i, smf.tempo) {
+            let _ = writeln!(
+                xml,
+                "<direction placement=\"above\"><direction-type><words/></direction-type>\
+                 <sound tempo=\"{:.2}\"/></direction>",
+                tempo
+            );
+        }
+
+        let mut measure = 0;
+        let mut segments = segments(&track.notes, smf.ticks_per_quarter);
+        // Rest to the end of the last measure
+        if let Some(last) = segments.last() {
+            let end = last.start + last.length;
+            let filled = (end + measure_length - 1) / measure_length * measure_length;
+            if filled > end {
+                segments.push(Segment {
+                    start: end,
+                    length: filled - end,
+                    keys: Vec::new(),
+                });
+            }
+        }
+        for segment in segments {
+            let mut time = segment.start;
+            let end = segment.start + segment.length;
+            while time < end {
+                let bar = time / measure_length;
+                if bar > measure {
+                    measure = bar;
+                    let _ = write!(xml, "</measure>\n<measure number=\"{}\">\n", measure + 1);
+                }
+                let room = ((bar + 1) * measure_length).min(end) - time;
+                let value = NOTE_VALUES
+                    .iter()
+                    .find(|v| v.0 <= room)
+                    .copied()
+                    .unwrap_or(NOTE_VALUES[NOTE_VALUES.len() - 1]);
+                let tie_stop = time > segment.start;
+                let tie_start = time + value.0 < end;
+                write_note(&mut xml, &segment.keys, value, names, tie_stop, tie_start);
+                time += value.0;
+            }
+        }
+        xml.push_str("</measure>\n</part>\n");
+    }
+    xml.push_str("</score-partwise>\n");
+    Ok(xml)
+}
+
+fn write_note(
+    xml: &mut String,
+    keys: &[u8],
+    (length, kind, dotted): (i64, &str, bool),
+    names: &[(&str, i32); 12],
+    tie_stop: bool,
+    tie_start: bool,
+) {
+    let dot = if dotted { "<dot/>" } else { "" };
+    if keys.is_empty() {
+        let _ = writeln!(
+            xml,
+            "<note><rest/><duration>{}</duration><type>{}</type>{}</note>",
+            length, kind, dot
+        );
+        return;
+    }
+    for (i, &key) in keys.iter().enumerate() {
+        let (step, alter) = names[usize::from(key % 12)];
+        let octave = i32::from(key) / 12 - 1;
+        let chord = if i > 0 { "<chord/>" } else { "" };
+        let alter = if alter != 0 {
+            format!("<alter>{}</alter>", alter)
+        } else {
+            String::new()
+        };
+        let (mut ties, mut tied) = (String::new(), String::new());
+        for (on, kind) in [(tie_stop, "stop"), (tie_start, "start")] {
+            if on {
+                let _ = write!(ties, "<tie type=\"{}\"/>", kind);
+                let _ = write!(tied, "<tied type=\"{}\"/>", kind);
+            }
+        }
+        let notations = if tied.is_empty() {
+            String::new()
+        } else {
+            format!("<notations>{}</notations>", tied)
+        };
+        let _ = writeln!(
+            xml,
+            "<note>{}<pitch><step>{}</step>{}<octave>{}</octave></pitch>\
+             <duration>{}</duration>{}<type>{}</type>{}{}</note>",
+            chord, step, alter, octave, length, ties, kind, dot, notations
+        );
+    }
+}
+
+fn midi_error(e: impl std::fmt::Display) -> String {
+    format!("Invalid MIDI file: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A format 1 file with 480 ticks per quarter and the given track bodies.
+    fn smf(tracks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = b"MThd\0\0\0\x06\0\x01".to_vec();
+        bytes.extend((tracks.len() as u16).to_be_bytes());
+        bytes.extend(480u16.to_be_bytes());
+        for track in tracks {
+            bytes.extend(b"MTrk");
+            bytes.extend((track.len() as u32 + 4).to_be_bytes());
+            bytes.extend(track);
+            bytes.extend([0x00, 0xFF, 0x2F, 0x00]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn notes_are_quantized_into_measures() {
+        // A conductor track with 3/4 at 100 bpm, then a melody
+        let conductor = vec![
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, // 3/4
+            0x00, 0xFF, 0x51, 0x03, 0x09, 0x27, 0xC0, // 600000 µs = 100 bpm
+        ];
+        let melody = vec![
+            0x00, 0xFF, 0x03, 0x04, b'L', b'e', b'a', b'd', // name
+            // A slightly late C4 quarter, with running status for its release
+            0x0A, 0x90, 60, 80, 0x83, 0x5C, 60, 0, // on at 10, off at 486
+            // E4+G4 quarter chord, then a D4 half crossing the barline
+            0x00, 0x90, 64, 80, 0x00, 67, 80, 0x83, 0x60, 0x80, 64, 0, 0x00, 0x80, 67, 0, 0x00,
+            0x90, 62, 80, 0x87, 0x40, 0x80, 62, 0,
+        ];
+        let xml = to_musicxml(&smf(&[conductor, melody])).unwrap();
+
+        assert!(xml.contains("<part-name>Lead</part-name>"));
+        assert_eq!(xml.matches("<score-part ").count(), 1);
+        assert!(xml.contains("<beats>3</beats><beat-type>4</beat-type>"));
+        assert!(xml.contains("<sound tempo=\"100.00\"/>"));
+        assert!(xml.contains(
+            "<note><pitch><step>C</step><octave>4</octave></pitch><duration>4</duration><type>quarter</type></note>"
+        ));
+        assert!(xml.contains("<note><chord/><pitch><step>G</step>"));
+        // D4 fills the rest of bar 1 and is tied into bar 2
+        let bar2 = &xml[xml.find("<measure number=\"2\">").unwrap()..];
+        assert!(bar2.starts_with(
+            "<measure number=\"2\">\n<note><pitch><step>D</step><octave>4</octave></pitch>\
+             <duration>4</duration><tie type=\"stop\"/>"
+        ));
+        // Padded with a rest to a whole bar
+        assert!(bar2.contains("<note><rest/><duration>8</duration><type>half</type></note>"));
+        assert_eq!(xml.matches("<measure ").count(), 2);
+    }
+
+    #[test]
+    fn drums_and_empty_tracks_are_dropped() {
+        let drums = vec![0x00, 0x99, 36, 100, 0x83, 0x60, 0x89, 36, 0];
+        let piano = vec![
+            0x00, 0xFF, 0x59, 0x02, 0xFD, 0x00, // 3 flats
+            0x00, 0x90, 51, 80, 0x83, 0x60, 0x90, 51, 0,
+        ];
+        let xml = to_musicxml(&smf(&[drums.clone(), piano])).unwrap();
+        assert_eq!(xml.matches("<score-part ").count(), 1);
+        assert!(xml.contains("<part-name>Track 1</part-name>"));
+        // Eb3, spelled with the key's flats, on a bass clef
+        assert!(xml.contains("<step>E</step><alter>-1</alter><octave>3</octave>"));
+        assert!(xml.contains("<sign>F</sign>"));
+
+        assert_eq!(
+            to_musicxml(&smf(&[drums])).unwrap_err(),
+            "Invalid MIDI file: no notes"
+        );
+        assert!(to_musicxml(b"RIFF").is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Native MusicXML handling for features that don't need the analyzer.
+//!
+//! Note indices follow music21's numbering so they line up with
+//! `NoteLocator.index`: each `<part>` staff is its own stream (a two-staff
+//! piano part becomes two streams, the way music21 splits it into
+//! `PartStaff`s), rests and grace notes are skipped (the analyzer's
+//! default), and a chord counts once.
+
+pub mod annotate;
+pub mod excerpt;
+pub mod highlight;
+pub mod markers;
+pub mod metadata;
+pub mod midi;
+pub mod mxl;
+pub mod parts;
+pub mod repeats;
+pub mod timing;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::models::NoteLocator;
+
+/// Number of staves in each `<part>`, in document order.
+pub fn staves_per_part(xml: &str) -> Result<Vec<u32>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut staves = Vec::new();
+    let mut in_staves = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if e.local_name().as_ref() == b"part" => staves.push(1),
+            Event::Start(e) if e.local_name().as_ref() == b"staves" => in_staves = true,
+            Event::Start(e) if e.local_name().as_ref() == b"staff" => in_staves = true,
+            Event::Text(t) if in_staves => {
+                let n: u32 = t.unescape().map_err(xml_error)?.trim().parse().unwrap_or(1);
+                if let Some(count) = staves.last_mut() {
+                    *count = (*count).max(n);
+                }
+            }
+            Event::End(_) => in_staves = false,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(staves)
+}
+
+/// Stream index of a staff within a part, given `staves_per_part` output.
+pub fn stream_index(staves: &[u32], part: usize, staff: u32) -> i32 {
+    let before: u32 = staves.iter().take(part).sum();
+    (before + staff.max(1) - 1) as i32
+}
+
+/// Every note of every stream, numbered like `NoteLocator.index`, with its
+/// length in `duration_beats`. A chord is one entry carrying its last-listed
+/// tone, as the analyzer matches it.
+pub fn stream_notes(xml: &str) -> Result<Vec<Vec<NoteLocator>>, String> {
+    Ok(read_streams(xml)?
+        .into_iter()
+        .map(|stream| stream.into_iter().map(|(note, _)| note).collect())
+        .collect())
+}
+
+/// Onset of every note of `stream_notes`, in quarter notes from the start
+/// of its part. Measures are laid end to end in document order, as in
+/// `timing::measure_timings`, so this matches a note's `absolute_beat`.
+pub fn stream_onsets(xml: &str) -> Result<Vec<Vec<f64>>, String> {
+    Ok(read_streams(xml)?
+        .into_iter()
+        .map(|stream| stream.into_iter().map(|(_, onset)| onset).collect())
+        .collect())
+}
+
+/// `stream_notes` paired with `stream_onsets`.
+pub fn read_streams(xml: &str) -> Result<Vec<Vec<(NoteLocator, f64)>>, String> {
+    let staves = staves_per_part(xml)?;
+    let mut reader = Reader::from_str(xml);
+    let mut streams: Vec<Vec<(NoteLocator, f64)>> =
+        vec![Vec::new(); staves.iter().sum::<u32>() as usize];
+    // Slurs open in each stream
+    let mut slurs = vec![0u32; streams.len()];
+
+    let mut part: Option<usize> = None;
+    let mut measure = 0;
+    // Time is tracked in quarter notes: where the current measure starts,
+    // the `<note>`/`<backup>`/`<forward>` cursor within it, and the furthest
+    // the cursor got
+    let mut meter = timing::Meter::default();
+    let mut divisions = 1.0;
+    let (mut measure_start, mut cursor, mut filled) = (0.0, 0.0, 0.0);
+    let mut element: Option<Vec<u8>> = None;
+    let mut container: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if is_element(&e, "part") => {
+                part = Some(part.map_or(0, |p| p + 1));
+                measure_start = 0.0;
+            }
+            Event::Start(e) if is_element(&e, "measure") => {
+                measure = measure_number(&e).unwrap_or(measure + 1);
+                (cursor, filled) = (0.0, 0.0);
+            }
+            Event::Start(e) if is_element(&e, "note") => {
+                let kind = NoteKind::from_events(&read_note_body(&mut reader)?);
+                let onset = measure_start + cursor;
+                let quarters = kind.duration.unwrap_or(0.0) / divisions;
+                if !kind.chord && !kind.grace {
+                    cursor += quarters;
+                    filled = f64::max(filled, cursor);
+                }
+                if kind.rest || kind.grace {
+                    continue;
+                }
+                let stream = stream_index(&staves, part.unwrap_or(0), kind.staff) as usize;
+                let (Some(notes), Some(open)) = (streams.get_mut(stream), slurs.get_mut(stream))
+                else {
+                    continue;
+                };
+                let mut articulations = kind.articulations;
+                if *open > 0 || kind.slur_start || kind.slur_stop {
+                    articulations.push("slur".to_string());
+                }
+                *open = (*open + u32::from(kind.slur_start)).saturating_sub(kind.slur_stop.into());
+
+                let pitch = kind.pitch.unwrap_or_default();
+                match notes.last_mut() {
+                    Some((last, _)) if kind.chord => {
+                        last.pitch = pitch;
+                        for articulation in articulations {
+                            if !last.articulations.contains(&articulation) {
+                                last.articulations.push(articulation);
+                            }
+                        }
+                    }
+                    _ => notes.push((
+                        NoteLocator {
+                            index: notes.len() as i32,
+                            measure,
+                            pitch,
+                            duration_beats: Some(quarters),
+                            articulations,
+                            ..Default::default()
+                        },
+                        onset,
+                    )),
+                }
+            }
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if matches!(name.as_slice(), b"backup" | b"forward") {
+                    container = Some(name.clone());
+                }
+                element = Some(name);
+            }
+            Event::Text(t) => {
+                let Some(name) = element.as_deref() else {
+                    continue;
+                };
+                let text = t.unescape().map_err(xml_error)?;
+                let text = text.trim();
+                match name {
+                    b"divisions" => divisions = text.parse().unwrap_or(divisions),
+                    b"beats" => meter.beats = text.parse().unwrap_or(meter.beats),
+                    b"beat-type" => meter.beat_type = text.parse().unwrap_or(meter.beat_type),
+                    b"duration" => {
+                        let quarters = text.parse::<f64>().unwrap_or(0.0) / divisions;
+                        match container.as_deref() {
+                            Some(b"backup") => cursor -= quarters,
+                            Some(_) => cursor += quarters,
+                            None => {}
+                        }
+                        filled = f64::max(filled, cursor);
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                if name.as_ref() == b"measure" {
+                    measure_start += if filled > 0.0 {
+                        filled
+                    } else {
+                        meter.bar_length()
+                    };
+                }
+                if container.as_deref() == Some(name.as_ref()) {
+                    container = None;
+                }
+                element = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(streams)
+}
+
+/// `<fifths>` and `<mode>` of the first `<key>` in the score. A signature
+/// is shared by every part, so the first one found stands for the score.
+pub fn first_key(xml: &str) -> Result<Option<(i32, Option<String>)>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut in_key = false;
+    let mut element: Option<Vec<u8>> = None;
+    let mut fifths: Option<i32> = None;
+    let mut mode: Option<String> = None;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if is_element(&e, "key") => in_key = true,
+            Event::Start(e) if in_key => element = Some(e.local_name().as_ref().to_vec()),
+            Event::Text(t) if in_key => {
+                let text = t.unescape().map_err(xml_error)?.trim().to_string();
+                match element.as_deref() {
+                    Some(b"fifths") => fifths = text.parse().ok(),
+                    Some(b"mode") => mode = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == b"key" => {
+                if fifths.is_some() {
+                    break;
+                }
+                in_key = false;
+            }
+            Event::End(_) => element = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(fifths.map(|f| (f, mode)))
+}
+
+/// Events after a `<note>` start, up to and including its end.
+pub fn read_note_body(reader: &mut Reader<&[u8]>) -> Result<Vec<Event<'static>>, String> {
+    let mut body = Vec::new();
+    loop {
+        let inner = reader.read_event().map_err(xml_error)?;
+        let done = matches!(&inner, Event::End(end) if end.local_name().as_ref() == b"note")
+            || matches!(inner, Event::Eof);
+        body.push(inner.into_owned());
+        if done {
+            return Ok(body);
+        }
+    }
+}
+
+/// What a buffered `<note>` element turned out to be.
+#[derive(Debug, Default)]
+pub struct NoteKind {
+    pub rest: bool,
+    pub chord: bool,
+    pub grace: bool,
+    pub staff: u32,
+    /// `<duration>` in divisions; None for a grace note.
+    pub duration: Option<f64>,
+    /// Spelled the way music21's `nameWithOctave` does ("F#4", "B-3").
+    pub pitch: Option<String>,
+    /// Element names inside `<articulations>` ("staccato", "accent", ...).
+    pub articulations: Vec<String>,
+    pub slur_start: bool,
+    pub slur_stop: bool,
+}
+
+impl NoteKind {
+    /// Inspect the events between a `<note>` start and its end.
+    pub fn from_events(events: &[Event]) -> Self {
+        let mut kind = NoteKind {
+            staff: 1,
+            ..Default::default()
+        };
+        let mut element: Option<Vec<u8>> = None;
+        let mut in_articulations = false;
+        let (mut step, mut alter, mut octave) = (None, 0, None);
+        for event in events {
+            match event {
+                Event::Start(e) | Event::Empty(e) => {
+                    let name = e.local_name();
+                    match name.as_ref() {
+                        b"rest" => kind.rest = true,
+                        b"chord" => kind.chord = true,
+                        b"grace" => kind.grace = true,
+                        b"articulations" => in_articulations = matches!(event, Event::Start(_)),
+                        b"slur" => match attribute(e, "type").as_deref() {
+                            Some("start") => kind.slur_start = true,
+                            Some("stop") => kind.slur_stop = true,
+                            _ => {}
+                        },
+                        _ if in_articulations => kind
+                            .articulations
+                            .push(String::from_utf8_lossy(name.as_ref()).into_owned()),
+                        _ => {}
+                    }
+                    element =
+                        matches!(event, Event::Start(_)).then(|| e.local_name().as_ref().to_vec());
+                }
+                Event::Text(t) => {
+                    let Ok(text) = t.unescape() else {
+                        continue;
+                    };
+                    let text = text.trim();
+                    match element.as_deref() {
+                        Some(b"staff") => kind.staff = text.parse().unwrap_or(kind.staff),
+                        Some(b"step") => step = Some(text.to_string()),
+                        Some(b"alter") => alter = text.parse::<f64>().unwrap_or(0.0).round() as i32,
+                        Some(b"octave") => octave = Some(text.to_string()),
+                        Some(b"duration") => kind.duration = text.parse().ok(),
+                        _ => {}
+                    }
+                }
+                Event::End(e) => {
+                    if e.local_name().as_ref() == b"articulations" {
+                        in_articulations = false;
+                    }
+                    element = None;
+                }
+                _ => {}
+            }
+        }
+        if let (Some(step), Some(octave)) = (step, octave) {
+            let accidental = if alter >= 0 { "#" } else { "-" };
+            kind.pitch = Some(format!(
+                "{}{}{}",
+                step,
+                accidental.repeat(alter.unsigned_abs() as usize),
+                octave
+            ));
+        }
+        kind
+    }
+}
+
+pub fn is_element(e: &BytesStart, name: &str) -> bool {
+    e.local_name().as_ref() == name.as_bytes()
+}
+
+/// Unescaped value of an attribute, if present.
+pub fn attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Numeric part of a `<measure number="...">`, read the way music21 does
+/// (leading digits, so "12a" is measure 12).
+pub fn measure_number(e: &BytesStart) -> Option<i32> {
+    let number = attribute(e, "number")?;
+    let digits: String = number.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+pub fn xml_error(e: impl std::fmt::Display) -> String {
+    format!("Invalid MusicXML: {}", e)
+}
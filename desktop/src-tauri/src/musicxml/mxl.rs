@@ -0,0 +1,115 @@
+//! Compressed MusicXML (`.mxl`): a zip archive whose
+//! `META-INF/container.xml` names the score file inside it.
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use zip::ZipArchive;
+
+use super::{attribute, is_element, midi, xml_error};
+
+const CONTAINER: &str = "META-INF/container.xml";
+
+pub fn is_mxl(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mxl"))
+}
+
+/// A score file as MusicXML text, decompressing it if it's an `.mxl` and
+/// converting it if it's MIDI.
+pub fn read_score(path: &Path) -> Result<String, String> {
+    if midi::is_midi(path) {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        return midi::to_musicxml(&bytes);
+    }
+    if !is_mxl(path) {
+        return std::fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e));
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    extract(&bytes)
+}
+
+/// The score inside an `.mxl` archive: the container's first rootfile, or
+/// without a container the first `.musicxml`/`.xml` file outside `META-INF`.
+pub fn extract(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(mxl_error)?;
+    let root = match read_entry(&mut archive, CONTAINER) {
+        Ok(container) => rootfile(&container)?
+            .ok_or_else(|| "Invalid MXL file: container.xml names no rootfile".to_string())?,
+        Err(_) => archive
+            .file_names()
+            .filter(|name| !name.starts_with("META-INF/"))
+            .find(|name| name.ends_with(".musicxml") || name.ends_with(".xml"))
+            .map(str::to_string)
+            .ok_or_else(|| "Invalid MXL file: no score inside".to_string())?,
+    };
+    read_entry(&mut archive, &root)
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String, String> {
+    let mut file = archive.by_name(name).map_err(mxl_error)?;
+    let mut text = String::new();
+    file.read_to_string(&mut text).map_err(mxl_error)?;
+    Ok(text)
+}
+
+/// `full-path` of the container's first `<rootfile>`.
+fn rootfile(container: &str) -> Result<Option<String>, String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "rootfile") => {
+                return Ok(attribute(&e, "full-path"));
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+fn mxl_error(e: impl std::fmt::Display) -> String {
+    format!("Invalid MXL file: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    const SCORE: &str = "<score-partwise><part id=\"P1\"/></score-partwise>";
+
+    fn archive(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn finds_the_score_through_the_container() {
+        let container = r#"<?xml version="1.0"?><container><rootfiles>
+            <rootfile full-path="scores/Song.musicxml" media-type="application/vnd.recordare.musicxml+xml"/>
+            </rootfiles></container>"#;
+        let bytes = archive(&[
+            ("mimetype", "application/vnd.recordare.musicxml"),
+            ("META-INF/container.xml", container),
+            ("other.xml", "<not-it/>"),
+            ("scores/Song.musicxml", SCORE),
+        ]);
+        assert_eq!(extract(&bytes).unwrap(), SCORE);
+
+        let bare = archive(&[("Song.xml", SCORE)]);
+        assert_eq!(extract(&bare).unwrap(), SCORE);
+        assert!(extract(b"not a zip").is_err());
+        assert!(is_mxl(Path::new("song.MXL")));
+        assert!(!is_mxl(Path::new("song.musicxml")));
+    }
+}
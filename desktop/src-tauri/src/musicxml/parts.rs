@@ -0,0 +1,112 @@
+//! The staves a score can be analyzed by, for picking which to analyze.
+
+use std::collections::HashMap;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+
+use super::{attribute, is_element, staves_per_part, stream_notes, xml_error};
+
+/// One analyzable staff: a `<part>`, or one staff of a multi-staff part.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PartInfo {
+    /// Index to pass in `AnalyzerConfig.parts`, as music21 numbers staves.
+    pub index: usize,
+    pub part_id: String,
+    /// The `<part-name>`, empty if the score gives none.
+    pub name: String,
+    /// Staff within the part (1 for the upper staff of a piano part), and
+    /// how many the part has.
+    pub staff: u32,
+    pub staves: u32,
+    /// Notes on the staff, chords counted once.
+    pub notes: usize,
+}
+
+pub fn list_parts(xml: &str) -> Result<Vec<PartInfo>, String> {
+    let (names, ids) = part_names(xml)?;
+    let staves = staves_per_part(xml)?;
+    let notes = stream_notes(xml)?;
+
+    let mut parts = Vec::new();
+    for (id, &count) in ids.iter().zip(&staves) {
+        for staff in 1..=count {
+            let index = parts.len();
+            parts.push(PartInfo {
+                index,
+                part_id: id.clone(),
+                name: names.get(id).cloned().unwrap_or_default(),
+                staff,
+                staves: count,
+                notes: notes.get(index).map_or(0, Vec::len),
+            });
+        }
+    }
+    Ok(parts)
+}
+
+/// `<part-name>` by part id, and the ids of the `<part>`s in document order.
+fn part_names(xml: &str) -> Result<(HashMap<String, String>, Vec<String>), String> {
+    let mut reader = Reader::from_str(xml);
+    let mut names = HashMap::new();
+    let mut ids = Vec::new();
+    let mut score_part: Option<String> = None;
+    let mut in_name = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) if is_element(&e, "score-part") => {
+                score_part = attribute(&e, "id");
+            }
+            Event::End(e) if e.local_name().as_ref() == b"score-part" => score_part = None,
+            Event::Start(e) if is_element(&e, "part-name") => in_name = score_part.is_some(),
+            Event::End(e) if e.local_name().as_ref() == b"part-name" => in_name = false,
+            Event::Text(t) if in_name => {
+                if let Some(id) = &score_part {
+                    let name = t.unescape().map_err(xml_error)?;
+                    names.insert(id.clone(), name.trim().to_string());
+                }
+            }
+            Event::Start(e) if is_element(&e, "part") => {
+                ids.push(attribute(&e, "id").unwrap_or_default());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((names, ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_each_staff_of_each_part() {
+        let note = "<note><pitch><step>C</step><octave>4</octave></pitch></note>";
+        let xml = format!(
+            r#"<score-partwise><part-list>
+            <score-part id="P1"><part-name>Voice</part-name></score-part>
+            <score-part id="P2"><part-name>Piano</part-name></score-part>
+            </part-list>
+            <part id="P1"><measure number="1">{n}{n}</measure></part>
+            <part id="P2"><measure number="1"><attributes><staves>2</staves></attributes>
+            {n}<note><pitch><step>C</step><octave>3</octave></pitch><staff>2</staff></note>
+            </measure></part></score-partwise>"#,
+            n = note
+        );
+
+        let parts = list_parts(&xml).unwrap();
+        let summary: Vec<(usize, &str, u32, usize)> = parts
+            .iter()
+            .map(|p| (p.index, p.name.as_str(), p.staff, p.notes))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(0, "Voice", 1, 2), (1, "Piano", 1, 1), (2, "Piano", 2, 1)]
+        );
+        assert_eq!(parts[2].staves, 2);
+        assert_eq!(parts[1].part_id, "P2");
+    }
+}
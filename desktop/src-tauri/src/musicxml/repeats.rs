@@ -0,0 +1,201 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+use super::{attribute, is_element, measure_number, xml_error};
+
+/// Written vs played measure order for a score with repeats and voltas.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MeasureMap {
+    /// Written measure number of each measure in playback order.
+    pub played: Vec<i32>,
+}
+
+impl MeasureMap {
+    /// 1-based playback position of the first time `written` is played.
+    pub fn first_played(&self, written: i32) -> Option<i32> {
+        self.played
+            .iter()
+            .position(|&m| m == written)
+            .map(|i| i as i32 + 1)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MeasureInfo {
+    number: i32,
+    forward_repeat: bool,
+    /// Number of times to play the section ending here (`times`, default 2).
+    backward_repeat: Option<u32>,
+    /// Passes this measure is played in, when it's inside a volta bracket.
+    endings: Vec<u32>,
+}
+
+/// Unroll the repeat structure of the first part. All parts share one
+/// barline structure, so the others aren't read.
+pub fn measure_map(xml: &str) -> Result<MeasureMap, String> {
+    Ok(MeasureMap {
+        played: unroll(&read_measures(xml)?)
+            .into_iter()
+            .map(|i| i.number)
+            .collect(),
+    })
+}
+
+fn read_measures(xml: &str) -> Result<Vec<MeasureInfo>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut measures: Vec<MeasureInfo> = Vec::new();
+    let mut open_ending: Option<Vec<u32>> = None;
+    let mut close_ending = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::End(e) if e.local_name().as_ref() == b"part" => break,
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "measure") => {
+                let number = measure_number(&e).unwrap_or(measures.len() as i32 + 1);
+                measures.push(MeasureInfo {
+                    number,
+                    endings: open_ending.clone().unwrap_or_default(),
+                    ..Default::default()
+                });
+            }
+            Event::End(e) if e.local_name().as_ref() == b"measure" && close_ending => {
+                open_ending = None;
+                close_ending = false;
+            }
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "repeat") => {
+                let Some(measure) = measures.last_mut() else {
+                    continue;
+                };
+                match attribute(&e, "direction").as_deref() {
+                    Some("forward") => measure.forward_repeat = true,
+                    Some("backward") => {
+                        let times = attribute(&e, "times").and_then(|t| t.parse().ok());
+                        measure.backward_repeat = Some(times.unwrap_or(2));
+                    }
+                    _ => {}
+                }
+            }
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "ending") => {
+                let Some(measure) = measures.last_mut() else {
+                    continue;
+                };
+                let numbers: Vec<u32> = attribute(&e, "number")
+                    .unwrap_or_default()
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter_map(|n| n.parse().ok())
+                    .collect();
+                match attribute(&e, "type").as_deref() {
+                    Some("start") => {
+                        measure.endings = numbers.clone();
+                        open_ending = Some(numbers);
+                    }
+                    Some("stop") | Some("discontinue") => close_ending = true,
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(measures)
+}
+
+fn unroll(measures: &[MeasureInfo]) -> Vec<&MeasureInfo> {
+    // Malformed repeats could otherwise loop forever
+    let limit = measures.len() * 16;
+    let mut played = Vec::new();
+    let mut section_start = 0;
+    let mut pass = 1;
+    let mut jumped_back = false;
+    let mut in_endings = false;
+    let mut i = 0;
+
+    while i < measures.len() && played.len() < limit {
+        let measure = &measures[i];
+
+        if measure.forward_repeat && !jumped_back {
+            section_start = i;
+            pass = 1;
+        }
+        jumped_back = false;
+
+        if measure.endings.is_empty() {
+            if in_endings {
+                // Leaving a volta group starts a fresh section
+                in_endings = false;
+                section_start = i;
+                pass = 1;
+            }
+        } else {
+            in_endings = true;
+            if !measure.endings.contains(&pass) {
+                i += 1;
+                continue;
+            }
+        }
+
+        played.push(measure);
+
+        if let Some(times) = measure.backward_repeat {
+            if pass < times {
+                pass += 1;
+                i = section_start;
+                jumped_back = true;
+                in_endings = false;
+                continue;
+            }
+            pass = 1;
+            section_start = i + 1;
+        }
+        i += 1;
+    }
+
+    played
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_repeated_section() {
+        let xml = r#"<score-partwise><part id="P1">
+<measure number="1"/>
+<measure number="2"><barline location="left"><repeat direction="forward"/></barline></measure>
+<measure number="3"><barline location="right"><repeat direction="backward"/></barline></measure>
+<measure number="4"/>
+</part></score-partwise>"#;
+        let map = measure_map(xml).unwrap();
+        assert_eq!(map.played, vec![1, 2, 3, 2, 3, 4]);
+        assert_eq!(map.first_played(4), Some(6));
+    }
+
+    #[test]
+    fn first_and_second_ending() {
+        let xml = r#"<score-partwise><part id="P1">
+<measure number="1"><barline location="left"><repeat direction="forward"/></barline></measure>
+<measure number="2"/>
+<measure number="3">
+  <barline location="left"><ending number="1" type="start"/></barline>
+  <barline location="right"><ending number="1" type="stop"/><repeat direction="backward"/></barline>
+</measure>
+<measure number="4">
+  <barline location="left"><ending number="2" type="start"/></barline>
+  <barline location="right"><ending number="2" type="discontinue"/></barline>
+</measure>
+<measure number="5"/>
+<measure number="6"><barline location="right"><repeat direction="backward"/></barline></measure>
+</part></score-partwise>"#;
+        let map = measure_map(xml).unwrap();
+        assert_eq!(map.played, vec![1, 2, 3, 1, 2, 4, 5, 6, 5, 6]);
+    }
+
+    #[test]
+    fn no_repeats_is_identity() {
+        let xml = r#"<score-partwise><part id="P1"><measure number="1"/><measure number="2"/></part>
+<part id="P2"><measure number="1"/><measure number="2"/></part></score-partwise>"#;
+        assert_eq!(measure_map(xml).unwrap().played, vec![1, 2]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::{attribute, is_element, measure_number, xml_error};
+
+/// Where a measure sits on the score's timeline, in quarter notes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasureTiming {
+    /// Offset of beat 1 from the start of the piece. Negative for a pickup,
+    /// whose notes music21 numbers as if the bar were full.
+    pub start: f64,
+    /// Length of one beat of the measure's meter (a dotted quarter in 6/8).
+    pub beat_length: f64,
+    /// Length of a full bar of the measure's meter.
+    pub bar_length: f64,
+}
+
+impl MeasureTiming {
+    /// Offset from the start of the piece of a 1-based `NoteLocator.beat`.
+    pub fn absolute_beat(&self, beat: f64) -> f64 {
+        self.start + (beat - 1.0) * self.beat_length
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Meter {
+    pub(super) beats: f64,
+    pub(super) beat_type: f64,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter {
+            beats: 4.0,
+            beat_type: 4.0,
+        }
+    }
+}
+
+impl Meter {
+    pub(super) fn bar_length(&self) -> f64 {
+        self.beats * 4.0 / self.beat_type
+    }
+
+    /// music21's beat unit: compound meters (6/8, 9/8, 12/16) beat in
+    /// groups of three.
+    fn beat_length(&self) -> f64 {
+        let compound = self.beats > 3.0 && self.beats % 3.0 == 0.0;
+        let unit = 4.0 / self.beat_type;
+        if compound {
+            unit * 3.0
+        } else {
+            unit
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MeasureInfo {
+    number: i32,
+    meter: Meter,
+    /// Furthest the `<note>`/`<backup>`/`<forward>` cursor got, in quarters.
+    filled: f64,
+    /// `<sound tempo>` values with the cursor position they appear at.
+    tempos: Vec<(f64, f64)>,
+}
+
+impl MeasureInfo {
+    fn length(&self) -> f64 {
+        if self.filled > 0.0 {
+            self.filled
+        } else {
+            self.meter.bar_length()
+        }
+    }
+}
+
+/// Timing of every measure of the first part, keyed by written number. All
+/// parts share one meter and barline structure, so the others aren't read.
+/// Measures are laid out in document order; repeats aren't unrolled.
+pub fn measure_timings(xml: &str) -> Result<HashMap<i32, MeasureTiming>, String> {
+    let measures = read_measures(xml)?;
+    let mut timings = HashMap::new();
+    let mut start = 0.0;
+    for (i, measure) in measures.iter().enumerate() {
+        let bar = measure.meter.bar_length();
+        let length = measure.length();
+        // music21 pads only an opening pickup on the left
+        let padding = if i == 0 { (bar - length).max(0.0) } else { 0.0 };
+        timings.entry(measure.number).or_insert(MeasureTiming {
+            start: start - padding,
+            beat_length: measure.meter.beat_length(),
+            bar_length: bar,
+        });
+        start += length;
+    }
+    Ok(timings)
+}
+
+/// Every `<sound tempo>` of the first part as (onset, quarter notes per
+/// minute), onsets on the `absolute_beat` timeline.
+pub fn tempo_changes(xml: &str) -> Result<Vec<(f64, f64)>, String> {
+    let mut changes = Vec::new();
+    let mut start = 0.0;
+    for measure in read_measures(xml)? {
+        changes.extend(measure.tempos.iter().map(|&(at, bpm)| (start + at, bpm)));
+        start += measure.length();
+    }
+    Ok(changes)
+}
+
+fn read_measures(xml: &str) -> Result<Vec<MeasureInfo>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut measures: Vec<MeasureInfo> = Vec::new();
+    let mut meter = Meter::default();
+    let mut divisions = 1.0;
+    let mut cursor = 0.0;
+    // Element whose text is being read, and whether it's inside a `<note>`
+    // carrying `<chord/>` or `<grace/>` (which don't advance the cursor)
+    let mut element: Option<Vec<u8>> = None;
+    let mut container: Option<Vec<u8>> = None;
+    let mut no_advance = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::End(e) if e.local_name().as_ref() == b"part" => break,
+            Event::Start(e) if is_element(&e, "measure") => {
+                let number = measure_number(&e).unwrap_or(measures.len() as i32 + 1);
+                measures.push(MeasureInfo {
+                    number,
+                    meter,
+                    filled: 0.0,
+                    tempos: Vec::new(),
+                });
+                cursor = 0.0;
+            }
+            Event::Start(e) | Event::Empty(e) if is_element(&e, "sound") => {
+                let tempo = attribute(&e, "tempo").and_then(|t| t.parse::<f64>().ok());
+                if let (Some(tempo), Some(measure)) = (tempo, measures.last_mut()) {
+                    measure.tempos.push((cursor, tempo));
+                }
+            }
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if matches!(name.as_slice(), b"note" | b"backup" | b"forward") {
+                    container = Some(name.clone());
+                    no_advance = false;
+                }
+                element = Some(name);
+            }
+            Event::Empty(e) => {
+                if matches!(e.local_name().as_ref(), b"chord" | b"grace") {
+                    no_advance = true;
+                }
+            }
+            Event::Text(t) => {
+                let Some(name) = element.as_deref() else {
+                    continue;
+                };
+                let text = t.unescape().map_err(xml_error)?;
+                let text = text.trim();
+                match name {
+                    b"divisions" => divisions = text.parse().unwrap_or(divisions),
+                    // Additive meters ("3+2") count their summed beats
+                    b"beats" => {
+                        let beats: Option<f64> =
+                            text.split('+').map(|b| b.trim().parse::<f64>().ok()).sum();
+                        meter.beats = beats.unwrap_or(meter.beats);
+                    }
+                    b"beat-type" => meter.beat_type = text.parse().unwrap_or(meter.beat_type),
+                    b"duration" => {
+                        let quarters = text.parse::<f64>().unwrap_or(0.0) / divisions;
+                        match container.as_deref() {
+                            Some(b"backup") => cursor -= quarters,
+                            Some(b"note") if no_advance => {}
+                            Some(_) => cursor += quarters,
+                            None => {}
+                        }
+                        if let Some(measure) = measures.last_mut() {
+                            measure.filled = measure.filled.max(cursor);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                if name.as_ref() == b"time" {
+                    if let Some(measure) = measures.last_mut() {
+                        measure.meter = meter;
+                    }
+                }
+                if container.as_deref() == Some(name.as_ref()) {
+                    container = None;
+                }
+                element = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(measures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure(number: i32, time: Option<(u32, u32)>, quarters: &[u32]) -> String {
+        let attributes = match time {
+            Some((beats, beat_type)) => format!(
+                "<attributes><divisions>2</divisions><time><beats>{}</beats>\
+                 <beat-type>{}</beat-type></time></attributes>",
+                beats, beat_type
+            ),
+            None => String::new(),
+        };
+        let notes: String = quarters
+            .iter()
+            .map(|q| {
+                format!(
+                    "<note><pitch><step>C</step><octave>4</octave></pitch>\
+                     <duration>{}</duration></note>",
+                    q * 2
+                )
+            })
+            .collect();
+        format!(
+            r#"<measure number="{}">{}{}</measure>"#,
+            number, attributes, notes
+        )
+    }
+
+    fn score(measures: &[String]) -> String {
+        format!(
+            r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+            measures.concat()
+        )
+    }
+
+    #[test]
+    fn accumulates_across_a_meter_change() {
+        let xml = score(&[
+            measure(1, Some((4, 4)), &[2, 2]),
+            measure(2, None, &[1, 1, 1, 1]),
+            measure(3, Some((3, 4)), &[3]),
+            measure(4, None, &[1, 2]),
+        ]);
+        let timings = measure_timings(&xml).unwrap();
+
+        let starts: Vec<f64> = (1..=4).map(|m| timings[&m].start).collect();
+        assert_eq!(starts, vec![0.0, 4.0, 8.0, 11.0]);
+        assert_eq!(timings[&2].absolute_beat(3.0), 6.0);
+        assert_eq!(timings[&4].absolute_beat(2.0), 12.0);
+    }
+
+    #[test]
+    fn pickup_and_compound_meter() {
+        let xml = score(&[measure(0, Some((6, 8)), &[1]), measure(1, None, &[3])]);
+        let timings = measure_timings(&xml).unwrap();
+
+        // music21 puts the pickup's note on beat 2 of a padded 6/8 bar
+        assert_eq!(timings[&0].beat_length, 1.5);
+        assert!(timings[&0].absolute_beat(2.0 + 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(timings[&1].start, 1.0);
+    }
+
+    #[test]
+    fn tempo_changes_are_placed_on_the_timeline() {
+        // 72 from the downbeat, 96 after the first quarter of measure 2
+        let first =
+            measure(1, Some((4, 4)), &[4]).replacen("<note>", r#"<sound tempo="72"/><note>"#, 1);
+        let second = measure(2, None, &[1, 1, 1, 1]).replacen(
+            "</note>",
+            r#"</note><direction><sound tempo="96"/></direction>"#,
+            1,
+        );
+        let xml = score(&[first, second]);
+        assert_eq!(tempo_changes(&xml).unwrap(), vec![(0.0, 72.0), (5.0, 96.0)]);
+    }
+}
@@ -0,0 +1,361 @@
+//! A pure-Rust repetition finder standing in for the analyzer sidecar when
+//! `AnalyzerConfig.use_native_engine` is set. It reports the same repeats as
+//! the analyzer's `_find_repeats_in_part`, but reads them off a suffix array
+//! instead of extending a match from every pair of positions.
+//!
+//! It only reads MusicXML (and MIDI, once converted), and doesn't cover the sidecar options that change
+//! how notes are read (see `supports`); those runs still go to the sidecar.
+//! Notes come from `musicxml::stream_notes`, so they have no `beat`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::config::AnalyzerConfig;
+use crate::models::{AnalysisResult, NoteLocator, Pattern, StaffPatternData};
+use crate::musicxml::{self, midi, mxl, parts};
+use crate::pitch;
+
+/// Whether the native engine can analyze `path` with `config`.
+pub fn supports(path: &Path, config: &AnalyzerConfig) -> bool {
+    let musicxml = mxl::is_mxl(path)
+        || midi::is_midi(path)
+        || path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("musicxml"));
+    musicxml && config.numbers_notes_as_written() && !config.include_layout
+}
+
+/// Analyze a score the way the sidecar would: the parts in `config.parts`,
+/// or else a treble and a bass staff, with pattern ids unique across them.
+pub fn analyze(xml: &str, config: &AnalyzerConfig) -> Result<AnalysisResult, String> {
+    let streams = musicxml::stream_notes(xml)?;
+    let infos = parts::list_parts(xml)?;
+
+    let picked: Vec<(usize, String)> = match &config.parts {
+        Some(indices) => indices
+            .iter()
+            .map(|&i| {
+                if i >= streams.len() {
+                    return Err(format!("No part {}; the score has {}", i, streams.len()));
+                }
+                Ok((i, format!("Part {}", i + 1)))
+            })
+            .collect::<Result<_, _>>()?,
+        None => {
+            // The first two-staff part's staves, as the analyzer's `_grand_staff` finds them
+            let grand_staff = infos
+                .iter()
+                .find(|p| p.staves == 2 && p.staff == 1)
+                .filter(|_| config.split_grand_staff);
+            let first = grand_staff.map_or(0, |p| p.index);
+            [(first, "Treble"), (first + 1, "Bass")]
+                .into_iter()
+                .filter(|&(i, _)| i < streams.len())
+                .map(|(i, name)| (i, name.to_string()))
+                .collect()
+        }
+    };
+
+    let min_length = config.search_length().max(1) as usize;
+    let mut staves: Vec<StaffPatternData> = Vec::new();
+    for (index, default_name) in picked {
+        let notes = &streams[index];
+        let sigs = signatures(notes, config.match_rhythm, config.transposition_invariant);
+        let id_offset: usize = staves.iter().map(|s| s.patterns.len()).sum();
+        let patterns = find_repeats(&sigs, min_length)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (length, positions))| Pattern {
+                id: (id_offset + i) as i32,
+                length: length as i32,
+                count: positions.len() as i32,
+                notes: notes[positions[0]..positions[0] + length]
+                    .iter()
+                    .map(|note| NoteLocator {
+                        // Filled in by `postprocess::apply` when asked for
+                        articulations: Vec::new(),
+                        ..note.clone()
+                    })
+                    .collect(),
+                positions: positions.into_iter().map(|p| p as i32).collect(),
+                ..Default::default()
+            })
+            .collect();
+        let name = infos
+            .get(index)
+            .map(|p| p.name.clone())
+            .filter(|name| !name.is_empty())
+            .unwrap_or(default_name);
+        staves.push(StaffPatternData {
+            part_index: index as i32,
+            part_name: name,
+            patterns,
+            ..Default::default()
+        });
+    }
+
+    Ok(AnalysisResult {
+        parts: staves,
+        musicxml_content: xml.to_string(),
+        ..Default::default()
+    })
+}
+
+/// A symbol per note, equal for notes that match: by pitch and duration, by
+/// pitch alone without `match_rhythm`, and with the pitch replaced by the
+/// interval from the previous note when `transposed`, as the analyzer's
+/// `_signatures`.
+fn signatures(notes: &[NoteLocator], match_rhythm: bool, transposed: bool) -> Vec<u32> {
+    let midi: Vec<Option<i32>> = notes.iter().map(|n| pitch::to_midi(&n.pitch)).collect();
+    let mut symbols: HashMap<(Option<i32>, Option<i64>), u32> = HashMap::new();
+    notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| {
+            let pitch = if transposed {
+                i.checked_sub(1)
+                    .and_then(|prev| Some(midi[i]? - midi[prev]?))
+            } else {
+                midi[i]
+            };
+            // In millionths of a quarter, so float noise doesn't split notes
+            let duration =
+                match_rhythm.then(|| (note.duration_beats.unwrap_or(0.0) * 1e6).round() as i64);
+            let next = symbols.len() as u32;
+            *symbols.entry((pitch, duration)).or_insert(next)
+        })
+        .collect()
+}
+
+/// Repeated runs of at least `min_length` symbols, as (length, positions)
+/// most significant (length × count) first. A run inside a longer repeated
+/// one is dropped, and runs sharing a long enough prefix are reported as
+/// that prefix, as the analyzer deduplicates them.
+fn find_repeats(sigs: &[u32], min_length: usize) -> Vec<(usize, Vec<usize>)> {
+    let sa = suffix_array(sigs);
+    let lcp = lcp_array(sigs, &sa);
+
+    // Every distinct repeated run is the common prefix of a range of the
+    // suffix array (an LCP interval), and occurs where those suffixes start
+    let mut repeats: Vec<(&[u32], Vec<usize>)> = Vec::new();
+    let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+    for i in 1..=sigs.len() {
+        let common = lcp.get(i).copied().unwrap_or(0);
+        let mut left = i - 1;
+        while stack.last().is_some_and(|&(length, _)| common < length) {
+            let (length, start) = stack.pop().unwrap_or_default();
+            if length >= min_length {
+                let mut positions = sa[start..i].to_vec();
+                positions.sort_unstable();
+                repeats.push((&sigs[sa[start]..sa[start] + length], positions));
+            }
+            left = start;
+        }
+        if stack.last().is_some_and(|&(length, _)| common > length) {
+            stack.push((common, left));
+        }
+    }
+
+    repeats.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.1.cmp(&b.1)));
+    let mut maximal: Vec<(&[u32], Vec<usize>)> = Vec::new();
+    for (run, positions) in repeats {
+        let dominated = maximal.iter().any(|(longer, _)| {
+            longer.len() > run.len() && longer.windows(run.len()).any(|w| w == run)
+        });
+        if !dominated {
+            maximal.push((run, positions));
+        }
+    }
+
+    let mut found: Vec<(usize, Vec<usize>)> = common_prefixes(maximal, min_length)
+        .into_iter()
+        .map(|(run, positions)| (run.len(), positions))
+        .collect();
+    found.sort_by(|a, b| {
+        (b.0 * b.1.len())
+            .cmp(&(a.0 * a.1.len()))
+            .then_with(|| a.1.cmp(&b.1))
+    });
+    found
+}
+
+/// The analyzer's `_extract_common_prefixes`: runs sharing a prefix of at
+/// least `min_length` are replaced by the longest such prefix, occurring
+/// wherever any of them does.
+fn common_prefixes(
+    runs: Vec<(&[u32], Vec<usize>)>,
+    min_length: usize,
+) -> Vec<(&[u32], Vec<usize>)> {
+    let mut prefixes: HashMap<&[u32], BTreeSet<usize>> = HashMap::new();
+    for (i, (a, a_positions)) in runs.iter().enumerate() {
+        for (b, b_positions) in &runs[i + 1..] {
+            let shared = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+            if shared >= min_length {
+                let positions = prefixes.entry(&a[..shared]).or_default();
+                positions.extend(a_positions);
+                positions.extend(b_positions);
+            }
+        }
+    }
+    if prefixes.is_empty() {
+        return runs;
+    }
+
+    let mut sorted: Vec<(&[u32], BTreeSet<usize>)> = prefixes.into_iter().collect();
+    sorted.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(b.0)));
+    let mut maximal: Vec<(&[u32], Vec<usize>)> = Vec::new();
+    for (prefix, positions) in sorted {
+        if !maximal.iter().any(|(longer, _)| longer.starts_with(prefix)) {
+            maximal.push((prefix, positions.into_iter().collect()));
+        }
+    }
+
+    let kept: Vec<(&[u32], Vec<usize>)> = runs
+        .into_iter()
+        .filter(|(run, _)| !maximal.iter().any(|(prefix, _)| run.starts_with(prefix)))
+        .collect();
+    maximal.extend(kept);
+    maximal
+}
+
+/// Start of every suffix of `s` in sorted order, by prefix doubling.
+fn suffix_array(s: &[u32]) -> Vec<usize> {
+    let n = s.len();
+    let mut sa: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<usize> = s.iter().map(|&c| c as usize).collect();
+    let mut k = 1;
+    while k < n {
+        // Past the end sorts first
+        let key: Vec<(usize, usize)> = (0..n)
+            .map(|i| (rank[i], rank.get(i + k).map_or(0, |r| r + 1)))
+            .collect();
+        sa.sort_by_key(|&i| key[i]);
+        let mut next = vec![0; n];
+        for w in 1..n {
+            next[sa[w]] = next[sa[w - 1]] + usize::from(key[sa[w - 1]] != key[sa[w]]);
+        }
+        rank = next;
+        if rank[sa[n - 1]] == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+/// `lcp[i]`: length of the common prefix of suffixes `sa[i - 1]` and
+/// `sa[i]`, 0 for the first (Kasai's algorithm).
+fn lcp_array(s: &[u32], sa: &[usize]) -> Vec<usize> {
+    let n = s.len();
+    let mut rank = vec![0; n];
+    for (i, &start) in sa.iter().enumerate() {
+        rank[start] = i;
+    }
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for i in 0..n {
+        if rank[i] == 0 {
+            h = 0;
+            continue;
+        }
+        let j = sa[rank[i] - 1];
+        while i + h < n && j + h < n && s[i + h] == s[j + h] {
+            h += 1;
+        }
+        lcp[rank[i]] = h;
+        h = h.saturating_sub(1);
+    }
+    lcp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Symbols for a string, one per character.
+    fn sigs(s: &str) -> Vec<u32> {
+        s.chars().map(u32::from).collect()
+    }
+
+    #[test]
+    fn suffix_array_sorts_suffixes() {
+        let s = sigs("mississippi");
+        let sa = suffix_array(&s);
+        let mut naive: Vec<usize> = (0..s.len()).collect();
+        naive.sort_by_key(|&i| &s[i..]);
+        assert_eq!(sa, naive);
+        assert_eq!(lcp_array(&s, &sa), vec![0, 1, 1, 4, 0, 0, 1, 0, 2, 1, 3]);
+    }
+
+    #[test]
+    fn finds_maximal_repeats_like_the_analyzer() {
+        assert_eq!(
+            find_repeats(&sigs("abcXabcYabc"), 3),
+            vec![(3, vec![0, 4, 8])]
+        );
+        // "bcd" also repeats, but only inside "abcd"
+        assert_eq!(
+            find_repeats(&sigs("abcdQabcdRabx"), 3),
+            vec![(4, vec![0, 5])]
+        );
+        // Two runs sharing "abcd" are reported as it
+        assert_eq!(
+            find_repeats(&sigs("abcdeQabcdeRabcdfSabcdf"), 4),
+            vec![(4, vec![0, 6, 12, 18])]
+        );
+        assert!(find_repeats(&sigs("abcdef"), 2).is_empty());
+    }
+
+    #[test]
+    fn analyzes_a_grand_staff() {
+        let note = |step: char, staff: u32| {
+            format!(
+                "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                 <duration>1</duration><staff>{}</staff></note>",
+                step, staff
+            )
+        };
+        let measures: String = (1..=2)
+            .map(|m| {
+                let right: String = "CDEF".chars().map(|s| note(s, 1)).collect();
+                let left: String = "CGEG".chars().map(|s| note(s, 2)).collect();
+                format!(
+                    r#"<measure number="{}"><attributes><staves>2</staves></attributes>{}<backup><duration>4</duration></backup>{}</measure>"#,
+                    m, right, left
+                )
+            })
+            .collect();
+        let xml = format!(
+            r#"<score-partwise><part-list><score-part id="P1"><part-name>Piano</part-name></score-part></part-list><part id="P1">{}</part></score-partwise>"#,
+            measures
+        );
+
+        let result = analyze(&xml, &AnalyzerConfig::default()).unwrap();
+        assert_eq!(result.parts.len(), 2);
+        let (right, left) = (&result.parts[0], &result.parts[1]);
+        assert_eq!((right.part_index, left.part_index), (0, 1));
+        assert_eq!(right.part_name, "Piano");
+        assert_eq!(right.patterns[0].positions, vec![0, 4]);
+        assert_eq!(right.patterns[0].notes[1].pitch, "D4");
+        assert_eq!(left.patterns[0].id, 1);
+
+        let only_left = AnalyzerConfig {
+            parts: Some(vec![1]),
+            ..Default::default()
+        };
+        assert_eq!(analyze(&xml, &only_left).unwrap().parts[0].part_index, 1);
+        let missing = AnalyzerConfig {
+            parts: Some(vec![2]),
+            ..Default::default()
+        };
+        assert!(analyze(&xml, &missing).is_err());
+
+        assert!(supports(Path::new("a.mxl"), &AnalyzerConfig::default()));
+        assert!(!supports(Path::new("a.pdf"), &AnalyzerConfig::default()));
+        let layout = AnalyzerConfig {
+            include_layout: true,
+            ..Default::default()
+        };
+        assert!(!supports(Path::new("a.musicxml"), &layout));
+    }
+}
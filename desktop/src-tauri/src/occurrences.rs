@@ -0,0 +1,343 @@
+//! Where each occurrence of a pattern falls in the score. The analyzer only
+//! reports the notes of a pattern's first occurrence, so the rest are
+//! located through the score's own note numbering.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AnalysisResult, NoteLocator, Pattern, StaffPatternData};
+use crate::musicxml::{self, repeats::MeasureMap};
+
+/// First note at which two occurrences of a pattern differ.
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    /// Position within the pattern (0 = its first note).
+    pub offset: usize,
+    pub first: NoteLocator,
+    pub second: NoteLocator,
+}
+
+/// A note of a pattern whose articulations aren't the same in every
+/// occurrence.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArticulationDifference {
+    /// Position within the pattern (0 = its first note).
+    pub offset: usize,
+    /// Pitch of the note in the first occurrence.
+    pub pitch: String,
+    /// Articulations of the note in each occurrence, in `positions` order.
+    pub articulations: Vec<Vec<String>>,
+}
+
+/// A pattern listed under a measure in `AnalysisResult.measure_index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatternRef {
+    /// Index of the staff in `AnalysisResult.parts`.
+    pub staff: usize,
+    pub pattern_id: i32,
+}
+
+/// Measures covered by the first occurrence of a pattern.
+pub fn first_span(pattern: &Pattern) -> (i32, i32) {
+    let measures = pattern.notes.iter().map(|n| n.measure);
+    (
+        measures.clone().min().unwrap_or(0),
+        measures.max().unwrap_or(0),
+    )
+}
+
+/// A pattern of one staff by id, with the staff it belongs to.
+pub fn find_pattern(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+) -> Result<(&StaffPatternData, &Pattern), String> {
+    let staff_data = result.staff(staff).ok_or_else(|| {
+        format!(
+            "The score has no staff {} (it has {})",
+            staff,
+            result.parts.len()
+        )
+    })?;
+    let pattern = staff_data
+        .patterns
+        .iter()
+        .find(|p| p.id == pattern_id)
+        .ok_or_else(|| format!("No pattern {} in staff {}", pattern_id, staff))?;
+    Ok((staff_data, pattern))
+}
+
+/// All notes of the staff's stream in the result's score, if it parses.
+pub fn staff_notes(result: &AnalysisResult, staff: &StaffPatternData) -> Vec<NoteLocator> {
+    musicxml::stream_notes(&result.musicxml_content)
+        .ok()
+        .and_then(|mut streams| {
+            let index = usize::try_from(staff.part_index).ok()?;
+            (index < streams.len()).then(|| streams.swap_remove(index))
+        })
+        .unwrap_or_default()
+}
+
+/// (start, end) measures of each occurrence, in the same frame as the
+/// pattern's notes: `measure_map` is applied to measures read from the
+/// score when the result is in the played frame. Occurrences that can't be
+/// located are left out, except the first, which the pattern describes.
+pub fn spans(
+    pattern: &Pattern,
+    notes: &[NoteLocator],
+    measure_map: Option<&MeasureMap>,
+) -> Vec<(i32, i32)> {
+    let measure = |index: i32| {
+        let written = notes.get(usize::try_from(index).ok()?)?.measure;
+        Some(measure_map.map_or(written, |map| map.first_played(written).unwrap_or(written)))
+    };
+    pattern
+        .positions
+        .iter()
+        .enumerate()
+        .filter_map(
+            |(i, &pos)| match (measure(pos), measure(pos + pattern.length - 1)) {
+                (Some(start), Some(end)) => Some((start, end)),
+                _ if i == 0 => Some(first_span(pattern)),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// Each measure any pattern occurrence touches, in score order, with the
+/// patterns found there (in staff order, each listed once).
+pub fn measure_index(result: &AnalysisResult) -> Vec<(i32, Vec<PatternRef>)> {
+    let mut index: BTreeMap<i32, Vec<PatternRef>> = BTreeMap::new();
+    for (staff, data) in result.parts.iter().enumerate() {
+        let notes = staff_notes(result, data);
+        for pattern in &data.patterns {
+            let pattern_ref = PatternRef {
+                staff,
+                pattern_id: pattern.id,
+            };
+            for (start, end) in spans(pattern, &notes, result.measure_map.as_ref()) {
+                for measure in start..=end {
+                    let refs = index.entry(measure).or_default();
+                    if !refs.contains(&pattern_ref) {
+                        refs.push(pattern_ref);
+                    }
+                }
+            }
+        }
+    }
+    index.into_iter().collect()
+}
+
+/// Compare occurrences `first` and `second` (indices into `positions`) of a
+/// pattern note by note, as read from the score. Notes are compared by
+/// pitch, the only property the score's numbering reports. None when the
+/// two are identical.
+pub fn compare_occurrences(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+    first: usize,
+    second: usize,
+) -> Result<Option<Divergence>, String> {
+    let (staff_data, pattern) = find_pattern(result, staff, pattern_id)?;
+    let notes = staff_notes(result, staff_data);
+    let a = occurrence_notes(pattern, &notes, first)?;
+    let b = occurrence_notes(pattern, &notes, second)?;
+
+    Ok(a.iter()
+        .zip(b)
+        .position(|(x, y)| x.pitch != y.pitch)
+        .map(|offset| Divergence {
+            offset,
+            first: a[offset].clone(),
+            second: b[offset].clone(),
+        }))
+}
+
+/// Notes of the pattern whose articulations differ between occurrences,
+/// as written in the score.
+pub fn compare_articulations(
+    result: &AnalysisResult,
+    staff: usize,
+    pattern_id: i32,
+) -> Result<Vec<ArticulationDifference>, String> {
+    let (staff_data, pattern) = find_pattern(result, staff, pattern_id)?;
+    let notes = staff_notes(result, staff_data);
+    let occurrences = (0..pattern.positions.len())
+        .map(|i| occurrence_notes(pattern, &notes, i))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = occurrences.first() else {
+        return Ok(Vec::new());
+    };
+
+    Ok(first
+        .iter()
+        .enumerate()
+        .filter_map(|(offset, note)| {
+            let articulations: Vec<Vec<String>> = occurrences
+                .iter()
+                .map(|o| o[offset].articulations.clone())
+                .collect();
+            articulations
+                .iter()
+                .any(|a| *a != articulations[0])
+                .then(|| ArticulationDifference {
+                    offset,
+                    pitch: note.pitch.clone(),
+                    articulations,
+                })
+        })
+        .collect())
+}
+
+/// The score's notes for occurrence `i` (an index into `positions`).
+fn occurrence_notes<'a>(
+    pattern: &Pattern,
+    notes: &'a [NoteLocator],
+    i: usize,
+) -> Result<&'a [NoteLocator], String> {
+    let start = *pattern
+        .positions
+        .get(i)
+        .ok_or_else(|| format!("Pattern {} has no occurrence {}", pattern.id, i))?;
+    usize::try_from(start)
+        .ok()
+        .and_then(|start| notes.get(start..start + pattern.length.max(0) as usize))
+        .ok_or_else(|| format!("Failed to locate occurrence {} in the score", i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_xml(step: char) -> String {
+        format!(
+            "<note><pitch><step>{}</step><octave>4</octave></pitch></note>",
+            step
+        )
+    }
+
+    fn measure(number: i32, steps: &str) -> String {
+        let notes: String = steps.chars().map(note_xml).collect();
+        format!(r#"<measure number="{}">{}</measure>"#, number, notes)
+    }
+
+    /// Measures C D E F | C D G F, with a pattern claiming both are the same.
+    fn near_repeat() -> AnalysisResult {
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 0,
+                    length: 4,
+                    count: 2,
+                    positions: vec![0, 4],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1">{}{}</part></score-partwise>"#,
+                measure(1, "CDEF"),
+                measure(2, "CDGF")
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn near_repeat_differs_on_third_note() {
+        let result = near_repeat();
+        let divergence = compare_occurrences(&result, 0, 0, 0, 1).unwrap().unwrap();
+        assert_eq!(divergence.offset, 2);
+        assert_eq!(divergence.first.pitch, "E4");
+        assert_eq!(divergence.second.pitch, "G4");
+        assert_eq!(divergence.second.measure, 2);
+
+        assert!(compare_occurrences(&result, 0, 0, 1, 1).unwrap().is_none());
+        assert!(compare_occurrences(&result, 0, 0, 0, 2).is_err());
+    }
+
+    #[test]
+    fn pattern_across_a_barline_is_listed_under_both_measures() {
+        let pattern = |id: i32, length: i32, position: i32| Pattern {
+            id,
+            length,
+            count: 1,
+            positions: vec![position],
+            ..Default::default()
+        };
+        let result = AnalysisResult {
+            parts: vec![StaffPatternData {
+                // Notes 10–12: the last two of measure 3 and the first of 4
+                patterns: vec![pattern(2, 2, 0), pattern(7, 3, 10)],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+                (1..=4).map(|m| measure(m, "CDEF")).collect::<String>()
+            ),
+            ..Default::default()
+        };
+
+        let refs = |id| {
+            vec![PatternRef {
+                staff: 0,
+                pattern_id: id,
+            }]
+        };
+        assert_eq!(
+            measure_index(&result),
+            vec![(1, refs(2)), (3, refs(7)), (4, refs(7))]
+        );
+    }
+
+    #[test]
+    fn staccato_against_slurred_occurrence() {
+        let note = |step: char, notations: &str| {
+            format!(
+                "<note><pitch><step>{}</step><octave>4</octave></pitch>\
+                 <notations>{}</notations></note>",
+                step, notations
+            )
+        };
+        let staccato = "<articulations><staccato/></articulations>";
+        let detached: String = "CDE".chars().map(|s| note(s, staccato)).collect();
+        let slurred = [
+            note('C', r#"<slur type="start"/>"#),
+            note('D', ""),
+            note(
+                'E',
+                r#"<slur type="stop"/><articulations><accent/></articulations>"#,
+            ),
+        ]
+        .concat();
+        let result = AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 0,
+                    length: 3,
+                    count: 2,
+                    positions: vec![0, 3],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">{}{}</measure></part></score-partwise>"#,
+                detached, slurred
+            ),
+            ..Default::default()
+        };
+
+        let differences = compare_articulations(&result, 0, 0).unwrap();
+        assert_eq!(differences.len(), 3);
+        assert_eq!(differences[1].pitch, "D4");
+        assert_eq!(
+            differences[1].articulations,
+            vec![vec!["staccato".to_string()], vec!["slur".to_string()]]
+        );
+        assert_eq!(differences[2].articulations[1], vec!["accent", "slur"]);
+    }
+}
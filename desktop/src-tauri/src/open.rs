@@ -0,0 +1,146 @@
+//! Scores the app is asked to open from outside it: dropped on the window,
+//! passed on the command line when the OS launches it for a file
+//! association (handed to the running app by the single-instance plugin
+//! when it's already open), or sent by macOS as `RunEvent::Opened`. Each becomes an
+//! `open-file-request` event, held until the frontend asks for them when it
+//! isn't listening yet.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::error::{self, AnalyzeError};
+
+/// Emitted with an `OpenFileRequest` once the frontend is ready for it.
+pub const OPEN_FILE_EVENT: &str = "open-file-request";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenSource {
+    /// Dropped on the window.
+    Drop,
+    /// On the command line, as Linux and Windows launch the app for a file,
+    /// this launch's or a second one's.
+    Launch,
+    /// Sent by macOS when a file is opened with the app while it runs or
+    /// launches it.
+    #[cfg_attr(not(any(target_os = "macos", target_os = "ios")), allow(dead_code))]
+    Association,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpenFileRequest {
+    pub path: String,
+    pub source: OpenSource,
+    /// Why the file can't be analyzed, such as a type the analyzer doesn't
+    /// read, for the frontend to show instead of starting an analysis.
+    pub error: Option<AnalyzeError>,
+}
+
+impl OpenFileRequest {
+    pub fn new(path: &Path, source: OpenSource) -> Self {
+        OpenFileRequest {
+            path: path.to_string_lossy().into_owned(),
+            source,
+            error: error::check_score(path).err(),
+        }
+    }
+}
+
+/// Requests made before the frontend called `take_open_requests`.
+#[derive(Debug, Default)]
+pub struct OpenRequests {
+    inner: Mutex<Queue>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    ready: bool,
+    pending: Vec<OpenFileRequest>,
+}
+
+impl OpenRequests {
+    /// The requests to emit now, or none when the frontend isn't ready and
+    /// they were queued.
+    pub fn submit(&self, requests: Vec<OpenFileRequest>) -> Vec<OpenFileRequest> {
+        let mut queue = self.inner.lock().unwrap();
+        if queue.ready {
+            requests
+        } else {
+            queue.pending.extend(requests);
+            Vec::new()
+        }
+    }
+
+    /// Everything queued so far; later requests are emitted as they come.
+    pub fn take(&self) -> Vec<OpenFileRequest> {
+        let mut queue = self.inner.lock().unwrap();
+        queue.ready = true;
+        std::mem::take(&mut queue.pending)
+    }
+}
+
+/// The files among the app's command-line arguments (without the program
+/// name), resolved against `cwd`. Flags, such as the `-psn_` macOS adds, are
+/// skipped.
+pub fn launch_paths(args: impl IntoIterator<Item = String>, cwd: Option<&Path>) -> Vec<PathBuf> {
+    args.into_iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| match cwd {
+            Some(cwd) => cwd.join(arg),
+            None => PathBuf::from(arg),
+        })
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_wait_for_the_frontend() {
+        let requests = OpenRequests::default();
+        let drop = |path: &str| OpenFileRequest::new(Path::new(path), OpenSource::Drop);
+        assert!(requests.submit(vec![drop("/scores/a.musicxml")]).is_empty());
+        let queued = requests.take();
+        assert_eq!(queued.len(), 1);
+        assert!(matches!(
+            queued[0].error,
+            Some(AnalyzeError::Io {
+                not_found: true,
+                ..
+            })
+        ));
+        assert_eq!(requests.submit(vec![drop("/scores/b.mxl")]).len(), 1);
+        assert!(requests.take().is_empty());
+    }
+
+    #[test]
+    fn launch_arguments_are_checked_files() {
+        let dir = std::env::temp_dir().join(format!("smrh-open-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("etude.musicxml"), "<score-partwise/>").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let args = ["-psn_0_12345", "etude.musicxml", "missing.mxl", "notes.txt"];
+        let paths = launch_paths(args.map(String::from), Some(&dir));
+        assert_eq!(
+            paths,
+            vec![dir.join("etude.musicxml"), dir.join("notes.txt")]
+        );
+
+        let request = OpenFileRequest::new(&paths[1], OpenSource::Launch);
+        assert!(matches!(
+            request.error,
+            Some(AnalyzeError::UnsupportedFormat { .. })
+        ));
+        assert_eq!(
+            OpenFileRequest::new(&paths[0], OpenSource::Launch).error,
+            None
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Bounded collection of the analyzer's output, so a runaway process can't
+//! exhaust memory.
+
+/// Start of the error returned when the analyzer exceeds `OutputLimits`.
+pub const EXCESSIVE_OUTPUT: &str = "analyzer produced excessive output";
+
+/// Stderr lines and stdout bytes kept for the error message.
+const TAIL_LINES: usize = 20;
+const TAIL_BYTES: usize = 2048;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutputLimits {
+    /// Stdout collected, including a line still being assembled.
+    pub max_stdout_bytes: usize,
+    /// Stderr lines of any kind, progress events included.
+    pub max_stderr_lines: usize,
+}
+
+/// Stdout and stderr of one run, collected until a limit is crossed.
+#[derive(Debug)]
+pub struct CappedOutput {
+    limits: OutputLimits,
+    pub stdout: String,
+    /// Stderr lines that weren't progress events.
+    pub stderr_lines: Vec<String>,
+    stderr_count: usize,
+    /// Bytes of the stdout lines parsed as they arrived instead of kept.
+    streamed_bytes: usize,
+}
+
+impl CappedOutput {
+    pub fn new(limits: OutputLimits) -> Self {
+        CappedOutput {
+            limits,
+            stdout: String::new(),
+            stderr_lines: Vec::new(),
+            stderr_count: 0,
+            streamed_bytes: 0,
+        }
+    }
+
+    pub fn push_stdout(&mut self, line: &str) {
+        self.stdout.push_str(line);
+        self.stdout.push('\n');
+    }
+
+    /// Count a stdout line that was consumed rather than kept.
+    pub fn count_stdout(&mut self, line: &str) {
+        self.streamed_bytes += line.len() + 1;
+    }
+
+    /// Count a stderr line, keeping it when it's `Some` (not a progress event).
+    pub fn push_stderr(&mut self, line: Option<String>) {
+        self.stderr_count += 1;
+        self.stderr_lines.extend(line);
+    }
+
+    /// Error with the tail of the output once a limit is crossed. `pending`
+    /// is what the line buffers hold without a newline yet, which counts
+    /// against the byte limit too.
+    pub fn check(&self, stdout_pending: usize, stderr_pending: usize) -> Result<(), String> {
+        let stdout_len = self.stdout.len() + self.streamed_bytes + stdout_pending;
+        let exceeded = if stdout_len > self.limits.max_stdout_bytes {
+            format!("more than {} bytes on stdout", self.limits.max_stdout_bytes)
+        } else if self.stderr_count > self.limits.max_stderr_lines {
+            format!("more than {} lines on stderr", self.limits.max_stderr_lines)
+        } else if stderr_pending > self.limits.max_stdout_bytes {
+            format!("a stderr line over {} bytes", self.limits.max_stdout_bytes)
+        } else {
+            return Ok(());
+        };
+        Err(format!(
+            "{}: {}. Last output:\n{}",
+            EXCESSIVE_OUTPUT,
+            exceeded,
+            self.tail()
+        ))
+    }
+
+    /// The last stderr lines and stdout bytes, for error messages.
+    pub fn tail(&self) -> String {
+        let start = self.stderr_lines.len().saturating_sub(TAIL_LINES);
+        let mut tail = self.stderr_lines[start..].join("\n");
+
+        let mut from = self.stdout.len().saturating_sub(TAIL_BYTES);
+        while !self.stdout.is_char_boundary(from) {
+            from += 1;
+        }
+        if from < self.stdout.len() {
+            if !tail.is_empty() {
+                tail.push('\n');
+            }
+            tail.push_str(&self.stdout[from..]);
+        }
+        tail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_buffer::LineBuffer;
+
+    const LIMITS: OutputLimits = OutputLimits {
+        max_stdout_bytes: 1024,
+        max_stderr_lines: 100,
+    };
+
+    #[test]
+    fn endless_stderr_aborts_at_the_line_cap() {
+        let mut output = CappedOutput::new(LIMITS);
+        let mut produced = 0;
+        let error = loop {
+            produced += 1;
+            output.push_stderr(Some(format!("warning {}", produced)));
+            if let Err(error) = output.check(0, 0) {
+                break error;
+            }
+        };
+
+        assert_eq!(produced, 101);
+        assert!(error.starts_with(EXCESSIVE_OUTPUT));
+        assert!(error.ends_with("warning 100\nwarning 101"));
+        assert!(!error.contains("warning 81\n"));
+    }
+
+    #[test]
+    fn stdout_without_newlines_still_counts() {
+        let mut output = CappedOutput::new(LIMITS);
+        let mut stdout = LineBuffer::default();
+        for line in stdout.push(b"{\"treble\":\n") {
+            output.push_stdout(&line);
+        }
+        assert!(output.check(stdout.pending_len(), 0).is_ok());
+
+        let mut chunks = 0;
+        while output.check(stdout.pending_len(), 0).is_ok() {
+            assert!(stdout.push(&[b'x'; 100]).is_empty());
+            chunks += 1;
+        }
+        assert_eq!(chunks, 11);
+    }
+}
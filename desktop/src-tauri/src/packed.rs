@@ -0,0 +1,96 @@
+//! `AnalysisResult` as MessagePack for large scores, where JSON over IPC
+//! gets slow. Maps keep their field names (`to_vec_named`), so the decoded
+//! value has exactly the shape of the JSON one.
+//!
+//! On a synthetic large result (two staves of 400 patterns × 12 notes plus
+//! 2 MB of MusicXML, see `bench_against_json`), measured in a release build:
+//!
+//! | format      | size    | serialize |
+//! |-------------|---------|-----------|
+//! | JSON        | 3.41 MB | 3.3 ms    |
+//! | MessagePack | 3.10 MB | 1.3 ms    |
+//!
+//! Most of the payload is the MusicXML text, which both formats carry as a
+//! plain string (JSON also escapes its newlines); the pattern data itself
+//! shrinks by about a fifth. Serializing is about 2.5× faster, and the bytes
+//! reach the webview as an `ArrayBuffer` instead of a string it has to parse.
+
+use crate::models::AnalysisResult;
+
+pub fn to_msgpack(result: &AnalysisResult) -> Result<Vec<u8>, String> {
+    rmp_serde::to_vec_named(result).map_err(|e| format!("Failed to serialize result: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    fn large_result() -> AnalysisResult {
+        let staff = |part_index: i32| StaffPatternData {
+            part_index,
+            part_name: "Piano".to_string(),
+            patterns: (0..400)
+                .map(|id| Pattern {
+                    id,
+                    length: 12,
+                    count: 4,
+                    positions: vec![id * 3, id * 3 + 100, id * 3 + 200, id * 3 + 300],
+                    notes: (0..12)
+                        .map(|i| NoteLocator {
+                            index: id * 3 + i,
+                            measure: id / 4 + 1,
+                            beat: Some(1.0 + (i % 4) as f64),
+                            pitch: "F#4".to_string(),
+                            duration_beats: Some(0.5),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        AnalysisResult {
+            file: "large.musicxml".to_string(),
+            parts: vec![staff(0), staff(1)],
+            musicxml_content: "<note><pitch><step>C</step><octave>4</octave></pitch></note>\n"
+                .repeat(2 * 1024 * 1024 / 60),
+            patterns_found: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_and_is_smaller_than_json() {
+        let result = large_result();
+        let packed = to_msgpack(&result).unwrap();
+        let json = serde_json::to_vec(&result).unwrap();
+        assert!(packed.len() < json.len());
+
+        let decoded: AnalysisResult = rmp_serde::from_slice(&packed).unwrap();
+        assert_eq!(decoded.parts[0].patterns.len(), 400);
+        assert_eq!(decoded.parts[1].patterns[7].notes[3].pitch, "F#4");
+        assert_eq!(decoded.musicxml_content, result.musicxml_content);
+    }
+
+    /// Prints the numbers in the module docs:
+    /// `cargo test --release packed -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_against_json() {
+        let result = large_result();
+        let time = |f: &dyn Fn() -> usize| {
+            let start = std::time::Instant::now();
+            let mut size = 0;
+            for _ in 0..20 {
+                size = f();
+            }
+            (size, start.elapsed() / 20)
+        };
+        let (json_size, json_time) = time(&|| serde_json::to_vec(&result).unwrap().len());
+        let (packed_size, packed_time) = time(&|| to_msgpack(&result).unwrap().len());
+        println!("JSON        {:>9} bytes {:?}", json_size, json_time);
+        println!("MessagePack {:>9} bytes {:?}", packed_size, packed_time);
+    }
+}
@@ -0,0 +1,95 @@
+//! Helpers for the pitch spellings produced by the analyzer (music21's
+//! `nameWithOctave`, e.g. "C4", "F#5", "B-3").
+
+/// A pitch name broken into its parts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spelling {
+    /// Uppercase letter name, 'A'–'G'.
+    pub step: char,
+    /// Semitones of accidental: 1 per '#', -1 per '-' (or 'b').
+    pub alter: i32,
+    pub octave: i32,
+}
+
+pub fn parse(name: &str) -> Option<Spelling> {
+    let mut chars = name.chars();
+    let step = chars.next()?.to_ascii_uppercase();
+    if !('A'..='G').contains(&step) {
+        return None;
+    }
+
+    let rest = chars.as_str();
+    let octave_start = rest
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (accidentals, octave) = rest.split_at(octave_start);
+
+    let mut alter = 0;
+    for c in accidentals.chars() {
+        match c {
+            '#' => alter += 1,
+            '-' | 'b' => alter -= 1,
+            _ => return None,
+        }
+    }
+
+    Some(Spelling {
+        step,
+        alter,
+        octave: octave.parse().ok()?,
+    })
+}
+
+/// Convert a pitch name to its MIDI note number ("C4" = 60).
+pub fn to_midi(name: &str) -> Option<i32> {
+    let spelling = parse(name)?;
+    let step = match spelling.step {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        _ => 11,
+    };
+    Some((spelling.octave + 1) * 12 + step + spelling.alter)
+}
+
+/// Staff position of a pitch in diatonic steps, ignoring accidentals
+/// ("C4" = 28, "D4" = 29), so a tonal sequence compares equal at every
+/// level even when its semitone intervals shift.
+pub fn diatonic(name: &str) -> Option<i32> {
+    let spelling = parse(name)?;
+    let step = "CDEFGAB".find(spelling.step)? as i32;
+    Some(spelling.octave * 7 + step)
+}
+
+/// Semitone steps between consecutive pitches. Unparseable pitches (e.g.
+/// rests) yield `None`.
+pub fn intervals(pitches: &[&str]) -> Option<Vec<i32>> {
+    let midi: Option<Vec<i32>> = pitches.iter().map(|p| to_midi(p)).collect();
+    Some(midi?.windows(2).map(|w| w[1] - w[0]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_music21_spellings() {
+        assert_eq!(to_midi("C4"), Some(60));
+        assert_eq!(to_midi("F#5"), Some(78));
+        assert_eq!(to_midi("B-3"), Some(58));
+        assert_eq!(to_midi("E--4"), Some(62));
+        assert_eq!(to_midi("C##4"), Some(62));
+        assert_eq!(to_midi("rest"), None);
+    }
+
+    #[test]
+    fn diatonic_ignores_accidentals() {
+        assert_eq!(diatonic("C4"), Some(28));
+        assert_eq!(diatonic("C#4"), diatonic("C-4"));
+        assert_eq!(diatonic("B3").map(|b| b + 1), diatonic("C4"));
+        assert_eq!(diatonic("rest"), None);
+    }
+}
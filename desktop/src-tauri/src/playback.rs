@@ -0,0 +1,527 @@
+//! Playing a pattern or a span of measures from the latest analysis. The
+//! notes are synthesized with the sine voice of `export::click` and played
+//! through a rodio `Sink` on the default output device, and
+//! `playback-position` events follow the sink's position for the cursor.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStream, OutputStreamHandle, Sink};
+use serde::Serialize;
+
+use crate::export::click::{self, SAMPLE_RATE};
+use crate::jobs::CancelFlag;
+use crate::models::{AnalysisResult, NoteLocator};
+use crate::{musicxml, pitch};
+
+/// Event emitted with a `PlaybackPosition` while something plays.
+pub const POSITION_EVENT: &str = "playback-position";
+
+/// How often the position is checked, and sent when it has changed.
+pub const POSITION_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Silence between the occurrences of a pattern.
+const OCCURRENCE_GAP_SECONDS: f64 = 0.5;
+/// Silence after the last note.
+const TAIL_SECONDS: f64 = 0.5;
+const ATTACK_SECONDS: f64 = 0.01;
+const RELEASE_SECONDS: f64 = 0.05;
+
+/// One note to sound, with the score note it stands for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tone {
+    /// Seconds from the start of playback.
+    pub start: f64,
+    pub seconds: f64,
+    pub midi: i32,
+    pub part_index: i32,
+    /// `NoteLocator.index` of the note in its stream.
+    pub index: i32,
+    pub measure: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaybackPosition {
+    pub playback_id: u64,
+    /// How far the sink has played.
+    pub seconds: f64,
+    /// The note sounding last, None before the first.
+    pub part_index: Option<i32>,
+    pub index: Option<i32>,
+    pub measure: Option<i32>,
+    /// Set while `pause_playback` holds it.
+    pub paused: bool,
+    /// Set on the last event, when playback ended or was stopped.
+    pub finished: bool,
+}
+
+/// What `play_pattern` and `play_range` started.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PlaybackStarted {
+    pub playback_id: u64,
+    /// Length of the playback.
+    pub seconds: f64,
+}
+
+/// A playback started by `Player::play`.
+#[derive(Clone)]
+pub struct Playing {
+    pub id: u64,
+    pub sink: Arc<Sink>,
+    /// Set by `Player::stop`.
+    pub stopped: Arc<CancelFlag>,
+}
+
+/// The latest analysis, the output device and what is playing on it.
+#[derive(Default)]
+pub struct Player {
+    result: Mutex<Option<AnalysisResult>>,
+    output: Mutex<Option<OutputStreamHandle>>,
+    next_id: AtomicU64,
+    current: Mutex<Option<Playing>>,
+}
+
+impl Player {
+    /// Play from `result` from now on.
+    pub fn load(&self, result: &AnalysisResult) {
+        *self.result.lock().unwrap() = Some(result.clone());
+    }
+
+    pub fn with_result<T>(
+        &self,
+        f: impl FnOnce(&AnalysisResult) -> Result<T, String>,
+    ) -> Result<T, String> {
+        match self.result.lock().unwrap().as_ref() {
+            Some(result) => f(result),
+            None => Err("Nothing has been analyzed yet".to_string()),
+        }
+    }
+
+    /// A new sink on the default output device, which is opened on first
+    /// use and again after it has gone away.
+    pub fn sink(&self) -> Result<Sink, String> {
+        let mut output = self.output.lock().unwrap();
+        if let Some(handle) = output.as_ref() {
+            if let Ok(sink) = Sink::try_new(handle) {
+                return Ok(sink);
+            }
+        }
+        let handle = open_output()?;
+        let sink = Sink::try_new(&handle).map_err(|e| format!("Failed to play audio: {}", e))?;
+        *output = Some(handle);
+        Ok(sink)
+    }
+
+    /// Stop whatever is playing and play `samples` (mono, at
+    /// `SAMPLE_RATE`) through `sink`.
+    pub fn play(&self, sink: Sink, samples: Vec<f32>) -> Playing {
+        self.stop();
+        sink.append(SamplesBuffer::new(1, SAMPLE_RATE, samples));
+        let playing = Playing {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            sink: Arc::new(sink),
+            stopped: Arc::new(CancelFlag::default()),
+        };
+        *self.current.lock().unwrap() = Some(playing.clone());
+        playing
+    }
+
+    /// Hold the playback where it is. Returns false if nothing was playing.
+    pub fn pause(&self) -> bool {
+        self.with_current(|playing| playing.sink.pause())
+    }
+
+    /// Carry on after `pause`. Returns false if nothing was playing.
+    pub fn resume(&self) -> bool {
+        self.with_current(|playing| playing.sink.play())
+    }
+
+    /// Returns false if nothing was playing.
+    pub fn stop(&self) -> bool {
+        match self.current.lock().unwrap().take() {
+            Some(playing) => {
+                playing.sink.stop();
+                playing.stopped.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget playback `id` once it has ended by itself.
+    pub fn finish(&self, id: u64) {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|playing| playing.id == id) {
+            *current = None;
+        }
+    }
+
+    fn with_current(&self, f: impl FnOnce(&Playing)) -> bool {
+        self.current.lock().unwrap().as_ref().map(f).is_some()
+    }
+}
+
+/// The default output device, opened on a thread that keeps it open for as
+/// long as the app runs, since rodio's stream can't leave the thread that
+/// opened it.
+fn open_output() -> Result<OutputStreamHandle, String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("audio-output".to_string())
+        .spawn(move || match OutputStream::try_default() {
+            Ok((_stream, handle)) => {
+                let _ = tx.send(Ok(handle));
+                loop {
+                    std::thread::park();
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Err(format!("Failed to open audio output: {}", e)));
+            }
+        })
+        .map_err(|e| format!("Failed to open audio output: {}", e))?;
+    rx.recv()
+        .map_err(|e| format!("Failed to open audio output: {}", e))?
+}
+
+/// Every occurrence of a pattern in score order, back to back, each at the
+/// score's tempo where it's written.
+pub fn pattern_tones(
+    result: &AnalysisResult,
+    part_index: i32,
+    pattern_id: i32,
+) -> Result<Vec<Tone>, String> {
+    let pattern = result
+        .parts
+        .iter()
+        .filter(|part| part.part_index == part_index)
+        .flat_map(|part| &part.patterns)
+        .find(|p| p.id == pattern_id)
+        .ok_or_else(|| format!("No pattern {} in part {}", pattern_id, part_index))?;
+    let streams = musicxml::read_streams(&result.musicxml_content)?;
+    let stream = usize::try_from(part_index)
+        .ok()
+        .and_then(|i| streams.get(i))
+        .ok_or_else(|| format!("The score has no part {}", part_index))?;
+    let tempo_map = click::tempo_map(&result.musicxml_content, None)?;
+
+    let mut positions = pattern.positions.clone();
+    positions.sort_unstable();
+    let mut tones = Vec::new();
+    let mut time = 0.0;
+    for position in positions {
+        let first = usize::try_from(position).unwrap_or(usize::MAX);
+        let occurrence: Vec<_> = stream
+            .iter()
+            .skip(first)
+            .take(pattern.length.max(0) as usize)
+            .collect();
+        let Some((_, first_onset)) = occurrence.first() else {
+            continue;
+        };
+        let offset = click::seconds_at(&tempo_map, *first_onset);
+        let mut end = time;
+        for (note, onset) in occurrence {
+            let Some(tone) = to_tone(note, *onset, part_index, &tempo_map, time - offset) else {
+                continue;
+            };
+            end = f64::max(end, tone.start + tone.seconds);
+            tones.push(tone);
+        }
+        time = end + OCCURRENCE_GAP_SECONDS;
+    }
+    if tones.is_empty() {
+        return Err(format!("Pattern {} has no notes to play", pattern_id));
+    }
+    Ok(tones)
+}
+
+/// The notes of every analyzed part in measures `start_measure..=end_measure`,
+/// together, following the score's tempo marks.
+pub fn range_tones(
+    result: &AnalysisResult,
+    start_measure: i32,
+    end_measure: i32,
+) -> Result<Vec<Tone>, String> {
+    if start_measure > end_measure {
+        return Err(format!(
+            "Start measure {} is after end measure {}",
+            start_measure, end_measure
+        ));
+    }
+    let streams = musicxml::read_streams(&result.musicxml_content)?;
+    let tempo_map = click::tempo_map(&result.musicxml_content, None)?;
+
+    let picked: Vec<_> = result
+        .parts
+        .iter()
+        .filter_map(|part| {
+            let stream = streams.get(usize::try_from(part.part_index).ok()?)?;
+            Some((part.part_index, stream))
+        })
+        .flat_map(|(part_index, stream)| {
+            stream
+                .iter()
+                .filter(|(note, _)| (start_measure..=end_measure).contains(&note.measure))
+                .map(move |(note, onset)| (part_index, note, *onset))
+        })
+        .collect();
+    let first_onset = picked
+        .iter()
+        .map(|(_, _, onset)| *onset)
+        .fold(f64::INFINITY, f64::min);
+    let offset = click::seconds_at(&tempo_map, first_onset);
+
+    let mut tones: Vec<Tone> = picked
+        .into_iter()
+        .filter_map(|(part_index, note, onset)| {
+            to_tone(note, onset, part_index, &tempo_map, -offset)
+        })
+        .collect();
+    if tones.is_empty() {
+        return Err(format!(
+            "No notes in measures {}-{}",
+            start_measure, end_measure
+        ));
+    }
+    tones.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(tones)
+}
+
+/// `note` sounding `shift` seconds after its place on the tempo map, or
+/// None when its pitch can't be read.
+fn to_tone(
+    note: &NoteLocator,
+    onset: f64,
+    part_index: i32,
+    tempo_map: &[(f64, f64)],
+    shift: f64,
+) -> Option<Tone> {
+    let start = click::seconds_at(tempo_map, onset);
+    let end = click::seconds_at(tempo_map, onset + note.duration_beats.unwrap_or(1.0));
+    Some(Tone {
+        start: start + shift,
+        seconds: end - start,
+        midi: pitch::to_midi(&note.pitch)?,
+        part_index,
+        index: note.index,
+        measure: note.measure,
+    })
+}
+
+/// Length of the playback in seconds.
+pub fn duration(tones: &[Tone]) -> f64 {
+    tones
+        .iter()
+        .map(|t| t.start + t.seconds)
+        .fold(0.0, f64::max)
+        + TAIL_SECONDS
+}
+
+/// Mono samples at `SAMPLE_RATE` of the tones as sine notes with a short
+/// attack, decaying while held and released after.
+pub fn samples(tones: &[Tone]) -> Vec<f32> {
+    let rate = f64::from(SAMPLE_RATE);
+    let mut mix = vec![0.0f64; (duration(tones) * rate) as usize];
+    for tone in tones {
+        let frequency = 440.0 * 2f64.powf(f64::from(tone.midi - 69) / 12.0);
+        let first = (tone.start * rate).round() as usize;
+        let length = ((tone.seconds + RELEASE_SECONDS) * rate) as usize;
+        for (i, sample) in mix.iter_mut().skip(first).take(length).enumerate() {
+            let t = i as f64 / rate;
+            let envelope = if t < ATTACK_SECONDS {
+                t / ATTACK_SECONDS
+            } else if t < tone.seconds {
+                (-(t - ATTACK_SECONDS) * 2.0).exp()
+            } else {
+                (-(tone.seconds - ATTACK_SECONDS) * 2.0).exp()
+                    * (1.0 - (t - tone.seconds) / RELEASE_SECONDS)
+            };
+            *sample += (std::f64::consts::TAU * frequency * t).sin() * 0.25 * envelope;
+        }
+    }
+    mix.iter().map(|s| s.clamp(-1.0, 1.0) as f32).collect()
+}
+
+/// The tone last started at `seconds`.
+fn sounding_at(tones: &[Tone], seconds: f64) -> Option<&Tone> {
+    tones.iter().rev().find(|t| t.start <= seconds)
+}
+
+/// Where `playing` is in `tones`, by its sink.
+pub fn position(playing: &Playing, tones: &[Tone]) -> PlaybackPosition {
+    let seconds = playing.sink.get_pos().as_secs_f64().min(duration(tones));
+    at(playing.id, tones, seconds, playing.sink.is_paused())
+}
+
+fn at(playback_id: u64, tones: &[Tone], seconds: f64, paused: bool) -> PlaybackPosition {
+    let tone = sounding_at(tones, seconds);
+    PlaybackPosition {
+        playback_id,
+        seconds,
+        part_index: tone.map(|t| t.part_index),
+        index: tone.map(|t| t.index),
+        measure: tone.map(|t| t.measure),
+        paused,
+        finished: false,
+    }
+}
+
+/// Call `on_position` whenever the sink has moved on or been paused or
+/// resumed, checking every `POSITION_INTERVAL` until it has played
+/// everything or been stopped, and end with a `finished` position.
+pub async fn follow(
+    playing: &Playing,
+    tones: &[Tone],
+    mut on_position: impl FnMut(PlaybackPosition),
+) {
+    let mut last: Option<PlaybackPosition> = None;
+    while !playing.sink.empty() {
+        let current = position(playing, tones);
+        if last.as_ref() != Some(&current) {
+            on_position(current.clone());
+            last = Some(current);
+        }
+        tokio::select! {
+            _ = playing.stopped.cancelled() => break,
+            _ = tokio::time::sleep(POSITION_INTERVAL) => {}
+        }
+    }
+    // A stopped sink forgets its position, so it's where it was last seen
+    let seconds = if playing.stopped.is_cancelled() {
+        last.map_or(0.0, |last| last.seconds)
+    } else {
+        duration(tones)
+    };
+    on_position(PlaybackPosition {
+        finished: true,
+        ..at(playing.id, tones, seconds, false)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    /// C D E | C D E in quarter notes at 60 bpm, the second bar an
+    /// octave up.
+    fn result() -> AnalysisResult {
+        let bar = |octave| {
+            ["C", "D", "E"]
+                .iter()
+                .map(|step| {
+                    format!(
+                        "<note><pitch><step>{}</step><octave>{}</octave></pitch>\
+                         <duration>1</duration></note>",
+                        step, octave
+                    )
+                })
+                .collect::<String>()
+        };
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                part_index: 0,
+                patterns: vec![Pattern {
+                    id: 7,
+                    length: 2,
+                    count: 2,
+                    positions: vec![3, 0],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">
+<attributes><divisions>1</divisions><time><beats>3</beats><beat-type>4</beat-type></time></attributes>
+<direction><sound tempo="60"/></direction>{}</measure><measure number="2">{}</measure></part></score-partwise>"#,
+                bar(4),
+                bar(5)
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn occurrences_play_back_to_back() {
+        let tones = pattern_tones(&result(), 0, 7).unwrap();
+        let played: Vec<(f64, i32, i32)> =
+            tones.iter().map(|t| (t.start, t.midi, t.index)).collect();
+        assert_eq!(
+            played,
+            vec![(0.0, 60, 0), (1.0, 62, 1), (2.5, 72, 3), (3.5, 74, 4)]
+        );
+        assert!(pattern_tones(&result(), 1, 7).is_err());
+        assert!(pattern_tones(&result(), 0, 8).is_err());
+    }
+
+    #[test]
+    fn a_range_plays_its_measures_in_score_time() {
+        let tones = range_tones(&result(), 2, 2).unwrap();
+        let starts: Vec<f64> = tones.iter().map(|t| t.start).collect();
+        assert_eq!(starts, vec![0.0, 1.0, 2.0]);
+        assert!(tones.iter().all(|t| t.measure == 2));
+        assert!(range_tones(&result(), 3, 4).is_err());
+        assert!(range_tones(&result(), 2, 1).is_err());
+
+        assert_eq!(
+            samples(&tones).len(),
+            (3.5 * f64::from(SAMPLE_RATE)) as usize
+        );
+        assert_eq!(sounding_at(&tones, 1.5).map(|t| t.index), Some(4));
+    }
+
+    #[test]
+    fn starting_stops_the_previous_playback() {
+        let player = Player::default();
+        assert!(player.with_result(|_| Ok(())).is_err());
+        assert!(!player.pause());
+        let first = player.play(Sink::new_idle().0, vec![0.0; 10]);
+        let second = player.play(Sink::new_idle().0, vec![0.0; 10]);
+        assert!(first.stopped.is_cancelled());
+        assert!(!second.stopped.is_cancelled());
+        // A finished earlier playback doesn't clear the current one
+        player.finish(first.id);
+        assert!(player.pause());
+        assert!(second.sink.is_paused());
+        assert!(player.resume());
+        assert!(!second.sink.is_paused());
+        assert!(player.stop());
+        assert!(second.stopped.is_cancelled());
+        player.finish(second.id);
+        assert!(!player.stop());
+    }
+
+    #[tokio::test]
+    async fn the_position_follows_what_the_sink_has_played() {
+        let tones = range_tones(&result(), 2, 2).unwrap();
+        let player = Player::default();
+        let (sink, mut output) = Sink::new_idle();
+        let playing = player.play(sink, samples(&tones));
+
+        // The device pulls a second and a half of audio
+        output
+            .by_ref()
+            .take((1.5 * f64::from(SAMPLE_RATE)) as usize)
+            .for_each(drop);
+        let now = position(&playing, &tones);
+        assert!((now.seconds - 1.5).abs() < 0.01, "{}", now.seconds);
+        assert_eq!((now.index, now.paused), (Some(4), false));
+
+        // Paused, it plays silence without moving on
+        player.pause();
+        output.by_ref().take(SAMPLE_RATE as usize).for_each(drop);
+        let paused = position(&playing, &tones);
+        assert!(paused.paused);
+        assert!((paused.seconds - now.seconds).abs() < 0.01);
+
+        player.stop();
+        let mut sent = Vec::new();
+        follow(&playing, &tones, |position| sent.push(position)).await;
+        let last = sent.last().unwrap();
+        assert!(last.finished);
+        assert!((last.seconds - 1.5).abs() < 0.01);
+    }
+}
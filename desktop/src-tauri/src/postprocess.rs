@@ -0,0 +1,379 @@
+use crate::classify;
+use crate::config::{AnalyzerConfig, MeasureFrame};
+use crate::displacement;
+use crate::harmony;
+use crate::inversion;
+use crate::keys::{Key, Mode};
+use crate::models::{AnalysisResult, NoteLocator};
+use crate::musicxml::{self, repeats, timing};
+use crate::occurrences;
+use crate::runs;
+use crate::scaling;
+use crate::sequences;
+
+/// Apply the Rust-side enrichment steps selected in `config` to a parsed
+/// result. These are the options `reprocess_result` can change without a
+/// new analyzer run; see `AnalyzerConfig` for which is which.
+pub fn apply(result: &mut AnalysisResult, config: &AnalyzerConfig) -> Result<(), String> {
+    let min_length = config.min_length();
+    for staff in result.staves_mut() {
+        staff.patterns.retain(|p| {
+            p.length >= min_length && config.min_occurrences.is_none_or(|min| p.count >= min)
+        });
+    }
+
+    if config.classify_patterns {
+        for staff in result.staves_mut() {
+            for pattern in &mut staff.patterns {
+                pattern.category = classify::classify(&pattern.notes).map(str::to_string);
+            }
+        }
+    }
+
+    if config.infer_harmony {
+        for staff in result.staves_mut() {
+            for pattern in &mut staff.patterns {
+                pattern.harmony = harmony::infer(&pattern.notes);
+            }
+        }
+    }
+
+    if config.detect_rhythmic_scaling {
+        for staff in result.staves_mut() {
+            scaling::link(&mut staff.patterns);
+        }
+    }
+
+    if config.detect_inversions {
+        for staff in result.staves_mut() {
+            inversion::link(&mut staff.patterns, config.inversion_match);
+        }
+    }
+
+    if config.detect_sequences {
+        let streams = musicxml::stream_notes(&result.musicxml_content)?;
+        for staff in result.staves_mut() {
+            if let Some(notes) = streams.get(staff.part_index as usize) {
+                staff.sequences = sequences::detect(notes);
+            }
+        }
+    }
+
+    // Otherwise a pattern note's index points at another note of the score
+    if config.capture_articulations && config.numbers_notes_as_written() {
+        capture_articulations(result)?;
+    }
+
+    if config.annotate_scale_degrees {
+        annotate_scale_degrees(result)?;
+    }
+
+    // Before any renumbering, while measures still match the score's
+    if config.compute_absolute_beats {
+        compute_absolute_beats(result)?;
+    }
+    if config.detect_metric_displacement {
+        displacement::annotate(result)?;
+    }
+
+    if config.measure_frame == MeasureFrame::Played {
+        let map = repeats::measure_map(&result.musicxml_content)?;
+        for_each_note(result, |note| {
+            if let Some(played) = map.first_played(note.measure) {
+                note.measure = played;
+            }
+        });
+        result.measure_map = Some(map);
+    }
+
+    // In the frame chosen above
+    if config.by_measure {
+        result.measure_index = occurrences::measure_index(result);
+    }
+
+    // Last, so the steps above see every note
+    if config.run_length_encode {
+        for_each_notes(result, |notes| *notes = runs::encode(notes));
+    }
+
+    result.patterns_found = pattern_count(result) > 0;
+    Ok(())
+}
+
+/// Set `absolute_beat` on every pattern and sequence note with a known beat.
+pub fn compute_absolute_beats(result: &mut AnalysisResult) -> Result<(), String> {
+    let timings = timing::measure_timings(&result.musicxml_content)?;
+    for_each_note(result, |note| {
+        note.absolute_beat = timings
+            .get(&note.measure)
+            .zip(note.beat)
+            .map(|(t, beat)| t.absolute_beat(beat));
+    });
+    Ok(())
+}
+
+/// Copy each pattern note's articulations from the score. Sequence notes
+/// are read from the score and already carry them. Pattern notes must be
+/// numbered as `musicxml::stream_notes` numbers them.
+pub fn capture_articulations(result: &mut AnalysisResult) -> Result<(), String> {
+    let streams = musicxml::stream_notes(&result.musicxml_content)?;
+    for staff in result.staves_mut() {
+        let Some(stream) = usize::try_from(staff.part_index)
+            .ok()
+            .and_then(|i| streams.get(i))
+        else {
+            continue;
+        };
+        for note in staff.patterns.iter_mut().flat_map(|p| &mut p.notes) {
+            if let Some(written) = usize::try_from(note.index).ok().and_then(|i| stream.get(i)) {
+                note.articulations = written.articulations.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set `result.key` and the scale degree of every pattern and sequence note.
+pub fn annotate_scale_degrees(result: &mut AnalysisResult) -> Result<(), String> {
+    let key = match musicxml::first_key(&result.musicxml_content)? {
+        Some((fifths, mode)) => Some(Key::from_fifths(
+            fifths,
+            Mode::from_musicxml(mode.as_deref()),
+        )),
+        None => {
+            let streams = musicxml::stream_notes(&result.musicxml_content)?;
+            Key::estimate(streams.iter().flatten().map(|n| n.pitch.as_str()))
+        }
+    };
+    let Some(key) = key else {
+        return Ok(());
+    };
+
+    for_each_note(result, |note| {
+        let degree = key.scale_degree(&note.pitch);
+        note.scale_degree = degree.map(|d| d.degree);
+        note.degree_alteration = degree.map(|d| d.alteration);
+    });
+    result.key = Some(key);
+    Ok(())
+}
+
+/// Undo every step `apply` can take, giving back the result as the
+/// analyzer produced it so it can be processed again with other options.
+pub fn reset(result: &mut AnalysisResult) {
+    for_each_notes(result, |notes| *notes = runs::expand(notes));
+    for staff in result.staves_mut() {
+        staff.sequences.clear();
+        for pattern in &mut staff.patterns {
+            pattern.category = None;
+            pattern.harmony = None;
+            pattern.metric_displacement = None;
+            pattern.scaled_from = None;
+            pattern.inversion_of = None;
+        }
+    }
+    let map = result.measure_map.take();
+    for_each_note(result, |note| {
+        if let Some(written) = map.as_ref().and_then(|map| {
+            let played = usize::try_from(note.measure - 1).ok()?;
+            map.played.get(played).copied()
+        }) {
+            note.measure = written;
+        }
+        note.absolute_beat = None;
+        note.scale_degree = None;
+        note.degree_alteration = None;
+        note.articulations.clear();
+    });
+    result.key = None;
+    result.measure_index.clear();
+}
+
+/// Run `f` on every pattern and sequence note of every staff.
+fn for_each_note(result: &mut AnalysisResult, mut f: impl FnMut(&mut NoteLocator)) {
+    for_each_notes(result, |notes| notes.iter_mut().for_each(&mut f));
+}
+
+/// Run `f` on the note list of every pattern and sequence of every staff.
+fn for_each_notes(result: &mut AnalysisResult, mut f: impl FnMut(&mut Vec<NoteLocator>)) {
+    for staff in result.staves_mut() {
+        let pattern_notes = staff.patterns.iter_mut().map(|p| &mut p.notes);
+        let sequence_notes = staff.sequences.iter_mut().map(|s| &mut s.notes);
+        pattern_notes.chain(sequence_notes).for_each(&mut f);
+    }
+}
+
+pub fn pattern_count(result: &AnalysisResult) -> usize {
+    result.staves().iter().map(|s| s.patterns.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData};
+
+    /// Measures 1–2 repeated, then 3: played order 1 2 1 2 3.
+    fn repeated_result() -> AnalysisResult {
+        let note = "<note><pitch><step>C</step><octave>4</octave></pitch></note>";
+        AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 0,
+                    length: 2,
+                    count: 2,
+                    positions: vec![0, 2],
+                    notes: [2, 3]
+                        .into_iter()
+                        .map(|measure| NoteLocator {
+                            measure,
+                            pitch: "C4".to_string(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">{n}</measure>
+<measure number="2">{n}<barline><repeat direction="backward"/></barline></measure>
+<measure number="3">{n}</measure></part></score-partwise>"#,
+                n = note
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn measures(result: &AnalysisResult) -> Vec<i32> {
+        result.parts[0].patterns[0]
+            .notes
+            .iter()
+            .map(|n| n.measure)
+            .collect()
+    }
+
+    /// Keeps the two-note pattern of `repeated_result`.
+    fn two_note() -> AnalyzerConfig {
+        AnalyzerConfig {
+            min_pattern_length: Some(2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reprocessing_switches_measure_frames_back_and_forth() {
+        let mut result = repeated_result();
+        let played = AnalyzerConfig {
+            measure_frame: MeasureFrame::Played,
+            classify_patterns: true,
+            ..two_note()
+        };
+        apply(&mut result, &played).unwrap();
+        assert_eq!(measures(&result), vec![2, 5]);
+
+        // Applying the same options again must not renumber twice
+        reset(&mut result);
+        apply(&mut result, &played).unwrap();
+        assert_eq!(measures(&result), vec![2, 5]);
+
+        reset(&mut result);
+        apply(&mut result, &two_note()).unwrap();
+        assert_eq!(measures(&result), vec![2, 3]);
+        assert!(result.measure_map.is_none());
+        assert_eq!(result.parts[0].patterns[0].category, None);
+    }
+
+    #[test]
+    fn two_note_patterns_are_dropped_unless_allowed() {
+        let mut result = repeated_result();
+        apply(&mut result, &AnalyzerConfig::default()).unwrap();
+        assert!(result.parts[0].patterns.is_empty());
+        assert!(!result.patterns_found);
+
+        let mut result = repeated_result();
+        apply(&mut result, &two_note()).unwrap();
+        assert_eq!(result.parts[0].patterns.len(), 1);
+        assert!(result.patterns_found);
+
+        // The analyzer keeps its own default unless told otherwise
+        assert!(AnalyzerConfig::default().sidecar_args().is_empty());
+        assert_eq!(AnalyzerConfig::default().search_length(), 4);
+        assert_eq!(two_note().sidecar_args(), vec!["2"]);
+        assert_eq!(two_note().search_length(), 2);
+    }
+
+    #[test]
+    fn articulations_are_only_captured_when_indices_match_the_score() {
+        // A grace note, then an accented and a plain note, twice
+        let notes = r#"<note><grace/><pitch><step>B</step><octave>3</octave></pitch></note>
+<note><pitch><step>C</step><octave>4</octave></pitch><duration>1</duration>
+<notations><articulations><accent/></articulations></notations></note>
+<note><pitch><step>D</step><octave>4</octave></pitch><duration>1</duration></note>"#;
+        let result = |indices: [i32; 2]| AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    length: 2,
+                    count: 2,
+                    notes: indices
+                        .into_iter()
+                        .map(|index| NoteLocator {
+                            index,
+                            measure: 1,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1"><measure number="1">{n}{n}</measure></part></score-partwise>"#,
+                n = notes
+            ),
+            ..Default::default()
+        };
+        let articulations = |result: &AnalysisResult| -> Vec<Vec<String>> {
+            result.parts[0].patterns[0]
+                .notes
+                .iter()
+                .map(|n| n.articulations.clone())
+                .collect()
+        };
+        let capture = AnalyzerConfig {
+            capture_articulations: true,
+            ..two_note()
+        };
+
+        // The accented note is the first the analyzer numbers by default
+        let mut skipped_grace = result([0, 1]);
+        apply(&mut skipped_grace, &capture).unwrap();
+        assert_eq!(articulations(&skipped_grace), vec![vec!["accent"], vec![]]);
+
+        // With the grace note counted it is second, which the score's
+        // numbering can't follow, so nothing is captured
+        let mut with_grace = result([1, 2]);
+        let graced = AnalyzerConfig {
+            include_grace_notes: true,
+            ..capture
+        };
+        apply(&mut with_grace, &graced).unwrap();
+        assert_eq!(articulations(&with_grace), vec![Vec::<String>::new(); 2]);
+    }
+
+    #[test]
+    fn patterns_heard_too_rarely_are_dropped() {
+        let mut result = repeated_result();
+        let thrice = AnalyzerConfig {
+            min_occurrences: Some(3),
+            match_rhythm: false,
+            transposition_invariant: true,
+            ..two_note()
+        };
+        apply(&mut result, &thrice).unwrap();
+        assert!(result.parts[0].patterns.is_empty());
+        assert_eq!(
+            thrice.sidecar_args(),
+            vec!["2", "--ignore-rhythm", "--transposed"]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Background analyses started before the user asks for them, so the
+//! result is already cached by the time they do.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::error::AnalyzeError;
+use crate::jobs::CancelFlag;
+
+/// Ended prefetches whose outcome `await_prefetch` can still report, the
+/// oldest forgotten first.
+const MAX_FINISHED: usize = 32;
+
+/// How a prefetch ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PrefetchStatus {
+    /// The result is in the cache.
+    Ready,
+    Failed {
+        error: AnalyzeError,
+    },
+    Cancelled,
+}
+
+struct Prefetch {
+    cache_key: String,
+    cancel: Arc<CancelFlag>,
+    status: watch::Receiver<Option<PrefetchStatus>>,
+}
+
+#[derive(Default)]
+pub struct Prefetches {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Prefetch>>,
+    finished: Mutex<VecDeque<(u64, PrefetchStatus)>>,
+}
+
+impl Prefetches {
+    /// Register a prefetch. The task reports its outcome through the
+    /// returned sender.
+    pub fn start(
+        &self,
+        cache_key: String,
+    ) -> (u64, Arc<CancelFlag>, watch::Sender<Option<PrefetchStatus>>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(CancelFlag::default());
+        let (tx, status) = watch::channel(None);
+        self.running.lock().unwrap().insert(
+            id,
+            Prefetch {
+                cache_key,
+                cancel: cancel.clone(),
+                status,
+            },
+        );
+        (id, cancel, tx)
+    }
+
+    /// Returns false if no such prefetch is known.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.running.lock().unwrap().get(&id) {
+            Some(prefetch) => {
+                prefetch.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget the task of a prefetch that reported its outcome, keeping only
+    /// the outcome, so prefetches nobody awaits don't pile up.
+    pub fn finish(&self, id: u64) {
+        let Some(prefetch) = self.running.lock().unwrap().remove(&id) else {
+            return;
+        };
+        let Some(status) = prefetch.status.borrow().clone() else {
+            return;
+        };
+        let mut finished = self.finished.lock().unwrap();
+        finished.push_back((id, status));
+        while finished.len() > MAX_FINISHED {
+            finished.pop_front();
+        }
+    }
+
+    /// Wait for a prefetch to end and forget it. None if no such prefetch
+    /// is known (or it was already awaited, or ended long ago).
+    pub async fn wait(&self, id: u64) -> Option<PrefetchStatus> {
+        let running = self
+            .running
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|p| p.status.clone());
+        let outcome = match running {
+            Some(status) => Some(wait_for(status).await),
+            None => None,
+        };
+        self.running.lock().unwrap().remove(&id);
+        let mut finished = self.finished.lock().unwrap();
+        let ended = finished.iter().position(|(ended, _)| *ended == id);
+        let recorded = ended.and_then(|i| finished.remove(i)).map(|(_, s)| s);
+        outcome.or(recorded)
+    }
+
+    /// Wait for any unfinished prefetch of `cache_key`, so a foreground
+    /// analysis of the same file reuses it instead of starting another.
+    pub async fn wait_for_key(&self, cache_key: &str) {
+        let pending: Vec<_> = self
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|p| p.cache_key == cache_key && p.status.borrow().is_none())
+            .map(|p| p.status.clone())
+            .collect();
+        for status in pending {
+            wait_for(status).await;
+        }
+    }
+}
+
+async fn wait_for(mut status: watch::Receiver<Option<PrefetchStatus>>) -> PrefetchStatus {
+    match status.wait_for(Option::is_some).await {
+        Ok(status) => status.clone().unwrap_or(PrefetchStatus::Cancelled),
+        // The task ended without reporting, e.g. it panicked
+        Err(_) => PrefetchStatus::Failed {
+            error: AnalyzeError::from("Prefetch ended unexpectedly".to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_returns_reported_status_once() {
+        let prefetches = Prefetches::default();
+        let (id, _cancel, tx) = prefetches.start("abc".to_string());
+        tx.send(Some(PrefetchStatus::Ready)).unwrap();
+
+        prefetches.wait_for_key("abc").await;
+        assert_eq!(prefetches.wait(id).await, Some(PrefetchStatus::Ready));
+        assert_eq!(prefetches.wait(id).await, None);
+    }
+
+    #[tokio::test]
+    async fn finished_prefetches_are_forgotten() {
+        let prefetches = Prefetches::default();
+        let ids: Vec<u64> = (0..MAX_FINISHED + 1)
+            .map(|_| {
+                let (id, _cancel, tx) = prefetches.start("abc".to_string());
+                tx.send(Some(PrefetchStatus::Cancelled)).unwrap();
+                prefetches.finish(id);
+                id
+            })
+            .collect();
+        assert!(prefetches.running.lock().unwrap().is_empty());
+        assert_eq!(prefetches.finished.lock().unwrap().len(), MAX_FINISHED);
+
+        // Awaiting after the end still reports it, unless it is the oldest
+        assert_eq!(prefetches.wait(ids[0]).await, None);
+        assert_eq!(
+            prefetches.wait(ids[MAX_FINISHED]).await,
+            Some(PrefetchStatus::Cancelled)
+        );
+        assert_eq!(prefetches.wait(ids[MAX_FINISHED]).await, None);
+    }
+
+    #[tokio::test]
+    async fn cancel_reaches_the_task() {
+        let prefetches = Prefetches::default();
+        let (id, cancel, _tx) = prefetches.start("abc".to_string());
+        assert!(prefetches.cancel(id));
+        assert!(cancel.is_cancelled());
+        assert!(!prefetches.cancel(id + 1));
+    }
+}
@@ -0,0 +1,156 @@
+//! A running analyzer as `sidecar`'s run loop sees it, however it was
+//! started: by the Tauri shell plugin in the app, or with `std::process`
+//! (`spawn`) in headless mode, where there is no app to run the plugin.
+
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+/// Events sent before the run loop has read them.
+const EVENT_BUFFER: usize = 64;
+
+/// How often the status of a process whose output has ended is checked.
+const EXIT_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessEvent {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    /// The process exited, with its code unless a signal ended it. Nothing
+    /// follows it.
+    Terminated(Option<i32>),
+    /// Watching the process failed.
+    Error(String),
+}
+
+pub struct Process {
+    pub events: mpsc::Receiver<ProcessEvent>,
+    pub pid: u32,
+    kill: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Process {
+    pub fn new(
+        events: mpsc::Receiver<ProcessEvent>,
+        pid: u32,
+        kill: impl FnOnce() + Send + 'static,
+    ) -> Self {
+        Process {
+            events,
+            pid,
+            kill: Some(Box::new(kill)),
+        }
+    }
+
+    /// Kill the process, without waiting for it to exit.
+    pub fn kill(&mut self) {
+        if let Some(kill) = self.kill.take() {
+            kill();
+        }
+    }
+}
+
+/// The channel a `Process`'s events are sent on.
+pub fn channel() -> (mpsc::Sender<ProcessEvent>, mpsc::Receiver<ProcessEvent>) {
+    mpsc::channel(EVENT_BUFFER)
+}
+
+/// Start `program` with `args`, its output read on threads of its own.
+pub fn spawn(program: &Path, args: &[String]) -> std::io::Result<Process> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let (tx, events) = channel();
+    let readers = [
+        forward(child.stdout.take(), tx.clone(), ProcessEvent::Stdout),
+        forward(child.stderr.take(), tx.clone(), ProcessEvent::Stderr),
+    ];
+    let pid = child.id();
+    let child = Arc::new(Mutex::new(child));
+    let waited = child.clone();
+    std::thread::spawn(move || {
+        // The pipes close as the process exits, so its output is all sent
+        // before it's reported gone
+        for reader in readers.into_iter().flatten() {
+            let _ = reader.join();
+        }
+        let event = loop {
+            match waited.lock().unwrap().try_wait() {
+                Ok(Some(status)) => break ProcessEvent::Terminated(status.code()),
+                Ok(None) => {}
+                Err(e) => break ProcessEvent::Error(e.to_string()),
+            }
+            std::thread::sleep(EXIT_POLL);
+        };
+        let _ = tx.blocking_send(event);
+    });
+    Ok(Process::new(events, pid, move || {
+        let _ = child.lock().unwrap().kill();
+    }))
+}
+
+/// Send what `pipe` yields as events made by `event`, until it ends or
+/// nothing listens any more.
+fn forward(
+    pipe: Option<impl Read + Send + 'static>,
+    tx: mpsc::Sender<ProcessEvent>,
+    event: fn(Vec<u8>) -> ProcessEvent,
+) -> Option<JoinHandle<()>> {
+    let mut pipe = pipe?;
+    Some(std::thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        while let Ok(read @ 1..) = pipe.read(&mut buffer) {
+            if tx.blocking_send(event(buffer[..read].to_vec())).is_err() {
+                break;
+            }
+        }
+    }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    async fn events(process: &mut Process) -> Vec<ProcessEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = process.events.recv().await {
+            events.push(event);
+        }
+        events
+    }
+
+    fn sh(script: &str) -> Process {
+        spawn(
+            Path::new("/bin/sh"),
+            &["-c".to_string(), script.to_string()],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn output_is_sent_before_the_exit() {
+        let mut process = sh("printf out; printf err >&2; exit 3");
+        let sent = events(&mut process).await;
+        assert!(sent.contains(&ProcessEvent::Stdout(b"out".to_vec())));
+        assert!(sent.contains(&ProcessEvent::Stderr(b"err".to_vec())));
+        assert_eq!(sent.last(), Some(&ProcessEvent::Terminated(Some(3))));
+    }
+
+    #[tokio::test]
+    async fn a_killed_process_is_reported_gone() {
+        let mut process = sh("exec sleep 10");
+        process.kill();
+        let sent = tokio::time::timeout(Duration::from_secs(5), events(&mut process))
+            .await
+            .unwrap();
+        assert_eq!(sent, vec![ProcessEvent::Terminated(None)]);
+    }
+}
@@ -0,0 +1,225 @@
+//! Project files (`.smrh`): a score with its analysis, the settings it was
+//! analyzed with and the user's annotations, as JSON.
+//!
+//! Every file carries a `version`. Fields added later are optional, so they
+//! don't change it and older versions of the app skip them; the version is
+//! only bumped when a field changes meaning. Older files are migrated on
+//! load, and files of a newer version are rejected rather than misread.
+
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::AnalyzerConfig;
+use crate::models::AnalysisResult;
+
+pub const PROJECT_VERSION: u32 = 1;
+
+pub const PROJECT_EXTENSION: &str = "smrh";
+
+/// What the user changed about one detected pattern.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatternAnnotation {
+    pub part_index: i32,
+    pub pattern_id: i32,
+    /// Shown instead of the pattern's number.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Marked as a false positive.
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    #[serde(default)]
+    pub patterns: Vec<PatternAnnotation>,
+    /// Ids of the patterns shown in the viewer, when not all of them are.
+    #[serde(default)]
+    pub enabled_patterns: Option<Vec<i32>>,
+    /// Notes on the piece as a whole.
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectState {
+    /// The score, as opened.
+    pub source_path: String,
+    /// Base64 of the score file, when it is embedded so the project opens
+    /// without it.
+    #[serde(default)]
+    pub embedded_source: Option<String>,
+    pub result: AnalysisResult,
+    /// The options `result` was analyzed with.
+    #[serde(default)]
+    pub config: AnalyzerConfig,
+    #[serde(default)]
+    pub annotations: Annotations,
+}
+
+#[derive(Serialize)]
+struct ProjectFile<'a> {
+    version: u32,
+    #[serde(flatten)]
+    state: &'a ProjectState,
+}
+
+/// Write `state` to `path`, first embedding the score file when `embed` is
+/// set and it isn't already.
+pub fn save(path: &Path, state: &ProjectState, embed: bool) -> Result<(), String> {
+    let embedded;
+    let state = if embed && state.embedded_source.is_none() {
+        let bytes = std::fs::read(&state.source_path)
+            .map_err(|e| format!("Failed to read score to embed: {}", e))?;
+        embedded = ProjectState {
+            embedded_source: Some(STANDARD.encode(bytes)),
+            ..state.clone()
+        };
+        &embedded
+    } else {
+        state
+    };
+    let json = serde_json::to_string_pretty(&ProjectFile {
+        version: PROJECT_VERSION,
+        state,
+    })
+    .map_err(|e| format!("Failed to serialize project: {}", e))?;
+    // Written under a temp name first, as in `AnalysisCache::put`
+    let tmp = path.with_extension("smrh.tmp");
+    std::fs::write(&tmp, json).map_err(|e| format!("Failed to write project: {}", e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to write project: {}", e))
+}
+
+pub fn load(path: &Path) -> Result<ProjectState, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read project: {}", e))?;
+    let invalid = |reason: String| format!("Invalid project file: {}", reason);
+    let mut value: Value = serde_json::from_str(&json).map_err(|e| invalid(e.to_string()))?;
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("missing version".to_string()))?;
+    if version > u64::from(PROJECT_VERSION) {
+        return Err(format!(
+            "Project is version {}, this app reads up to version {}; update the app to open it",
+            version, PROJECT_VERSION
+        ));
+    }
+    migrate(&mut value, version as u32);
+    serde_json::from_value(value).map_err(|e| invalid(e.to_string()))
+}
+
+/// Bring a project of an older `version` up to `PROJECT_VERSION`. There
+/// are none yet; each bump adds a step here.
+fn migrate(_value: &mut Value, _version: u32) {}
+
+/// When the score has moved or been deleted but is embedded, write the
+/// copy into `dir` and point `source_path` at it. Returns whether it did.
+pub fn restore_source(state: &mut ProjectState, dir: &Path) -> Result<bool, String> {
+    let source = Path::new(&state.source_path);
+    let Some(b64) = state.embedded_source.as_deref() else {
+        return Ok(false);
+    };
+    if source.exists() {
+        return Ok(false);
+    }
+    let bytes = STANDARD
+        .decode(b64)
+        .map_err(|e| format!("Invalid embedded score: {}", e))?;
+    let name = source.file_name().unwrap_or("score.musicxml".as_ref());
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to restore score: {}", e))?;
+    let restored = dir.join(name);
+    std::fs::write(&restored, bytes).map_err(|e| format!("Failed to restore score: {}", e))?;
+    state.source_path = restored.to_string_lossy().into_owned();
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projects_round_trip_with_the_score_embedded() {
+        let dir = std::env::temp_dir().join(format!("smrh-project-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let score = dir.join("minuet.musicxml");
+        std::fs::write(&score, "<score-partwise/>").unwrap();
+        let state = ProjectState {
+            source_path: score.to_string_lossy().into_owned(),
+            embedded_source: None,
+            result: AnalysisResult {
+                file: "minuet.musicxml".to_string(),
+                ..Default::default()
+            },
+            config: AnalyzerConfig {
+                min_occurrences: Some(3),
+                ..Default::default()
+            },
+            annotations: Annotations {
+                patterns: vec![PatternAnnotation {
+                    pattern_id: 2,
+                    name: Some("Opening motif".to_string()),
+                    hidden: true,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        };
+        let path = dir.join("minuet.smrh");
+        save(&path, &state, true).unwrap();
+
+        let mut loaded = load(&path).unwrap();
+        assert_eq!(loaded.result.file, "minuet.musicxml");
+        assert_eq!(loaded.config.min_occurrences, Some(3));
+        assert_eq!(loaded.annotations, state.annotations);
+
+        let restore_dir = dir.join("restored");
+        assert!(!restore_source(&mut loaded, &restore_dir).unwrap());
+        std::fs::remove_file(&score).unwrap();
+        assert!(restore_source(&mut loaded, &restore_dir).unwrap());
+        assert_eq!(
+            Path::new(&loaded.source_path),
+            restore_dir.join("minuet.musicxml")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&loaded.source_path).unwrap(),
+            "<score-partwise/>"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn newer_versions_are_rejected_and_unknown_fields_skipped() {
+        let dir = std::env::temp_dir().join(format!("smrh-project-v-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.smrh");
+        let result = serde_json::to_value(AnalysisResult::default()).unwrap();
+
+        let project = serde_json::json!({
+            "version": 1,
+            "source_path": "/scores/a.mxl",
+            "result": result,
+            "added_later": [1, 2],
+        });
+        std::fs::write(&path, project.to_string()).unwrap();
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.source_path, "/scores/a.mxl");
+        assert_eq!(loaded.annotations, Annotations::default());
+
+        let newer = serde_json::json!({ "version": 2, "source_path": "", "result": result });
+        std::fs::write(&path, newer.to_string()).unwrap();
+        assert!(load(&path).unwrap_err().contains("version 2"));
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(load(&path).unwrap_err().contains("missing version"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+//! Recently opened scores, most recent first, kept in the `PatternDb` so
+//! they survive restarts.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::db::PatternDb;
+use crate::models::AnalysisResult;
+use crate::musicxml::metadata::{self, ScoreMetadata};
+use crate::musicxml::{midi, mxl};
+
+/// Entries kept; opening another drops the oldest.
+pub const MAX_RECENT: usize = 20;
+
+/// Image types shown as their own thumbnail.
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentFile {
+    pub path: String,
+    #[serde(flatten)]
+    pub metadata: ScoreMetadata,
+    /// Seconds since the Unix epoch.
+    pub opened_at: u64,
+    pub analyzed_at: Option<u64>,
+    /// An image of the score, for now only the file itself when it is one.
+    pub thumbnail: Option<String>,
+}
+
+/// Move `path` to the front, reading its metadata if it's MusicXML.
+/// Metadata from an earlier analysis is kept for other formats.
+pub fn add(db: &PatternDb, path: &str) -> Result<RecentFile, String> {
+    let file = Path::new(path);
+    let read = mxl::is_mxl(file)
+        || midi::is_midi(file)
+        || file
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("musicxml"));
+    let metadata = if read {
+        Some(metadata::read_metadata(&mxl::read_score(file)?)?)
+    } else {
+        None
+    };
+    update(db, path, |entry| {
+        if let Some(metadata) = metadata {
+            entry.metadata = metadata;
+        }
+    })
+}
+
+/// Record a finished analysis of `path`, taking the metadata from the
+/// MusicXML the analyzer read (so PDFs and images get it too).
+pub fn record_analysis(db: &PatternDb, path: &str, result: &AnalysisResult) -> Result<(), String> {
+    let metadata = metadata::read_metadata(&result.musicxml_content)?;
+    update(db, path, |entry| {
+        entry.metadata = metadata;
+        entry.analyzed_at = Some(now());
+    })?;
+    Ok(())
+}
+
+/// Move the entry for `path` (a new one if it isn't listed) to the front,
+/// apply `f` to it and save.
+fn update(
+    db: &PatternDb,
+    path: &str,
+    f: impl FnOnce(&mut RecentFile),
+) -> Result<RecentFile, String> {
+    db.update_recent(path, MAX_RECENT, |existing| {
+        let mut entry = existing.unwrap_or_else(|| RecentFile {
+            path: path.to_string(),
+            metadata: ScoreMetadata::default(),
+            opened_at: 0,
+            analyzed_at: None,
+            thumbnail: None,
+        });
+        entry.opened_at = now();
+        entry.thumbnail = thumbnail(path);
+        f(&mut entry);
+        entry
+    })
+}
+
+fn thumbnail(path: &str) -> Option<String> {
+    let ext = Path::new(path)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    IMAGE_EXTENSIONS
+        .contains(&ext.as_str())
+        .then(|| path.to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_recent_first_and_persisted() {
+        let dir = std::env::temp_dir().join(format!("smrh-recent-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let score = dir.join("a.musicxml");
+        std::fs::write(
+            &score,
+            r#"<score-partwise><work><work-title>Minuet</work-title></work>
+            <part-list><score-part id="P1"/></part-list></score-partwise>"#,
+        )
+        .unwrap();
+        let score = score.to_string_lossy().into_owned();
+        let photo = dir.join("b.png").to_string_lossy().into_owned();
+
+        let db = PatternDb::open(&dir.join("patterns.sqlite3")).unwrap();
+        let added = add(&db, &score).unwrap();
+        assert_eq!(added.metadata.title.as_deref(), Some("Minuet"));
+        assert_eq!(added.metadata.parts, 1);
+        add(&db, &photo).unwrap();
+        let result = AnalysisResult {
+            musicxml_content: "<score-partwise><movement-title>Study</movement-title>\
+                               </score-partwise>"
+                .to_string(),
+            ..Default::default()
+        };
+        record_analysis(&db, &photo, &result).unwrap();
+        drop(db);
+
+        let db = PatternDb::open(&dir.join("patterns.sqlite3")).unwrap();
+        let loaded = db.recent_files().unwrap();
+        let paths: Vec<&str> = loaded.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec![photo.as_str(), score.as_str()]);
+        assert_eq!(loaded[0].metadata.title.as_deref(), Some("Study"));
+        assert!(loaded[0].analyzed_at.is_some());
+        assert_eq!(loaded[0].thumbnail.as_deref(), Some(photo.as_str()));
+
+        // Reopening moves a file back to the front
+        add(&db, &score).unwrap();
+        assert_eq!(db.recent_files().unwrap()[0].path, score);
+        drop(db);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
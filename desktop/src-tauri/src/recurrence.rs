@@ -0,0 +1,120 @@
+//! How far apart a pattern's occurrences fall, for plotting where motifs
+//! recur across a piece.
+
+use serde::Serialize;
+
+use crate::models::AnalysisResult;
+use crate::occurrences;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PatternRecurrence {
+    /// Index of the staff in `AnalysisResult.parts`.
+    pub staff: usize,
+    pub pattern_id: i32,
+    /// Starting measure of each located occurrence, in score order.
+    pub measures: Vec<i32>,
+    /// Measures between consecutive starts.
+    pub gaps: Vec<i32>,
+    pub min_gap: Option<i32>,
+    pub max_gap: Option<i32>,
+    pub mean_gap: Option<f64>,
+}
+
+/// Recurrence of every pattern of every staff, in staff order.
+pub fn pattern_recurrence_map(result: &AnalysisResult) -> Vec<PatternRecurrence> {
+    result
+        .parts
+        .iter()
+        .enumerate()
+        .flat_map(|(staff, staff_data)| {
+            let notes = occurrences::staff_notes(result, staff_data);
+            staff_data.patterns.iter().map(move |pattern| {
+                let mut measures: Vec<i32> =
+                    occurrences::spans(pattern, &notes, result.measure_map.as_ref())
+                        .into_iter()
+                        .map(|(start, _)| start)
+                        .collect();
+                measures.sort();
+                recurrence(staff, pattern.id, measures)
+            })
+        })
+        .collect()
+}
+
+fn recurrence(staff: usize, pattern_id: i32, measures: Vec<i32>) -> PatternRecurrence {
+    let gaps: Vec<i32> = measures.windows(2).map(|w| w[1] - w[0]).collect();
+    let mean_gap = (!gaps.is_empty()).then(|| gaps.iter().sum::<i32>() as f64 / gaps.len() as f64);
+    PatternRecurrence {
+        staff,
+        pattern_id,
+        min_gap: gaps.iter().copied().min(),
+        max_gap: gaps.iter().copied().max(),
+        mean_gap,
+        gaps,
+        measures,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NoteLocator, Pattern, StaffPatternData};
+
+    #[test]
+    fn gaps_between_occurrences_at_measures_1_5_and_13() {
+        // One note per measure, so note index i is in measure i + 1
+        let measures: String = (1..=16)
+            .map(|m| {
+                format!(
+                    r#"<measure number="{}"><note><pitch><step>C</step><octave>4</octave></pitch></note></measure>"#,
+                    m
+                )
+            })
+            .collect();
+        let result = AnalysisResult {
+            parts: vec![StaffPatternData {
+                patterns: vec![Pattern {
+                    id: 0,
+                    length: 2,
+                    count: 3,
+                    positions: vec![0, 4, 12],
+                    notes: (0..2)
+                        .map(|i| NoteLocator {
+                            index: i,
+                            measure: i + 1,
+                            ..Default::default()
+                        })
+                        .collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            musicxml_content: format!(
+                r#"<score-partwise><part id="P1">{}</part></score-partwise>"#,
+                measures
+            ),
+            ..Default::default()
+        };
+
+        let map = pattern_recurrence_map(&result);
+        assert_eq!(
+            map,
+            vec![PatternRecurrence {
+                staff: 0,
+                pattern_id: 0,
+                measures: vec![1, 5, 13],
+                gaps: vec![4, 8],
+                min_gap: Some(4),
+                max_gap: Some(8),
+                mean_gap: Some(6.0),
+            }]
+        );
+    }
+
+    #[test]
+    fn single_occurrence_has_no_gaps() {
+        let recurrence = recurrence(1, 2, vec![7]);
+        assert!(recurrence.gaps.is_empty());
+        assert_eq!(recurrence.mean_gap, None);
+    }
+}
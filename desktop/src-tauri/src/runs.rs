@@ -0,0 +1,107 @@
+//! Run-length encoding of repeated notes, so a tremolo or ostinato doesn't
+//! send the same note hundreds of times.
+
+use crate::models::NoteLocator;
+
+/// Fold each run of identical consecutive notes into its first note with
+/// `repeat` set to the run's length. Notes are identical when they share
+/// everything but index and onset, follow each other in the stream and sit
+/// in the same measure, so `expand` can rebuild them exactly. Every entry of
+/// the output has `repeat` of at least 1.
+pub fn encode(notes: &[NoteLocator]) -> Vec<NoteLocator> {
+    let mut encoded: Vec<NoteLocator> = Vec::new();
+    let mut last: Option<&NoteLocator> = None;
+    for note in notes {
+        match (encoded.last_mut(), last) {
+            (Some(run), Some(previous)) if continues(previous, note) => run.repeat += 1,
+            _ => encoded.push(NoteLocator {
+                repeat: 1,
+                ..note.clone()
+            }),
+        }
+        last = Some(note);
+    }
+    encoded
+}
+
+/// Undo `encode`, stepping index and onsets through each run.
+pub fn expand(notes: &[NoteLocator]) -> Vec<NoteLocator> {
+    let mut expanded = Vec::with_capacity(notes.iter().map(|n| n.repeat.max(1) as usize).sum());
+    for note in notes {
+        let step = note.duration_beats.unwrap_or(0.0);
+        for k in 0..note.repeat.max(1) {
+            let offset = step * k as f64;
+            expanded.push(NoteLocator {
+                index: note.index + k,
+                beat: note.beat.map(|b| b + offset),
+                absolute_beat: note.absolute_beat.map(|b| b + offset),
+                repeat: 0,
+                ..note.clone()
+            });
+        }
+    }
+    expanded
+}
+
+fn continues(previous: &NoteLocator, note: &NoteLocator) -> bool {
+    let Some(step) = previous.duration_beats.filter(|&d| d > 0.0) else {
+        return false;
+    };
+    let follows = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => (a + step - b).abs() < 1e-9,
+        (None, None) => true,
+        _ => false,
+    };
+    note.index == previous.index + 1
+        && previous.chord_group.is_none()
+        && note.chord_group.is_none()
+        && follows(previous.beat, note.beat)
+        && follows(previous.absolute_beat, note.absolute_beat)
+        && NoteLocator {
+            index: previous.index,
+            beat: previous.beat,
+            absolute_beat: previous.absolute_beat,
+            ..note.clone()
+        } == *previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eighth(index: i32, measure: i32, beat: f64, pitch: &str) -> NoteLocator {
+        NoteLocator {
+            index,
+            measure,
+            beat: Some(beat),
+            pitch: pitch.to_string(),
+            duration_beats: Some(0.5),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn eight_identical_eighths_collapse_to_one_entry() {
+        let notes: Vec<NoteLocator> = (0..8)
+            .map(|i| eighth(10 + i, 3, 1.0 + 0.5 * i as f64, "D4"))
+            .collect();
+
+        let encoded = encode(&notes);
+        assert_eq!(encoded.len(), 1);
+        assert_eq!((encoded[0].index, encoded[0].repeat), (10, 8));
+        assert_eq!(expand(&encoded), notes);
+    }
+
+    #[test]
+    fn runs_break_on_pitch_and_barline() {
+        let notes = vec![
+            eighth(0, 1, 4.0, "D4"),
+            eighth(1, 1, 4.5, "D4"),
+            eighth(2, 2, 1.0, "D4"),
+            eighth(3, 2, 1.5, "E4"),
+        ];
+        let repeats: Vec<i32> = encode(&notes).iter().map(|n| n.repeat).collect();
+        assert_eq!(repeats, vec![2, 1, 1]);
+        assert_eq!(expand(&encode(&notes)), notes);
+    }
+}
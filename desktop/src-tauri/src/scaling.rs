@@ -0,0 +1,89 @@
+//! Augmentation and diminution: a motif repeated with every note value
+//! multiplied by the same factor.
+
+use crate::models::{NoteLocator, Pattern};
+
+const EPSILON: f64 = 1e-6;
+
+/// Factor turning `original`'s note values into `scaled`'s, when both play
+/// the same pitches and every duration is scaled by it. Identical rhythms
+/// (a factor of 1) and notes without a known duration give None.
+pub fn ratio(original: &[NoteLocator], scaled: &[NoteLocator]) -> Option<f64> {
+    if original.is_empty() || original.len() != scaled.len() {
+        return None;
+    }
+    let mut factor: Option<f64> = None;
+    for (a, b) in original.iter().zip(scaled) {
+        if a.pitch != b.pitch {
+            return None;
+        }
+        let (from, to) = (a.duration_beats?, b.duration_beats?);
+        if from <= 0.0 || to <= 0.0 {
+            return None;
+        }
+        let step = to / from;
+        match factor {
+            Some(f) if (f - step).abs() > EPSILON => return None,
+            _ => factor = Some(step),
+        }
+    }
+    factor.filter(|f| (f - 1.0).abs() > EPSILON)
+}
+
+/// Set `scaled_from` on each pattern that rescales one starting earlier in
+/// the staff, naming the earliest such pattern. Ratios above 1 are
+/// augmentations, below 1 diminutions.
+pub fn link(patterns: &mut [Pattern]) {
+    let start = |p: &Pattern| p.positions.iter().copied().min().unwrap_or(i32::MAX);
+    let mut order: Vec<usize> = (0..patterns.len()).collect();
+    order.sort_by_key(|&i| start(&patterns[i]));
+
+    for (k, &i) in order.iter().enumerate() {
+        let found = order[..k].iter().find_map(|&j| {
+            ratio(&patterns[j].notes, &patterns[i].notes).map(|r| (patterns[j].id, r))
+        });
+        patterns[i].scaled_from = found;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motif(id: i32, position: i32, durations: &[f64]) -> Pattern {
+        let notes = ["C4", "E4", "G4"]
+            .iter()
+            .zip(durations)
+            .map(|(pitch, &d)| NoteLocator {
+                pitch: pitch.to_string(),
+                duration_beats: Some(d),
+                ..Default::default()
+            })
+            .collect();
+        Pattern {
+            id,
+            length: 3,
+            count: 2,
+            positions: vec![position],
+            notes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn augmentation_links_to_the_original() {
+        let mut patterns = vec![
+            motif(1, 12, &[1.0, 1.0, 2.0]),
+            motif(0, 0, &[0.5, 0.5, 1.0]),
+            motif(2, 20, &[0.5, 1.0, 1.0]),
+        ];
+        link(&mut patterns);
+        assert_eq!(patterns[0].scaled_from, Some((0, 2.0)));
+        assert_eq!(patterns[1].scaled_from, None);
+        assert_eq!(patterns[2].scaled_from, None);
+
+        let (original, augmented) = (&patterns[1].notes, &patterns[0].notes);
+        assert_eq!(ratio(augmented, original), Some(0.5));
+        assert_eq!(ratio(original, original), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Melodic sequences: a motif restated several times in a row, each time
+//! shifted by the same number of scale steps.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::NoteLocator;
+use crate::pitch;
+
+const MIN_MOTIF_LENGTH: usize = 2;
+const MAX_MOTIF_LENGTH: usize = 8;
+/// Statements needed before a shifted repetition counts as a sequence.
+const MIN_STATEMENTS: usize = 3;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sequence {
+    /// Notes per statement.
+    pub length: i32,
+    /// Diatonic steps from one statement to the next (1 = a step higher).
+    pub interval: i32,
+    pub count: i32,
+    /// Note index where each statement starts.
+    pub positions: Vec<i32>,
+    /// Notes of the first statement.
+    pub notes: Vec<NoteLocator>,
+}
+
+/// Find sequences in one staff's notes, scanning left to right and
+/// preferring the longest motif at each position. Motifs are compared by
+/// diatonic contour, so a tonal sequence whose semitone intervals shift
+/// slightly from level to level still matches. Plain scale runs, which
+/// would otherwise look like a sequence of any motif length, are ignored.
+pub fn detect(notes: &[NoteLocator]) -> Vec<Sequence> {
+    let steps: Vec<Option<i32>> = notes.iter().map(|n| pitch::diatonic(&n.pitch)).collect();
+    let mut sequences = Vec::new();
+
+    let mut start = 0;
+    'scan: while start < steps.len() {
+        for length in (MIN_MOTIF_LENGTH..=MAX_MOTIF_LENGTH).rev() {
+            let (count, interval) = statements(&steps, start, length);
+            if count >= MIN_STATEMENTS && !is_uniform_line(&steps[start..start + count * length]) {
+                sequences.push(Sequence {
+                    length: length as i32,
+                    interval,
+                    count: count as i32,
+                    positions: (0..count).map(|k| (start + k * length) as i32).collect(),
+                    notes: notes[start..start + length].to_vec(),
+                });
+                start += count * length;
+                continue 'scan;
+            }
+        }
+        start += 1;
+    }
+    sequences
+}
+
+/// How many consecutive statements of the motif at `start` follow at a
+/// constant non-zero shift, and that shift.
+fn statements(steps: &[Option<i32>], start: usize, length: usize) -> (usize, i32) {
+    let Some(motif) = contour(steps, start, length) else {
+        return (0, 0);
+    };
+    let level = |k: usize| steps[start + k * length];
+
+    let mut count = 1;
+    let mut interval = 0;
+    while start + (count + 1) * length <= steps.len()
+        && contour(steps, start + count * length, length).as_ref() == Some(&motif)
+    {
+        let (Some(prev), Some(next)) = (level(count - 1), level(count)) else {
+            break;
+        };
+        if next == prev || (count > 1 && next - prev != interval) {
+            break;
+        }
+        interval = next - prev;
+        count += 1;
+    }
+    (count, interval)
+}
+
+/// Steps between consecutive notes of a motif, relative to its first note.
+fn contour(steps: &[Option<i32>], start: usize, length: usize) -> Option<Vec<i32>> {
+    let window = steps.get(start..start + length)?;
+    let first = (*window.first()?)?;
+    window.iter().map(|s| s.map(|s| s - first)).collect()
+}
+
+fn is_uniform_line(steps: &[Option<i32>]) -> bool {
+    let steps: Vec<i32> = steps.iter().flatten().copied().collect();
+    steps.windows(3).all(|w| w[1] - w[0] == w[2] - w[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(pitches: &[&str]) -> Vec<NoteLocator> {
+        pitches
+            .iter()
+            .enumerate()
+            .map(|(i, p)| NoteLocator {
+                index: i as i32,
+                pitch: p.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_ascending_stepwise_sequence() {
+        // C-D-E, D-E-F, E-F-G, F-G-A: a three-note rising figure moved up
+        // a step each time, with a whole-then-half step contour shift
+        let notes = notes(&[
+            "G3", "C4", "D4", "E4", "D4", "E4", "F4", "E4", "F4", "G4", "F4", "G4", "A4", "C3",
+        ]);
+        let sequences = detect(&notes);
+
+        assert_eq!(sequences.len(), 1);
+        let sequence = &sequences[0];
+        assert_eq!(sequence.length, 3);
+        assert_eq!(sequence.interval, 1);
+        assert_eq!(sequence.positions, vec![1, 4, 7, 10]);
+        assert_eq!(sequence.notes[0].pitch, "C4");
+    }
+
+    #[test]
+    fn ignores_scales_and_exact_repeats() {
+        assert!(detect(&notes(&["C4", "D4", "E4", "F4", "G4", "A4", "B4", "C5"])).is_empty());
+        assert!(detect(&notes(&["C4", "E4", "C4", "E4", "C4", "E4"])).is_empty());
+    }
+}
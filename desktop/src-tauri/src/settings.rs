@@ -0,0 +1,89 @@
+//! The analysis options the user last saved, used by the analysis commands
+//! when they're called without a config.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::config::AnalyzerConfig;
+
+pub struct Settings {
+    path: PathBuf,
+    config: Mutex<AnalyzerConfig>,
+}
+
+impl Settings {
+    /// Settings stored at `path`, with the defaults when the file is missing
+    /// or can't be read (with a warning).
+    pub fn load(path: PathBuf) -> Self {
+        let config = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable settings {}: {}", path.display(), e);
+                AnalyzerConfig::default()
+            }),
+            Err(_) => AnalyzerConfig::default(),
+        };
+        Settings {
+            path,
+            config: Mutex::new(config),
+        }
+    }
+
+    pub fn get(&self) -> AnalyzerConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// `config` if given, otherwise the saved settings.
+    pub fn or_saved(&self, config: Option<AnalyzerConfig>) -> AnalyzerConfig {
+        config.unwrap_or_else(|| self.get())
+    }
+
+    /// Save `config` to disk and use it from now on.
+    pub fn set(&self, config: AnalyzerConfig) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create settings dir: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(&config)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        // Written under a temp name first, as in `AnalysisCache::put`
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(|e| format!("Failed to write settings: {}", e))?;
+        std::fs::rename(&tmp, &self.path)
+            .map_err(|e| format!("Failed to write settings: {}", e))?;
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_settings_are_loaded_back() {
+        let dir = std::env::temp_dir().join(format!("smrh-settings-test-{}", std::process::id()));
+        let path = dir.join("settings.json");
+        let settings = Settings::load(path.clone());
+        assert!(settings.get().match_rhythm);
+
+        let config = AnalyzerConfig {
+            min_occurrences: Some(3),
+            match_rhythm: false,
+            ..Default::default()
+        };
+        settings.set(config).unwrap();
+        let loaded = Settings::load(path.clone());
+        assert_eq!(loaded.get().min_occurrences, Some(3));
+        assert!(!loaded.or_saved(None).match_rhythm);
+        assert!(
+            loaded
+                .or_saved(Some(AnalyzerConfig::default()))
+                .match_rhythm
+        );
+
+        std::fs::write(&path, "{not json").unwrap();
+        assert_eq!(Settings::load(path).get().min_occurrences, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
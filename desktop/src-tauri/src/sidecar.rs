@@ -0,0 +1,630 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tauri_plugin_shell::process::CommandEvent;
+use tauri_plugin_shell::ShellExt;
+
+use crate::config::AnalyzerConfig;
+use crate::download::TempScore;
+use crate::error::{self, AnalyzeError};
+use crate::folder::FileTag;
+use crate::integrity;
+use crate::jobs::{AnalysisSlots, CancelFlag, Stopped};
+use crate::line_buffer::LineBuffer;
+use crate::models::{AnalysisError, AnalysisResult, Progress};
+use crate::musicxml::{midi, mxl, parts};
+use crate::output::CappedOutput;
+use crate::process::{self, Process, ProcessEvent};
+use crate::split;
+use crate::stream;
+use crate::warnings;
+
+/// Everything the analyzer printed during one run.
+#[derive(Debug, Clone)]
+pub struct SidecarOutput {
+    /// Stdout lines that weren't result chunks.
+    pub stdout: String,
+    /// The result rebuilt from its chunks as they arrived, None when the
+    /// analyzer printed none.
+    pub streamed: Result<Option<AnalysisResult>, String>,
+    /// Stderr lines that weren't progress events.
+    pub stderr_lines: Vec<String>,
+    /// Every progress event, in the order the analyzer emitted it.
+    pub progress: Vec<Progress>,
+    pub exit_code: Option<i32>,
+    /// Set when the run was cancelled before the analyzer finished.
+    pub stopped: Option<Stopped>,
+}
+
+/// Start of the error returned when the analyzer runs past
+/// `AnalyzerConfig.timeout_secs` or goes quiet for `inactivity_timeout_secs`.
+pub const ANALYZER_TIMED_OUT: &str = "analyzer timed out";
+
+/// How long a terminated analyzer gets to exit before it is killed.
+const TERMINATE_GRACE: Duration = Duration::from_secs(2);
+/// How long to wait for the exit to be reported after a kill before giving up.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// Debug view of a run: the unfiltered output plus the parse outcome.
+#[derive(Debug, Clone, Serialize)]
+pub struct RawAnalysis {
+    pub stdout: String,
+    pub stderr: Vec<String>,
+    pub exit_code: Option<i32>,
+    pub result: Option<AnalysisResult>,
+    pub parse_error: Option<AnalyzeError>,
+}
+
+/// Where analyzer runs happen: in the app, started by the Tauri shell plugin
+/// with progress sent to the webview, or headless (see `headless`).
+pub trait Host: Clone + Send + Sync + 'static {
+    /// Where the analyzer may be installed, for `integrity::locate`.
+    fn candidates(&self) -> Vec<PathBuf>;
+    /// Start the analyzer, found at `analyzer`, with `args`.
+    fn spawn(&self, analyzer: &Path, args: &[String]) -> Result<Process, AnalyzeError>;
+    fn emit_progress(&self, progress: &Progress);
+    /// The cap on analyzers running at once.
+    fn slots(&self) -> AnalysisSlots;
+}
+
+impl Host for tauri::AppHandle {
+    fn candidates(&self) -> Vec<PathBuf> {
+        integrity::candidates(self.path().resource_dir().ok(), integrity::exe_dir())
+    }
+
+    fn spawn(&self, _analyzer: &Path, args: &[String]) -> Result<Process, AnalyzeError> {
+        // The shell plugin resolves the bundled sidecar itself
+        let sidecar = self
+            .shell()
+            .sidecar("analyzer")
+            .map_err(|e| AnalyzeError::Spawn {
+                message: format!("Failed to create sidecar: {}", e),
+            })?
+            .args(args)
+            // Raw chunks are split into lines by LineBuffer, which keeps
+            // multi-byte characters intact
+            .set_raw_out(true);
+        let (mut rx, child) = sidecar.spawn().map_err(|e| AnalyzeError::Spawn {
+            message: format!(
+                "Failed to spawn sidecar: {} (path: {})",
+                e,
+                args.first().map_or("", String::as_str)
+            ),
+        })?;
+        let pid = child.pid();
+        let (tx, events) = process::channel();
+        tauri::async_runtime::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let event = match event {
+                    CommandEvent::Stdout(bytes) => ProcessEvent::Stdout(bytes),
+                    CommandEvent::Stderr(bytes) => ProcessEvent::Stderr(bytes),
+                    CommandEvent::Terminated(payload) => ProcessEvent::Terminated(payload.code),
+                    CommandEvent::Error(e) => ProcessEvent::Error(e),
+                    _ => continue,
+                };
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Process::new(events, pid, move || {
+            let _ = child.kill();
+        }))
+    }
+
+    fn emit_progress(&self, progress: &Progress) {
+        let _ = self.emit("analyze-progress", progress);
+    }
+
+    fn slots(&self) -> AnalysisSlots {
+        self.state::<AnalysisSlots>().inner().clone()
+    }
+}
+
+/// Run the analyzer sidecar on a local file, forwarding progress events
+/// (tagged with `tag` when the file is part of a job). When `cancel` fires
+/// the sidecar is stopped and the output so far is returned with `stopped`
+/// set. An analyzer that times out is stopped too, and an error returned.
+/// Waits for one of the host's `AnalysisSlots`, or for one per staff when a
+/// score of several staves is analyzed by a sidecar per staff, up to
+/// `max_concurrent_staves` at once (see `run_staves`). `forward` gets each
+/// chunk of the result as the analyzer prints it; the runs of a split score
+/// are merged first, so none of theirs are forwarded.
+pub async fn run(
+    host: &impl Host,
+    path: &str,
+    config: &AnalyzerConfig,
+    cancel: Option<&CancelFlag>,
+    tag: Option<FileTag>,
+    forward: Option<&stream::Sink<'_>>,
+) -> Result<SidecarOutput, AnalyzeError> {
+    error::check_score(Path::new(path))?;
+    let extracted = plain_input(Path::new(path))?;
+    let input = match &extracted {
+        Some(temp) => temp.path().to_string_lossy().into_owned(),
+        None => path.to_string(),
+    };
+
+    let weights = staff_weights(&input, config);
+    if weights.len() > 1 {
+        return run_staves(host, &input, config, cancel, tag, weights).await;
+    }
+    let emit = |progress: &mut Progress| {
+        if let Some(tag) = tag {
+            progress.job_id = Some(tag.job_id);
+            progress.file_id = Some(tag.file_id);
+        }
+        host.emit_progress(progress);
+    };
+    let _slot = host.slots().acquire().await;
+    run_input(host, &input, &[], config, cancel, forward, emit).await
+}
+
+/// Notes on each staff the analysis covers, when it is split into a run per
+/// staff; empty when it isn't. Only MusicXML is split, since converting a
+/// PDF or image in every run would cost more than it saves.
+fn staff_weights(input: &str, config: &AnalyzerConfig) -> Vec<usize> {
+    let is_musicxml = Path::new(input)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("musicxml"));
+    if config.max_concurrent_staves < 2 || !is_musicxml {
+        return Vec::new();
+    }
+    // A score the staves can't be listed of is left to the analyzer to report
+    let staves = std::fs::read_to_string(input)
+        .map_err(|e| e.to_string())
+        .and_then(|xml| parts::list_parts(&xml));
+    match staves {
+        Ok(staves) => split::weights(&staves, config),
+        Err(e) => {
+            tracing::warn!("Not splitting analysis by staff: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Analyze each staff `weights` lists in a sidecar of its own, with their
+/// progress merged into one stream of events and their results into one
+/// result. The first run to fail stops the others.
+async fn run_staves<H: Host>(
+    host: &H,
+    input: &str,
+    config: &AnalyzerConfig,
+    cancel: Option<&CancelFlag>,
+    tag: Option<FileTag>,
+    weights: Vec<usize>,
+) -> Result<SidecarOutput, AnalyzeError> {
+    let staves = weights.len();
+    tracing::info!(staves, "Analyzing staves concurrently");
+    let merger = Arc::new(Mutex::new(split::ProgressMerger::new(weights)));
+    let progress_log = Arc::new(Mutex::new(Vec::new()));
+    let stop_all = Arc::new(CancelFlag::default());
+    let runs = split::schedule(staves, config.max_concurrent_staves, &host.slots(), |run| {
+        let host = host.clone();
+        let config = config.clone();
+        let input = input.to_string();
+        let (merger, progress_log) = (merger.clone(), progress_log.clone());
+        let stop_all = stop_all.clone();
+        async move {
+            if stop_all.is_cancelled() {
+                return Ok(None);
+            }
+            let emit = |progress: &mut Progress| {
+                let mut merged = merger.lock().unwrap().merge(run, progress);
+                if let Some(tag) = tag {
+                    merged.job_id = Some(tag.job_id);
+                    merged.file_id = Some(tag.file_id);
+                }
+                host.emit_progress(&merged);
+                progress_log.lock().unwrap().push(merged);
+            };
+            let args = ["--staff".to_string(), run.to_string()];
+            let output =
+                run_input(&host, &input, &args, &config, Some(&stop_all), None, emit).await;
+            if !matches!(&output, Ok(o) if o.exit_code == Some(0) || o.stopped.is_some()) {
+                stop_all.cancel();
+            }
+            output.map(Some)
+        }
+    });
+    let runs: Vec<_> = runs.into_iter().map(tauri::async_runtime::spawn).collect();
+
+    let joined = async {
+        let mut outputs = Vec::new();
+        for run in runs {
+            outputs.push(run.await.map_err(|e| AnalyzeError::Other {
+                message: format!("Analyzer task failed: {}", e),
+            })?);
+        }
+        Ok::<_, AnalyzeError>(outputs)
+    };
+    tokio::pin!(joined);
+    let parent_cancelled = async {
+        match cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    let outputs = tokio::select! {
+        outputs = &mut joined => outputs,
+        _ = parent_cancelled => {
+            stop_all.cancel();
+            (&mut joined).await
+        }
+    };
+
+    let mut outputs: Vec<SidecarOutput> = outputs?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut merged = SidecarOutput {
+        stdout: String::new(),
+        streamed: Ok(None),
+        stderr_lines: split::merge_stderr(outputs.iter().map(|o| o.stderr_lines.clone()).collect()),
+        progress: std::mem::take(&mut *progress_log.lock().unwrap()),
+        exit_code: Some(0),
+        stopped: None,
+    };
+    if cancel.is_some_and(CancelFlag::is_cancelled) {
+        let forced_kill = outputs
+            .iter()
+            .any(|o| o.stopped.is_some_and(|s| s.forced_kill));
+        merged.stopped = Some(Stopped { forced_kill });
+        return Ok(merged);
+    }
+
+    // A run that failed outranks the runs its failure stopped
+    let mut results = Vec::new();
+    for output in outputs.iter_mut().filter(|o| o.stopped.is_none()) {
+        results.push(parse_result(output)?);
+    }
+    if results.len() < staves {
+        return Err(AnalyzeError::Other {
+            message: format!("Only {} of {} staves were analyzed", results.len(), staves),
+        });
+    }
+    merged.streamed = Ok(split::merge(results));
+    Ok(merged)
+}
+
+/// One sidecar run on `input`, a file the analyzer reads as it is, with
+/// `extra_args` after the config's. Progress events are handed to
+/// `on_progress` and then logged as it leaves them, and result chunks to
+/// `forward` as they are read.
+async fn run_input(
+    host: &impl Host,
+    input: &str,
+    extra_args: &[String],
+    config: &AnalyzerConfig,
+    cancel: Option<&CancelFlag>,
+    forward: Option<&stream::Sink<'_>>,
+    mut on_progress: impl FnMut(&mut Progress),
+) -> Result<SidecarOutput, AnalyzeError> {
+    // Fail with the paths probed rather than the shell plugin's spawn error
+    let candidates = host.candidates();
+    let analyzer =
+        integrity::locate(&candidates).map_err(|message| AnalyzeError::Spawn { message })?;
+    tracing::info!("Using analyzer at {}", analyzer.display());
+
+    let mut args = vec![input.to_string()];
+    args.extend(config.sidecar_args());
+    args.extend_from_slice(extra_args);
+    args.push("--stream".to_string());
+
+    tracing::info!(?args, "Spawning analyzer");
+
+    let mut child = host.spawn(analyzer, &args).inspect_err(|e| {
+        tracing::error!("Failed to spawn analyzer: {}", e.message());
+    })?;
+
+    let mut output = CappedOutput::new(config.output_limits());
+    let mut progress_log: Vec<Progress> = Vec::new();
+    let mut exit_code: Option<i32> = None;
+    let mut stopped: Option<Stopped> = None;
+    let mut running = true;
+    let mut stdout_decoder = LineBuffer::default();
+    let mut assembler = stream::Assembler::default();
+    let forward = |chunk: &stream::Chunk| {
+        if let Some(forward) = forward {
+            forward(chunk);
+        }
+    };
+    let mut stderr_decoder = LineBuffer::default();
+
+    let started = tokio::time::Instant::now();
+    let mut last_progress = started;
+
+    // Returns whether the line was a progress event
+    let mut handle_stderr = |output: &mut CappedOutput, line: String| -> bool {
+        // Try to parse as progress JSON
+        if let Ok(progress) = serde_json::from_str::<Progress>(&line) {
+            let mut progress = progress.with_derived();
+            on_progress(&mut progress);
+            progress_log.push(progress);
+            output.push_stderr(None);
+            true
+        } else {
+            // Not progress - collect for potential error reporting
+            tracing::info!(target: "analyzer", "{}", line);
+            output.push_stderr(Some(line));
+            false
+        }
+    };
+
+    loop {
+        let cancelled = async {
+            match cancel {
+                Some(cancel) => cancel.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        // Whichever limit runs out first, with how to describe it
+        let overall = config.timeout().map(|t| (started + t, "ran for", t));
+        let inactive = config
+            .inactivity_timeout()
+            .map(|t| (last_progress + t, "sent no progress for", t));
+        let deadline = overall.into_iter().chain(inactive).min_by_key(|d| d.0);
+        let timed_out = async {
+            match deadline {
+                Some((at, _, _)) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        let event = tokio::select! {
+            event = child.events.recv() => event,
+            _ = cancelled => {
+                if std::mem::take(&mut running) {
+                    stopped = Some(stop(&mut child).await);
+                }
+                break;
+            }
+            _ = timed_out => {
+                if std::mem::take(&mut running) {
+                    stop(&mut child).await;
+                }
+                if let Some(line) = stderr_decoder.finish() {
+                    handle_stderr(&mut output, line);
+                }
+                let (reason, limit) =
+                    deadline.map_or(("ran for", Duration::ZERO), |d| (d.1, d.2));
+                tracing::warn!("Analyzer timed out: {} {} s", reason, limit.as_secs());
+                return Err(AnalyzeError::Timeout {
+                    message: format!(
+                        "{}: {} {} s. Last output:\n{}",
+                        ANALYZER_TIMED_OUT,
+                        reason,
+                        limit.as_secs(),
+                        output.tail()
+                    ),
+                    stderr: output.stderr_lines,
+                });
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+        match event {
+            ProcessEvent::Stderr(bytes) => {
+                for line in stderr_decoder.push(&bytes) {
+                    if handle_stderr(&mut output, line) {
+                        last_progress = tokio::time::Instant::now();
+                    }
+                }
+            }
+            ProcessEvent::Stdout(bytes) => {
+                for line in stdout_decoder.push(&bytes) {
+                    if assembler.push_line(&line, forward) {
+                        output.count_stdout(&line);
+                    } else {
+                        output.push_stdout(&line);
+                    }
+                }
+            }
+            ProcessEvent::Terminated(code) => {
+                exit_code = code;
+                break;
+            }
+            ProcessEvent::Error(err) => {
+                return Err(AnalyzeError::Sidecar {
+                    message: format!("Command error: {}", err),
+                    stderr: output.stderr_lines,
+                    exit_code: None,
+                });
+            }
+        }
+
+        // A runaway analyzer is stopped rather than buffered without bound
+        if let Err(message) =
+            output.check(stdout_decoder.pending_len(), stderr_decoder.pending_len())
+        {
+            if std::mem::take(&mut running) {
+                stop(&mut child).await;
+            }
+            return Err(AnalyzeError::Sidecar {
+                message,
+                stderr: output.stderr_lines,
+                exit_code: None,
+            });
+        }
+    }
+
+    if let Some(line) = stderr_decoder.finish() {
+        handle_stderr(&mut output, line);
+    }
+    if let Some(line) = stdout_decoder.finish() {
+        if !assembler.push_line(&line, forward) {
+            output.push_stdout(&line);
+        }
+    }
+
+    tracing::info!(
+        ?exit_code,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        progress_events = progress_log.len(),
+        stopped = stopped.is_some(),
+        "Analyzer finished"
+    );
+    Ok(SidecarOutput {
+        stdout: output.stdout,
+        streamed: assembler.finish(),
+        stderr_lines: output.stderr_lines,
+        progress: progress_log,
+        exit_code,
+        stopped,
+    })
+}
+
+/// The analyzer reads plain MusicXML, so compressed scores are extracted
+/// and MIDI files converted first into a temp file to pass it instead; its
+/// `musicxml_content` is then that MusicXML. None for other files.
+pub fn plain_input(path: &Path) -> Result<Option<TempScore>, AnalyzeError> {
+    if !mxl::is_mxl(path) && !midi::is_midi(path) {
+        return Ok(None);
+    }
+    let xml = mxl::read_score(path)?;
+    Ok(Some(TempScore::write("score", "musicxml", xml.as_bytes())?))
+}
+
+/// Ask the analyzer to exit, then kill it if it hasn't within
+/// `TERMINATE_GRACE`. Resolves even if the exit is never reported.
+async fn stop(child: &mut Process) -> Stopped {
+    if terminate(child.pid) && wait_for_exit(child, TERMINATE_GRACE).await {
+        return Stopped { forced_kill: false };
+    }
+    tracing::warn!("Analyzer did not exit after terminate, killing it");
+    child.kill();
+    wait_for_exit(child, KILL_GRACE).await;
+    Stopped { forced_kill: true }
+}
+
+/// Send SIGTERM. Returns false where there's no graceful signal to send.
+#[cfg(unix)]
+fn terminate(pid: u32) -> bool {
+    // SAFETY: kill(2) has no memory-safety preconditions
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) == 0 }
+}
+
+#[cfg(not(unix))]
+fn terminate(_pid: u32) -> bool {
+    false
+}
+
+/// Drain events until the process exits. Returns false on timeout.
+async fn wait_for_exit(child: &mut Process, within: Duration) -> bool {
+    let exited = async {
+        while let Some(event) = child.events.recv().await {
+            if matches!(event, ProcessEvent::Terminated(_)) {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(within, exited).await.is_ok()
+}
+
+/// Turn collected sidecar output into a result, surfacing analyzer errors.
+/// A streamed result is taken out of `output`.
+pub fn parse_result(output: &mut SidecarOutput) -> Result<AnalysisResult, AnalyzeError> {
+    let stdout_buffer = &output.stdout;
+
+    if output.stopped.is_some() {
+        return Err(AnalyzeError::cancelled());
+    }
+
+    // Check for error JSON in stdout first (Python prints errors to stdout as JSON)
+    if let Ok(err) = serde_json::from_str::<AnalysisError>(stdout_buffer) {
+        return Err(AnalyzeError::Sidecar {
+            message: err.error,
+            stderr: output.stderr_lines.clone(),
+            exit_code: output.exit_code,
+        });
+    }
+
+    // Check exit code
+    if output.exit_code != Some(0) {
+        // Filter out Python warnings, keep only actual errors
+        let filtered_stderr: String = output
+            .stderr_lines
+            .iter()
+            .filter(|line| !line.contains("Warning") && !line.contains("warnings.warn"))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n");
+        let error_msg = if filtered_stderr.trim().is_empty() {
+            format!("Process failed with exit code: {:?}", output.exit_code)
+        } else {
+            filtered_stderr
+        };
+        return Err(AnalyzeError::Sidecar {
+            message: format!("Analyzer failed: {}", error_msg),
+            stderr: output.stderr_lines.clone(),
+            exit_code: output.exit_code,
+        });
+    }
+
+    let streamed = std::mem::replace(&mut output.streamed, Ok(None)).map_err(|message| {
+        tracing::warn!("Failed to assemble streamed result: {}", message);
+        AnalyzeError::Parse { message }
+    })?;
+    let stdout_buffer = &output.stdout;
+    let mut result = match streamed {
+        Some(result) => result,
+        None => parse_document(stdout_buffer)?,
+    };
+    result.warnings = warnings::collect(&output.stderr_lines);
+    Ok(result)
+}
+
+/// A result printed as one JSON document, as without `--stream`.
+fn parse_document(stdout_buffer: &str) -> Result<AnalysisResult, AnalyzeError> {
+    serde_json::from_str::<AnalysisResult>(stdout_buffer).map_err(|e| {
+        // Full output goes to the log only; the error carries a short snippet
+        tracing::warn!("Failed to parse analyzer output: {}\n{}", e, stdout_buffer);
+        AnalyzeError::Parse {
+            message: format!(
+                "Failed to parse output: {} (near: {:?})",
+                e,
+                error_snippet(stdout_buffer, e.line(), e.column())
+            ),
+        }
+    })
+}
+
+/// Max characters of context kept on each side of a parse error location.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Extract a short excerpt of `buffer` around a 1-based `line`/`column`
+/// position as reported by `serde_json::Error`.
+fn error_snippet(buffer: &str, line: usize, column: usize) -> String {
+    let line_start: usize = buffer
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let mut offset = (line_start + column.saturating_sub(1)).min(buffer.len());
+    while !buffer.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before: String = buffer[..offset]
+        .chars()
+        .rev()
+        .take(SNIPPET_CONTEXT)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let after: String = buffer[offset..].chars().take(SNIPPET_CONTEXT).collect();
+
+    let prefix = if before.len() < offset { "..." } else { "" };
+    let suffix = if offset + after.len() < buffer.len() {
+        "..."
+    } else {
+        ""
+    };
+    format!("{}{}{}{}", prefix, before, after, suffix)
+}
@@ -0,0 +1,311 @@
+//! Analyzing a score's staves concurrently. The analyzer compares the notes
+//! of one staff at a time, so a piano score takes twice as long as either
+//! hand alone; with `AnalyzerConfig.max_concurrent_staves` above 1 each
+//! staff gets its own analyzer (`--staff`), and the runs' progress and
+//! results are merged here into those of one analysis.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use crate::config::AnalyzerConfig;
+use crate::jobs::AnalysisSlots;
+use crate::models::{AnalysisResult, Progress};
+use crate::musicxml::metadata::DEFAULT_STAVES;
+use crate::musicxml::parts::PartInfo;
+
+/// Index of each staff an analysis of the score covers, in the order the
+/// analyzer reports them (`--staff` numbers them from 0): `config.parts`,
+/// or the treble and bass it picks when they aren't set. Parts the score
+/// doesn't have are left out.
+pub fn selected(staves: &[PartInfo], config: &AnalyzerConfig) -> Vec<usize> {
+    if let Some(parts) = &config.parts {
+        return parts
+            .iter()
+            .copied()
+            .filter(|&index| index < staves.len())
+            .collect();
+    }
+    // As the analyzer's `_grand_staff`: the first two-staff part's staves
+    let first = staves
+        .iter()
+        .find(|staff| config.split_grand_staff && staff.staves >= 2)
+        .map_or(0, |staff| staff.index + 1 - staff.staff as usize);
+    (first..first + DEFAULT_STAVES)
+        .filter(|&index| index < staves.len())
+        .collect()
+}
+
+/// Notes on each selected staff, which weigh its share of the progress.
+pub fn weights(staves: &[PartInfo], config: &AnalyzerConfig) -> Vec<usize> {
+    selected(staves, config)
+        .into_iter()
+        .map(|index| staves[index].notes)
+        .collect()
+}
+
+/// The run of each of `staves` staves by `run`, as tasks to spawn. Each
+/// waits until fewer than `max_at_once` of the others are running and one
+/// of `slots` is free, and holds both until it ends.
+pub fn schedule<F, Fut>(
+    staves: usize,
+    max_at_once: usize,
+    slots: &AnalysisSlots,
+    run: F,
+) -> Vec<impl Future<Output = Fut::Output> + Send + 'static>
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future + Send + 'static,
+    Fut::Output: Send,
+{
+    let limit = Arc::new(Semaphore::new(max_at_once.max(1)));
+    (0..staves)
+        .map(|staff| {
+            let (limit, slots, run) = (limit.clone(), slots.clone(), run(staff));
+            async move {
+                let _staff = limit.acquire_owned().await;
+                let _slot = slots.acquire().await;
+                run.await
+            }
+        })
+        .collect()
+}
+
+/// Progress of concurrent runs as that of one analysis: each run's fraction
+/// weighed by its staff's notes, so `current`/`total` count notes analyzed.
+#[derive(Debug, Clone)]
+pub struct ProgressMerger {
+    weights: Vec<usize>,
+    fractions: Vec<f64>,
+}
+
+impl ProgressMerger {
+    pub fn new(weights: Vec<usize>) -> Self {
+        // An empty staff still counts, so its run moves the bar
+        let weights: Vec<usize> = weights.into_iter().map(|w| w.max(1)).collect();
+        ProgressMerger {
+            fractions: vec![0.0; weights.len()],
+            weights,
+        }
+    }
+
+    /// The whole analysis's progress after `progress` from run `run`.
+    pub fn merge(&mut self, run: usize, progress: &Progress) -> Progress {
+        if let (Some(done), Some(fraction)) = (self.fractions.get_mut(run), progress.fraction) {
+            // Stages start over at 0, which isn't the run going backwards
+            *done = done.max(fraction);
+        }
+        let total: usize = self.weights.iter().sum();
+        let current: f64 = self
+            .weights
+            .iter()
+            .zip(&self.fractions)
+            .map(|(&weight, fraction)| weight as f64 * fraction)
+            .sum();
+        Progress {
+            current: current.round() as i32,
+            total: total as i32,
+            message: format!(
+                "{} (staff {} of {})",
+                progress.message,
+                run + 1,
+                self.weights.len()
+            ),
+            fraction: None,
+            determinate: false,
+            job_id: None,
+            file_id: None,
+            ..progress.clone()
+        }
+        .with_derived()
+    }
+}
+
+/// The runs' results, in staff order, as one: staves concatenated and
+/// pattern ids renumbered to stay unique, as the analyzer numbers them in a
+/// single run.
+pub fn merge(runs: Vec<AnalysisResult>) -> Option<AnalysisResult> {
+    let mut runs = runs.into_iter();
+    let mut merged = runs.next()?;
+    let mut offset: i32 = merged.parts.iter().map(|p| p.patterns.len() as i32).sum();
+    for run in runs {
+        for mut part in run.parts {
+            for pattern in &mut part.patterns {
+                pattern.id += offset;
+            }
+            offset += part.patterns.len() as i32;
+            merged.parts.push(part);
+        }
+    }
+    Some(merged)
+}
+
+/// The runs' stderr lines, without those an earlier run printed as well:
+/// every run parses the whole score, so its warnings are printed by each.
+pub fn merge_stderr(runs: Vec<Vec<String>>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    for lines in runs {
+        let earlier = merged.len();
+        for line in lines {
+            if !merged[..earlier].contains(&line) {
+                merged.push(line);
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Pattern, StaffPatternData, Stage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn staff(index: usize, staff: u32, staves: u32, notes: usize) -> PartInfo {
+        PartInfo {
+            index,
+            part_id: format!("P{}", index),
+            name: String::new(),
+            staff,
+            staves,
+            notes,
+        }
+    }
+
+    #[test]
+    fn selects_the_staves_the_analyzer_would() {
+        // Voice, then the two staves of a piano part
+        let score = [staff(0, 1, 1, 30), staff(1, 1, 2, 200), staff(2, 2, 2, 100)];
+        let config = AnalyzerConfig::default();
+        assert_eq!(selected(&score, &config), vec![1, 2]);
+        assert_eq!(weights(&score, &config), vec![200, 100]);
+
+        let unsplit = AnalyzerConfig {
+            split_grand_staff: false,
+            ..Default::default()
+        };
+        assert_eq!(selected(&score, &unsplit), vec![0, 1]);
+        assert_eq!(selected(&score[..1], &unsplit), vec![0]);
+
+        let picked = AnalyzerConfig {
+            parts: Some(vec![2, 0]),
+            ..Default::default()
+        };
+        assert_eq!(weights(&score, &picked), vec![100, 30]);
+
+        // A part the score doesn't have can't be split into a run
+        let missing = AnalyzerConfig {
+            parts: Some(vec![2, 7]),
+            ..Default::default()
+        };
+        assert_eq!(selected(&score, &missing), vec![2]);
+        assert_eq!(weights(&score, &missing), vec![100]);
+    }
+
+    /// A stand-in sidecar run, counting how many are running at once.
+    async fn sidecar(running: Arc<AtomicUsize>, peak: Arc<AtomicUsize>) {
+        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+        peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn split_scores_never_run_more_sidecars_than_there_are_slots() {
+        let slots = AnalysisSlots::new(3);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        // A batch of four scores of four staves each, every score allowed
+        // four runs at once
+        let scores: Vec<_> = (0..4)
+            .map(|_| {
+                let runs = schedule(4, 4, &slots, |_| sidecar(running.clone(), peak.clone()));
+                runs.into_iter().map(tokio::spawn).collect::<Vec<_>>()
+            })
+            .collect();
+        for runs in scores {
+            for run in runs {
+                run.await.unwrap();
+            }
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+
+        // One score is held to its own limit even with slots to spare
+        let peak = Arc::new(AtomicUsize::new(0));
+        let runs = schedule(6, 2, &AnalysisSlots::new(8), |_| {
+            sidecar(running.clone(), peak.clone())
+        });
+        for run in runs.into_iter().map(tokio::spawn).collect::<Vec<_>>() {
+            run.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn progress_is_weighed_by_notes() {
+        let event = |current, total| {
+            Progress {
+                progress_type: "progress".to_string(),
+                stage: Stage::Analyze,
+                current,
+                total,
+                message: "Patterns found".to_string(),
+                fraction: None,
+                determinate: false,
+                job_id: None,
+                file_id: None,
+            }
+            .with_derived()
+        };
+        let mut merger = ProgressMerger::new(vec![300, 100]);
+        let merged = merger.merge(1, &event(1, 1));
+        assert_eq!((merged.current, merged.total), (100, 400));
+        assert_eq!(merged.fraction, Some(0.25));
+        assert_eq!(merged.message, "Patterns found (staff 2 of 2)");
+
+        // A stage starting over doesn't take back what was done
+        assert_eq!(merger.merge(1, &event(0, 1)).current, 100);
+        assert_eq!(merger.merge(0, &event(1, 2)).current, 250);
+        assert_eq!(merger.merge(0, &event(0, 0)).current, 250);
+    }
+
+    #[test]
+    fn results_merge_with_unique_pattern_ids() {
+        let run = |part_index: i32, patterns: usize| AnalysisResult {
+            musicxml_content: "<score-partwise/>".to_string(),
+            parts: vec![StaffPatternData {
+                part_index,
+                patterns: (0..patterns as i32)
+                    .map(|id| Pattern {
+                        id,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let merged = merge(vec![run(1, 2), run(2, 3)]).unwrap();
+        assert_eq!(merged.parts.len(), 2);
+        assert_eq!(merged.parts[1].part_index, 2);
+        let ids: Vec<i32> = merged
+            .staves()
+            .iter()
+            .flat_map(|s| &s.patterns)
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        assert!(merge(Vec::new()).is_none());
+
+        let lines = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            merge_stderr(vec![
+                lines(&["MusicXMLWarning: x", "a", "a"]),
+                lines(&["MusicXMLWarning: x", "b"])
+            ]),
+            lines(&["MusicXMLWarning: x", "a", "a", "b"])
+        );
+    }
+}
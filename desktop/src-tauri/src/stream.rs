@@ -0,0 +1,199 @@
+//! A result as newline-delimited chunks. The analyzer prints its result this
+//! way (with `--stream`) so each line is parsed as it arrives rather than a
+//! document of tens of megabytes at the end, and `analyze_music_streamed`
+//! sends each chunk on to the frontend as it is read.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{AnalysisResult, Pattern, StaffPatternData};
+
+/// Event each chunk is emitted as by `analyze_music_streamed`.
+pub const CHUNK_EVENT: &str = "analysis-chunk";
+
+/// Where chunks are sent as they are read.
+pub type Sink<'a> = dyn Fn(&Chunk) + Send + Sync + 'a;
+
+/// Patterns per `Patterns` chunk, as in the analyzer's `PATTERN_BATCH`.
+pub const PATTERN_BATCH: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Chunk {
+    /// A staff without its patterns, which follow in `Patterns` chunks.
+    Part { part: StaffPatternData },
+    /// More patterns of the `part`-th staff sent so far.
+    Patterns { part: usize, patterns: Vec<Pattern> },
+    /// Every other field of the result, which comes last. `parts` is empty.
+    Result { result: AnalysisResult },
+}
+
+/// What `analyze_music_streamed` returns once every chunk is sent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreamSummary {
+    pub file: String,
+    pub parts: usize,
+    pub patterns: usize,
+    pub patterns_found: bool,
+    pub chunks: usize,
+}
+
+/// Rebuilds a result from its chunks.
+#[derive(Debug, Default)]
+pub struct Assembler {
+    parts: Vec<StaffPatternData>,
+    rest: Option<AnalysisResult>,
+    error: Option<String>,
+}
+
+impl Assembler {
+    /// Take a line of output, returning false when it isn't a chunk. A chunk
+    /// is handed to `forward` before it's kept.
+    pub fn push_line(&mut self, line: &str, forward: impl FnOnce(&Chunk)) -> bool {
+        if !line.starts_with("{\"type\"") {
+            return false;
+        }
+        match serde_json::from_str::<Chunk>(line) {
+            Ok(chunk) => {
+                forward(&chunk);
+                self.push(chunk);
+            }
+            Err(e) => {
+                self.error
+                    .get_or_insert(format!("Failed to parse chunk: {}", e));
+            }
+        }
+        true
+    }
+
+    pub fn push(&mut self, chunk: Chunk) {
+        match chunk {
+            Chunk::Part { part } => self.parts.push(part),
+            Chunk::Patterns { part, patterns } => match self.parts.get_mut(part) {
+                Some(staff) => staff.patterns.extend(patterns),
+                None => {
+                    self.error
+                        .get_or_insert(format!("Patterns for part {} came before it", part));
+                }
+            },
+            Chunk::Result { result } => self.rest = Some(result),
+        }
+    }
+
+    /// The result, None when no `Result` chunk arrived.
+    pub fn finish(self) -> Result<Option<AnalysisResult>, String> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        Ok(self.rest.map(|mut result| {
+            result.parts = self.parts;
+            result
+        }))
+    }
+}
+
+/// `result` split into chunks, `batch` patterns at a time.
+pub fn chunks(mut result: AnalysisResult, batch: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    for (i, mut part) in std::mem::take(&mut result.parts).into_iter().enumerate() {
+        let patterns = std::mem::take(&mut part.patterns);
+        chunks.push(Chunk::Part { part });
+        let mut patterns = patterns.into_iter().peekable();
+        while patterns.peek().is_some() {
+            chunks.push(Chunk::Patterns {
+                part: i,
+                patterns: patterns.by_ref().take(batch.max(1)).collect(),
+            });
+        }
+    }
+    chunks.push(Chunk::Result { result });
+    chunks
+}
+
+/// Counts of `result` and of the chunks `chunks(result, batch)` makes.
+pub fn summary(result: &AnalysisResult, batch: usize) -> StreamSummary {
+    let batches = |n: usize| n.div_ceil(batch.max(1));
+    StreamSummary {
+        file: result.file.clone(),
+        parts: result.parts.len(),
+        patterns: result.parts.iter().map(|p| p.patterns.len()).sum(),
+        patterns_found: result.patterns_found,
+        chunks: 1 + result
+            .parts
+            .iter()
+            .map(|p| 1 + batches(p.patterns.len()))
+            .sum::<usize>(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result() -> AnalysisResult {
+        let staff = |part_index, ids: std::ops::Range<i32>| StaffPatternData {
+            part_index,
+            part_name: format!("Staff {}", part_index),
+            patterns: ids
+                .map(|id| Pattern {
+                    id,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        AnalysisResult {
+            file: "opera.musicxml".to_string(),
+            parts: vec![staff(0, 0..5), staff(1, 5..5), staff(2, 5..7)],
+            musicxml_content: "<score-partwise/>".to_string(),
+            patterns_found: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chunked_lines_rebuild_the_result() {
+        let chunks = chunks(result(), 2);
+        // Staff 0 in three batches, staff 1 without any, staff 2 in one
+        assert_eq!(chunks.len(), (1 + 3) + 1 + (1 + 1) + 1);
+        assert_eq!(summary(&result(), 2).chunks, chunks.len());
+
+        let lines: Vec<String> = chunks
+            .iter()
+            .map(|chunk| serde_json::to_string(chunk).unwrap())
+            .collect();
+        let mut assembler = Assembler::default();
+        assert!(!assembler.push_line("{\"error\": \"nope\"}", |_| {}));
+        // Each chunk is forwarded as its line is read
+        let mut forwarded = Vec::new();
+        for line in &lines {
+            assert!(assembler.push_line(line, |chunk| {
+                forwarded.push(serde_json::to_string(chunk).unwrap())
+            }));
+        }
+        assert_eq!(forwarded, lines);
+        let rebuilt = assembler.finish().unwrap().unwrap();
+        let ids: Vec<Vec<i32>> = rebuilt
+            .parts
+            .iter()
+            .map(|p| p.patterns.iter().map(|pattern| pattern.id).collect())
+            .collect();
+        assert_eq!(ids, vec![vec![0, 1, 2, 3, 4], vec![], vec![5, 6]]);
+        assert_eq!(rebuilt.parts[2].part_name, "Staff 2");
+        assert_eq!(rebuilt.musicxml_content, "<score-partwise/>");
+        assert_eq!(summary(&rebuilt, 2).patterns, 7);
+    }
+
+    #[test]
+    fn out_of_order_or_broken_chunks_fail() {
+        let mut assembler = Assembler::default();
+        assert!(assembler.push_line(r#"{"type":"patterns","part":0,"patterns":[]}"#, |_| {}));
+        assert!(assembler.finish().unwrap_err().contains("part 0"));
+
+        let mut assembler = Assembler::default();
+        assert!(assembler.push_line(r#"{"type":"part","part":"#, |_| {
+            panic!("a broken chunk was forwarded")
+        }));
+        assert!(assembler.finish().is_err());
+        assert_eq!(Assembler::default().finish().unwrap().map(|r| r.file), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! Compact tokens naming a score and the options it was analyzed with, so
+//! someone else can reproduce the analysis.
+//!
+//! A token is `v<version>.` followed by base64url JSON holding the content
+//! hash and the options that differ from the defaults. The version is bumped
+//! whenever an option changes meaning, and tokens of another version are
+//! rejected rather than read differently.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::config::AnalyzerConfig;
+
+pub const TOKEN_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisToken {
+    /// Hex SHA-256 of the score file (see `cache::content_hash`).
+    pub content_hash: String,
+    pub config: AnalyzerConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    #[serde(rename = "h")]
+    content_hash: String,
+    /// Options that differ from `AnalyzerConfig::default()`.
+    #[serde(rename = "c", default)]
+    config: Map<String, Value>,
+}
+
+pub fn make(token: &AnalysisToken) -> Result<String, String> {
+    let to_map = |config: &AnalyzerConfig| match serde_json::to_value(config) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err("Failed to encode options: not an object".to_string()),
+        Err(e) => Err(format!("Failed to encode options: {}", e)),
+    };
+    let defaults = to_map(&AnalyzerConfig::default())?;
+    let mut config = to_map(&token.config)?;
+    config.retain(|name, value| defaults.get(name) != Some(value));
+
+    let payload = Payload {
+        content_hash: token.content_hash.clone(),
+        config,
+    };
+    let json =
+        serde_json::to_vec(&payload).map_err(|e| format!("Failed to encode token: {}", e))?;
+    Ok(format!(
+        "v{}.{}",
+        TOKEN_VERSION,
+        URL_SAFE_NO_PAD.encode(json)
+    ))
+}
+
+pub fn parse(token: &str) -> Result<AnalysisToken, String> {
+    let invalid = |reason: &str| format!("Invalid analysis token: {}", reason);
+    let (version, body) = token
+        .trim()
+        .split_once('.')
+        .ok_or_else(|| invalid("missing version"))?;
+    let version: u32 = version
+        .strip_prefix('v')
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid("missing version"))?;
+    if version != TOKEN_VERSION {
+        return Err(format!(
+            "Analysis token is version {}, this app reads version {}",
+            version, TOKEN_VERSION
+        ));
+    }
+
+    let json = URL_SAFE_NO_PAD
+        .decode(body)
+        .map_err(|e| invalid(&e.to_string()))?;
+    let payload: Payload = serde_json::from_slice(&json).map_err(|e| invalid(&e.to_string()))?;
+    let hash = &payload.content_hash;
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid("bad content hash"));
+    }
+    let config = serde_json::from_value(Value::Object(payload.config))
+        .map_err(|e| invalid(&e.to_string()))?;
+    Ok(AnalysisToken {
+        content_hash: payload.content_hash,
+        config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MeasureFrame;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn round_trips_changed_options() {
+        let token = AnalysisToken {
+            content_hash: HASH.to_string(),
+            config: AnalyzerConfig {
+                classify_patterns: true,
+                measure_frame: MeasureFrame::Played,
+                ..Default::default()
+            },
+        };
+        let text = make(&token).unwrap();
+        assert!(text.starts_with("v1."));
+        assert!(text.len() < 200);
+
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed.content_hash, HASH);
+        assert!(parsed.config.classify_patterns);
+        assert_eq!(parsed.config.measure_frame, MeasureFrame::Played);
+        assert!(parsed.config.chords_as_single_event);
+    }
+
+    #[test]
+    fn rejects_other_versions_and_garbage() {
+        let text = make(&AnalysisToken {
+            content_hash: HASH.to_string(),
+            config: AnalyzerConfig::default(),
+        })
+        .unwrap();
+        let future = text.replacen("v1.", "v2.", 1);
+        assert!(parse(&future).unwrap_err().contains("version 2"));
+        assert!(parse("v1.not*base64").is_err());
+        assert!(parse("hello").is_err());
+
+        let short_hash = URL_SAFE_NO_PAD.encode(r#"{"h":"abc"}"#);
+        assert!(parse(&format!("v1.{}", short_hash)).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! Warnings the analyzer printed to stderr, collapsed so a problem repeated
+//! in every measure shows up once with a count.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyzerWarning {
+    /// The warning text without its source location or category.
+    pub message: String,
+    /// How many times the analyzer printed it.
+    pub count: usize,
+    /// The first occurrence's full line ("convert.py:88: UserWarning: ...").
+    pub detail: String,
+}
+
+impl fmt::Display for AnalyzerWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if self.count > 1 {
+            write!(f, " (x{})", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Python `warnings` lines among non-progress stderr lines, deduplicated by
+/// message in order of first appearance. The source lines Python echoes
+/// beneath each warning are dropped.
+pub fn collect(stderr_lines: &[String]) -> Vec<AnalyzerWarning> {
+    let mut warnings: Vec<AnalyzerWarning> = Vec::new();
+    for line in stderr_lines {
+        let Some(message) = warning_message(line) else {
+            continue;
+        };
+        match warnings.iter_mut().find(|w| w.message == message) {
+            Some(warning) => warning.count += 1,
+            None => warnings.push(AnalyzerWarning {
+                message: message.to_string(),
+                count: 1,
+                detail: line.trim().to_string(),
+            }),
+        }
+    }
+    warnings
+}
+
+/// "path.py:12: SomeWarning: text" -> "text".
+fn warning_message(line: &str) -> Option<&str> {
+    if line.contains("warnings.warn") {
+        return None;
+    }
+    let (_, message) = line.split_once("Warning: ")?;
+    Some(message.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_warnings_collapse_with_a_count() {
+        let mut lines = Vec::new();
+        for m in 1..=37 {
+            lines.push(format!(
+                "/app/music21/musicxml/xmlToM21.py:{}: MusicXMLWarning: measure has no divisions",
+                800 + m
+            ));
+            lines.push("  warnings.warn(".to_string());
+        }
+        lines.push("conversion.py:5: UserWarning: low resolution scan".to_string());
+        lines.push("Traceback (most recent call last):".to_string());
+
+        let warnings = collect(&lines);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].to_string(), "measure has no divisions (x37)");
+        assert_eq!(
+            warnings[0].detail,
+            "/app/music21/musicxml/xmlToM21.py:801: MusicXMLWarning: measure has no divisions"
+        );
+        assert_eq!(warnings[1].to_string(), "low resolution scan");
+    }
+}
@@ -0,0 +1,172 @@
+//! Watching score files for saves made in another program. There is at most
+//! one watcher per path, and all of them stop when the window closes.
+//!
+//! Each watcher is a `notify` watcher on the file's directory, since an
+//! editor that saves by writing a temp file and renaming it over the score
+//! replaces the file a watch on the file itself would follow.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEventKind, Debouncer};
+
+/// Event emitted with the path when a watched file changes.
+pub const FILE_CHANGED_EVENT: &str = "file-changed";
+
+/// How long a file has to stay unchanged after a change before it's
+/// reported, so an editor writing it in several steps sends one event.
+pub const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Active watchers by path. Dropping one shuts down its notify watcher
+/// and debouncer threads.
+pub struct Watchers {
+    debounce: Duration,
+    active: Mutex<HashMap<PathBuf, Debouncer<RecommendedWatcher>>>,
+}
+
+impl Default for Watchers {
+    fn default() -> Self {
+        Watchers::with_debounce(DEBOUNCE)
+    }
+}
+
+impl Watchers {
+    pub fn with_debounce(debounce: Duration) -> Self {
+        Watchers {
+            debounce,
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Call `on_change` each time `path` (already canonical) is saved and
+    /// then left alone for the debounce, until it is unwatched. Returns
+    /// false when the path is already watched.
+    pub fn watch(
+        &self,
+        path: &Path,
+        on_change: impl Fn() + Send + 'static,
+    ) -> Result<bool, String> {
+        let mut active = self.active.lock().unwrap();
+        if active.contains_key(path) {
+            return Ok(false);
+        }
+        let dir = path
+            .parent()
+            .ok_or_else(|| format!("{} has no directory to watch", path.display()))?;
+        let watched = path.to_path_buf();
+        let mut debouncer = new_debouncer(self.debounce, move |events: DebounceEventResult| {
+            match events {
+                Ok(events) => {
+                    let saved = events
+                        .iter()
+                        .any(|e| e.path == watched && e.kind == DebouncedEventKind::Any);
+                    // A file deleted without coming back isn't a save
+                    if saved && watched.exists() {
+                        on_change();
+                    }
+                }
+                Err(e) => tracing::warn!("Watching {} failed: {}", watched.display(), e),
+            }
+        })
+        .map_err(|e| format!("Failed to watch {}: {}", path.display(), e))?;
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", path.display(), e))?;
+        active.insert(path.to_path_buf(), debouncer);
+        Ok(true)
+    }
+
+    /// Stop the watcher for `path`, returning whether there was one.
+    pub fn unwatch(&self, path: &Path) -> bool {
+        // Taken out first so its threads shut down without holding the lock
+        let removed = self.active.lock().unwrap().remove(path);
+        removed.is_some()
+    }
+
+    /// Stop every watcher, returning how many there were.
+    pub fn unwatch_all(&self) -> usize {
+        let removed = std::mem::take(&mut *self.active.lock().unwrap());
+        removed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("smrh-watch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl Fn() + Send + 'static) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        (count, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn the_same_path_is_watched_once() {
+        let dir = scratch("once");
+        let watchers = Watchers::default();
+        let count = || watchers.active.lock().unwrap().len();
+        let path = dir.join("a.musicxml");
+        assert!(watchers.watch(&path, || {}).unwrap());
+        assert!(!watchers.watch(&path, || {}).unwrap());
+        assert!(watchers.watch(&dir.join("b.musicxml"), || {}).unwrap());
+        assert_eq!(count(), 2);
+
+        assert_eq!(watchers.unwatch_all(), 2);
+        assert_eq!(count(), 0);
+        assert!(watchers.watch(&path, || {}).unwrap());
+        assert!(watchers.unwatch(&path));
+        assert!(!watchers.unwatch(&path));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_burst_of_writes_and_a_replacing_save_are_reported_once_each() {
+        let dir = scratch("burst");
+        let path = dir.join("score.musicxml");
+        std::fs::write(&path, "<score-partwise/>").unwrap();
+        let watchers = Watchers::with_debounce(Duration::from_millis(200));
+        let (saves, on_change) = counter();
+        watchers.watch(&path, on_change).unwrap();
+
+        for i in 0..5 {
+            std::fs::write(&path, format!("<score-partwise>{}</score-partwise>", i)).unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::thread::sleep(Duration::from_millis(800));
+        assert_eq!(saves.load(Ordering::SeqCst), 1);
+
+        // Saved by writing a temp file and renaming it over the score
+        let temp = dir.join(".score.musicxml.tmp");
+        std::fs::write(&temp, "<score-partwise/>").unwrap();
+        std::fs::rename(&temp, &path).unwrap();
+        std::thread::sleep(Duration::from_millis(800));
+        assert_eq!(saves.load(Ordering::SeqCst), 2);
+
+        // Other files in the directory aren't reported
+        std::fs::write(dir.join("other.musicxml"), "").unwrap();
+        std::thread::sleep(Duration::from_millis(800));
+        assert_eq!(saves.load(Ordering::SeqCst), 2);
+
+        // Nothing is reported once the watcher is dropped
+        assert_eq!(watchers.unwatch_all(), 1);
+        std::fs::write(&path, "<score-partwise/>").unwrap();
+        std::thread::sleep(Duration::from_millis(800));
+        assert_eq!(saves.load(Ordering::SeqCst), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,5 @@
-import { useState, useMemo, useEffect } from "react";
+import { useState, useMemo, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { open } from "@tauri-apps/plugin-dialog";
 import { listen } from "@tauri-apps/api/event";
 import { SheetMusicViewer, Pattern } from "./components/SheetMusicViewer";
 import { PatternList } from "./components/pattern-list/PatternList";
@@ -12,6 +11,7 @@ import {
   useTimeSignature,
 } from "./context/TimeSignatureContext";
 import { PlaybackProvider } from "./context/PlaybackContext";
+import { expandRuns } from "./utils/runs";
 
 interface PartPatterns {
   part_index: number;
@@ -21,33 +21,106 @@ interface PartPatterns {
 
 interface AnalysisResult {
   file: string;
-  treble: PartPatterns;
-  bass: PartPatterns;
+  parts: PartPatterns[]; // Every analyzed staff, in score order
   musicxml_content: string;
+  patterns_found: boolean;
+  key?: Key | null; // With annotate_scale_degrees
+  measure_index?: [number, PatternRef[]][]; // With by_measure, in score order
+  warnings?: AnalyzerWarning[];
+  progress_log?: Progress[]; // With include_progress_log
 }
 
+interface PatternRef {
+  staff: number; // Index into parts
+  pattern_id: number;
+}
+
+interface Key {
+  tonic: string;
+  mode: "major" | "minor";
+  fifths: number;
+  estimated: boolean; // Guessed from the notes; the score has no key signature
+}
+
+// One analyzer warning, however many times it was printed
+interface AnalyzerWarning {
+  message: string;
+  count: number;
+  detail: string; // First occurrence's full line
+}
+
+// Stage names sent by the backend; unknown stages pass through as-is
+type Stage =
+  | "queued"
+  | "spawn"
+  | "converting"
+  | "extracting"
+  | "omr"
+  | "merging"
+  | "analyzing"
+  | "parse"
+  | "cache"
+  | "done"
+  | "cancelled"
+  | (string & {});
+
 interface Progress {
   type: string;
-  stage: string;
+  stage: Stage;
   current: number;
   total: number;
   message: string;
+  fraction: number | null;
+  determinate: boolean; // Known total: show a bar, otherwise a spinner
+}
+
+// `AnalyzeError` in src-tauri/src/error.rs
+interface AnalyzeError {
+  kind:
+    | "io"
+    | "unsupported_format"
+    | "file_too_large"
+    | "forbidden"
+    | "spawn"
+    | "parse"
+    | "sidecar"
+    | "timeout"
+    | "cancelled"
+    | "other";
+  message: string;
+  not_found?: boolean; // io
+  stderr?: string[]; // sidecar, timeout
+  exit_code?: number | null; // sidecar
+}
+
+// `OpenFileRequest` in src-tauri/src/open.rs
+interface OpenFileRequest {
+  path: string;
+  source: "drop" | "launch" | "association";
+  error: AnalyzeError | null;
+}
+
+function isAnalyzeError(err: unknown): err is AnalyzeError {
+  return typeof err === "object" && err !== null && "kind" in err;
 }
 
 const LAST_FILE_STORAGE_KEY = "smrh_last_file_path";
 
 function AppContent() {
   const [musicXml, setMusicXml] = useState<string | null>(null);
-  const [treblePatterns, setTreblePatterns] = useState<Pattern[]>([]);
-  const [bassPatterns, setBassPatterns] = useState<Pattern[]>([]);
+  const [parts, setParts] = useState<PartPatterns[]>([]);
   const [enabledPatterns, setEnabledPatterns] = useState<Set<number>>(
     new Set()
   );
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [noPatterns, setNoPatterns] = useState(false);
   const [fileName, setFileName] = useState<string | null>(null);
   const [progress, setProgress] = useState<Progress | null>(null);
+  const [analysisId, setAnalysisId] = useState<number | null>(null);
   const [darkMode, setDarkMode] = useState(false);
+  // The file being watched for saves from another editor
+  const watchedPath = useRef<string | null>(null);
   const { setTimeSignature } = useTimeSignature();
 
   const patternColors = useMemo(() => new Map<number, string>(), []);
@@ -61,6 +134,32 @@ function AppContent() {
     };
   }, []);
 
+  useEffect(() => {
+    const unlisten = listen<{ analysis_id: number }>(
+      "analyze-started",
+      (event) => {
+        setAnalysisId(event.payload.analysis_id);
+      }
+    );
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, []);
+
+  useEffect(() => {
+    // Re-analyze when the open score is saved in another program. The
+    // event carries the canonical path, so reload the one that was opened
+    const unlisten = listen<string>("file-changed", () => {
+      if (watchedPath.current) {
+        loadFile(watchedPath.current);
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
   useEffect(() => {
     document.body.classList.toggle("dark", darkMode);
     document.body.classList.toggle("light", !darkMode);
@@ -68,8 +167,8 @@ function AppContent() {
 
   // Combine all patterns for the viewer
   const allPatterns = useMemo(
-    () => [...treblePatterns, ...bassPatterns],
-    [treblePatterns, bassPatterns]
+    () => parts.flatMap((part) => part.patterns),
+    [parts]
   );
 
   const filteredPatterns = useMemo(
@@ -80,12 +179,20 @@ function AppContent() {
   async function loadFile(path: string) {
     setIsLoading(true);
     setError(null);
+    setNoPatterns(false);
     setProgress(null);
 
     try {
       const filename = path.split("/").pop() || path;
       const ext = path.match(/\.([^.]+)$/)?.[1]?.toLowerCase();
-      const isFileMusicXml = ext === "musicxml" || ext === "xml";
+      // read_file decompresses .mxl and converts MIDI, so those can be
+      // shown right away too
+      const isFileMusicXml =
+        ext === "musicxml" ||
+        ext === "xml" ||
+        ext === "mxl" ||
+        ext === "mid" ||
+        ext === "midi";
 
       // If we have a musicxml file, we can set the music xml instantly.
       if (isFileMusicXml) {
@@ -95,61 +202,115 @@ function AppContent() {
 
       setFileName(filename);
 
-      // Analyze for patterns
-      const result = await invoke<AnalysisResult>("analyze_music", { path });
+      // Analyze for patterns, asking first if the file is unusually large
+      const analyze = (confirmLarge: boolean) =>
+        invoke<AnalysisResult>("analyze_music", { path, confirmLarge });
+      let result: AnalysisResult;
+      try {
+        result = await analyze(false);
+      } catch (err) {
+        if (
+          !isAnalyzeError(err) ||
+          err.kind !== "file_too_large" ||
+          !window.confirm(
+            `${filename} is very large and may take a long time to analyze. Continue?`
+          )
+        ) {
+          throw err;
+        }
+        result = await analyze(true);
+      }
       console.log("result:", result);
 
-      setTreblePatterns(
-        result.treble.patterns.map((pattern) => ({ ...pattern, partIndex: 0 }))
-      );
-      setBassPatterns(
-        result.bass.patterns.map((pattern) => ({ ...pattern, partIndex: 1 }))
+      // part_index is the staff's position in the rendered score
+      setParts(
+        result.parts.map((part) => ({
+          ...part,
+          patterns: part.patterns.map((p) => ({
+            ...p,
+            partIndex: part.part_index,
+            notes: expandRuns(p.notes),
+          })),
+        }))
       );
 
       if (!isFileMusicXml) {
         setMusicXml(result.musicxml_content);
       }
 
+      setNoPatterns(!result.patterns_found);
+
       // Enable all patterns by default
-      const allIds = [
-        ...result.treble.patterns.map((p) => p.id),
-        ...result.bass.patterns.map((p) => p.id),
-      ];
+      const allIds = result.parts.flatMap((part) =>
+        part.patterns.map((p) => p.id)
+      );
       setEnabledPatterns(new Set(allIds));
       localStorage.setItem(LAST_FILE_STORAGE_KEY, path);
+
+      if (watchedPath.current !== path) {
+        if (watchedPath.current) {
+          invoke("unwatch_file", { path: watchedPath.current });
+        }
+        invoke("watch_file", { path });
+        watchedPath.current = path;
+      }
     } catch (err) {
-      setError(String(err));
+      if (!isAnalyzeError(err)) {
+        setError(String(err));
+      } else if (err.kind !== "cancelled") {
+        setError(err.message);
+      }
+      // Don't try to reopen a file that has since been moved or deleted, or
+      // that has to be opened again before it can be read
+      if (
+        isAnalyzeError(err) &&
+        ((err.kind === "io" && err.not_found) || err.kind === "forbidden")
+      ) {
+        localStorage.removeItem(LAST_FILE_STORAGE_KEY);
+      }
       setMusicXml(null);
-      setTreblePatterns([]);
-      setBassPatterns([]);
+      setParts([]);
     } finally {
       setIsLoading(false);
       setProgress(null);
+      setAnalysisId(null);
     }
   }
 
   useEffect(() => {
-    const savedPath = localStorage.getItem(LAST_FILE_STORAGE_KEY);
-    if (savedPath) {
-      loadFile(savedPath);
-    }
+    // Files dropped on the window or opened with the app from the OS
+    const openRequested = (request: OpenFileRequest) => {
+      if (request.error) {
+        setError(request.error.message);
+      } else {
+        loadFile(request.path);
+      }
+    };
+    const unlisten = listen<OpenFileRequest>("open-file-request", (event) =>
+      openRequested(event.payload)
+    );
+    // Requests from before this listener existed, such as the file the app
+    // was launched for, take the place of the last file
+    invoke<OpenFileRequest[]>("take_open_requests").then((requests) => {
+      const last = requests[requests.length - 1];
+      const savedPath = localStorage.getItem(LAST_FILE_STORAGE_KEY);
+      if (last) {
+        openRequested(last);
+      } else if (savedPath) {
+        loadFile(savedPath);
+      }
+    });
+    return () => {
+      unlisten.then((f) => f());
+    };
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, []);
 
   async function handleOpenFile() {
-    const selected = await open({
-      multiple: false,
-      filters: [
-        {
-          name: "Music Document",
-          extensions: ["pdf", "jpg", "png", "musicxml", "mxl"],
-        },
-      ],
-    });
+    // Picked through the backend, which then lets read_file read it
+    const path = await invoke<string | null>("open_score");
+    if (!path) return;
 
-    if (!selected) return;
-
-    const path = typeof selected === "string" ? selected : selected;
     await loadFile(path);
   }
 
@@ -218,11 +379,30 @@ function AppContent() {
         >
           {isLoading
             ? progress
-              ? progress.message
+              ? progress.fraction !== null
+                ? `${progress.message} (${Math.round(progress.fraction * 100)}%)`
+                : progress.message
               : "Loading..."
             : "Open File"}
         </button>
 
+        {isLoading &&
+          progress &&
+          (progress.determinate ? (
+            <progress value={progress.fraction ?? 0} max={1} />
+          ) : (
+            <span className="spinner" aria-label="Working" />
+          ))}
+
+        {isLoading && analysisId !== null && (
+          <button
+            onClick={() => invoke("cancel_analysis", { analysisId })}
+            style={{ padding: "8px 16px", cursor: "pointer" }}
+          >
+            Cancel
+          </button>
+        )}
+
         {fileName && (
           <span style={{ fontSize: "14px", color: "#aaa" }}>{fileName}</span>
         )}
@@ -231,6 +411,12 @@ function AppContent() {
           <span style={{ fontSize: "14px", color: "#ff6b6b" }}>{error}</span>
         )}
 
+        {noPatterns && (
+          <span style={{ fontSize: "14px", color: "#aaa" }}>
+            No repetition detected in this piece
+          </span>
+        )}
+
         <div style={{ flex: 1 }} />
 
         <TempoInput />
@@ -252,32 +438,18 @@ function AppContent() {
             flexDirection: "column",
           }}
         >
-          {/* Treble patterns - top half */}
-          <div
-            style={{
-              flex: 1,
-              overflowY: "auto",
-            }}
-          >
-            <PatternList
-              title="Treble"
-              patterns={treblePatterns}
-              enabledPatterns={enabledPatterns}
-              onTogglePattern={handleTogglePattern}
-              onToggleAllPatterns={handleToggleAllPatternsOfType}
-            />
-          </div>
-
-          {/* Bass patterns - bottom half */}
-          <div style={{ flex: 1, overflowY: "auto" }}>
-            <PatternList
-              title="Bass"
-              patterns={bassPatterns}
-              enabledPatterns={enabledPatterns}
-              onTogglePattern={handleTogglePattern}
-              onToggleAllPatterns={handleToggleAllPatternsOfType}
-            />
-          </div>
+          {/* One pattern list per staff, sharing the height */}
+          {parts.map((part) => (
+            <div key={part.part_index} style={{ flex: 1, overflowY: "auto" }}>
+              <PatternList
+                title={part.part_name}
+                patterns={part.patterns}
+                enabledPatterns={enabledPatterns}
+                onTogglePattern={handleTogglePattern}
+                onToggleAllPatterns={handleToggleAllPatternsOfType}
+              />
+            </div>
+          ))}
         </aside>
 
         {/* Sheet music viewer */}
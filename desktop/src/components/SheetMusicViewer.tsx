@@ -16,15 +16,38 @@ export interface NoteLocator {
   measure: number;
   beat: number | null;
   pitch: string;
+  duration_beats?: number | null; // Written length in quarter notes
+  absolute_beat?: number | null; // Quarter notes from the start of the piece
+  chord_group?: number | null; // Index of the chord's first event, if any
+  is_grace?: boolean;
+  tied?: boolean; // Continues a tie from the previous note of this pitch
+  x?: number | null; // Engraved position in tenths, with include_layout
+  y?: number | null;
+  page?: number | null;
+  scale_degree?: number | null; // 1-7 from the tonic, with annotate_scale_degrees
+  degree_alteration?: number | null; // Semitones off the key's scale
+  repeat?: number; // Identical notes folded into this one, with run_length_encode
+  articulations?: string[]; // "staccato", "slur", ..., with capture_articulations
 }
 
 export interface Pattern {
   id: number;
-  partIndex: number; // 0 = treble, 1 = bass
+  partIndex: number; // Staff in the rendered score, top staff 0
   length: number;
   count: number;
   positions: number[];
   notes: NoteLocator[];
+  harmony?: string | null; // chord symbol, when infer_harmony is on
+  metric_displacement?: MetricDisplacement | null; // with detect_metric_displacement
+  scaled_from?: [number, number] | null; // [pattern id, duration ratio], with detect_rhythmic_scaling
+  inversion_of?: number | null; // id of the pattern this one mirrors, with detect_inversions
+}
+
+// A steady recurrence period out of step with the barline, in quarter notes
+export interface MetricDisplacement {
+  period: number;
+  bar_length: number;
+  displacement: number;
 }
 
 // Position data for rendering React overlays
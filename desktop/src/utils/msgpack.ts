@@ -0,0 +1,136 @@
+import { invoke, InvokeArgs } from "@tauri-apps/api/core";
+
+// Minimal MessagePack decoder for the payloads of `*_packed` commands.
+// Covers everything rmp-serde emits for our types: nil, booleans, ints,
+// floats, strings, binary, arrays and maps (decoded as plain objects).
+
+const textDecoder = new TextDecoder();
+
+class Reader {
+  private offset = 0;
+  private view: DataView;
+
+  constructor(private bytes: Uint8Array) {
+    this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  }
+
+  read(): unknown {
+    const byte = this.view.getUint8(this.offset++);
+
+    if (byte <= 0x7f) return byte;
+    if (byte >= 0xe0) return byte - 0x100;
+    if ((byte & 0xf0) === 0x80) return this.map(byte & 0x0f);
+    if ((byte & 0xf0) === 0x90) return this.array(byte & 0x0f);
+    if ((byte & 0xe0) === 0xa0) return this.str(byte & 0x1f);
+
+    switch (byte) {
+      case 0xc0:
+        return null;
+      case 0xc2:
+        return false;
+      case 0xc3:
+        return true;
+      case 0xc4:
+        return this.bin(this.uint(1));
+      case 0xc5:
+        return this.bin(this.uint(2));
+      case 0xc6:
+        return this.bin(this.uint(4));
+      case 0xca:
+        return this.number(4, (o) => this.view.getFloat32(o));
+      case 0xcb:
+        return this.number(8, (o) => this.view.getFloat64(o));
+      case 0xcc:
+        return this.uint(1);
+      case 0xcd:
+        return this.uint(2);
+      case 0xce:
+        return this.uint(4);
+      case 0xcf:
+        return this.number(8, (o) => Number(this.view.getBigUint64(o)));
+      case 0xd0:
+        return this.number(1, (o) => this.view.getInt8(o));
+      case 0xd1:
+        return this.number(2, (o) => this.view.getInt16(o));
+      case 0xd2:
+        return this.number(4, (o) => this.view.getInt32(o));
+      case 0xd3:
+        return this.number(8, (o) => Number(this.view.getBigInt64(o)));
+      case 0xd9:
+        return this.str(this.uint(1));
+      case 0xda:
+        return this.str(this.uint(2));
+      case 0xdb:
+        return this.str(this.uint(4));
+      case 0xdc:
+        return this.array(this.uint(2));
+      case 0xdd:
+        return this.array(this.uint(4));
+      case 0xde:
+        return this.map(this.uint(2));
+      case 0xdf:
+        return this.map(this.uint(4));
+      default:
+        throw new Error(`Unsupported MessagePack type 0x${byte.toString(16)}`);
+    }
+  }
+
+  private number(size: number, get: (offset: number) => number): number {
+    const value = get(this.offset);
+    this.offset += size;
+    return value;
+  }
+
+  private uint(size: 1 | 2 | 4): number {
+    return this.number(size, (o) =>
+      size === 1
+        ? this.view.getUint8(o)
+        : size === 2
+          ? this.view.getUint16(o)
+          : this.view.getUint32(o)
+    );
+  }
+
+  private str(length: number): string {
+    const value = textDecoder.decode(
+      this.bytes.subarray(this.offset, this.offset + length)
+    );
+    this.offset += length;
+    return value;
+  }
+
+  private bin(length: number): Uint8Array {
+    const value = this.bytes.slice(this.offset, this.offset + length);
+    this.offset += length;
+    return value;
+  }
+
+  private array(length: number): unknown[] {
+    const items = new Array(length);
+    for (let i = 0; i < length; i++) items[i] = this.read();
+    return items;
+  }
+
+  private map(length: number): Record<string, unknown> {
+    const entries: Record<string, unknown> = {};
+    for (let i = 0; i < length; i++) {
+      const key = String(this.read());
+      entries[key] = this.read();
+    }
+    return entries;
+  }
+}
+
+export function decodeMsgpack<T>(data: ArrayBuffer | Uint8Array): T {
+  const bytes = data instanceof Uint8Array ? data : new Uint8Array(data);
+  return new Reader(bytes).read() as T;
+}
+
+// Invoke a command that returns MessagePack bytes and decode the result,
+// e.g. `invokePacked<AnalysisResult>("analyze_music_packed", { path })`.
+export async function invokePacked<T>(
+  command: string,
+  args?: InvokeArgs
+): Promise<T> {
+  return decodeMsgpack<T>(await invoke<ArrayBuffer>(command, args));
+}
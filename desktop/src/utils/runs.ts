@@ -0,0 +1,20 @@
+import type { NoteLocator } from "../components/SheetMusicViewer";
+
+// Undo the backend's run_length_encode option: an entry with `repeat` n
+// stands for n identical consecutive notes, each one duration further on.
+export function expandRuns(notes: NoteLocator[]): NoteLocator[] {
+  return notes.flatMap((note) => {
+    const count = Math.max(note.repeat ?? 1, 1);
+    const step = note.duration_beats ?? 0;
+    return Array.from({ length: count }, (_, k) => ({
+      ...note,
+      index: note.index + k,
+      beat: note.beat === null ? null : note.beat + step * k,
+      absolute_beat:
+        note.absolute_beat == null
+          ? note.absolute_beat
+          : note.absolute_beat + step * k,
+      repeat: 0,
+    }));
+  });
+}